@@ -0,0 +1,722 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::bytecode::{CallInfo, Constant, Op, Proto};
+use crate::compiler::stack_effect;
+use crate::diagnostic::Diagnostic;
+use crate::eval::{BinOp, Evaluator};
+use crate::stdlib::Builtins;
+use crate::token::Span;
+
+// An `.alinc` file holds a compiled script:
+//
+//   magic, FORMAT, COMPILER, source file name, source hash, global names,
+//   file names, then the script's `Proto` and the warnings compiling it
+//   gave.
+//
+// Integers are LEB128 varints and strings are length-prefixed UTF-8.
+// Globals and builtins are stored by name and numbered again on loading,
+// since their numbers only mean something to the evaluator that compiled
+// them.
+
+const MAGIC: &[u8] = b"ALINC\0";
+
+/// Bumped whenever the encoding changes.
+const FORMAT: u64 = 3;
+
+/// The codes of the warnings compiling can give, which are kept with the
+/// code so that running it again repeats them.
+const WARNINGS: [&str; 3] = ["W0001", "W0002", "W0301"];
+
+/// Identifies the build that wrote a file: a hash of the sources that decide
+/// what a script compiles to and what the code means. A file from any other
+/// build, even of the same version, was checked and optimized by a different
+/// resolver and optimizer, so it is compiled again instead.
+const COMPILER: u64 = fingerprint(&[
+    include_str!("token.rs"),
+    include_str!("lexer.rs"),
+    include_str!("ast.rs"),
+    include_str!("parser.rs"),
+    include_str!("resolve.rs"),
+    include_str!("optimize.rs"),
+    include_str!("compiler.rs"),
+    include_str!("bytecode.rs"),
+    include_str!("alinc.rs"),
+    include_str!("eval.rs"),
+    include_str!("iter.rs"),
+    include_str!("vm.rs"),
+    include_str!("stdlib/mod.rs"),
+    include_str!("stdlib/base.rs"),
+    include_str!("stdlib/csv.rs"),
+    include_str!("stdlib/fs.rs"),
+    include_str!("stdlib/json.rs"),
+    include_str!("stdlib/math.rs"),
+    include_str!("stdlib/process.rs"),
+    include_str!("stdlib/random.rs"),
+    include_str!("stdlib/stdin.rs"),
+    include_str!("stdlib/string.rs"),
+    include_str!("stdlib/time.rs"),
+    include_str!("stdlib/toml.rs"),
+    include_str!("stdlib/yaml.rs"),
+]);
+
+const BINARY_OPS: [BinOp; 10] = [
+    BinOp::Add,
+    BinOp::Sub,
+    BinOp::Mul,
+    BinOp::Div,
+    BinOp::Equal,
+    BinOp::NotEqual,
+    BinOp::Less,
+    BinOp::LessEqual,
+    BinOp::Greater,
+    BinOp::GreaterEqual,
+];
+
+/// A compiled script read back from disk.
+pub struct Module {
+    /// The source file it was compiled from.
+    pub file: String,
+    /// The `source_hash` of that file when it was compiled.
+    pub source_hash: u64,
+    pub script: Rc<Proto>,
+    /// What lexing and resolving the source warned about.
+    pub warnings: Vec<Diagnostic>,
+}
+
+/// FNV-1a, which unlike the standard library's hasher is guaranteed to
+/// stay the same between Rust releases.
+pub const fn source_hash(source: &str) -> u64 {
+    fnv(0xcbf29ce484222325, source.as_bytes())
+}
+
+const fn fnv(mut hash: u64, bytes: &[u8]) -> u64 {
+    let mut i = 0;
+    while i < bytes.len() {
+        hash = (hash ^ bytes[i] as u64).wrapping_mul(0x100000001b3);
+        i += 1;
+    }
+    hash
+}
+
+/// Hashes `sources` as one text, with a separator so moving code between
+/// them still changes the result.
+const fn fingerprint(sources: &[&str]) -> u64 {
+    let mut hash = 0xcbf29ce484222325;
+    let mut i = 0;
+    while i < sources.len() {
+        hash = fnv(hash, sources[i].as_bytes());
+        hash = fnv(hash, &[0]);
+        i += 1;
+    }
+    hash
+}
+
+/// Where `alin compile` puts the compiled form of a source file: beside it,
+/// with an `.alinc` extension.
+pub fn compiled_path(path: &str) -> PathBuf {
+    Path::new(path).with_extension("alinc")
+}
+
+/// Where running a script caches its compiled form: in alin's directory of
+/// the user's cache, named after a hash of the script's full path, so that
+/// running never writes beside the script.
+pub fn cache_path(path: &str) -> PathBuf {
+    let full = fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+    let cache = env::var_os("XDG_CACHE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".cache")))
+        .or_else(|| env::var_os("LOCALAPPDATA").map(PathBuf::from))
+        .unwrap_or_else(env::temp_dir);
+    cache.join("alin").join(format!("{:016x}.alinc", source_hash(&full.to_string_lossy())))
+}
+
+impl Evaluator {
+    /// Serializes a script this evaluator compiled from `file`, with the
+    /// warnings compiling it gave.
+    pub fn encode(&self, script: &Proto, warnings: &[Diagnostic], file: &str, source_hash: u64) -> Vec<u8> {
+        let mut encoder = Encoder { names: &self.globals.names, ..Encoder::default() };
+        encoder.proto(script);
+        encoder.uint(warnings.len() as u64);
+        for warning in warnings {
+            encoder.warning(warning);
+        }
+        let body = std::mem::take(&mut encoder.out);
+
+        let mut header = Encoder::default();
+        header.out.extend_from_slice(MAGIC);
+        header.uint(FORMAT);
+        header.out.extend_from_slice(&COMPILER.to_le_bytes());
+        header.str(file);
+        header.out.extend_from_slice(&source_hash.to_le_bytes());
+        header.uint(encoder.globals.len() as u64);
+        for name in &encoder.globals {
+            header.str(name);
+        }
+        header.uint(encoder.files.len() as u64);
+        for name in &encoder.files {
+            header.str(name);
+        }
+        header.out.extend(body);
+        header.out
+    }
+
+    /// Reads a script written by `encode`, possibly by another run of alin.
+    /// Files from a different build of alin are rejected.
+    pub fn decode(&mut self, bytes: &[u8]) -> Result<Module, String> {
+        let mut decoder = Decoder { bytes, pos: 0, globals: Vec::new(), files: Vec::new(), builtins: &self.builtins };
+        if decoder.take(MAGIC.len())? != MAGIC {
+            return Err("not a compiled alin file".to_string());
+        }
+        if decoder.uint()? != FORMAT || decoder.take(8)? != COMPILER.to_le_bytes() {
+            return Err("compiled by a different build of alin".to_string());
+        }
+        let file = decoder.str()?;
+        let source_hash = u64::from_le_bytes(decoder.take(8)?.try_into().map_err(|_| "truncated file")?);
+        for _ in 0..decoder.uint()? {
+            let name = decoder.str()?;
+            decoder.globals.push(self.globals.id(&name));
+        }
+        for _ in 0..decoder.uint()? {
+            let name = decoder.str()?;
+            decoder.files.push(name.into());
+        }
+        let script = decoder.proto()?;
+        let warnings = (0..decoder.uint()?).map(|_| decoder.warning()).collect::<Result<_, _>>()?;
+        if decoder.pos != bytes.len() {
+            return Err("unexpected data after the script".to_string());
+        }
+        Ok(Module { file, source_hash, script, warnings })
+    }
+}
+
+#[derive(Default)]
+struct Encoder<'a> {
+    /// The evaluator's global names, by number.
+    names: &'a [String],
+    /// Global names in the order the file numbers them.
+    globals: Vec<&'a str>,
+    global_ids: HashMap<u32, u64>,
+    files: Vec<Rc<str>>,
+    file_ids: HashMap<Rc<str>, u64>,
+    out: Vec<u8>,
+}
+
+impl<'a> Encoder<'a> {
+    fn uint(&mut self, mut n: u64) {
+        loop {
+            let byte = (n & 0x7f) as u8;
+            n >>= 7;
+            if n == 0 {
+                self.out.push(byte);
+                return;
+            }
+            self.out.push(byte | 0x80);
+        }
+    }
+
+    fn str(&mut self, s: &str) {
+        self.uint(s.len() as u64);
+        self.out.extend_from_slice(s.as_bytes());
+    }
+
+    fn global(&mut self, id: u32) -> u32 {
+        if let Some(&index) = self.global_ids.get(&id) {
+            return index as u32;
+        }
+        let index = self.globals.len() as u64;
+        self.globals.push(&self.names[id as usize]);
+        self.global_ids.insert(id, index);
+        index as u32
+    }
+
+    fn span(&mut self, span: &Span) {
+        let file = match self.file_ids.get(&span.file) {
+            Some(&index) => index,
+            None => {
+                let index = self.files.len() as u64;
+                self.files.push(span.file.clone());
+                self.file_ids.insert(span.file.clone(), index);
+                index
+            }
+        };
+        self.uint(file);
+        for n in [span.offset, span.len, span.line, span.column] {
+            self.uint(n as u64);
+        }
+    }
+
+    fn warning(&mut self, warning: &Diagnostic) {
+        self.str(warning.code);
+        self.str(&warning.message);
+        self.uint(warning.labels.len() as u64);
+        for label in &warning.labels {
+            self.span(&label.span);
+            self.str(&label.message);
+            self.uint(label.primary as u64);
+        }
+        self.uint(warning.notes.len() as u64);
+        for note in &warning.notes {
+            self.str(note);
+        }
+    }
+
+    fn proto(&mut self, proto: &Proto) {
+        self.str(&proto.name);
+        self.uint(proto.params.len() as u64);
+        for param in &proto.params {
+            self.str(param);
+        }
+        self.uint(proto.is_generator as u64);
+        self.span(&proto.span);
+        self.uint(proto.slots.len() as u64);
+        for slot in &proto.slots {
+            self.str(slot);
+        }
+
+        self.uint(proto.constants.len() as u64);
+        for constant in &proto.constants {
+            match constant {
+                Constant::Number(n) => {
+                    self.uint(0);
+                    self.out.extend_from_slice(&n.to_le_bytes());
+                }
+                Constant::String(s) => {
+                    self.uint(1);
+                    self.str(s);
+                }
+                Constant::Function(function) => {
+                    self.uint(2);
+                    self.proto(function);
+                }
+            }
+        }
+
+        self.uint(proto.spans.len() as u64);
+        for span in &proto.spans {
+            self.span(span);
+        }
+
+        self.uint(proto.calls.len() as u64);
+        for call in &proto.calls {
+            self.str(&call.name);
+            // Slots are stored one higher, leaving 0 for none.
+            self.uint(call.slot.map_or(0, |slot| slot as u64 + 1));
+            let global = self.global(call.global);
+            self.uint(global as u64);
+            self.uint(call.builtin.is_some() as u64);
+            self.uint(call.span as u64);
+        }
+
+        self.uint(proto.code.len() as u64);
+        for &op in &proto.code {
+            let (tag, operands) = self.op(op);
+            self.uint(tag);
+            for operand in operands {
+                self.uint(operand as u64);
+            }
+        }
+    }
+
+    /// An instruction's tag and operands, with globals numbered for the
+    /// file. Unused operands are 0 and take one byte each.
+    fn op(&mut self, op: Op) -> (u64, [u32; 3]) {
+        match op {
+            Op::Const(index) => (0, [index, 0, 0]),
+            Op::Nil => (1, [0; 3]),
+            Op::True => (2, [0; 3]),
+            Op::False => (3, [0; 3]),
+            Op::Pop => (4, [0; 3]),
+            Op::PopN(n) => (5, [n, 0, 0]),
+            Op::Dup => (6, [0; 3]),
+            Op::LoadName { slot, global, span } => (7, [slot, self.global(global), span]),
+            Op::LoadGlobal { global, span } => (8, [self.global(global), span, 0]),
+            Op::StoreName { slot, global } => (9, [slot, self.global(global), 0]),
+            Op::StoreGlobal(global) => (10, [self.global(global), 0, 0]),
+            Op::List(n) => (11, [n, 0, 0]),
+            Op::Append => (12, [0; 3]),
+            Op::Extend { span } => (13, [span, 0, 0]),
+            Op::Tuple(n) => (14, [n, 0, 0]),
+            Op::Map(n) => (15, [n, 0, 0]),
+            Op::Index { spans } => (16, [spans, 0, 0]),
+            Op::Binary { op, spans } => {
+                let op = BINARY_OPS.iter().position(|&known| known == op).unwrap_or(0);
+                (17, [op as u32, spans, 0])
+            }
+            Op::MakeFunction(index) => (18, [index, 0, 0]),
+            Op::Callee { call } => (19, [call, 0, 0]),
+            Op::Call { argc, call } => (20, [argc, call, 0]),
+            Op::TailCall { argc, call } => (21, [argc, call, 0]),
+            Op::Return => (22, [0; 3]),
+            Op::Jump(target) => (23, [target, 0, 0]),
+            Op::JumpIfFalse(target) => (24, [target, 0, 0]),
+            Op::Iterate { span } => (25, [span, 0, 0]),
+            Op::Next { exit, span } => (26, [exit, span, 0]),
+            Op::EndLoop => (27, [0; 3]),
+            Op::Yield => (28, [0; 3]),
+            Op::Unpack { count, span } => (29, [count, span, 0]),
+            Op::UnpackList { count, rest, span } => (30, [count, rest as u32, span]),
+            Op::UnpackMap { span } => (31, [span, 0, 0]),
+            Op::Field { key } => (32, [key, 0, 0]),
+            Op::StoreIndex { span } => (33, [span, 0, 0]),
+            Op::EndStatement => (34, [0; 3]),
+            Op::SpreadError { span } => (35, [span, 0, 0]),
+            Op::YieldError { span } => (36, [span, 0, 0]),
+            Op::LoopControlError { span } => (37, [span, 0, 0]),
+        }
+    }
+}
+
+struct Decoder<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+    /// The evaluator's number for each global the file names.
+    globals: Vec<u32>,
+    files: Vec<Rc<str>>,
+    builtins: &'a Builtins,
+}
+
+impl Decoder<'_> {
+    fn take(&mut self, len: usize) -> Result<&[u8], String> {
+        let end = self.pos.checked_add(len).filter(|&end| end <= self.bytes.len()).ok_or("truncated file")?;
+        let bytes = &self.bytes[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn uint(&mut self) -> Result<u64, String> {
+        let mut n = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.take(1)?[0];
+            n |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+        Err("malformed number".to_string())
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        u32::try_from(self.uint()?).map_err(|_| "number out of range".to_string())
+    }
+
+    fn str(&mut self) -> Result<String, String> {
+        let len = self.uint()? as usize;
+        let bytes = self.take(len)?.to_vec();
+        String::from_utf8(bytes).map_err(|_| "malformed string".to_string())
+    }
+
+    fn global(&self, index: u32) -> Result<u32, String> {
+        self.globals.get(index as usize).copied().ok_or_else(|| format!("unknown global {}", index))
+    }
+
+    fn span(&mut self) -> Result<Span, String> {
+        let file = self.uint()? as usize;
+        let file = self.files.get(file).cloned().ok_or("unknown file")?;
+        Ok(Span {
+            file,
+            offset: self.uint()? as usize,
+            len: self.uint()? as usize,
+            line: self.uint()? as usize,
+            column: self.uint()? as usize,
+        })
+    }
+
+    fn warning(&mut self) -> Result<Diagnostic, String> {
+        let code = self.str()?;
+        let code = WARNINGS
+            .into_iter()
+            .find(|known| *known == code)
+            .ok_or_else(|| format!("unknown warning {}", code))?;
+        let mut warning = Diagnostic::warning(code, self.str()?);
+        for _ in 0..self.uint()? {
+            let (span, message) = (self.span()?, self.str()?);
+            warning = match self.uint()? {
+                1 => warning.with_label(span, message),
+                _ => warning.with_secondary(span, message),
+            };
+        }
+        for _ in 0..self.uint()? {
+            warning = warning.with_note(self.str()?);
+        }
+        Ok(warning)
+    }
+
+    fn proto(&mut self) -> Result<Rc<Proto>, String> {
+        let mut proto = Proto { name: self.str()?, ..Proto::default() };
+        for _ in 0..self.uint()? {
+            let param = self.str()?;
+            proto.params.push(param);
+        }
+        proto.is_generator = self.uint()? != 0;
+        proto.span = self.span()?;
+        for _ in 0..self.uint()? {
+            let slot = self.str()?;
+            proto.slots.push(slot);
+        }
+
+        for _ in 0..self.uint()? {
+            let constant = match self.uint()? {
+                0 => Constant::Number(f64::from_le_bytes(self.take(8)?.try_into().map_err(|_| "truncated file")?)),
+                1 => Constant::String(self.str()?),
+                2 => Constant::Function(self.proto()?),
+                tag => return Err(format!("unknown constant kind {}", tag)),
+            };
+            proto.constants.push(constant);
+        }
+
+        for _ in 0..self.uint()? {
+            let span = self.span()?;
+            proto.spans.push(span);
+        }
+
+        for _ in 0..self.uint()? {
+            let name = self.str()?;
+            let slot = self.u32()?.checked_sub(1);
+            let global = self.u32()?;
+            let global = self.global(global)?;
+            let builtin = match self.uint()? {
+                0 => None,
+                _ => Some(self.builtins.get(&name).ok_or_else(|| format!("unknown builtin `{}`", name))?),
+            };
+            let span = self.u32()?;
+            proto.calls.push(CallInfo { name, slot, global, builtin, span });
+        }
+
+        for _ in 0..self.uint()? {
+            let op = self.op()?;
+            proto.code.push(op);
+        }
+        check(&proto)?;
+        Ok(Rc::new(proto))
+    }
+
+    fn op(&mut self) -> Result<Op, String> {
+        let tag = self.uint()?;
+        let [a, b, c] = [self.u32()?, self.u32()?, self.u32()?];
+        Ok(match tag {
+            0 => Op::Const(a),
+            1 => Op::Nil,
+            2 => Op::True,
+            3 => Op::False,
+            4 => Op::Pop,
+            5 => Op::PopN(a),
+            6 => Op::Dup,
+            7 => Op::LoadName { slot: a, global: self.global(b)?, span: c },
+            8 => Op::LoadGlobal { global: self.global(a)?, span: b },
+            9 => Op::StoreName { slot: a, global: self.global(b)? },
+            10 => Op::StoreGlobal(self.global(a)?),
+            11 => Op::List(a),
+            12 => Op::Append,
+            13 => Op::Extend { span: a },
+            14 => Op::Tuple(a),
+            15 => Op::Map(a),
+            16 => Op::Index { spans: a },
+            17 => {
+                let op = *BINARY_OPS.get(a as usize).ok_or("unknown operator")?;
+                Op::Binary { op, spans: b }
+            }
+            18 => Op::MakeFunction(a),
+            19 => Op::Callee { call: a },
+            20 => Op::Call { argc: a, call: b },
+            21 => Op::TailCall { argc: a, call: b },
+            22 => Op::Return,
+            23 => Op::Jump(a),
+            24 => Op::JumpIfFalse(a),
+            25 => Op::Iterate { span: a },
+            26 => Op::Next { exit: a, span: b },
+            27 => Op::EndLoop,
+            28 => Op::Yield,
+            29 => Op::Unpack { count: a, span: b },
+            30 => Op::UnpackList { count: a, rest: b != 0, span: c },
+            31 => Op::UnpackMap { span: a },
+            32 => Op::Field { key: a },
+            33 => Op::StoreIndex { span: a },
+            34 => Op::EndStatement,
+            35 => Op::SpreadError { span: a },
+            36 => Op::YieldError { span: a },
+            37 => Op::LoopControlError { span: a },
+            _ => return Err(format!("unknown instruction {}", tag)),
+        })
+    }
+}
+
+/// Checks that every table index and jump in `proto` is in range, so a
+/// damaged file is rejected rather than crashing the VM.
+fn check(proto: &Proto) -> Result<(), String> {
+    let in_range = |index: u32, len: usize| (index as usize) < len;
+    let constant = |index: u32| proto.constants.get(index as usize);
+    let ok = proto.calls.iter().all(|call| {
+        in_range(call.span, proto.spans.len()) && call.slot.is_none_or(|slot| in_range(slot, proto.slots.len()))
+    }) && proto.code.iter().all(|&op| match op {
+        Op::Const(index) => matches!(constant(index), Some(Constant::Number(_) | Constant::String(_))),
+        Op::MakeFunction(index) => matches!(constant(index), Some(Constant::Function(_))),
+        Op::Field { key } => matches!(constant(key), Some(Constant::String(_))),
+        Op::LoadName { slot, span, .. } => in_range(slot, proto.slots.len()) && in_range(span, proto.spans.len()),
+        Op::StoreName { slot, .. } => in_range(slot, proto.slots.len()),
+        Op::Index { spans } | Op::Binary { spans, .. } => (spans as usize) + 3 <= proto.spans.len(),
+        Op::Callee { call } | Op::Call { call, .. } | Op::TailCall { call, .. } => in_range(call, proto.calls.len()),
+        Op::Jump(target) | Op::JumpIfFalse(target) => in_range(target, proto.code.len()),
+        Op::Next { exit, span } => in_range(exit, proto.code.len()) && in_range(span, proto.spans.len()),
+        Op::LoadGlobal { span, .. }
+        | Op::Extend { span }
+        | Op::Iterate { span }
+        | Op::Unpack { span, .. }
+        | Op::UnpackList { span, .. }
+        | Op::UnpackMap { span }
+        | Op::StoreIndex { span }
+        | Op::SpreadError { span }
+        | Op::YieldError { span }
+        | Op::LoopControlError { span } => in_range(span, proto.spans.len()),
+        _ => true,
+    });
+    if ok && stack_depths_agree(proto) {
+        Ok(())
+    } else {
+        Err(format!("damaged code in `{}`", proto.name))
+    }
+}
+
+/// Follows every path through the code, whose jumps are known to be in
+/// range, checking that no instruction takes more values than the stack
+/// holds, that paths meeting at an instruction agree on the stack's depth
+/// and on where the iterators of `Iterate` are, that `Next` always finds
+/// one on top, and that none runs off the end.
+fn stack_depths_agree(proto: &Proto) -> bool {
+    let code = &proto.code;
+    // The depth before each instruction, with the positions of iterators.
+    let mut states: Vec<Option<(u64, Vec<u64>)>> = vec![None; code.len()];
+    let mut pending = vec![(0, 0, Vec::new())];
+    while let Some((ip, depth, mut iterators)) = pending.pop() {
+        let Some(&op) = code.get(ip) else {
+            return false;
+        };
+        match &states[ip] {
+            Some((seen, at)) if *seen == depth && *at == iterators => continue,
+            Some(_) => return false,
+            None => states[ip] = Some((depth, iterators.clone())),
+        }
+        let (pops, pushes) = stack_effect(op);
+        // Some instructions read values they leave in place.
+        let needs = match op {
+            Op::Dup | Op::StoreName { .. } | Op::StoreGlobal(_) | Op::UnpackMap { .. } => 1,
+            Op::Append | Op::Extend { .. } | Op::Next { .. } => 2,
+            _ => pops,
+        };
+        if depth < needs as u64 {
+            return false;
+        }
+        if matches!(op, Op::Next { .. }) && iterators.last() != Some(&(depth - 1)) {
+            return false;
+        }
+        let next = depth - pops as u64 + pushes as u64;
+        iterators.retain(|&at| at < depth - pops as u64);
+        if let Op::Iterate { .. } = op {
+            iterators.push(next - 1);
+        }
+        match op {
+            Op::Jump(target) => pending.push((target as usize, depth, iterators)),
+            Op::JumpIfFalse(target) => {
+                pending.extend([(target as usize, next, iterators.clone()), (ip + 1, next, iterators)]);
+            }
+            Op::Next { exit, .. } => {
+                pending.extend([(exit as usize, depth, iterators.clone()), (ip + 1, next, iterators)]);
+            }
+            // These leave the function or always fail.
+            Op::Return
+            | Op::TailCall { .. }
+            | Op::SpreadError { .. }
+            | Op::YieldError { .. }
+            | Op::LoopControlError { .. } => {}
+            _ => pending.push((ip + 1, next, iterators)),
+        }
+    }
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostic::{Color, Emitter, ErrorFormat};
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn script(code: Vec<Op>) -> Proto {
+        Proto { name: "<script>".to_string(), code, ..Proto::default() }
+    }
+
+    #[test]
+    fn accepts_balanced_code() {
+        let code = vec![Op::True, Op::JumpIfFalse(4), Op::Nil, Op::Jump(5), Op::False, Op::List(1), Op::Return];
+        assert!(check(&script(code)).is_ok());
+    }
+
+    #[test]
+    fn rejects_taking_more_than_the_stack_holds() {
+        assert!(check(&script(vec![Op::Nil, Op::List(5), Op::Return])).is_err());
+        assert!(check(&script(vec![Op::Nil, Op::Map(1), Op::Return])).is_err());
+        assert!(check(&script(vec![Op::Return])).is_err());
+    }
+
+    #[test]
+    fn rejects_paths_that_disagree_on_the_depth() {
+        let code = vec![Op::True, Op::JumpIfFalse(3), Op::Nil, Op::Nil, Op::Return];
+        assert!(check(&script(code)).is_err());
+    }
+
+    #[test]
+    fn rejects_next_without_an_iterator() {
+        let (iterate, next) = (Op::Iterate { span: 0 }, |exit| Op::Next { exit, span: 0 });
+        let looped = || vec![Op::Nil, iterate, next(5), Op::Pop, Op::Jump(2), Op::EndLoop, Op::Return];
+        let mut proto = script(looped());
+        proto.spans.push(Span::default());
+        assert!(check(&proto).is_ok());
+        proto.code = vec![Op::Nil, Op::Nil, next(4), Op::Pop, Op::Return];
+        assert!(check(&proto).is_err());
+        // The body must leave the iterator where `Next` finds it.
+        proto.code = looped();
+        proto.code[3] = Op::Nil;
+        assert!(check(&proto).is_err());
+        proto.code = vec![Op::Nil, iterate, Op::Pop, Op::Nil, next(6), Op::Pop, Op::Return];
+        assert!(check(&proto).is_err());
+    }
+
+    #[test]
+    fn rejects_running_off_the_end() {
+        assert!(check(&script(vec![Op::Nil])).is_err());
+        assert!(check(&script(vec![Op::Nil, Op::Jump(3), Op::Return, Op::Nil])).is_err());
+    }
+
+    #[test]
+    fn keeps_the_warnings_of_a_script() {
+        let source = "fn f() {\n    x = 1 @\n    return 2\n}\nprint(f())\n";
+        let mut lexer = Lexer::new(source, "main.alin");
+        let tokens = lexer.tokenize().unwrap();
+        let mut warnings = lexer.warnings().to_vec();
+        let mut ast = Parser::new(tokens).parse().unwrap();
+        let mut evaluator = Evaluator::new();
+        warnings.extend(evaluator.resolve(&mut ast).unwrap());
+        let codes: Vec<&str> = warnings.iter().map(|warning| warning.code).collect();
+        assert_eq!(codes, ["W0002", "W0301"]);
+
+        let script = evaluator.compile(&ast);
+        let bytes = evaluator.encode(&script, &warnings, "main.alin", source_hash(source));
+        let module = Evaluator::new().decode(&bytes).unwrap();
+        let mut emitter = Emitter::new(ErrorFormat::Human, Color::Never);
+        emitter.add_source("main.alin", source);
+        let render = |warnings: &[Diagnostic]| warnings.iter().map(|w| emitter.render(w)).collect::<Vec<_>>();
+        assert_eq!(render(&module.warnings), render(&warnings));
+    }
+
+    #[test]
+    fn caches_away_from_the_script() {
+        let path = "scripts/hello.alin";
+        assert_ne!(cache_path(path), compiled_path(path));
+        assert_eq!(cache_path(path).parent().and_then(Path::file_name), Some("alin".as_ref()));
+        assert_eq!(cache_path(path), cache_path(path));
+        assert_ne!(cache_path(path), cache_path("scripts/other.alin"));
+    }
+}
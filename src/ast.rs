@@ -1,15 +1,50 @@
-use crate::token::Token;
+use crate::token::{Span, Token};
 
+/// Sebuah node AST beserta lokasinya di source code
 #[derive(Debug, Clone)]
-pub enum Expr {
+pub struct Expr {
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+impl Expr {
+    pub fn new(kind: ExprKind, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+/// Slot lokal sebuah nama di dalam fungsinya, diisi oleh resolver; `None`
+/// untuk nama global, dan sebelum di-resolve
+pub type Slot = Option<u32>;
+
+#[derive(Debug, Clone)]
+pub enum ExprKind {
     /// Angka literal, seperti `42`, `3.14`
     Number(f64),
 
     /// String literal, seperti `"hello"` atau `'world'`
     StringLiteral(String),
 
+    /// Literal `true` atau `false`
+    Bool(bool),
+
+    /// Literal `nil`
+    Nil,
+
+    /// List literal, seperti `[1, 2, 3]`
+    List(Vec<Expr>),
+
+    /// Tuple literal, seperti `(a, b)` atau `a, b` pada `return a, b`
+    Tuple(Vec<Expr>),
+
+    /// Map literal, seperti `{name: "Alin", age: 3}` atau `{name, age}`
+    Map(Vec<(String, Expr)>),
+
+    /// Spread di dalam list literal: `[1, ...xs]`
+    Spread(Box<Expr>),
+
     /// Variabel seperti `x`, `nama_user`
-    Variable(String),
+    Variable(String, Slot),
 
     /// Operasi biner seperti `1 + 2`, `x * 3`
     Binary {
@@ -18,9 +53,22 @@ pub enum Expr {
         right: Box<Expr>,
     },
 
+    /// Akses indeks seperti `xs[0]`
+    Index {
+        object: Box<Expr>,
+        index: Box<Expr>,
+    },
+
     /// Assignment seperti `x = 10`
     Assignment {
         name: String,
+        slot: Slot,
+        expr: Box<Expr>,
+    },
+
+    /// Destructuring assignment seperti `x, y = y, x` atau `[first, ...rest] = xs`
+    Destructure {
+        pattern: Pattern,
         expr: Box<Expr>,
     },
 
@@ -40,9 +88,18 @@ pub enum Expr {
         body: Box<Expr>,                     // biasanya Block
     },
 
+    /// `for` loop: `for x in xs { ... }`
+    For {
+        var: String,
+        slot: Slot,
+        iterable: Box<Expr>,
+        body: Box<Expr>,                     // biasanya Block
+    },
+
     /// Definisi fungsi: `fn name(params) { body }`
     FunctionDef {
         name: String,
+        slot: Slot,
         params: Vec<String>,
         body: Box<Expr>,
     },
@@ -50,13 +107,45 @@ pub enum Expr {
     /// Pemanggilan fungsi: `print(x)`
     FunctionCall {
         name: String,
+        slot: Slot,
         args: Vec<Expr>,
     },
 
     /// `return` statement
     Return(Box<Expr>),
 
+    /// `yield` statement, menjadikan fungsi sebuah generator
+    Yield(Box<Expr>),
+
     Break, // untuk keluar dari loop
 
     Continue, // untuk melanjutkan ke iterasi berikutnya dari loop
+
+    /// Statement yang gagal di-parse; sudah dilaporkan sebagai syntax error
+    Error,
+}
+
+/// Target di sebelah kiri `=`
+#[derive(Debug, Clone)]
+pub enum Pattern {
+    /// Variabel biasa: `x`
+    Variable(String, Slot),
+
+    /// Elemen list atau map: `xs[0]`, `person["name"]`
+    Index {
+        object: Box<Expr>,
+        index: Box<Expr>,
+    },
+
+    /// `x, y` atau `(x, y)`
+    Tuple(Vec<Pattern>),
+
+    /// `[first, second, ...rest]`
+    List {
+        items: Vec<Pattern>,
+        rest: Option<(String, Slot)>,
+    },
+
+    /// `{name, age}` atau `{name: n}`
+    Map(Vec<(String, Pattern)>),
 }
@@ -0,0 +1,135 @@
+use std::rc::Rc;
+
+use crate::eval::BinOp;
+use crate::stdlib::BuiltinFn;
+use crate::token::Span;
+
+/// One instruction of the stack machine in `vm`. Every instruction leaves
+/// the stack as described; `span` operands index `Proto::spans` and point
+/// at the code blamed if the instruction fails.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Op {
+    /// Pushes `constants[i]`.
+    Const(u32),
+    Nil,
+    True,
+    False,
+    Pop,
+    /// Pops this many values.
+    PopN(u32),
+    Dup,
+
+    /// Pushes a name that may be local: the slot if it has been assigned,
+    /// otherwise the global, otherwise a builtin module.
+    LoadName { slot: u32, global: u32, span: u32 },
+    /// Pushes a global, or a builtin module of that name.
+    LoadGlobal { global: u32, span: u32 },
+    /// Assigns the top of the stack, keeping it there: to the slot if it is
+    /// already set or no such global exists, otherwise to the global.
+    StoreName { slot: u32, global: u32 },
+    StoreGlobal(u32),
+
+    /// Pops this many values into a list.
+    List(u32),
+    /// Pops a value and adds it to the list below it.
+    Append,
+    /// Pops an iterable and adds all its items to the list below it.
+    Extend { span: u32 },
+    Tuple(u32),
+    /// Pops this many pairs of a string constant key and a value.
+    Map(u32),
+
+    /// Pops an index and an object and pushes the item. `spans` starts
+    /// three spans: the expression, the object and the index.
+    Index { spans: u32 },
+    /// Pops two operands and pushes the result, with spans like `Index`.
+    Binary { op: BinOp, spans: u32 },
+
+    /// Pushes a new function made from the `Proto` in `constants[i]`.
+    MakeFunction(u32),
+    /// Pushes what `calls[call]` names: a script function, or nil standing
+    /// in for the builtin. Its arguments are pushed after it.
+    Callee { call: u32 },
+    /// Pops the arguments and the callee and pushes the result.
+    Call { argc: u32, call: u32 },
+    /// Like `Call` followed by `Return`, but replaces the current frame
+    /// instead of growing the call stack.
+    TailCall { argc: u32, call: u32 },
+    /// Pops the result and leaves the current function.
+    Return,
+
+    Jump(u32),
+    /// Pops a condition and jumps unless it is true.
+    JumpIfFalse(u32),
+
+    /// Pops a value and pushes a loop over it: the number of errors
+    /// reported so far, then the iterator.
+    Iterate { span: u32 },
+    /// Pushes the loop's next item, or jumps to `exit` when it is done.
+    Next { exit: u32, span: u32 },
+    /// Pops the loop and pushes nil, failing if producing an item did.
+    EndLoop,
+    /// Pops a value and suspends the generator, handing the value to
+    /// whoever resumed it.
+    Yield,
+
+    /// Pops a list or tuple of exactly `count` items and pushes them, the
+    /// first on top.
+    Unpack { count: u32, span: u32 },
+    /// Like `Unpack`, but with `rest` the extra items are pushed first as a
+    /// list.
+    UnpackList { count: u32, rest: bool, span: u32 },
+    /// Checks that the top of the stack is a map.
+    UnpackMap { span: u32 },
+    /// Replaces the map on top with its value at the string constant `key`,
+    /// or nil.
+    Field { key: u32 },
+    /// Pops an index, an object and a value, and stores the value there.
+    StoreIndex { span: u32 },
+
+    /// Ends a top-level statement, stopping if anything reported an error.
+    EndStatement,
+    /// Reports the error for `...` outside a list.
+    SpreadError { span: u32 },
+    /// Reports the error for `yield` outside a generator.
+    YieldError { span: u32 },
+    /// Reports the error for `break` or `continue` at the top level.
+    LoopControlError { span: u32 },
+}
+
+/// A constant pool entry.
+#[derive(Debug)]
+pub enum Constant {
+    Number(f64),
+    String(String),
+    Function(Rc<Proto>),
+}
+
+/// A function call as written in the source, resolved as far as possible
+/// when compiling.
+#[derive(Debug)]
+pub struct CallInfo {
+    pub name: String,
+    /// The local slot holding the name, if the caller assigns it.
+    pub slot: Option<u32>,
+    pub global: u32,
+    /// The builtin to fall back on when the name isn't a script function.
+    pub builtin: Option<BuiltinFn>,
+    pub span: u32,
+}
+
+/// The compiled code of a function, or of a script's top level.
+#[derive(Debug, Default)]
+pub struct Proto {
+    pub name: String,
+    pub params: Vec<String>,
+    pub is_generator: bool,
+    /// The definition, as for `Function::span`.
+    pub span: Span,
+    /// Names of the local variables, parameters first.
+    pub slots: Vec<String>,
+    pub code: Vec<Op>,
+    pub constants: Vec<Constant>,
+    pub spans: Vec<Span>,
+    pub calls: Vec<CallInfo>,
+}
@@ -0,0 +1,499 @@
+use std::collections::HashMap;
+use std::mem;
+use std::rc::Rc;
+
+use crate::ast::{Expr, ExprKind, Pattern};
+use crate::bytecode::{CallInfo, Constant, Op, Proto};
+use crate::eval::{BinOp, Globals};
+use crate::iter::contains_yield;
+use crate::resolve;
+use crate::stdlib::Builtins;
+use crate::token::Span;
+
+/// Translates syntax trees into bytecode for the stack machine in `vm`.
+///
+/// Every expression compiles to code that pushes exactly one value. Names a
+/// function assigns get numbered local slots; all other names are globals,
+/// numbered in the evaluator's table so compiled code never hashes them.
+pub struct Compiler<'a> {
+    globals: &'a mut Globals,
+    builtins: &'a Builtins,
+    proto: Proto,
+    /// Local slots by name, or `None` at the top level.
+    slots: Option<HashMap<String, u32>>,
+    loops: Vec<Loop>,
+    /// How many values the code compiled so far leaves on the stack, so
+    /// jumps out of the middle of an expression can drop the extras.
+    depth: u32,
+    /// The top-level statement being compiled, if any.
+    statement: Option<Statement>,
+}
+
+struct Loop {
+    /// Where `continue` jumps to.
+    start: usize,
+    depth: u32,
+    /// `break` jumps, patched to the end of the loop.
+    breaks: Vec<usize>,
+}
+
+struct Statement {
+    /// Blamed for a `break` that reaches it.
+    span: Span,
+    /// `return` jumps, patched to the end of the statement.
+    exits: Vec<usize>,
+}
+
+impl<'a> Compiler<'a> {
+    pub fn new(globals: &'a mut Globals, builtins: &'a Builtins) -> Self {
+        Self {
+            globals,
+            builtins,
+            proto: Proto { name: "<script>".to_string(), ..Proto::default() },
+            slots: None,
+            loops: Vec::new(),
+            depth: 0,
+            statement: None,
+        }
+    }
+
+    /// Compiles top-level statements. Each ends by checking for errors, so
+    /// running stops after the first statement that fails, as with the
+    /// tree-walker.
+    pub fn script(mut self, exprs: &[Expr]) -> Rc<Proto> {
+        for expr in exprs {
+            self.statement = Some(Statement { span: expr.span.clone(), exits: Vec::new() });
+            self.compile(expr, false);
+            self.emit(Op::Pop);
+            let end = self.emit(Op::EndStatement);
+            for exit in self.statement.take().map(|statement| statement.exits).unwrap_or_default() {
+                self.patch(exit, end);
+            }
+        }
+        self.emit(Op::Nil);
+        self.emit(Op::Return);
+        Rc::new(self.proto)
+    }
+
+    fn function(&mut self, name: &str, params: &[String], body: &Expr, span: &Span) -> Rc<Proto> {
+        let slots = resolve::slots(params, body);
+        // A repeated parameter reads as the last argument given for it.
+        let index = slots.iter().enumerate().map(|(i, name)| (name.clone(), i as u32)).collect();
+        let proto = Proto {
+            name: name.to_string(),
+            params: params.to_vec(),
+            is_generator: contains_yield(body),
+            span: span.clone(),
+            slots,
+            ..Proto::default()
+        };
+
+        let outer_proto = mem::replace(&mut self.proto, proto);
+        let outer_slots = self.slots.replace(index);
+        let outer_loops = mem::take(&mut self.loops);
+        let outer_depth = mem::replace(&mut self.depth, 0);
+        let outer_statement = self.statement.take();
+
+        self.compile(body, true);
+        self.emit(Op::Pop);
+        self.emit(Op::Nil);
+        self.emit(Op::Return);
+
+        let proto = mem::replace(&mut self.proto, outer_proto);
+        self.slots = outer_slots;
+        self.loops = outer_loops;
+        self.depth = outer_depth;
+        self.statement = outer_statement;
+        Rc::new(proto)
+    }
+
+    /// Compiles `expr` to push its value. `statement` is whether a
+    /// generator's `yield` may appear here: directly in its body, a block,
+    /// a branch or a loop, the places the tree-walker can suspend.
+    fn compile(&mut self, expr: &Expr, statement: bool) {
+        match &expr.kind {
+            ExprKind::Number(n) => {
+                let index = self.constant(Constant::Number(*n));
+                self.emit(Op::Const(index));
+            }
+            ExprKind::StringLiteral(s) => {
+                let index = self.constant(Constant::String(s.clone()));
+                self.emit(Op::Const(index));
+            }
+            ExprKind::Bool(true) => {
+                self.emit(Op::True);
+            }
+            ExprKind::Bool(false) => {
+                self.emit(Op::False);
+            }
+            ExprKind::Nil | ExprKind::Error => {
+                self.emit(Op::Nil);
+            }
+            ExprKind::List(items) => {
+                if !items.iter().any(|item| matches!(item.kind, ExprKind::Spread(_))) {
+                    for item in items {
+                        self.compile(item, false);
+                    }
+                    self.emit(Op::List(items.len() as u32));
+                    return;
+                }
+                self.emit(Op::List(0));
+                for item in items {
+                    if let ExprKind::Spread(inner) = &item.kind {
+                        self.compile(inner, false);
+                        let span = self.span(&inner.span);
+                        self.emit(Op::Extend { span });
+                    } else {
+                        self.compile(item, false);
+                        self.emit(Op::Append);
+                    }
+                }
+            }
+            ExprKind::Tuple(items) => {
+                for item in items {
+                    self.compile(item, false);
+                }
+                self.emit(Op::Tuple(items.len() as u32));
+            }
+            ExprKind::Map(entries) => {
+                for (key, value) in entries {
+                    let key = self.constant(Constant::String(key.clone()));
+                    self.emit(Op::Const(key));
+                    self.compile(value, false);
+                }
+                self.emit(Op::Map(entries.len() as u32));
+            }
+            ExprKind::Spread(_) => {
+                let span = self.span(&expr.span);
+                self.emit(Op::SpreadError { span });
+            }
+            ExprKind::Variable(name, _) => self.load(name, &expr.span),
+            ExprKind::Assignment { name, expr: value, .. } => {
+                self.compile(value, false);
+                self.store(name);
+            }
+            ExprKind::Index { object, index } => {
+                self.compile(object, false);
+                self.compile(index, false);
+                let spans = self.spans([&expr.span, &object.span, &index.span]);
+                self.emit(Op::Index { spans });
+            }
+            ExprKind::Destructure { pattern, expr: value } => {
+                self.compile(value, false);
+                self.emit(Op::Dup);
+                let span = self.span(&value.span);
+                self.pattern(pattern, span);
+            }
+            ExprKind::Binary { left, op, right } => {
+                let Some(op) = BinOp::from_token(op) else {
+                    self.emit(Op::Nil);
+                    return;
+                };
+                self.compile(left, false);
+                self.compile(right, false);
+                let spans = self.spans([&expr.span, &left.span, &right.span]);
+                self.emit(Op::Binary { op, spans });
+            }
+            ExprKind::FunctionCall { name, args, .. } => {
+                let call = self.call(name, &expr.span);
+                self.emit(Op::Callee { call });
+                for arg in args {
+                    self.compile(arg, false);
+                }
+                self.emit(Op::Call { argc: args.len() as u32, call });
+            }
+            ExprKind::FunctionDef { name, params, body, .. } => {
+                let proto = self.function(name, params, body, &expr.span);
+                let index = self.constant(Constant::Function(proto));
+                self.emit(Op::MakeFunction(index));
+                self.store(name);
+                self.emit(Op::Pop);
+                self.emit(Op::Nil);
+            }
+            ExprKind::Block(statements) => {
+                for stmt in statements {
+                    self.compile(stmt, statement);
+                    self.emit(Op::Pop);
+                }
+                self.emit(Op::Nil);
+            }
+            ExprKind::If { condition, then_branch, else_branch } => {
+                self.compile(condition, false);
+                let skip_then = self.emit(Op::JumpIfFalse(0));
+                let depth = self.depth;
+                self.compile(then_branch, statement);
+                let skip_else = self.emit(Op::Jump(0));
+                self.patch(skip_then, self.here());
+                self.depth = depth;
+                match else_branch {
+                    Some(else_branch) => self.compile(else_branch, statement),
+                    None => {
+                        self.emit(Op::Nil);
+                    }
+                }
+                self.patch(skip_else, self.here());
+            }
+            ExprKind::While { condition, body } => {
+                let start = self.here();
+                let depth = self.depth;
+                self.compile(condition, false);
+                let exit = self.emit(Op::JumpIfFalse(0));
+                self.loops.push(Loop { start, depth, breaks: Vec::new() });
+                self.compile(body, statement);
+                self.emit(Op::Pop);
+                self.emit(Op::Jump(start as u32));
+                self.end_loop(&[exit], depth);
+                self.emit(Op::Nil);
+            }
+            ExprKind::For { var, iterable, body, .. } => {
+                self.compile(iterable, false);
+                let span = self.span(&iterable.span);
+                self.emit(Op::Iterate { span });
+                let depth = self.depth;
+                let start = self.emit(Op::Next { exit: 0, span });
+                self.store(var);
+                self.emit(Op::Pop);
+                self.loops.push(Loop { start, depth, breaks: Vec::new() });
+                self.compile(body, statement);
+                self.emit(Op::Pop);
+                self.emit(Op::Jump(start as u32));
+                self.end_loop(&[start], depth);
+                self.emit(Op::EndLoop);
+            }
+            ExprKind::Yield(value) if statement && self.proto.is_generator => {
+                self.compile(value, false);
+                self.emit(Op::Yield);
+                self.emit(Op::Nil);
+            }
+            ExprKind::Yield(_) => {
+                let span = self.span(&expr.span);
+                self.emit(Op::YieldError { span });
+            }
+            ExprKind::Break | ExprKind::Continue => {
+                let depth = self.depth;
+                self.loop_control(matches!(expr.kind, ExprKind::Break));
+                self.depth = depth + 1;
+            }
+            ExprKind::Return(value) => {
+                let depth = self.depth;
+                self.ret(value);
+                self.depth = depth + 1;
+            }
+        }
+    }
+
+    /// `break` jumps past the innermost loop, `continue` back to its start.
+    /// In a function outside any loop, both return nil, as with the
+    /// tree-walker.
+    fn loop_control(&mut self, is_break: bool) {
+        let Some(inner) = self.loops.last() else {
+            if self.slots.is_some() {
+                self.emit(Op::Nil);
+                self.emit(Op::Return);
+            } else {
+                let span = self.statement.as_ref().map(|statement| statement.span.clone()).unwrap_or_default();
+                let span = self.span(&span);
+                self.emit(Op::LoopControlError { span });
+            }
+            return;
+        };
+        let (start, extra) = (inner.start, self.depth - inner.depth);
+        if extra > 0 {
+            self.emit(Op::PopN(extra));
+        }
+        if is_break {
+            let jump = self.emit(Op::Jump(0));
+            if let Some(inner) = self.loops.last_mut() {
+                inner.breaks.push(jump);
+            }
+        } else {
+            self.emit(Op::Jump(start as u32));
+        }
+    }
+
+    /// At the top level, `return` only abandons the rest of its statement.
+    /// In a function, `return f(...)` reuses the frame for the call, except
+    /// in generators, which can't be replaced by what they return.
+    fn ret(&mut self, value: &Expr) {
+        if self.slots.is_none() {
+            self.compile(value, false);
+            self.emit(Op::PopN(self.depth));
+            let jump = self.emit(Op::Jump(0));
+            if let Some(statement) = &mut self.statement {
+                statement.exits.push(jump);
+            }
+            return;
+        }
+        match &value.kind {
+            ExprKind::FunctionCall { name, args, .. } if !self.proto.is_generator => {
+                let call = self.call(name, &value.span);
+                self.emit(Op::Callee { call });
+                for arg in args {
+                    self.compile(arg, false);
+                }
+                self.emit(Op::TailCall { argc: args.len() as u32, call });
+            }
+            _ => {
+                self.compile(value, false);
+                self.emit(Op::Return);
+            }
+        }
+    }
+
+    /// Points the loop's exits and `break`s here.
+    fn end_loop(&mut self, exits: &[usize], depth: u32) {
+        let end = self.here();
+        let inner = self.loops.pop().map(|inner| inner.breaks).unwrap_or_default();
+        for &jump in exits.iter().chain(&inner) {
+            self.patch(jump, end);
+        }
+        self.depth = depth;
+    }
+
+    /// Consumes the value on top of the stack, binding it to `pattern`.
+    fn pattern(&mut self, pattern: &Pattern, span: u32) {
+        match pattern {
+            Pattern::Variable(name, _) => {
+                self.store(name);
+                self.emit(Op::Pop);
+            }
+            Pattern::Index { object, index } => {
+                self.compile(object, false);
+                self.compile(index, false);
+                self.emit(Op::StoreIndex { span });
+            }
+            Pattern::Tuple(targets) => {
+                self.emit(Op::Unpack { count: targets.len() as u32, span });
+                for target in targets {
+                    self.pattern(target, span);
+                }
+            }
+            Pattern::List { items, rest } => {
+                self.emit(Op::UnpackList { count: items.len() as u32, rest: rest.is_some(), span });
+                for target in items {
+                    self.pattern(target, span);
+                }
+                if let Some((name, _)) = rest {
+                    self.store(name);
+                    self.emit(Op::Pop);
+                }
+            }
+            Pattern::Map(entries) => {
+                self.emit(Op::UnpackMap { span });
+                for (key, target) in entries {
+                    let key = self.constant(Constant::String(key.clone()));
+                    self.emit(Op::Dup);
+                    self.emit(Op::Field { key });
+                    self.pattern(target, span);
+                }
+                self.emit(Op::Pop);
+            }
+        }
+    }
+
+    fn load(&mut self, name: &str, span: &Span) {
+        let global = self.globals.id(name);
+        let span = self.span(span);
+        match self.slot(name) {
+            Some(slot) => self.emit(Op::LoadName { slot, global, span }),
+            None => self.emit(Op::LoadGlobal { global, span }),
+        };
+    }
+
+    fn store(&mut self, name: &str) {
+        let global = self.globals.id(name);
+        match self.slot(name) {
+            Some(slot) => self.emit(Op::StoreName { slot, global }),
+            None => self.emit(Op::StoreGlobal(global)),
+        };
+    }
+
+    fn slot(&self, name: &str) -> Option<u32> {
+        self.slots.as_ref()?.get(name).copied()
+    }
+
+    fn call(&mut self, name: &str, span: &Span) -> u32 {
+        let info = CallInfo {
+            name: name.to_string(),
+            slot: self.slot(name),
+            global: self.globals.id(name),
+            builtin: self.builtins.get(name),
+            span: self.span(span),
+        };
+        self.proto.calls.push(info);
+        self.proto.calls.len() as u32 - 1
+    }
+
+    fn constant(&mut self, constant: Constant) -> u32 {
+        self.proto.constants.push(constant);
+        self.proto.constants.len() as u32 - 1
+    }
+
+    fn span(&mut self, span: &Span) -> u32 {
+        self.proto.spans.push(span.clone());
+        self.proto.spans.len() as u32 - 1
+    }
+
+    /// Stores consecutive spans, returning the first one's index.
+    fn spans(&mut self, spans: [&Span; 3]) -> u32 {
+        let first = self.proto.spans.len() as u32;
+        self.proto.spans.extend(spans.into_iter().cloned());
+        first
+    }
+
+    fn here(&self) -> usize {
+        self.proto.code.len()
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        let (pops, pushes) = stack_effect(op);
+        self.depth = self.depth.saturating_sub(pops) + pushes;
+        self.proto.code.push(op);
+        self.proto.code.len() - 1
+    }
+
+    fn patch(&mut self, at: usize, target: usize) {
+        let target = target as u32;
+        match &mut self.proto.code[at] {
+            Op::Jump(to) | Op::JumpIfFalse(to) | Op::Next { exit: to, .. } => *to = target,
+            _ => {}
+        }
+    }
+}
+
+/// How many values `op` pops and then pushes when it falls through to the
+/// next instruction.
+pub(crate) fn stack_effect(op: Op) -> (u32, u32) {
+    match op {
+        Op::Const(_)
+        | Op::Nil
+        | Op::True
+        | Op::False
+        | Op::Dup
+        | Op::LoadName { .. }
+        | Op::LoadGlobal { .. }
+        | Op::MakeFunction(_)
+        | Op::Callee { .. }
+        | Op::Next { .. }
+        | Op::SpreadError { .. }
+        | Op::YieldError { .. }
+        | Op::LoopControlError { .. } => (0, 1),
+        Op::Pop | Op::Append | Op::Extend { .. } | Op::JumpIfFalse(_) | Op::Return | Op::Yield => (1, 0),
+        Op::PopN(n) => (n, 0),
+        Op::StoreName { .. }
+        | Op::StoreGlobal(_)
+        | Op::Jump(_)
+        | Op::UnpackMap { .. }
+        | Op::EndStatement => (0, 0),
+        Op::List(n) | Op::Tuple(n) => (n, 1),
+        Op::Map(n) => (2 * n, 1),
+        Op::Index { .. } | Op::Binary { .. } | Op::EndLoop => (2, 1),
+        Op::Call { argc, .. } => (argc + 1, 1),
+        Op::TailCall { argc, .. } => (argc + 1, 0),
+        Op::Iterate { .. } => (1, 2),
+        Op::Unpack { count, .. } => (1, count),
+        Op::UnpackList { count, rest, .. } => (1, count + rest as u32),
+        Op::Field { .. } => (1, 1),
+        Op::StoreIndex { .. } => (3, 0),
+    }
+}
@@ -0,0 +1,407 @@
+use std::cmp::Reverse;
+use std::fmt;
+use std::iter::Peekable;
+
+use crate::ast::{Expr, ExprKind};
+use crate::diagnostic::Diagnostic;
+use crate::error::AlinError;
+use crate::lexer::Lexer;
+use crate::lint::children;
+use crate::parser::Parser;
+use crate::token::{Comment, Span, SpannedToken, Token};
+
+/// A script exactly as written, for tools that rewrite source rather than
+/// run it. Every token is kept, invalid ones included, along with the
+/// whitespace, comments and ignored characters around it, grouped into a
+/// node for each expression the parser finds. Printing the tree gives back
+/// the source byte for byte, and lowering it gives the `ast::Expr`s that
+/// parsing the source does.
+pub struct SyntaxTree {
+    pub root: SyntaxNode,
+    /// What lexing found, warnings included; any errors among them leave
+    /// some tokens without a real value.
+    lex_diagnostics: Vec<Diagnostic>,
+}
+
+/// An expression, or the whole script at the root, with its tokens and the
+/// nodes of the expressions inside it in source order. Tokens the parser
+/// found no expression for, such as brackets, separators or a broken
+/// statement's leftovers, belong to the nearest node around them.
+pub struct SyntaxNode {
+    pub kind: NodeKind,
+    pub children: Vec<SyntaxElement>,
+}
+
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+/// A token with its source text and the trivia around it: `trailing` runs
+/// up to the end of the token's line, and `leading` is what comes before it
+/// from the line after the previous token.
+pub struct SyntaxToken {
+    pub token: Token,
+    pub text: String,
+    pub span: Span,
+    pub leading: Vec<Trivia>,
+    pub trailing: Vec<Trivia>,
+}
+
+/// Source text the parser never sees.
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TriviaKind {
+    Whitespace,
+    /// A `//` comment, up to the end of its line.
+    Comment,
+    /// A character the lexer ignores with a warning, such as a lone `!`.
+    Skipped,
+}
+
+/// What a node holds, named after the `ExprKind` it lowers to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NodeKind {
+    Root,
+    Number,
+    String,
+    Bool,
+    Nil,
+    List,
+    Tuple,
+    Map,
+    Spread,
+    Variable,
+    Binary,
+    Index,
+    Assignment,
+    Destructure,
+    Block,
+    If,
+    While,
+    For,
+    FunctionDef,
+    FunctionCall,
+    Return,
+    Yield,
+    Break,
+    Continue,
+    Error,
+}
+
+impl NodeKind {
+    fn of(kind: &ExprKind) -> Self {
+        match kind {
+            ExprKind::Number(_) => NodeKind::Number,
+            ExprKind::StringLiteral(_) => NodeKind::String,
+            ExprKind::Bool(_) => NodeKind::Bool,
+            ExprKind::Nil => NodeKind::Nil,
+            ExprKind::List(_) => NodeKind::List,
+            ExprKind::Tuple(_) => NodeKind::Tuple,
+            ExprKind::Map(_) => NodeKind::Map,
+            ExprKind::Spread(_) => NodeKind::Spread,
+            ExprKind::Variable(_, _) => NodeKind::Variable,
+            ExprKind::Binary { .. } => NodeKind::Binary,
+            ExprKind::Index { .. } => NodeKind::Index,
+            ExprKind::Assignment { .. } => NodeKind::Assignment,
+            ExprKind::Destructure { .. } => NodeKind::Destructure,
+            ExprKind::Block(_) => NodeKind::Block,
+            ExprKind::If { .. } => NodeKind::If,
+            ExprKind::While { .. } => NodeKind::While,
+            ExprKind::For { .. } => NodeKind::For,
+            ExprKind::FunctionDef { .. } => NodeKind::FunctionDef,
+            ExprKind::FunctionCall { .. } => NodeKind::FunctionCall,
+            ExprKind::Return(_) => NodeKind::Return,
+            ExprKind::Yield(_) => NodeKind::Yield,
+            ExprKind::Break => NodeKind::Break,
+            ExprKind::Continue => NodeKind::Continue,
+            ExprKind::Error => NodeKind::Error,
+        }
+    }
+}
+
+impl SyntaxTree {
+    /// Builds the tree for `source`, however broken it is; `file` names it
+    /// in spans.
+    pub fn parse(source: &str, file: &str) -> Self {
+        let (tokens, diagnostics) = Lexer::new(source, file).tokenize_partial();
+        let (exprs, _) = Parser::new(tokens.clone()).parse_partial();
+        let mut tokens = with_trivia(tokens, source).into_iter().peekable();
+        let root = build(NodeKind::Root, exprs.iter().collect(), usize::MAX, &mut tokens);
+        SyntaxTree { root, lex_diagnostics: diagnostics }
+    }
+
+    /// The AST of the tree's tokens, or the errors that stop it parsing.
+    pub fn lower(&self) -> Result<Vec<Expr>, AlinError> {
+        if self.lex_diagnostics.iter().any(Diagnostic::is_error) {
+            return Err(AlinError::Lex(self.lex_diagnostics.clone()));
+        }
+        let tokens = self
+            .root
+            .tokens()
+            .into_iter()
+            .map(|token| SpannedToken { token: token.token.clone(), span: token.span.clone() })
+            .collect();
+        Parser::new(tokens).parse()
+    }
+
+    /// Every comment in the tree, in order, as the lexer reports them.
+    pub fn comments(&self) -> Vec<Comment> {
+        let tokens = self.root.tokens();
+        let trivia = tokens.iter().flat_map(|token| token.leading.iter().chain(&token.trailing));
+        trivia
+            .filter(|trivia| trivia.kind == TriviaKind::Comment)
+            .map(|comment| {
+                let text = comment.text.trim_end().to_string();
+                let span = Span { len: text.len(), ..comment.span.clone() };
+                Comment { text, span }
+            })
+            .collect()
+    }
+}
+
+impl SyntaxNode {
+    /// The tokens under this node, in order.
+    pub fn tokens(&self) -> Vec<&SyntaxToken> {
+        let mut tokens = Vec::new();
+        self.collect_tokens(&mut tokens);
+        tokens
+    }
+
+    fn collect_tokens<'a>(&'a self, tokens: &mut Vec<&'a SyntaxToken>) {
+        for child in &self.children {
+            match child {
+                SyntaxElement::Node(node) => node.collect_tokens(tokens),
+                SyntaxElement::Token(token) => tokens.push(token),
+            }
+        }
+    }
+
+    fn dump(&self, f: &mut fmt::Formatter<'_>, depth: usize) -> fmt::Result {
+        writeln!(f, "{:indent$}{:?}", "", self.kind, indent = depth * 2)?;
+        let indent = (depth + 1) * 2;
+        for child in &self.children {
+            let token = match child {
+                SyntaxElement::Node(node) => {
+                    node.dump(f, depth + 1)?;
+                    continue;
+                }
+                SyntaxElement::Token(token) => token,
+            };
+            for trivia in &token.leading {
+                writeln!(f, "{:indent$}{:?} {:?}", "", trivia.kind, trivia.text)?;
+            }
+            writeln!(f, "{:indent$}{} {:?}", "", token.token, token.text)?;
+            for trivia in &token.trailing {
+                writeln!(f, "{:indent$}{:?} {:?}", "", trivia.kind, trivia.text)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The source text, exactly as parsed.
+impl fmt::Display for SyntaxTree {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.root)
+    }
+}
+
+impl fmt::Display for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.tokens().into_iter().try_for_each(|token| write!(f, "{}", token))
+    }
+}
+
+impl fmt::Display for SyntaxToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.leading.iter().try_for_each(|trivia| f.write_str(&trivia.text))?;
+        f.write_str(&self.text)?;
+        self.trailing.iter().try_for_each(|trivia| f.write_str(&trivia.text))
+    }
+}
+
+/// The tree as an indented outline of nodes, tokens and trivia.
+impl fmt::Debug for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.dump(f, 0)
+    }
+}
+
+/// Gathers tokens into a node of `kind` ending before offset `end`, with a
+/// child node for each of `exprs` that lines up with the tokens.
+fn build<I>(kind: NodeKind, mut exprs: Vec<&Expr>, end: usize, tokens: &mut Peekable<I>) -> SyntaxNode
+where
+    I: Iterator<Item = SyntaxToken>,
+{
+    // Kept last to first, so the next one is at the end.
+    exprs.sort_by_key(|expr| Reverse(expr.span.offset));
+    let mut elements = Vec::new();
+    while let Some(offset) = tokens.peek().map(|token| token.span.offset).filter(|&offset| offset < end) {
+        match exprs.last() {
+            Some(expr) if expr.span.offset <= offset => {
+                let expr = exprs.pop().unwrap();
+                let expr_end = expr.span.offset + expr.span.len;
+                // A node that starts inside a token already taken, or runs
+                // past this one, can't be kept; what is inside it can.
+                if expr.span.offset < offset || expr_end > end || expr.span.len == 0 {
+                    exprs.extend(children(expr));
+                    exprs.sort_by_key(|expr| Reverse(expr.span.offset));
+                    continue;
+                }
+                let node = build(NodeKind::of(&expr.kind), children(expr), expr_end, tokens);
+                elements.push(SyntaxElement::Node(node));
+            }
+            _ => elements.push(SyntaxElement::Token(tokens.next().unwrap())),
+        }
+    }
+    SyntaxNode { kind, children: elements }
+}
+
+/// Gives each token its text and the trivia between it and its neighbours.
+fn with_trivia(tokens: Vec<SpannedToken>, source: &str) -> Vec<SyntaxToken> {
+    let mut result: Vec<SyntaxToken> = Vec::new();
+    let mut position = tokens.first().map_or_else(Span::default, |first| Span {
+        offset: 0,
+        len: 0,
+        line: 1,
+        column: 1,
+        ..first.span.clone()
+    });
+    for SpannedToken { token, span } in tokens {
+        let mut trivia = trivia(&source[position.offset..span.offset], &position);
+        // What is left of the previous token's line belongs to it.
+        if let Some(previous) = result.last_mut() {
+            let line_end = trivia.iter().position(|trivia| trivia.text.contains('\n')).unwrap_or(trivia.len());
+            previous.trailing = trivia.drain(..line_end).collect();
+        }
+        let text = source[span.offset..span.offset + span.len].to_string();
+        position = after(&span, &text);
+        result.push(SyntaxToken { token, text, span, leading: trivia, trailing: Vec::new() });
+    }
+    result
+}
+
+/// Splits the text between two tokens, which starts at `start`, into
+/// trivia.
+fn trivia(mut text: &str, start: &Span) -> Vec<Trivia> {
+    let mut result = Vec::new();
+    let mut position = start.clone();
+    while let Some(c) = text.chars().next() {
+        let (kind, len) = if c.is_whitespace() {
+            (TriviaKind::Whitespace, text.find(|c: char| !c.is_whitespace()).unwrap_or(text.len()))
+        } else if text.starts_with("//") {
+            (TriviaKind::Comment, text.find('\n').unwrap_or(text.len()))
+        } else {
+            (TriviaKind::Skipped, c.len_utf8())
+        };
+        let (piece, rest) = text.split_at(len);
+        result.push(Trivia { kind, text: piece.to_string(), span: Span { len, ..position.clone() } });
+        position = after(&position, piece);
+        text = rest;
+    }
+    result
+}
+
+/// The empty span just after `text`, which starts where `start` does.
+fn after(start: &Span, text: &str) -> Span {
+    let mut end = Span { offset: start.offset + text.len(), len: 0, ..start.clone() };
+    for c in text.chars() {
+        if c == '\n' {
+            end.line += 1;
+            end.column = 1;
+        } else {
+            end.column += 1;
+        }
+    }
+    end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Scripts covering what the tree must keep: comments, blank lines,
+    /// CRLF line endings, odd spacing, skipped characters, and code that
+    /// does not lex or parse.
+    const SOURCES: &[&str] = &[
+        "",
+        "   \n\n",
+        "// only a comment",
+        "x = 1 // one\n\n\n// two\ny = [1,\n  2, // inside\n]\n",
+        "fn add(a, b) {\r\n    return a + b\r\n}\r\nprint(add(1, 2))\r\n",
+        "  x   =1+  2*(3 -x)  ;;print( x ,'s' )\t\n{a:1,b}  =  {a: x, b: 2}",
+        "if x { y } // after if\nelse { z }\nfor i in [1, ...xs] { continue }",
+        "x = 1 ! 2\ny = #",
+        "x = [1, 2",
+        "fn f(a, {",
+        "s = \"never closed\ny = 2\n",
+        "x = 1..2\nprint(1.2.3)",
+        "print(1 +)\nx = )\ny = 2\n",
+    ];
+
+    fn parse_directly(source: &str) -> Result<Vec<Expr>, AlinError> {
+        Parser::new(Lexer::new(source, "test.alin").tokenize()?).parse()
+    }
+
+    fn contains(node: &SyntaxNode, kind: NodeKind) -> bool {
+        node.kind == kind
+            || node.children.iter().any(|child| matches!(child, SyntaxElement::Node(node) if contains(node, kind)))
+    }
+
+    #[test]
+    fn prints_back_the_source() {
+        for source in SOURCES {
+            assert_eq!(SyntaxTree::parse(source, "test.alin").to_string(), *source);
+        }
+    }
+
+    #[test]
+    fn lowers_to_what_the_parser_gives() {
+        for source in SOURCES {
+            let lowered = SyntaxTree::parse(source, "test.alin").lower();
+            assert_eq!(format!("{:?}", lowered), format!("{:?}", parse_directly(source)), "for {:?}", source);
+        }
+    }
+
+    #[test]
+    fn keeps_trivia_beside_its_tokens() {
+        let tree = SyntaxTree::parse("x = 1 // one\r\n\r\n// two\ny ! = 2", "test.alin");
+        let tokens = tree.root.tokens();
+        let one = &tokens[2];
+        assert_eq!(one.text, "1");
+        let trailing: Vec<_> = one.trailing.iter().map(|trivia| (trivia.kind, trivia.text.as_str())).collect();
+        assert_eq!(trailing, [(TriviaKind::Whitespace, " "), (TriviaKind::Comment, "// one\r")]);
+        let y = &tokens[3];
+        let leading: Vec<_> = y.leading.iter().map(|trivia| (trivia.kind, trivia.text.as_str())).collect();
+        let expected =
+            [(TriviaKind::Whitespace, "\n\r\n"), (TriviaKind::Comment, "// two"), (TriviaKind::Whitespace, "\n")];
+        assert_eq!(leading, expected);
+        assert_eq!(y.trailing[1].kind, TriviaKind::Skipped);
+        let comments: Vec<_> = tree.comments().into_iter().map(|comment| (comment.text, comment.span.line)).collect();
+        assert_eq!(comments, [("// one".to_string(), 1), ("// two".to_string(), 3)]);
+    }
+
+    #[test]
+    fn broken_code_gets_error_nodes() {
+        let tree = SyntaxTree::parse("print(1 +)\nx = 2\n", "test.alin");
+        assert!(contains(&tree.root, NodeKind::Error));
+        assert!(contains(&tree.root, NodeKind::Assignment));
+        assert!(tree.lower().is_err());
+    }
+
+    #[test]
+    fn unterminated_string_fails_lowering() {
+        let tree = SyntaxTree::parse("s = 'abc\n", "test.alin");
+        assert_eq!(tree.to_string(), "s = 'abc\n");
+        let Err(AlinError::Lex(diagnostics)) = tree.lower() else {
+            panic!("lowering should fail on the lexer error");
+        };
+        assert_eq!(diagnostics[0].code, "E0002");
+    }
+}
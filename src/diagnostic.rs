@@ -0,0 +1,461 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::IsTerminal;
+use std::rc::Rc;
+
+use crate::stdlib::json::write_string;
+use crate::token::Span;
+
+// Codes are grouped by the stage that reports them:
+//
+//   E00xx / W00xx  lexer      E0001 invalid number, E0002 unterminated
+//                             string, W0001 lone `!`, W0002 unknown
+//                             character
+//   E01xx          parser     E0101 unexpected token,
+//                             E0102 invalid assignment target,
+//                             E0103 unclosed delimiter
+//   E02xx          evaluator  E0201 undefined variable, E0202 unknown function,
+//                             E0203 unsupported operands, E0204 cannot index,
+//                             E0205 index out of range, E0206 wrong argument
+//                             count, E0207 not callable, E0208 cannot unpack,
+//                             E0209 cannot assign to index, E0210 `break`
+//                             outside a loop, E0211 misplaced `...`,
+//                             E0212 `yield` outside a generator,
+//                             E0213 not iterable, E0214 iterator already
+//                             running, E0215 invalid builtin arguments,
+//                             E0216 maximum call depth exceeded,
+//                             E0217 collection stored inside itself
+//   E03xx / W03xx  resolver   E0301 undefined variable, E0302 unknown
+//                             function, E0303 used before its definition,
+//                             E0304 `break` or `continue` outside a loop,
+//                             W0301 unused variable
+//   E04xx          types      E0401 mismatched types, E0402 cannot index,
+//                             E0403 not iterable, E0404 wrong argument
+//                             count, E0405 cannot unpack, E0406 operands
+//                             not numbers or strings
+//   L00xx          linter     L0001 unused variable, L0002 unreachable
+//                             code, L0003 shadowing, L0004 assignment in
+//                             a condition, L0005 comparison with `nil`,
+//                             L0006 empty block
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// A message attached to a stretch of source. The primary label marks the
+/// problem itself; secondary labels point at code that explains it.
+#[derive(Debug, Clone)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    pub primary: bool,
+}
+
+/// One active function call when a runtime error happened, and how far it
+/// had got: the call it was making, or the error itself for the innermost.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEntry {
+    pub function: String,
+    pub span: Span,
+}
+
+/// An error or warning about a program, built up with the `with_*` methods.
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// A stable identifier such as `E0201`.
+    pub code: &'static str,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    /// The calls leading to a runtime error, innermost last.
+    pub trace: Vec<TraceEntry>,
+}
+
+impl Diagnostic {
+    pub fn error(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, code, message.into())
+    }
+
+    pub fn warning(code: &'static str, message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, code, message.into())
+    }
+
+    fn new(severity: Severity, code: &'static str, message: String) -> Self {
+        Self { severity, code, message, labels: Vec::new(), notes: Vec::new(), trace: Vec::new() }
+    }
+
+    pub fn with_label(self, span: Span, message: impl Into<String>) -> Self {
+        self.label(span, message.into(), true)
+    }
+
+    pub fn with_secondary(self, span: Span, message: impl Into<String>) -> Self {
+        self.label(span, message.into(), false)
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    fn label(mut self, span: Span, message: String, primary: bool) -> Self {
+        self.labels.push(Label { span, message, primary });
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+}
+
+/// How diagnostics are written to stderr.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum ErrorFormat {
+    /// rustc-style text with source snippets.
+    #[default]
+    Human,
+    /// One JSON object per line, for editors and other tools.
+    Json,
+}
+
+/// Whether human-readable diagnostics use ANSI colors.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Color {
+    /// Only when stderr is a terminal and `NO_COLOR` is unset.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// Renders diagnostics, quoting the source files they point into.
+#[derive(Debug, Default)]
+pub struct Emitter {
+    format: ErrorFormat,
+    color: bool,
+    sources: HashMap<Rc<str>, Rc<str>>,
+}
+
+impl Emitter {
+    pub fn new(format: ErrorFormat, color: Color) -> Self {
+        let color = match color {
+            Color::Always => true,
+            Color::Never => false,
+            Color::Auto => std::io::stderr().is_terminal() && std::env::var_os("NO_COLOR").is_none(),
+        };
+        Self { format, color, sources: HashMap::new() }
+    }
+
+    /// Makes the text of `file` available for snippets.
+    pub fn add_source(&mut self, file: &str, text: &str) {
+        self.sources.insert(file.into(), text.into());
+    }
+
+    pub fn emit(&self, diagnostic: &Diagnostic) {
+        eprint!("{}", self.render(diagnostic));
+    }
+
+    pub fn emit_all(&self, diagnostics: &[Diagnostic]) {
+        for diagnostic in diagnostics {
+            self.emit(diagnostic);
+        }
+    }
+
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        match self.format {
+            ErrorFormat::Human => self.render_human(diagnostic),
+            ErrorFormat::Json => render_json(diagnostic),
+        }
+    }
+
+    fn render_human(&self, diagnostic: &Diagnostic) -> String {
+        let severity = match diagnostic.severity {
+            Severity::Error => "1;31",
+            Severity::Warning => "1;33",
+        };
+        let mut out = format!(
+            "{}{}\n",
+            self.paint(severity, &format!("{}[{}]", diagnostic.severity, diagnostic.code)),
+            self.paint("1", &format!(": {}", diagnostic.message)),
+        );
+
+        let width = diagnostic.labels.iter().map(|label| label.span.line).max().unwrap_or(0).to_string().len();
+        let pad = " ".repeat(width);
+        let gutter = self.paint("1;34", &format!("{} |", pad));
+
+        // The primary label's file comes first, then any others in order.
+        let mut labels: Vec<&Label> = diagnostic.labels.iter().collect();
+        labels.sort_by_key(|label| !label.primary);
+        let mut files: Vec<&Rc<str>> = Vec::new();
+        for label in &labels {
+            if !files.contains(&&label.span.file) {
+                files.push(&label.span.file);
+            }
+        }
+
+        for (i, file) in files.into_iter().enumerate() {
+            let group: Vec<&Label> = labels.iter().copied().filter(|label| &label.span.file == file).collect();
+            let arrow = if i == 0 { "-->" } else { ":::" };
+            out += &format!("{}{} {}\n", pad, self.paint("1;34", arrow), group[0].span);
+
+            let Some(source) = self.sources.get(file) else {
+                continue;
+            };
+            out += &format!("{}\n", gutter);
+
+            let mut lines: Vec<usize> = group.iter().map(|label| label.span.line).collect();
+            lines.sort_unstable();
+            lines.dedup();
+            for (j, &line) in lines.iter().enumerate() {
+                if j > 0 && line > lines[j - 1] + 1 {
+                    out += &format!("{}\n", self.paint("1;34", "..."));
+                }
+                let mut on_line: Vec<&Label> = group.iter().copied().filter(|label| label.span.line == line).collect();
+                on_line.sort_by_key(|label| (!label.primary, label.span.column));
+
+                let (start, end) = line_bounds(source, on_line[0].span.offset);
+                out += &format!(
+                    "{} {}\n",
+                    self.paint("1;34", &format!("{:>width$} |", line)),
+                    source[start..end].trim_end_matches('\r'),
+                );
+                for label in on_line {
+                    out += &format!("{} {}\n", gutter, self.underline(source, label, end));
+                }
+            }
+        }
+
+        for note in &diagnostic.notes {
+            out += &format!("{} {} {}\n", pad, self.paint("1;34", "="), self.paint("1", "note:") + " " + note);
+        }
+        if !diagnostic.trace.is_empty() {
+            out += &self.render_trace(&diagnostic.trace);
+        }
+        out.push('\n');
+        out
+    }
+
+    /// A Python-style traceback, quoting each line and folding runs of the
+    /// same entry from deep recursion.
+    fn render_trace(&self, trace: &[TraceEntry]) -> String {
+        let mut out = format!("\n{}\n", self.paint("1", "traceback (most recent call last):"));
+        let mut i = 0;
+        while i < trace.len() {
+            let entry = &trace[i];
+            out += &format!("  {}, in {}\n", entry.span, entry.function);
+            if let Some(source) = self.sources.get(&entry.span.file) {
+                let (start, end) = line_bounds(source, entry.span.offset);
+                out += &format!("    {}\n", source[start..end].trim());
+            }
+            let repeats = trace[i + 1..].iter().take_while(|next| *next == entry).count();
+            if repeats > 0 {
+                out += &format!("  [previous entry repeated {} more time(s)]\n", repeats);
+            }
+            i += repeats + 1;
+        }
+        out
+    }
+
+    /// `^^^` (or `---` for a secondary label) under a label's span, stopping
+    /// at the end of its first line.
+    fn underline(&self, source: &str, label: &Label, line_end: usize) -> String {
+        let offset = label.span.offset.min(line_end);
+        let (start, _) = line_bounds(source, offset);
+        // Copy tabs so the marks line up with the quoted line.
+        let indent: String = source[start..offset]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let end = (offset + label.span.len).min(line_end);
+        let count = source[offset..end].chars().count().max(1);
+        let (mark, style) = if label.primary { ('^', "1;31") } else { ('-', "1;34") };
+        let marks = mark.to_string().repeat(count);
+        let text = if label.message.is_empty() { marks } else { format!("{} {}", marks, label.message) };
+        format!("{}{}", indent, self.paint(style, &text))
+    }
+
+    fn paint(&self, style: &str, text: &str) -> String {
+        if self.color {
+            format!("\x1b[{}m{}\x1b[0m", style, text)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+/// The byte range of the line containing `offset`, without its newline.
+fn line_bounds(source: &str, offset: usize) -> (usize, usize) {
+    let offset = offset.min(source.len());
+    let start = source[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let end = source[offset..].find('\n').map_or(source.len(), |i| offset + i);
+    (start, end)
+}
+
+fn render_json(diagnostic: &Diagnostic) -> String {
+    let mut out = String::from("{\"severity\":");
+    write_string(&mut out, &diagnostic.severity.to_string());
+    out += ",\"code\":";
+    write_string(&mut out, diagnostic.code);
+    out += ",\"message\":";
+    write_string(&mut out, &diagnostic.message);
+    out += ",\"labels\":[";
+    for (i, label) in diagnostic.labels.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        let span = &label.span;
+        out += "{\"file\":";
+        write_string(&mut out, &span.file);
+        out += &format!(
+            ",\"line\":{},\"column\":{},\"offset\":{},\"length\":{},\"primary\":{},\"message\":",
+            span.line, span.column, span.offset, span.len, label.primary
+        );
+        write_string(&mut out, &label.message);
+        out.push('}');
+    }
+    out += "],\"notes\":[";
+    for (i, note) in diagnostic.notes.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        write_string(&mut out, note);
+    }
+    out += "],\"trace\":[";
+    for (i, entry) in diagnostic.trace.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        out += "{\"function\":";
+        write_string(&mut out, &entry.function);
+        out += ",\"file\":";
+        write_string(&mut out, &entry.span.file);
+        out += &format!(",\"line\":{},\"column\":{}}}", entry.span.line, entry.span.column);
+    }
+    out += "]}\n";
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "x = 10\ny = \"a\" + x\n\nfn f() {\n    return y\n}\nprint(f())\n";
+
+    /// The span of the first `needle` in `SOURCE`.
+    fn span(needle: &str) -> Span {
+        let offset = SOURCE.find(needle).unwrap();
+        let start = SOURCE[..offset].rfind('\n').map_or(0, |i| i + 1);
+        Span {
+            file: "main.alin".into(),
+            offset,
+            len: needle.len(),
+            line: SOURCE[..offset].matches('\n').count() + 1,
+            column: SOURCE[start..offset].chars().count() + 1,
+        }
+    }
+
+    fn render(format: ErrorFormat, diagnostic: &Diagnostic) -> String {
+        let mut emitter = Emitter::new(format, Color::Never);
+        emitter.add_source("main.alin", SOURCE);
+        emitter.render(diagnostic)
+    }
+
+    #[test]
+    fn renders_a_single_label() {
+        let diagnostic = Diagnostic::error("E0203", "unsupported operands for `+`")
+            .with_label(span("\"a\" + x"), "string and number");
+        let expected = concat!(
+            "error[E0203]: unsupported operands for `+`\n",
+            " --> main.alin:2:5\n",
+            "  |\n",
+            "2 | y = \"a\" + x\n",
+            "  |     ^^^^^^^ string and number\n",
+            "\n",
+        );
+        assert_eq!(render(ErrorFormat::Human, &diagnostic), expected);
+    }
+
+    #[test]
+    fn renders_secondary_labels() {
+        let diagnostic = Diagnostic::error("E0203", "unsupported operands for `+`")
+            .with_label(span("\"a\" + x"), "string and number")
+            .with_secondary(span("10"), "`x` is a number here")
+            .with_secondary(span("return y"), "used here");
+        let expected = concat!(
+            "error[E0203]: unsupported operands for `+`\n",
+            " --> main.alin:2:5\n",
+            "  |\n",
+            "1 | x = 10\n",
+            "  |     -- `x` is a number here\n",
+            "2 | y = \"a\" + x\n",
+            "  |     ^^^^^^^ string and number\n",
+            "...\n",
+            "5 |     return y\n",
+            "  |     -------- used here\n",
+            "\n",
+        );
+        assert_eq!(render(ErrorFormat::Human, &diagnostic), expected);
+    }
+
+    #[test]
+    fn renders_notes() {
+        let diagnostic = Diagnostic::warning("W0301", "unused variable `x`")
+            .with_label(span("x"), "")
+            .with_note("if this is intentional, name it `_x`")
+            .with_note("second note");
+        let expected = concat!(
+            "warning[W0301]: unused variable `x`\n",
+            " --> main.alin:1:1\n",
+            "  |\n",
+            "1 | x = 10\n",
+            "  | ^\n",
+            "  = note: if this is intentional, name it `_x`\n",
+            "  = note: second note\n",
+            "\n",
+        );
+        assert_eq!(render(ErrorFormat::Human, &diagnostic), expected);
+    }
+
+    #[test]
+    fn underlines_only_the_first_line_of_a_long_span() {
+        let diagnostic = Diagnostic::error("E0101", "unexpected token")
+            .with_label(span("fn f() {\n    return y\n}"), "this function");
+        let expected = concat!(
+            "error[E0101]: unexpected token\n",
+            " --> main.alin:4:1\n",
+            "  |\n",
+            "4 | fn f() {\n",
+            "  | ^^^^^^^^ this function\n",
+            "\n",
+        );
+        assert_eq!(render(ErrorFormat::Human, &diagnostic), expected);
+    }
+
+    #[test]
+    fn renders_json() {
+        let diagnostic = Diagnostic::error("E0203", "unsupported operands for `+`")
+            .with_label(span("\"a\" + x"), "string and \"number\"")
+            .with_secondary(span("10"), "")
+            .with_note("a note");
+        let expected = concat!(
+            r#"{"severity":"error","code":"E0203","message":"unsupported operands for `+`","labels":["#,
+            r#"{"file":"main.alin","line":2,"column":5,"offset":11,"length":7,"primary":true,"#,
+            r#""message":"string and \"number\""},"#,
+            r#"{"file":"main.alin","line":1,"column":5,"offset":4,"length":2,"primary":false,"message":""}],"#,
+            r#""notes":["a note"],"trace":[]}"#,
+            "\n",
+        );
+        assert_eq!(render(ErrorFormat::Json, &diagnostic), expected);
+    }
+}
@@ -0,0 +1,49 @@
+use std::fmt;
+
+use crate::diagnostic::Diagnostic;
+
+/// Why source code could not be run, with the diagnostics explaining it.
+#[derive(Debug)]
+pub enum AlinError {
+    /// The source could not be split into tokens, e.g. a malformed number.
+    Lex(Vec<Diagnostic>),
+    /// The tokens don't form a program.
+    Parse(Vec<Diagnostic>),
+    /// The program reads names that can't exist when it does.
+    Resolve(Vec<Diagnostic>),
+    /// A statement failed while running.
+    Runtime(Vec<Diagnostic>),
+}
+
+impl AlinError {
+    /// Everything reported at the failing stage, warnings included.
+    pub fn diagnostics(&self) -> &[Diagnostic] {
+        match self {
+            AlinError::Lex(diagnostics)
+            | AlinError::Parse(diagnostics)
+            | AlinError::Resolve(diagnostics)
+            | AlinError::Runtime(diagnostics) => diagnostics,
+        }
+    }
+}
+
+/// The first error, as `file:line:column: message`.
+impl fmt::Display for AlinError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let stage = match self {
+            AlinError::Lex(_) => "lexer",
+            AlinError::Parse(_) => "syntax",
+            AlinError::Resolve(_) => "name",
+            AlinError::Runtime(_) => "runtime",
+        };
+        match self.diagnostics().iter().find(|d| d.is_error()) {
+            Some(error) => match error.labels.iter().find(|label| label.primary) {
+                Some(label) => write!(f, "{}: {} error: {}", label.span, stage, error.message),
+                None => write!(f, "{} error: {}", stage, error.message),
+            },
+            None => write!(f, "{} error", stage),
+        }
+    }
+}
+
+impl std::error::Error for AlinError {}
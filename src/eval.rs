@@ -1,71 +1,561 @@
-use std::collections::HashMap;
-use crate::ast::Expr;
-use crate::token::Token;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::rc::Rc;
+use crate::ast::{Expr, ExprKind, Pattern, Slot};
+use crate::bytecode::Proto;
+use crate::compiler::Compiler;
+use crate::diagnostic::{Diagnostic, TraceEntry};
+use crate::error::AlinError;
+use crate::iter::{contains_yield, Generator, Iter};
+use crate::resolve::Resolver;
+use crate::stdlib::random::Rng;
+use crate::stdlib::{BuiltinFn, Builtins};
+use crate::token::{Span, Token};
+use crate::vm::Vm;
 
 pub struct Evaluator {
-    env: HashMap<String, Value>,
+    pub(crate) globals: Globals,
+    /// The active calls of tree-walked functions, innermost last.
+    pub(crate) frames: Vec<Frame>,
+    /// The state of compiled code, including its own call frames.
+    pub(crate) vm: Vm,
+    engine: Engine,
+    /// Whether scripts are run through `optimize` first.
+    optimize: bool,
+    /// Whether more code may run later, as in the REPL, so functions can
+    /// use globals that aren't defined yet.
+    interactive: bool,
+    pub(crate) builtins: Builtins,
+    pub(crate) rng: Rng,
+    /// Arguments given to the script after its path.
+    pub(crate) args: Vec<String>,
+    /// Errors reported by the statement being run.
+    diagnostics: Vec<Diagnostic>,
+    /// The call (or loop, or destructuring) being evaluated, blamed by
+    /// errors raised away from any expression, such as a wrong argument
+    /// count.
+    pub(crate) site: Span,
+    /// Where `print` writes instead of stdout, when set.
+    pub(crate) output: Option<String>,
 }
 
 #[derive(Debug, Clone)]
 pub enum Value {
     Number(f64),
     String(String),
+    Bool(bool),
+    List(Rc<RefCell<Vec<Value>>>),
+    Tuple(Rc<Vec<Value>>),
+    Map(Rc<RefCell<BTreeMap<String, Value>>>),
+    Function(Rc<Function>),
+    Iterator(Rc<RefCell<Iter>>),
+    Error(Rc<ErrorValue>),
     Nil,
 }
 
+/// A failure returned to the script as a value, such as a missing file.
+/// `kind` is a short machine-readable tag like `not_found`.
+#[derive(Debug)]
+pub struct ErrorValue {
+    pub kind: String,
+    pub message: String,
+    pub fields: BTreeMap<String, Value>,
+}
+
+impl ErrorValue {
+    /// Reads `err.kind`, `err.message` or one of the extra fields.
+    fn get(&self, field: &str) -> Value {
+        match field {
+            "kind" => Value::String(self.kind.clone()),
+            "message" => Value::String(self.message.clone()),
+            _ => self.fields.get(field).cloned().unwrap_or(Value::Nil),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Function {
+    pub name: String,
+    pub params: Vec<String>,
+    pub body: Body,
+    /// Functions containing `yield` return a generator when called.
+    pub is_generator: bool,
+    /// The definition, pointed at when a call doesn't match it.
+    pub span: Span,
+}
+
+/// What a function runs: its syntax tree, or the bytecode compiled from it.
+#[derive(Debug)]
+pub enum Body {
+    Tree(Expr),
+    Code(Rc<Proto>),
+}
+
+/// Which interpreter runs top-level code.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum Engine {
+    /// Compiles to bytecode and runs it on the stack machine in `vm`.
+    #[default]
+    Vm,
+    /// Walks the syntax tree directly. Slower, but the reference for what
+    /// programs mean.
+    Tree,
+}
+
+/// Global variables, numbered so compiled code can reach them without
+/// hashing their names.
+#[derive(Default)]
+pub(crate) struct Globals {
+    ids: HashMap<String, u32>,
+    pub(crate) names: Vec<String>,
+    /// `None` for names that are mentioned somewhere but not yet defined.
+    pub(crate) values: Vec<Option<Value>>,
+}
+
+impl Globals {
+    /// The number of `name`, allocating one the first time it is seen.
+    pub(crate) fn id(&mut self, name: &str) -> u32 {
+        if let Some(&id) = self.ids.get(name) {
+            return id;
+        }
+        let id = self.names.len() as u32;
+        self.ids.insert(name.to_string(), id);
+        self.names.push(name.to_string());
+        self.values.push(None);
+        id
+    }
+
+    pub(crate) fn get(&self, name: &str) -> Option<&Value> {
+        self.ids.get(name).and_then(|&id| self.values[id as usize].as_ref())
+    }
+
+    fn set(&mut self, name: &str, value: Value) {
+        let id = self.id(name);
+        self.values[id as usize] = Some(value);
+    }
+}
+
+/// A binary operator, applied the same way by both engines.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+}
+
+impl BinOp {
+    pub fn from_token(token: &Token) -> Option<BinOp> {
+        Some(match token {
+            Token::Plus => BinOp::Add,
+            Token::Minus => BinOp::Sub,
+            Token::Star => BinOp::Mul,
+            Token::Slash => BinOp::Div,
+            Token::EqualEqual => BinOp::Equal,
+            Token::BangEqual => BinOp::NotEqual,
+            Token::Less => BinOp::Less,
+            Token::LessEqual => BinOp::LessEqual,
+            Token::Greater => BinOp::Greater,
+            Token::GreaterEqual => BinOp::GreaterEqual,
+            _ => return None,
+        })
+    }
+}
+
+impl fmt::Display for BinOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            BinOp::Add => "+",
+            BinOp::Sub => "-",
+            BinOp::Mul => "*",
+            BinOp::Div => "/",
+            BinOp::Equal => "==",
+            BinOp::NotEqual => "!=",
+            BinOp::Less => "<",
+            BinOp::LessEqual => "<=",
+            BinOp::Greater => ">",
+            BinOp::GreaterEqual => ">=",
+        };
+        write!(f, "`{}`", text)
+    }
+}
+
+/// Applies `op`, handing the operands back if it doesn't accept them.
+/// Comparisons give `true` or `false`.
+pub(crate) fn binary(op: BinOp, lhs: Value, rhs: Value) -> Result<Value, (Value, Value)> {
+    Ok(match (op, lhs, rhs) {
+        (BinOp::Equal, a, b) => Value::Bool(values_equal(&a, &b)),
+        (BinOp::NotEqual, a, b) => Value::Bool(!values_equal(&a, &b)),
+        (BinOp::Add, Value::Number(a), Value::Number(b)) => Value::Number(a + b),
+        (BinOp::Sub, Value::Number(a), Value::Number(b)) => Value::Number(a - b),
+        (BinOp::Mul, Value::Number(a), Value::Number(b)) => Value::Number(a * b),
+        (BinOp::Div, Value::Number(a), Value::Number(b)) => Value::Number(a / b),
+        (BinOp::Less, Value::Number(a), Value::Number(b)) => Value::Bool(a < b),
+        (BinOp::LessEqual, Value::Number(a), Value::Number(b)) => Value::Bool(a <= b),
+        (BinOp::Greater, Value::Number(a), Value::Number(b)) => Value::Bool(a > b),
+        (BinOp::GreaterEqual, Value::Number(a), Value::Number(b)) => Value::Bool(a >= b),
+        (BinOp::Add, Value::String(a), Value::String(b)) => Value::String(a + &b),
+        (BinOp::Less, Value::String(a), Value::String(b)) => Value::Bool(a < b),
+        (BinOp::LessEqual, Value::String(a), Value::String(b)) => Value::Bool(a <= b),
+        (BinOp::Greater, Value::String(a), Value::String(b)) => Value::Bool(a > b),
+        (BinOp::GreaterEqual, Value::String(a), Value::String(b)) => Value::Bool(a >= b),
+        (_, a, b) => return Err((a, b)),
+    })
+}
+
+impl Value {
+    /// The name of the value's kind, as used in error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::Bool(_) => "bool",
+            Value::List(_) => "list",
+            Value::Tuple(_) => "tuple",
+            Value::Map(_) => "map",
+            Value::Function(_) => "function",
+            Value::Iterator(_) => "iterator",
+            Value::Error(_) => "error",
+            Value::Nil => "nil",
+        }
+    }
+}
+
 impl std::fmt::Display for Value {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Value::Number(n) => write!(f, "{}", n),
             Value::String(s) => write!(f, "{}", s),
+            Value::List(items) => {
+                write!(f, "[")?;
+                write_items(f, items.borrow().iter())?;
+                write!(f, "]")
+            }
+            Value::Tuple(items) => {
+                write!(f, "(")?;
+                write_items(f, items.iter())?;
+                if items.len() == 1 {
+                    write!(f, ",")?;
+                }
+                write!(f, ")")
+            }
+            Value::Map(entries) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in entries.borrow().iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: ", key)?;
+                    write_items(f, std::iter::once(value))?;
+                }
+                write!(f, "}}")
+            }
+            Value::Function(func) => write!(f, "<fn {}>", func.name),
+            Value::Iterator(_) => write!(f, "<iterator>"),
+            Value::Error(err) => write!(f, "error({}): {}", err.kind, err.message),
+            Value::Bool(b) => write!(f, "{}", b),
             Value::Nil => write!(f, "nil"),
         }
     }
 }
 
-enum EvalResult {
+/// Writes collection elements, quoting strings so `["a b"]` stays readable.
+fn write_items<'a>(
+    f: &mut std::fmt::Formatter<'_>,
+    items: impl Iterator<Item = &'a Value>,
+) -> std::fmt::Result {
+    for (i, item) in items.enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        match item {
+            Value::String(s) => write!(f, "{:?}", s)?,
+            other => write!(f, "{}", other)?,
+        }
+    }
+    Ok(())
+}
+
+/// Only `true` and non-zero numbers count as true, as in `if` and `while`
+/// conditions.
+pub(crate) fn is_truthy(value: &Value) -> bool {
+    matches!(value, Value::Number(n) if *n != 0.0) || matches!(value, Value::Bool(true))
+}
+
+/// A running function call: its locals and where it was called from, for
+/// tracebacks.
+pub(crate) struct Frame {
+    pub(crate) function: Rc<Function>,
+    pub(crate) call_site: Span,
+    /// Locals by the slot the resolver gave them, `None` until assigned.
+    pub(crate) locals: Vec<Option<Value>>,
+}
+
+impl Frame {
+    fn local(&self, slot: u32) -> Option<&Value> {
+        self.locals.get(slot as usize)?.as_ref()
+    }
+
+    fn set_local(&mut self, slot: u32, value: Value) {
+        let slot = slot as usize;
+        if slot >= self.locals.len() {
+            self.locals.resize(slot + 1, None);
+        }
+        self.locals[slot] = Some(value);
+    }
+}
+
+/// Calls nested deeper than this fail instead of overflowing the stack.
+pub(crate) const MAX_CALL_DEPTH: usize = 1000;
+
+pub(crate) enum EvalResult {
     Value(Option<Value>),
     Break,
     Continue,
+    Return(Value),
+    /// A call in tail position, handed back to `call_function` so it can
+    /// reuse the current frame instead of recursing. The span is the call's.
+    TailCall(Rc<Function>, Vec<Value>, Span),
 }
 
 impl Evaluator {
     pub fn new() -> Self {
         Self {
-            env: HashMap::new(),
+            globals: Globals::default(),
+            frames: Vec::new(),
+            vm: Vm::default(),
+            engine: Engine::default(),
+            optimize: true,
+            interactive: false,
+            builtins: Builtins::new(),
+            rng: Rng::from_time(),
+            args: Vec::new(),
+            diagnostics: Vec::new(),
+            site: Span::default(),
+            output: None,
+        }
+    }
+
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.args = args;
+    }
+
+    pub fn set_engine(&mut self, engine: Engine) {
+        self.engine = engine;
+    }
+
+    pub fn set_optimize(&mut self, optimize: bool) {
+        self.optimize = optimize;
+    }
+
+    pub fn optimizes(&self) -> bool {
+        self.optimize
+    }
+
+    pub fn set_interactive(&mut self, interactive: bool) {
+        self.interactive = interactive;
+    }
+
+    pub fn is_interactive(&self) -> bool {
+        self.interactive
+    }
+
+    /// Records an error; `run` stops after the current statement. Errors
+    /// inside functions get a traceback of the active calls.
+    pub(crate) fn report(&mut self, mut diagnostic: Diagnostic) {
+        if self.call_depth() > 0 && diagnostic.trace.is_empty() {
+            let location = diagnostic
+                .labels
+                .iter()
+                .find(|label| label.primary)
+                .map_or_else(|| self.site.clone(), |label| label.span.clone());
+            diagnostic.trace = self.traceback(location);
         }
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// Each active call with the point it has reached, outermost first,
+    /// ending at `location` in the innermost one.
+    fn traceback(&self, location: Span) -> Vec<TraceEntry> {
+        let mut trace = Vec::new();
+        let mut function = "<script>".to_string();
+        let compiled = self.vm.frames.iter().filter_map(|frame| Some((frame.function.as_ref()?, &frame.call_site)));
+        let calls = self.frames.iter().map(|frame| (&frame.function, &frame.call_site)).chain(compiled);
+        for (callee, call_site) in calls {
+            trace.push(TraceEntry { function, span: call_site.clone() });
+            function = callee.name.clone();
+        }
+        trace.push(TraceEntry { function, span: location });
+        trace
+    }
+
+    /// How many function calls are active, in either engine.
+    fn call_depth(&self) -> usize {
+        let script = self.vm.frames.first().is_some_and(|frame| frame.function.is_none());
+        self.frames.len() + self.vm.frames.len() - script as usize
+    }
+
+    /// Reports an error at `span`.
+    fn error_at(&mut self, span: &Span, code: &'static str, message: String, label: &str) {
+        self.report(Diagnostic::error(code, message).with_label(span.clone(), label));
+    }
+
+    /// Reports an error at the current call site, for failures that have no
+    /// expression of their own.
+    pub(crate) fn error_here(&mut self, code: &'static str, message: String) {
+        let site = self.site.clone();
+        self.error_at(&site, code, message, "");
     }
 
-    pub fn run(&mut self, exprs: &[Expr]) {
+    /// Runs `f` with `span` as the call site.
+    pub(crate) fn at<T>(&mut self, span: &Span, f: impl FnOnce(&mut Self) -> T) -> T {
+        let outer = std::mem::replace(&mut self.site, span.clone());
+        let result = f(self);
+        self.site = outer;
+        result
+    }
+
+    /// Makes the `random` module deterministic.
+    pub fn seed(&mut self, seed: u64) {
+        self.rng = Rng::new(seed);
+    }
+
+    /// Runs top-level statements, stopping at the first one that fails.
+    /// Variables defined before the failure are kept.
+    pub fn run(&mut self, exprs: &[Expr]) -> Result<(), AlinError> {
+        if self.engine == Engine::Vm {
+            let script = self.compile(exprs);
+            return self.run_compiled(script);
+        }
         for expr in exprs {
             match self.eval(expr) {
-                EvalResult::Value(_) => {},
-                EvalResult::Break | EvalResult::Continue => {
-                    println!("'break' or 'continue' used outside of loop");
-                }
+                EvalResult::Value(_) | EvalResult::Return(_) | EvalResult::TailCall(..) => {},
+                EvalResult::Break | EvalResult::Continue => self.loop_control_error(&expr.span),
+            }
+            if !self.diagnostics.is_empty() {
+                return Err(self.take_errors());
             }
         }
+        Ok(())
+    }
+
+    /// Checks that every name `exprs` reads can exist by then, given the
+    /// globals defined so far, and gives each local its slot. Returns the
+    /// warnings about unused variables.
+    pub fn resolve(&self, exprs: &mut [Expr]) -> Result<Vec<Diagnostic>, AlinError> {
+        let defined = self
+            .globals
+            .names
+            .iter()
+            .zip(&self.globals.values)
+            .filter(|(_, value)| value.is_some())
+            .map(|(name, _)| name.clone());
+        Resolver::new(&self.builtins, defined, self.interactive).resolve(exprs)
+    }
+
+    /// Compiles top-level statements into bytecode for `run_compiled`.
+    pub fn compile(&mut self, exprs: &[Expr]) -> Rc<Proto> {
+        Compiler::new(&mut self.globals, &self.builtins).script(exprs)
+    }
+
+    /// The errors reported so far, leaving none behind for the next run.
+    pub(crate) fn take_errors(&mut self) -> AlinError {
+        AlinError::Runtime(std::mem::take(&mut self.diagnostics))
+    }
+
+    pub(crate) fn reported(&self) -> usize {
+        self.diagnostics.len()
     }
 
-    fn eval(&mut self, expr: &Expr) -> EvalResult {
-        match expr {
-            Expr::Number(n) => EvalResult::Value(Some(Value::Number(*n))),
-            Expr::StringLiteral(s) => EvalResult::Value(Some(Value::String(s.clone()))),
-            Expr::Variable(name) => {
-                EvalResult::Value(self.env.get(name).cloned())
+    pub(crate) fn eval(&mut self, expr: &Expr) -> EvalResult {
+        match &expr.kind {
+            ExprKind::Number(n) => EvalResult::Value(Some(Value::Number(*n))),
+            ExprKind::Bool(b) => EvalResult::Value(Some(Value::Bool(*b))),
+            ExprKind::Nil => EvalResult::Value(Some(Value::Nil)),
+            ExprKind::StringLiteral(s) => EvalResult::Value(Some(Value::String(s.clone()))),
+            ExprKind::List(items) => {
+                let mut values = Vec::new();
+                for item in items {
+                    if let ExprKind::Spread(inner) = &item.kind {
+                        let Some(value) = self.eval_value(inner) else {
+                            return EvalResult::Value(None);
+                        };
+                        let Some(spread) = self.at(&inner.span, |ev| ev.spread(value)) else {
+                            return EvalResult::Value(None);
+                        };
+                        values.extend(spread);
+                    } else {
+                        let Some(value) = self.eval_value(item) else {
+                            return EvalResult::Value(None);
+                        };
+                        values.push(value);
+                    }
+                }
+                EvalResult::Value(Some(Value::List(Rc::new(RefCell::new(values)))))
+            }
+            ExprKind::Tuple(items) => {
+                let Some(items) = self.eval_args(items) else {
+                    return EvalResult::Value(None);
+                };
+                EvalResult::Value(Some(Value::Tuple(Rc::new(items))))
+            }
+            ExprKind::Map(entries) => {
+                let mut map = BTreeMap::new();
+                for (key, value) in entries {
+                    let Some(value) = self.eval_value(value) else {
+                        return EvalResult::Value(None);
+                    };
+                    map.insert(key.clone(), value);
+                }
+                EvalResult::Value(Some(Value::Map(Rc::new(RefCell::new(map)))))
+            }
+            ExprKind::Spread(_) => {
+                self.spread_error(&expr.span);
+                EvalResult::Value(None)
+            }
+            ExprKind::Variable(name, slot) => {
+                let value = self.lookup(name, *slot);
+                if value.is_none() {
+                    self.undefined_variable(&expr.span, name);
+                }
+                EvalResult::Value(value)
             }
 
-            Expr::Assignment { name, expr } => {
+            ExprKind::Assignment { name, slot, expr } => {
                 match self.eval(expr) {
                     EvalResult::Value(Some(val)) => {
-                        self.env.insert(name.clone(), val.clone());
+                        self.assign(name, *slot, val.clone());
                         EvalResult::Value(Some(val))
                     },
                     _ => EvalResult::Value(None),
                 }
             }
 
-            Expr::Binary { left, op, right } => {
+            ExprKind::Index { object: object_expr, index: index_expr } => {
+                let (Some(object), Some(index)) = (self.eval_value(object_expr), self.eval_value(index_expr)) else {
+                    return EvalResult::Value(None);
+                };
+                EvalResult::Value(self.index(object, index, [&expr.span, &object_expr.span, &index_expr.span]))
+            }
+
+            ExprKind::Destructure { pattern, expr } => {
+                let Some(val) = self.eval_value(expr) else {
+                    return EvalResult::Value(None);
+                };
+                if !self.at(&expr.span, |ev| ev.assign_pattern(pattern, val.clone())) {
+                    return EvalResult::Value(None);
+                }
+                EvalResult::Value(Some(val))
+            }
+
+            ExprKind::Binary { left, op, right } => {
                 let lhs = match self.eval(left) {
                     EvalResult::Value(Some(val)) => val,
                     _ => return EvalResult::Value(None),
@@ -74,66 +564,57 @@ impl Evaluator {
                     EvalResult::Value(Some(val)) => val,
                     _ => return EvalResult::Value(None),
                 };
-
-                let result = match (lhs, rhs) {
-                    (Value::Number(a), Value::Number(b)) => {
-                        match op {
-                            Token::Plus => Value::Number(a + b),
-                            Token::Minus => Value::Number(a - b),
-                            Token::Star => Value::Number(a * b),
-                            Token::Slash => Value::Number(a / b),
-                            Token::EqualEqual => Value::Number((a == b) as i32 as f64),
-                            Token::BangEqual => Value::Number((a != b) as i32 as f64),
-                            Token::Less => Value::Number((a < b) as i32 as f64),
-                            Token::LessEqual => Value::Number((a <= b) as i32 as f64),
-                            _ => return EvalResult::Value(None),
-                        }
-                    }
-                    (Value::String(a), Value::String(b)) if op == &Token::Plus => {
-                        Value::String(a + &b)
-                    }
-                    _ => {
-                        println!("Type mismatch in binary expression");
-                        return EvalResult::Value(None);
-                    }
+                let Some(op) = BinOp::from_token(op) else {
+                    return EvalResult::Value(None);
                 };
-
-                EvalResult::Value(Some(result))
+                EvalResult::Value(self.apply(op, lhs, rhs, [&expr.span, &left.span, &right.span]))
             }
 
-            Expr::FunctionCall { name, args } => {
-                if name == "print" {
-                    let output: Vec<String> = args
-                        .iter()
-                        .map(|arg| match self.eval(arg) {
-                            EvalResult::Value(Some(val)) => val.to_string(),
-                            _ => "nil".to_string(),
-                        })
-                        .collect();
-                    println!("{}", output.join(" "));
-                    EvalResult::Value(Some(Value::Nil))
+            ExprKind::FunctionCall { name, slot, args } => {
+                if let Some(Value::Function(func)) = self.lookup(name, *slot) {
+                    let Some(args) = self.eval_args(args) else {
+                        return EvalResult::Value(None);
+                    };
+                    EvalResult::Value(self.at(&expr.span, |ev| ev.call_function(func, args)))
+                } else if let Some(builtin) = self.builtins.get(name) {
+                    let Some(args) = self.eval_args(args) else {
+                        return EvalResult::Value(None);
+                    };
+                    EvalResult::Value(self.call_builtin(name, builtin, &args, &expr.span))
                 } else {
-                    println!("Unknown function: {}", name);
-                    EvalResult::Value(Some(Value::Nil))
+                    self.unknown_function(&expr.span, name);
+                    EvalResult::Value(None)
                 }
             }
 
-            Expr::Block(statements) => {
+            ExprKind::FunctionDef { name, slot, params, body } => {
+                let func = Function {
+                    name: name.clone(),
+                    params: params.clone(),
+                    body: Body::Tree((**body).clone()),
+                    is_generator: contains_yield(body),
+                    span: expr.span.clone(),
+                };
+                self.assign(name, *slot, Value::Function(Rc::new(func)));
+                EvalResult::Value(Some(Value::Nil))
+            }
+
+            ExprKind::Block(statements) => {
                 for stmt in statements {
                     match self.eval(stmt) {
-                        EvalResult::Break => return EvalResult::Break,
-                        EvalResult::Continue => return EvalResult::Continue,
-                        val => {} // ignore other values
+                        EvalResult::Value(Some(_)) => {} // ignore other values
+                        other => return other,
                     }
                 }
                 EvalResult::Value(Some(Value::Nil))
             }
 
-            Expr::If { condition, then_branch, else_branch } => {
+            ExprKind::If { condition, then_branch, else_branch } => {
                 match self.eval(condition) {
-                    EvalResult::Value(Some(Value::Number(n))) if n != 0.0 => {
+                    EvalResult::Value(Some(cond)) if is_truthy(&cond) => {
                         self.eval(then_branch)
                     },
+                    EvalResult::Value(None) => EvalResult::Value(None),
                     _ => {
                         if let Some(else_expr) = else_branch {
                             self.eval(else_expr)
@@ -144,31 +625,520 @@ impl Evaluator {
                 }
             }
 
-            Expr::While { condition, body } => {
+            ExprKind::While { condition, body } => {
                 loop {
                     match self.eval(condition) {
-                        EvalResult::Value(Some(Value::Number(n))) if n != 0.0 => {
+                        EvalResult::Value(Some(cond)) if is_truthy(&cond) => {
                             match self.eval(body) {
                                 EvalResult::Break => break,
                                 EvalResult::Continue => continue,
-                                _ => {}
+                                EvalResult::Value(Some(_)) => {}
+                                other => return other,
                             }
                         }
+                        EvalResult::Value(None) => return EvalResult::Value(None),
                         _ => break,
                     }
                 }
                 EvalResult::Value(Some(Value::Nil))
             }
 
-            Expr::Break => EvalResult::Break,
-            Expr::Continue => EvalResult::Continue,
+            ExprKind::For { var, slot, iterable, body } => {
+                let Some(value) = self.eval_value(iterable) else {
+                    return EvalResult::Value(None);
+                };
+                let Some(mut iter) = self.at(&iterable.span, |ev| ev.iterate(value)) else {
+                    return EvalResult::Value(None);
+                };
+                let reported = self.diagnostics.len();
+                while let Some(item) = self.at(&iterable.span, |ev| ev.next_value(&mut iter)) {
+                    self.assign(var, *slot, item);
+                    match self.eval(body) {
+                        EvalResult::Break => break,
+                        EvalResult::Continue | EvalResult::Value(Some(_)) => {}
+                        other => return other,
+                    }
+                }
+                // An iterator also stops when producing an item fails.
+                if self.diagnostics.len() > reported {
+                    return EvalResult::Value(None);
+                }
+                EvalResult::Value(Some(Value::Nil))
+            }
+
+            ExprKind::Yield(_) => {
+                self.yield_error(&expr.span);
+                EvalResult::Value(None)
+            }
+
+            ExprKind::Break => EvalResult::Break,
+            ExprKind::Continue => EvalResult::Continue,
+
+            // `parse` never hands these over.
+            ExprKind::Error => EvalResult::Value(None),
+
+            ExprKind::Return(expr) => {
+                // Inside a function, `return f(...)` hands the call back to
+                // the caller's loop so deep recursion runs in constant stack.
+                if let ExprKind::FunctionCall { name, slot, args } = &expr.kind {
+                    let callee = if self.frames.is_empty() { None } else { self.lookup(name, *slot) };
+                    if let Some(Value::Function(func)) = callee {
+                        let Some(args) = self.eval_args(args) else {
+                            return EvalResult::Value(None);
+                        };
+                        return EvalResult::TailCall(func, args, expr.span.clone());
+                    }
+                }
+
+                match self.eval(expr) {
+                    EvalResult::Value(Some(val)) => EvalResult::Return(val),
+                    EvalResult::Value(None) => EvalResult::Value(None),
+                    other => other,
+                }
+            }
+        }
+    }
+
+    pub(crate) fn call_function(&mut self, mut func: Rc<Function>, mut args: Vec<Value>) -> Option<Value> {
+        let mut call_site = self.site.clone();
+        loop {
+            if let Body::Code(proto) = &func.body {
+                return self.call_compiled(func.clone(), proto.clone(), args, call_site);
+            }
+            if !self.check_arity(&func, args.len(), &call_site) {
+                return None;
+            }
+
+            // Parameters take the first slots.
+            let locals: Vec<Option<Value>> = args.into_iter().map(Some).collect();
+            if func.is_generator {
+                let generator = Generator::new(func, locals);
+                let iter = Iter::Generator(Box::new(generator));
+                return Some(Value::Iterator(Rc::new(RefCell::new(iter))));
+            }
+            if !self.check_depth(&func, &call_site) {
+                return None;
+            }
+            self.frames.push(Frame { function: func.clone(), call_site, locals });
+            let result = match &func.body {
+                Body::Tree(body) => self.eval(body),
+                Body::Code(_) => EvalResult::Value(None),
+            };
+            self.frames.pop();
+
+            match result {
+                EvalResult::TailCall(next, next_args, next_site) => {
+                    func = next;
+                    args = next_args;
+                    call_site = next_site;
+                }
+                EvalResult::Return(val) => return Some(val),
+                EvalResult::Value(None) => return None,
+                _ => return Some(Value::Nil),
+            }
+        }
+    }
+
+    /// Reports a call with the wrong number of arguments.
+    pub(crate) fn check_arity(&mut self, func: &Function, count: usize, call_site: &Span) -> bool {
+        if count == func.params.len() {
+            return true;
+        }
+        let message = format!("`{}` expects {} argument(s), got {}", func.name, func.params.len(), count);
+        let diagnostic = Diagnostic::error("E0206", message)
+            .with_label(call_site.clone(), "")
+            .with_secondary(func.span.clone(), format!("defined with ({})", func.params.join(", ")));
+        self.report(diagnostic);
+        false
+    }
+
+    /// Reports a call that would nest deeper than `MAX_CALL_DEPTH`.
+    pub(crate) fn check_depth(&mut self, func: &Function, call_site: &Span) -> bool {
+        if self.call_depth() < MAX_CALL_DEPTH {
+            return true;
+        }
+        let diagnostic = Diagnostic::error("E0216", "maximum call depth exceeded")
+            .with_label(call_site.clone(), format!("calling `{}`", func.name))
+            .with_note(format!("calls can be nested at most {} deep; is the recursion missing a base case?", MAX_CALL_DEPTH));
+        self.report(diagnostic);
+        false
+    }
+
+    /// Calls a native function at `span`.
+    pub(crate) fn call_builtin(&mut self, name: &str, builtin: BuiltinFn, args: &[Value], span: &Span) -> Option<Value> {
+        let reported = self.diagnostics.len();
+        let result = self.at(span, |ev| builtin(ev, args));
+        // A builtin that fails without reporting anything itself rejected
+        // its arguments.
+        if result.is_none() && self.diagnostics.len() == reported {
+            let types: Vec<&str> = args.iter().map(Value::type_name).collect();
+            let diagnostic = Diagnostic::error("E0215", format!("invalid arguments to `{}()`", name))
+                .with_label(span.clone(), "")
+                .with_note(format!("called with ({})", types.join(", ")));
+            self.report(diagnostic);
+        }
+        result
+    }
+
+    pub(crate) fn call_value(&mut self, callee: &Value, args: Vec<Value>) -> Option<Value> {
+        match callee {
+            Value::Function(func) => self.call_function(func.clone(), args),
+            other => {
+                self.error_here("E0207", format!("a {} is not callable", other.type_name()));
+                None
+            }
+        }
+    }
+
+    pub(crate) fn eval_value(&mut self, expr: &Expr) -> Option<Value> {
+        match self.eval(expr) {
+            EvalResult::Value(val) => val,
+            _ => None,
+        }
+    }
+
+    fn eval_args(&mut self, args: &[Expr]) -> Option<Vec<Value>> {
+        args.iter()
+            .map(|arg| match self.eval(arg) {
+                EvalResult::Value(Some(val)) => Some(val),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Looks a name up in its slot of the current call frame, then in the
+    /// globals, and finally among the builtin modules.
+    fn lookup(&self, name: &str, slot: Slot) -> Option<Value> {
+        slot.zip(self.frames.last())
+            .and_then(|(slot, frame)| frame.local(slot))
+            .or_else(|| self.globals.get(name))
+            .cloned()
+            .or_else(|| self.builtins.module(name))
+    }
+
+    /// Binds every name in `pattern` to the matching part of `val`.
+    fn assign_pattern(&mut self, pattern: &Pattern, val: Value) -> bool {
+        match pattern {
+            Pattern::Variable(name, slot) => {
+                self.assign(name, *slot, val);
+                true
+            }
+            Pattern::Index { object, index } => {
+                let (Some(object), Some(index)) = (self.eval_value(object), self.eval_value(index)) else {
+                    return false;
+                };
+                self.store_index(object, index, val)
+            }
+            Pattern::Tuple(targets) => {
+                let Some(values) = self.unpack_tuple(&val, targets.len()) else {
+                    return false;
+                };
+                targets.iter().zip(values).all(|(target, value)| self.assign_pattern(target, value))
+            }
+            Pattern::List { items, rest } => {
+                let Some((values, remaining)) = self.unpack_list(&val, items.len(), rest.is_some()) else {
+                    return false;
+                };
+                if !items.iter().zip(values).all(|(target, value)| self.assign_pattern(target, value)) {
+                    return false;
+                }
+                if let Some((name, slot)) = rest {
+                    self.assign(name, *slot, Value::List(Rc::new(RefCell::new(remaining))));
+                }
+                true
+            }
+            Pattern::Map(entries) => {
+                let Some(map) = self.unpack_map(&val) else {
+                    return false;
+                };
+                entries.iter().all(|(key, target)| {
+                    let value = map.borrow().get(key).cloned().unwrap_or(Value::Nil);
+                    self.assign_pattern(target, value)
+                })
+            }
+        }
+    }
+
+    /// Updates an existing local or global, otherwise defines the name in the
+    /// innermost scope: the slot of a local, or the globals.
+    pub(crate) fn assign(&mut self, name: &str, slot: Slot, val: Value) {
+        match (self.frames.last_mut(), slot) {
+            (Some(frame), Some(slot)) if frame.local(slot).is_some() || self.globals.get(name).is_none() => {
+                frame.set_local(slot, val);
+            }
+            _ => self.globals.set(name, val),
+        }
+    }
+
+    /// Every item of a spread value, or `None` if it isn't iterable or
+    /// producing an item fails.
+    pub(crate) fn spread(&mut self, value: Value) -> Option<Vec<Value>> {
+        let mut iter = self.iterate(value)?;
+        let reported = self.diagnostics.len();
+        let mut items = Vec::new();
+        while let Some(value) = self.next_value(&mut iter) {
+            items.push(value);
+        }
+        (self.diagnostics.len() == reported).then_some(items)
+    }
 
-            Expr::Return(expr) => self.eval(expr),
+    /// Reads `object[index]`. `spans` are the whole expression, the object
+    /// and the index.
+    pub(crate) fn index(&mut self, object: Value, index: Value, spans: [&Span; 3]) -> Option<Value> {
+        let [span, object_span, index_span] = spans;
+        let item = match (&object, &index) {
+            (Value::List(items), Value::Number(i)) if *i >= 0.0 => {
+                items.borrow().get(*i as usize).cloned()
+            }
+            (Value::Tuple(items), Value::Number(i)) if *i >= 0.0 => {
+                items.get(*i as usize).cloned()
+            }
+            (Value::Map(entries), Value::String(key)) => {
+                // Missing keys read as nil, so optional fields are easy to probe.
+                Some(entries.borrow().get(key).cloned().unwrap_or(Value::Nil))
+            }
+            (Value::Error(err), Value::String(field)) => Some(err.get(field)),
+            (Value::String(s), Value::Number(i)) if *i >= 0.0 => {
+                s.chars().nth(*i as usize).map(|c| Value::String(c.to_string()))
+            }
+            _ => {
+                let message = format!("cannot index a {} with a {}", object.type_name(), index.type_name());
+                let diagnostic = Diagnostic::error("E0204", message)
+                    .with_label(span.clone(), "")
+                    .with_secondary(object_span.clone(), object.type_name())
+                    .with_secondary(index_span.clone(), index.type_name());
+                self.report(diagnostic);
+                return None;
+            }
+        };
+        if item.is_none() {
+            let len = match &object {
+                Value::List(items) => items.borrow().len(),
+                Value::Tuple(items) => items.len(),
+                Value::String(s) => s.chars().count(),
+                _ => 0,
+            };
+            let diagnostic = Diagnostic::error("E0205", format!("index {} out of range", index))
+                .with_label(index_span.clone(), format!("the {} has length {}", object.type_name(), len));
+            self.report(diagnostic);
+        }
+        item
+    }
 
+    /// Applies a binary operator, reporting operands it doesn't accept.
+    /// `spans` are the whole expression and its two operands.
+    pub(crate) fn apply(&mut self, op: BinOp, lhs: Value, rhs: Value, spans: [&Span; 3]) -> Option<Value> {
+        let (lhs, rhs) = match binary(op, lhs, rhs) {
+            Ok(result) => return Some(result),
+            Err(operands) => operands,
+        };
+        let [span, left_span, right_span] = spans;
+        let diagnostic = Diagnostic::error(
+            "E0203",
+            format!("cannot apply {} to a {} and a {}", op, lhs.type_name(), rhs.type_name()),
+        )
+        .with_label(span.clone(), "")
+        .with_secondary(left_span.clone(), lhs.type_name())
+        .with_secondary(right_span.clone(), rhs.type_name());
+        self.report(diagnostic);
+        None
+    }
+
+    /// Performs `object[index] = val` for a destructuring target.
+    pub(crate) fn store_index(&mut self, object: Value, index: Value, val: Value) -> bool {
+        // A cycle would send printing, comparing and serializing round it
+        // until the native stack overflows.
+        if reaches(&val, &object, &mut HashSet::new()) {
+            self.error_here("E0217", format!("cannot store a {} inside itself", object.type_name()));
+            return false;
+        }
+        match (&object, &index) {
+            (Value::List(items), Value::Number(i)) if *i >= 0.0 && (*i as usize) < items.borrow().len() => {
+                items.borrow_mut()[*i as usize] = val;
+                true
+            }
+            (Value::Map(entries), Value::String(key)) => {
+                entries.borrow_mut().insert(key.clone(), val);
+                true
+            }
             _ => {
-                println!("Unsupported expression");
-                EvalResult::Value(None)
+                self.error_here("E0209", format!("cannot assign to index {} of a {}", index, object.type_name()));
+                false
+            }
+        }
+    }
+
+    /// The items of `val` for a tuple pattern with `count` targets.
+    pub(crate) fn unpack_tuple(&mut self, val: &Value, count: usize) -> Option<Vec<Value>> {
+        let Some(values) = sequence_items(val) else {
+            self.error_here("E0208", format!("cannot unpack a {} into {} targets", val.type_name(), count));
+            return None;
+        };
+        if values.len() != count {
+            self.error_here("E0208", format!("cannot unpack {} value(s) into {} targets", values.len(), count));
+            return None;
+        }
+        Some(values)
+    }
+
+    /// The first `count` items of `val` for a list pattern, and the items
+    /// left for its `...rest` target, if it has one.
+    pub(crate) fn unpack_list(&mut self, val: &Value, count: usize, rest: bool) -> Option<(Vec<Value>, Vec<Value>)> {
+        let Some(mut values) = sequence_items(val) else {
+            self.error_here("E0208", format!("cannot unpack a {} as a list", val.type_name()));
+            return None;
+        };
+        let enough = if rest { values.len() >= count } else { values.len() == count };
+        if !enough {
+            self.error_here("E0208", format!("cannot unpack {} value(s) into {} targets", values.len(), count));
+            return None;
+        }
+        let remaining = values.split_off(count);
+        Some((values, remaining))
+    }
+
+    pub(crate) fn unpack_map(&mut self, val: &Value) -> Option<Rc<RefCell<BTreeMap<String, Value>>>> {
+        match val {
+            Value::Map(map) => Some(map.clone()),
+            _ => {
+                self.error_here("E0208", format!("cannot unpack a {} as a map", val.type_name()));
+                None
             }
         }
     }
+
+    pub(crate) fn undefined_variable(&mut self, span: &Span, name: &str) {
+        self.error_at(span, "E0201", format!("undefined variable `{}`", name), "not defined");
+    }
+
+    pub(crate) fn unknown_function(&mut self, span: &Span, name: &str) {
+        self.error_at(span, "E0202", format!("unknown function `{}`", name), "not defined");
+    }
+
+    /// Reports a `break` or `continue` that reached the top-level statement
+    /// at `span`.
+    pub(crate) fn loop_control_error(&mut self, span: &Span) {
+        let diagnostic = Diagnostic::error("E0210", "`break` or `continue` outside of a loop")
+            .with_label(span.clone(), "reached the top level")
+            .with_note("`break` and `continue` only work inside `while` and `for`");
+        self.report(diagnostic);
+    }
+
+    pub(crate) fn spread_error(&mut self, span: &Span) {
+        let diagnostic = Diagnostic::error("E0211", "`...` outside of a list")
+            .with_label(span.clone(), "not inside `[...]`")
+            .with_note("spreading only works in list literals, like `[...xs, 4]`");
+        self.report(diagnostic);
+    }
+
+    pub(crate) fn yield_error(&mut self, span: &Span) {
+        let diagnostic = Diagnostic::error("E0212", "`yield` outside of a generator")
+            .with_label(span.clone(), "")
+            .with_note("a function containing `yield` returns a generator when called");
+        self.report(diagnostic);
+    }
+}
+
+/// The elements of a list or tuple, for destructuring.
+fn sequence_items(val: &Value) -> Option<Vec<Value>> {
+    match val {
+        Value::List(items) => Some(items.borrow().clone()),
+        Value::Tuple(items) => Some(items.as_ref().clone()),
+        _ => None,
+    }
+}
+
+/// Structural equality; values of different kinds are never equal.
+/// Whether `value` is the list or map `collection`, or holds it at any
+/// depth. `seen` keeps shared parts from being walked more than once.
+fn reaches(value: &Value, collection: &Value, seen: &mut HashSet<*const ()>) -> bool {
+    let pointer = match value {
+        Value::List(items) => Rc::as_ptr(items) as *const (),
+        Value::Map(entries) => Rc::as_ptr(entries) as *const (),
+        Value::Tuple(items) => Rc::as_ptr(items) as *const (),
+        _ => return false,
+    };
+    let target = match collection {
+        Value::List(items) => Rc::as_ptr(items) as *const (),
+        Value::Map(entries) => Rc::as_ptr(entries) as *const (),
+        _ => return false,
+    };
+    if pointer == target {
+        return true;
+    }
+    if !seen.insert(pointer) {
+        return false;
+    }
+    match value {
+        Value::List(items) => items.borrow().iter().any(|item| reaches(item, collection, seen)),
+        Value::Map(entries) => entries.borrow().values().any(|item| reaches(item, collection, seen)),
+        Value::Tuple(items) => items.iter().any(|item| reaches(item, collection, seen)),
+        _ => false,
+    }
+}
+
+fn values_equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x == y,
+        (Value::String(x), Value::String(y)) => x == y,
+        (Value::Bool(x), Value::Bool(y)) => x == y,
+        (Value::Nil, Value::Nil) => true,
+        (Value::List(x), Value::List(y)) => {
+            let (x, y) = (x.borrow(), y.borrow());
+            x.len() == y.len() && x.iter().zip(y.iter()).all(|(a, b)| values_equal(a, b))
+        }
+        (Value::Tuple(x), Value::Tuple(y)) => {
+            x.len() == y.len() && x.iter().zip(y.iter()).all(|(a, b)| values_equal(a, b))
+        }
+        (Value::Map(x), Value::Map(y)) => {
+            let (x, y) = (x.borrow(), y.borrow());
+            x.len() == y.len()
+                && x.iter().all(|(key, a)| y.get(key).is_some_and(|b| values_equal(a, b)))
+        }
+        (Value::Function(x), Value::Function(y)) => Rc::ptr_eq(x, y),
+        (Value::Iterator(x), Value::Iterator(y)) => Rc::ptr_eq(x, y),
+        (Value::Error(x), Value::Error(y)) => Rc::ptr_eq(x, y),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::playground::run_captured;
+
+    /// Runs `source` on both engines, checking they agree.
+    fn run(source: &str) -> String {
+        let tree = run_captured(source, Engine::Tree, true);
+        assert_eq!(run_captured(source, Engine::Vm, true), tree, "the engines disagree on:\n{}", source);
+        tree
+    }
+
+    #[test]
+    fn a_list_cannot_be_stored_inside_itself() {
+        let output = run("xs = [1]\nxs[0] = xs\n");
+        assert!(output.starts_with("error[E0217]: cannot store a list inside itself\n"), "{}", output);
+        let output = run("xs = [1]\nys = [xs]\nxs[0] = ys\n");
+        assert!(output.starts_with("error[E0217]: cannot store a list inside itself\n"), "{}", output);
+    }
+
+    #[test]
+    fn a_map_cannot_be_stored_inside_itself() {
+        let output = run("m = {}\nm[\"self\"] = (1, [m])\n");
+        assert!(output.starts_with("error[E0217]: cannot store a map inside itself\n"), "{}", output);
+    }
+
+    #[test]
+    fn destructuring_cannot_store_a_list_inside_itself() {
+        let output = run("xs = [1, 2]\n[xs[0], y] = [[xs], 2]\n");
+        assert!(output.starts_with("error[E0217]"), "{}", output);
+    }
+
+    #[test]
+    fn storing_equal_but_separate_collections_is_fine() {
+        let source = "xs = [1]\nshared = [2]\nxs[0] = [shared, shared, [1]]\n\
+                      print(xs, xs == xs, xs == [[[2], [2], [1]]])\n";
+        let output = run(source);
+        assert_eq!(output, "[[[2], [2], [1]]] true true\n");
+    }
 }
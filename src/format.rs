@@ -0,0 +1,691 @@
+use crate::ast::{Expr, ExprKind, Pattern};
+use crate::token::{Comment, Span, Token, KEYWORDS};
+
+const INDENT: &str = "    ";
+
+/// Prints a parsed script in the canonical style: four-space indents, one
+/// statement per line, spaces around binary operators and after commas,
+/// opening braces on the line that starts the block, and no semicolons
+/// except where the next statement would otherwise continue this one.
+///
+/// Comments are kept between the statements they were between, or after
+/// the token that ended their line. Lists, tuples, maps and calls with
+/// comments inside are written one item per line so theirs stay beside their
+/// items; any other comment inside an expression moves after its statement.
+/// Single blank lines between statements are kept.
+pub fn format(exprs: &[Expr], comments: &[Comment], source: &str) -> String {
+    let mut printer = Printer {
+        source,
+        comments,
+        next_comment: 0,
+        out: String::new(),
+        indent: 0,
+        last_line: 0,
+        at_expr_start: false,
+    };
+    printer.statements(exprs, source.len());
+    let mut out = printer.out;
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out
+}
+
+struct Printer<'a> {
+    source: &'a str,
+    comments: &'a [Comment],
+    /// The first comment not yet printed.
+    next_comment: usize,
+    out: String,
+    indent: usize,
+    /// The source line the last statement or comment printed ended on, or
+    /// 0 at the start of a block.
+    last_line: usize,
+    /// Whether nothing has been printed yet of a statement or of an
+    /// expression where `{` starts a block, so `{}` there needs wrapping.
+    at_expr_start: bool,
+}
+
+/// Where an expression appears, which decides what it may need wrapping in
+/// to be read back the same way.
+#[derive(Clone, Copy, PartialEq)]
+enum Place {
+    /// Where the parser reads a full expression, such as a statement or a
+    /// condition, and `{` starts a block.
+    Expr,
+    /// Where the parser reads a value, such as an argument, and `{}` is an
+    /// empty map.
+    Value,
+    /// Beside a binary operator or before `.` or `[`, where only
+    /// expressions binding at least this tightly can go unwrapped.
+    Operand(u8),
+}
+
+impl Printer<'_> {
+    /// Prints a block's statements, one per line, then any comments left
+    /// before `end`, the offset where the block closes.
+    fn statements(&mut self, exprs: &[Expr], end: usize) {
+        self.last_line = 0;
+        for (i, expr) in exprs.iter().enumerate() {
+            self.comments_before(expr.span.offset);
+            self.blank_line(expr.span.line);
+            self.new_line();
+            self.at_expr_start = true;
+            self.statement(expr);
+            // The next statement would otherwise be read as a call or index
+            // of this one.
+            let next = exprs.get(i + 1);
+            if next.is_some_and(opens_with_bracket) {
+                self.out.push(';');
+            }
+            let statement_end = expr.span.offset + expr.span.len;
+            let end_line = self.line_of(statement_end.saturating_sub(1).max(expr.span.offset));
+            self.trailing_comments(statement_end, end_line, next.map_or(end, |next| next.span.offset));
+            self.last_line = end_line;
+        }
+        self.comments_before(end);
+    }
+
+    fn statement(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Assignment { name, expr: value, .. } => {
+                self.push(name);
+                self.push(" = ");
+                self.values(value);
+            }
+            ExprKind::Destructure { pattern, expr: value } => {
+                match pattern {
+                    Pattern::Tuple(targets) if targets.len() > 1 => {
+                        self.list(targets, |p, target| p.pattern(target, false));
+                    }
+                    _ => self.pattern(pattern, true),
+                }
+                self.push(" = ");
+                self.values(value);
+            }
+            _ => self.expr(expr, Place::Expr),
+        }
+    }
+
+    /// A value where a statement or `return` allows a bare tuple.
+    fn values(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Tuple(items) if items.len() > 1 => self.list(items, |p, item| p.expr(item, Place::Value)),
+            _ => self.expr(expr, Place::Value),
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr, place: Place) {
+        let at_start = std::mem::take(&mut self.at_expr_start) || place == Place::Expr;
+        if needs_parens(expr, place) || (at_start && is_empty_map(expr)) {
+            self.push("(");
+            self.expr(expr, Place::Value);
+            self.push(")");
+            return;
+        }
+        match &expr.kind {
+            ExprKind::Number(n) => self.push(&n.to_string()),
+            ExprKind::StringLiteral(s) => string(s, &mut self.out),
+            ExprKind::Bool(b) => self.push(if *b { "true" } else { "false" }),
+            ExprKind::Nil => self.push("nil"),
+            ExprKind::Break => self.push("break"),
+            ExprKind::Continue => self.push("continue"),
+            ExprKind::Error => {}
+            ExprKind::Variable(name, _) => self.push(name),
+            ExprKind::List(items) => {
+                self.push("[");
+                self.collection(items, &expr.span, |item| &item.span, |p, _, item| p.expr(item, Place::Value));
+                self.push("]");
+            }
+            ExprKind::Tuple(items) => {
+                self.push("(");
+                let multiline =
+                    self.collection(items, &expr.span, |item| &item.span, |p, _, item| p.expr(item, Place::Value));
+                if items.len() == 1 && !multiline {
+                    self.push(",");
+                }
+                self.push(")");
+            }
+            ExprKind::Map(entries) => {
+                self.push("{");
+                self.collection(entries, &expr.span, |(_, value)| &value.span, |p, i, (key, value)| {
+                    map_key(key, &mut p.out);
+                    // `{a, b}` starting a statement is a block, so the
+                    // first entry is always written in full.
+                    let short = matches!(&value.kind, ExprKind::Variable(name, _) if name == key);
+                    if i == 0 || !short || !is_identifier(key) {
+                        p.push(": ");
+                        p.expr(value, Place::Value);
+                    }
+                });
+                self.push("}");
+            }
+            ExprKind::Spread(inner) => {
+                self.push("...");
+                self.expr(inner, Place::Value);
+            }
+            ExprKind::Binary { left, op, right } => {
+                let precedence = precedence(op);
+                self.at_expr_start = at_start;
+                self.expr(left, Place::Operand(precedence));
+                self.push(" ");
+                self.push(operator(op));
+                self.push(" ");
+                self.expr(right, Place::Operand(precedence + 1));
+            }
+            ExprKind::Index { object, index } => {
+                self.at_expr_start = at_start;
+                self.index(object, index);
+            }
+            ExprKind::Assignment { name, expr: value, .. } => {
+                self.push(name);
+                self.push(" = ");
+                self.expr(value, Place::Value);
+            }
+            ExprKind::Destructure { pattern, expr: value } => {
+                self.at_expr_start = at_start;
+                self.pattern(pattern, false);
+                self.push(" = ");
+                self.expr(value, Place::Value);
+            }
+            ExprKind::Block(statements) => self.block(statements, expr),
+            ExprKind::If { condition, then_branch, else_branch } => {
+                self.push("if ");
+                self.expr(condition, Place::Expr);
+                self.push(" ");
+                self.expr(then_branch, Place::Expr);
+                if let Some(else_branch) = else_branch {
+                    self.before_else(then_branch, else_branch);
+                    self.push("else ");
+                    self.expr(else_branch, Place::Expr);
+                }
+            }
+            ExprKind::While { condition, body } => {
+                self.push("while ");
+                self.expr(condition, Place::Expr);
+                self.push(" ");
+                self.expr(body, Place::Expr);
+            }
+            ExprKind::For { var, iterable, body, .. } => {
+                self.push("for ");
+                self.push(var);
+                self.push(" in ");
+                self.expr(iterable, Place::Expr);
+                self.push(" ");
+                self.expr(body, Place::Expr);
+            }
+            ExprKind::FunctionDef { name, params, body, .. } => {
+                self.push("fn ");
+                self.push(name);
+                self.push("(");
+                self.push(&params.join(", "));
+                self.push(") ");
+                self.expr(body, Place::Expr);
+            }
+            ExprKind::FunctionCall { name, args, .. } => {
+                self.push(name);
+                self.push("(");
+                self.collection(args, &expr.span, |arg| &arg.span, |p, _, arg| p.expr(arg, Place::Value));
+                self.push(")");
+            }
+            ExprKind::Return(value) => {
+                self.push("return ");
+                self.values(value);
+            }
+            ExprKind::Yield(value) => {
+                self.push("yield ");
+                self.expr(value, Place::Value);
+            }
+        }
+    }
+
+    fn index(&mut self, object: &Expr, index: &Expr) {
+        self.expr(object, Place::Operand(u8::MAX));
+        // `person.name` stays short, as long as it was written that way.
+        if let ExprKind::StringLiteral(field) = &index.kind {
+            let quoted = self.source[index.span.offset..].starts_with(['"', '\'']);
+            if !quoted && is_identifier(field) {
+                self.push(".");
+                self.push(field);
+                return;
+            }
+        }
+        self.push("[");
+        self.expr(index, Place::Value);
+        self.push("]");
+    }
+
+    /// Prints a pattern; `top` when it is the whole left side of a
+    /// statement, where `{a, b} = ...` can't be mistaken for a block.
+    fn pattern(&mut self, pattern: &Pattern, top: bool) {
+        match pattern {
+            Pattern::Variable(name, _) => self.push(name),
+            Pattern::Index { object, index } => self.index(object, index),
+            Pattern::Tuple(targets) => {
+                self.push("(");
+                self.list(targets, |p, target| p.pattern(target, false));
+                if targets.len() == 1 {
+                    self.push(",");
+                }
+                self.push(")");
+            }
+            Pattern::List { items, rest } => {
+                self.push("[");
+                self.list(items, |p, target| p.pattern(target, false));
+                if let Some((rest, _)) = rest {
+                    if !items.is_empty() {
+                        self.push(", ");
+                    }
+                    self.push("...");
+                    self.push(rest);
+                }
+                self.push("]");
+            }
+            Pattern::Map(entries) => {
+                self.push("{");
+                for (i, (key, target)) in entries.iter().enumerate() {
+                    if i > 0 {
+                        self.push(", ");
+                    }
+                    map_key(key, &mut self.out);
+                    let short = matches!(target, Pattern::Variable(name, _) if name == key);
+                    if (i == 0 && !top) || !short || !is_identifier(key) {
+                        self.push(": ");
+                        self.pattern(target, false);
+                    }
+                }
+                self.push("}");
+            }
+        }
+    }
+
+    fn list<T>(&mut self, items: &[T], mut item: impl FnMut(&mut Self, &T)) {
+        for (i, value) in items.iter().enumerate() {
+            if i > 0 {
+                self.push(", ");
+            }
+            item(self, value);
+        }
+    }
+
+    /// Prints the items of a list, tuple, map or call whose brackets are
+    /// at the ends of `span`, returning whether they went one per line.
+    /// They do, each with a trailing comma, when there are comments among
+    /// them to keep beside them; otherwise they share a line.
+    fn collection<T>(
+        &mut self,
+        items: &[T],
+        span: &Span,
+        item_span: impl Fn(&T) -> &Span,
+        mut item: impl FnMut(&mut Self, usize, &T),
+    ) -> bool {
+        let end = span.offset + span.len;
+        if !self.has_comments_before(end) {
+            for (i, value) in items.iter().enumerate() {
+                if i > 0 {
+                    self.push(", ");
+                }
+                item(self, i, value);
+            }
+            return false;
+        }
+        let outer_line = self.last_line;
+        let first = items.first().map_or(end, |first| item_span(first).offset);
+        self.trailing_comments(span.offset, self.line_of(span.offset), first);
+        self.last_line = 0;
+        self.indent += 1;
+        for (i, value) in items.iter().enumerate() {
+            let value_span = item_span(value);
+            self.comments_before(value_span.offset);
+            self.blank_line(value_span.line);
+            self.new_line();
+            item(self, i, value);
+            self.push(",");
+            let item_end = value_span.offset + value_span.len;
+            let end_line = self.line_of(item_end.saturating_sub(1).max(value_span.offset));
+            let next = items.get(i + 1).map_or(end, |next| item_span(next).offset);
+            self.trailing_comments(item_end, end_line, next);
+            self.last_line = end_line;
+        }
+        self.comments_before(end);
+        self.indent -= 1;
+        self.last_line = outer_line;
+        self.new_line();
+        true
+    }
+
+    /// Prints a block with its statements on their own lines, one indent
+    /// deeper.
+    fn block(&mut self, statements: &[Expr], block: &Expr) {
+        let end = block.span.offset + block.span.len;
+        if statements.is_empty() && !self.has_comments_before(end) {
+            self.push("{}");
+            return;
+        }
+        self.push("{");
+        let first = statements.first().map_or(end, |first| first.span.offset);
+        self.trailing_comments(block.span.offset, self.line_of(block.span.offset), first);
+        let outer_line = self.last_line;
+        self.indent += 1;
+        self.statements(statements, end);
+        self.indent -= 1;
+        self.last_line = outer_line;
+        self.new_line();
+        self.push("}");
+    }
+
+    fn push(&mut self, text: &str) {
+        self.at_expr_start = false;
+        self.out.push_str(text);
+    }
+
+    fn new_line(&mut self) {
+        if !self.out.is_empty() {
+            self.out.push('\n');
+        }
+        self.out.push_str(&INDENT.repeat(self.indent));
+    }
+
+    /// Ends an `if`'s then branch before its `else`: on the same line,
+    /// unless comments come between them, which stay after the `}` they
+    /// followed or on their own lines.
+    fn before_else(&mut self, then_branch: &Expr, else_branch: &Expr) {
+        if !self.has_comments_before(else_branch.span.offset) {
+            self.push(" ");
+            return;
+        }
+        let then_end = then_branch.span.offset + then_branch.span.len;
+        self.last_line = self.line_of(then_end.saturating_sub(1));
+        self.trailing_comments(then_end, self.last_line, else_branch.span.offset);
+        self.comments_before(else_branch.span.offset);
+        self.new_line();
+    }
+
+    /// Whether a comment not yet printed starts before `offset`.
+    fn has_comments_before(&self, offset: usize) -> bool {
+        self.comments.get(self.next_comment).is_some_and(|comment| comment.span.offset < offset)
+    }
+
+    fn comments_before(&mut self, offset: usize) {
+        while let Some(comment) = self.comments.get(self.next_comment).filter(|c| c.span.offset < offset) {
+            self.next_comment += 1;
+            self.blank_line(comment.span.line);
+            self.new_line();
+            self.out.push_str(&comment.text);
+            self.last_line = comment.span.line;
+        }
+    }
+
+    /// Adds to what was just printed the comments left inside it, which
+    /// ends at `end`, and those after it on its last line, `line`, up to
+    /// `next`. The first shares the line; the rest, which it would swallow,
+    /// go on lines of their own.
+    fn trailing_comments(&mut self, end: usize, line: usize, next: usize) {
+        let mut first = true;
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            if comment.span.offset >= next || (comment.span.offset >= end && comment.span.line != line) {
+                break;
+            }
+            self.next_comment += 1;
+            if first {
+                self.out.push_str("  ");
+            } else {
+                self.new_line();
+            }
+            self.out.push_str(&comment.text);
+            first = false;
+        }
+    }
+
+    /// Keeps one blank line before something starting on `line` if the
+    /// source had any.
+    fn blank_line(&mut self, line: usize) {
+        if self.last_line > 0 && line > self.last_line + 1 {
+            self.out.push('\n');
+        }
+    }
+
+    fn line_of(&self, offset: usize) -> usize {
+        self.source[..offset.min(self.source.len())].matches('\n').count() + 1
+    }
+}
+
+/// Whether a statement is printed starting with `(` or `[`.
+fn opens_with_bracket(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Destructure { pattern, .. } => pattern_opens_with_bracket(pattern),
+        _ => expr_opens_with_bracket(expr, Place::Expr),
+    }
+}
+
+fn expr_opens_with_bracket(expr: &Expr, place: Place) -> bool {
+    if needs_parens(expr, place) {
+        return true;
+    }
+    match &expr.kind {
+        // Only reached at the start of a statement, where it is wrapped.
+        ExprKind::Map(entries) => entries.is_empty(),
+        ExprKind::List(_) | ExprKind::Tuple(_) => true,
+        ExprKind::Binary { left, op, .. } => expr_opens_with_bracket(left, Place::Operand(precedence(op))),
+        ExprKind::Index { object, .. } => expr_opens_with_bracket(object, Place::Operand(u8::MAX)),
+        ExprKind::Destructure { pattern, .. } => pattern_opens_with_bracket(pattern),
+        _ => false,
+    }
+}
+
+fn pattern_opens_with_bracket(pattern: &Pattern) -> bool {
+    match pattern {
+        Pattern::Variable(_, _) | Pattern::Map(_) => false,
+        Pattern::Index { object, .. } => expr_opens_with_bracket(object, Place::Operand(u8::MAX)),
+        // A statement's tuple pattern is written bare.
+        Pattern::Tuple(targets) => targets.len() < 2 || pattern_opens_with_bracket(&targets[0]),
+        Pattern::List { .. } => true,
+    }
+}
+
+/// Whether `expr` must be wrapped in parentheses at `place`.
+fn needs_parens(expr: &Expr, place: Place) -> bool {
+    match place {
+        // `{}` there starts a block, not a map.
+        Place::Expr => is_empty_map(expr),
+        Place::Value => false,
+        Place::Operand(min) => expr_precedence(expr) < min,
+    }
+}
+
+fn is_empty_map(expr: &Expr) -> bool {
+    matches!(&expr.kind, ExprKind::Map(entries) if entries.is_empty())
+}
+
+/// How tightly an expression binds: atoms and postfix expressions most, then
+/// `*` and `/`, then `+` and `-`, then comparisons, and anything else least.
+fn expr_precedence(expr: &Expr) -> u8 {
+    match &expr.kind {
+        ExprKind::Binary { op, .. } => precedence(op),
+        // `1.x` would read as a malformed number.
+        ExprKind::Number(_) => u8::MAX - 1,
+        ExprKind::StringLiteral(_)
+        | ExprKind::Bool(_)
+        | ExprKind::Nil
+        | ExprKind::Break
+        | ExprKind::Continue
+        | ExprKind::Variable(_, _)
+        | ExprKind::List(_)
+        | ExprKind::Tuple(_)
+        | ExprKind::Map(_)
+        | ExprKind::Index { .. }
+        | ExprKind::FunctionCall { .. } => u8::MAX,
+        _ => 0,
+    }
+}
+
+fn precedence(op: &Token) -> u8 {
+    match op {
+        Token::Star | Token::Slash => 10,
+        Token::Plus | Token::Minus => 5,
+        _ => 1,
+    }
+}
+
+fn operator(op: &Token) -> &'static str {
+    match op {
+        Token::Plus => "+",
+        Token::Minus => "-",
+        Token::Star => "*",
+        Token::Slash => "/",
+        Token::EqualEqual => "==",
+        Token::BangEqual => "!=",
+        Token::Less => "<",
+        Token::LessEqual => "<=",
+        Token::Greater => ">",
+        Token::GreaterEqual => ">=",
+        _ => "?",
+    }
+}
+
+fn string(s: &str, out: &mut String) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+fn map_key(key: &str, out: &mut String) {
+    if is_identifier(key) {
+        out.push_str(key);
+    } else {
+        string(key, out);
+    }
+}
+
+/// Whether `name` lexes as an identifier rather than a keyword.
+pub(crate) fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+        && !KEYWORDS.contains(&name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cst::SyntaxTree;
+
+    fn fmt(source: &str) -> String {
+        let tree = SyntaxTree::parse(source, "test.alin");
+        format(&tree.lower().unwrap(), &tree.comments(), source)
+    }
+
+    /// Formats `source`, checks formatting the result changes nothing, and
+    /// returns it.
+    fn fmt_twice(source: &str) -> String {
+        let once = fmt(source);
+        assert_eq!(fmt(&once), once, "formatting is not idempotent for:\n{}", source);
+        once
+    }
+
+    #[test]
+    fn canonical_style() {
+        let source = "fn add(a,b){return a+b}\nx=add(1,2)\n[a,b]=[x,2*(x+1)]\nif x>1{print(x)}else{print(0)}\n";
+        let expected = "\
+fn add(a, b) {
+    return a + b
+}
+x = add(1, 2);
+[a, b] = [x, 2 * (x + 1)]
+if x > 1 {
+    print(x)
+} else {
+    print(0)
+}
+";
+        assert_eq!(fmt_twice(source), expected);
+    }
+
+    #[test]
+    fn comments_between_statements() {
+        let source = "// header\n\nx = 1 // one\n\n\n// before y\ny = 2\n// end\n";
+        assert_eq!(fmt_twice(source), "// header\n\nx = 1  // one\n\n// before y\ny = 2\n// end\n");
+    }
+
+    #[test]
+    fn comments_in_a_collection_keep_it_multiline() {
+        let source = "x = [1, // one\n  2, // two\n  // before three\n  3]\nm = {a: 1, // a\n b: 2}\n";
+        let expected = "\
+x = [
+    1,  // one
+    2,  // two
+    // before three
+    3,
+]
+m = {
+    a: 1,  // a
+    b: 2,
+}
+";
+        assert_eq!(fmt_twice(source), expected);
+    }
+
+    #[test]
+    fn comments_in_calls_and_tuples() {
+        let source = "print(1, // first\n 2)\nt = (1, // only\n)\n";
+        assert_eq!(fmt_twice(source), "print(\n    1,  // first\n    2,\n)\nt = (\n    1,  // only\n)\n");
+    }
+
+    #[test]
+    fn comment_after_a_brace_stays_there() {
+        let source = "if x { // why\n  print(1)\n} // after if\nelse {\n  print(2)\n}\n";
+        let expected = "if x {  // why\n    print(1)\n}  // after if\nelse {\n    print(2)\n}\n";
+        assert_eq!(fmt_twice(source), expected);
+    }
+
+    #[test]
+    fn comment_on_its_own_line_before_else() {
+        let source = "if x {\n  print(1)\n}\n// otherwise\nelse { print(2) }\n";
+        let expected = "if x {\n    print(1)\n}\n// otherwise\nelse {\n    print(2)\n}\n";
+        assert_eq!(fmt_twice(source), expected);
+    }
+
+    #[test]
+    fn comments_inside_an_expression_are_not_merged() {
+        assert_eq!(fmt_twice("y = 1 + // a\n  2 + // b\n  3\n"), "y = 1 + 2 + 3  // a\n// b\n");
+    }
+
+    #[test]
+    fn idempotent() {
+        let source = "\
+// A bit of everything.
+fn gen(n) { i = 0 while i < n { yield i i = i + 1 } }
+fn f(xs, m) {
+  {a, b: c} = m // destructure
+  for x in gen(3) { if x == 1 { continue } print([x, ...xs]) }
+  return a, c
+}
+(p, q) = f([1, 2], {a: 'x', b: \"y\\n\"})
+[first, ...rest] = [p, q, {}]
+while true { break }
+";
+        fmt_twice(source);
+    }
+
+    #[test]
+    fn empty_map_starting_a_statement_is_wrapped() {
+        let source = "x = 1
+({}).y = 2
+if ({}) == nil { ({}) + 1 }
+";
+        assert_eq!(fmt_twice(source), "x = 1;
+({}).y = 2
+if ({}) == nil {
+    ({}) + 1
+}
+");
+    }
+}
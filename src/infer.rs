@@ -0,0 +1,922 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use crate::ast::{Expr, ExprKind, Pattern};
+use crate::diagnostic::Diagnostic;
+use crate::iter::contains_yield;
+use crate::token::{Span, Token};
+
+/// A type as seen by the inference pass. `Var` is an unknown that gets
+/// resolved through unification.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Type {
+    Number,
+    String,
+    Bool,
+    Nil,
+    Var(usize),
+    List(Box<Type>),
+    Tuple(Vec<Type>),
+    /// Maps are heterogeneous, so their values are not tracked.
+    Map,
+    Iterator(Box<Type>),
+    Function(Vec<Type>, Box<Type>),
+}
+
+/// A possibly polymorphic type: `vars` are quantified over `ty`.
+#[derive(Debug, Clone)]
+pub struct Scheme {
+    vars: Vec<usize>,
+    ty: Type,
+}
+
+impl Scheme {
+    fn mono(ty: Type) -> Self {
+        Self { vars: Vec::new(), ty }
+    }
+}
+
+/// A top-level name together with its inferred type, in declaration order.
+pub struct Signature {
+    pub name: String,
+    pub params: Option<Vec<String>>,
+    pub scheme: Scheme,
+}
+
+pub struct Inference {
+    pub signatures: Vec<Signature>,
+    pub errors: Vec<Diagnostic>,
+}
+
+pub struct Inferer {
+    substitution: Vec<Option<Type>>,
+    scopes: Vec<HashMap<String, Scheme>>,
+    params: HashMap<String, Vec<String>>,
+    order: Vec<String>,
+    returns: Vec<Type>,
+    yields: Vec<Type>,
+    /// Operand types that must end up as Number or String, with the operator
+    /// and the operation's span.
+    ordered: Vec<(Type, &'static str, Span)>,
+    errors: Vec<Diagnostic>,
+}
+
+impl Inferer {
+    pub fn new() -> Self {
+        Self {
+            substitution: Vec::new(),
+            scopes: vec![HashMap::new()],
+            params: HashMap::new(),
+            order: Vec::new(),
+            returns: Vec::new(),
+            yields: Vec::new(),
+            ordered: Vec::new(),
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn infer_program(mut self, exprs: &[Expr]) -> Inference {
+        for expr in exprs {
+            self.infer(expr);
+        }
+
+        // `+` and the ordering operators work on both numbers and strings, so
+        // they can only be checked once everything else is known.
+        for (ty, symbol, span) in std::mem::take(&mut self.ordered) {
+            match self.resolve(&ty) {
+                Type::Number | Type::String | Type::Var(_) => {}
+                other => {
+                    let found = self.display(&other);
+                    let message = format!("operator `{}` expects Number or String, found {}", symbol, found);
+                    let diagnostic = Diagnostic::error("E0406", message)
+                        .with_label(span, format!("operands are {}", found));
+                    self.errors.push(diagnostic);
+                }
+            }
+        }
+
+        let globals = self.scopes.remove(0);
+        let signatures = self
+            .order
+            .iter()
+            .map(|name| {
+                let scheme = &globals[name];
+                Signature {
+                    name: name.clone(),
+                    params: self.params.get(name).cloned(),
+                    scheme: Scheme {
+                        vars: scheme.vars.clone(),
+                        ty: self.resolve(&scheme.ty),
+                    },
+                }
+            })
+            .collect();
+
+        Inference {
+            signatures,
+            errors: self.errors,
+        }
+    }
+
+    fn infer(&mut self, expr: &Expr) -> Type {
+        match &expr.kind {
+            ExprKind::Number(_) => Type::Number,
+            ExprKind::Bool(_) => Type::Bool,
+            ExprKind::Nil => Type::Nil,
+            ExprKind::StringLiteral(_) => Type::String,
+
+            ExprKind::List(items) => {
+                let elem = self.fresh();
+                for item in items {
+                    let ty = self.infer(item);
+                    self.unify(&elem, &ty, "list element", &item.span);
+                }
+                Type::List(Box::new(elem))
+            }
+
+            ExprKind::Tuple(items) => Type::Tuple(items.iter().map(|item| self.infer(item)).collect()),
+
+            ExprKind::Map(entries) => {
+                for (_, value) in entries {
+                    self.infer(value);
+                }
+                Type::Map
+            }
+
+            ExprKind::Spread(inner) => {
+                let ty = self.infer(inner);
+                match self.resolve(&ty) {
+                    Type::List(elem) | Type::Iterator(elem) => *elem,
+                    _ => self.fresh(),
+                }
+            }
+
+            ExprKind::Index { object, index } => {
+                let object_ty = self.infer(object);
+                let index_ty = self.infer(index);
+                match self.resolve(&object_ty) {
+                    Type::Map => {
+                        self.unify(&Type::String, &index_ty, "map key", &index.span);
+                        self.fresh()
+                    }
+                    Type::Tuple(items) => match &index.kind {
+                        ExprKind::Number(i) if (*i as usize) < items.len() => items[*i as usize].clone(),
+                        _ => self.fresh(),
+                    },
+                    Type::List(elem) => {
+                        self.unify(&Type::Number, &index_ty, "index", &index.span);
+                        *elem
+                    }
+                    Type::String => {
+                        self.unify(&Type::Number, &index_ty, "index", &index.span);
+                        Type::String
+                    }
+                    Type::Var(_) => self.fresh(),
+                    other => {
+                        let found = self.display(&other);
+                        let diagnostic = Diagnostic::error("E0402", format!("cannot index into {}", found))
+                            .with_label(object.span.clone(), format!("this is {}", found));
+                        self.errors.push(diagnostic);
+                        self.fresh()
+                    }
+                }
+            }
+
+            ExprKind::Variable(name, _) => match self.lookup(name) {
+                Some(scheme) => self.instantiate(&scheme),
+                None => self.fresh(),
+            },
+
+            ExprKind::Assignment { name, expr, .. } => {
+                let ty = self.infer(expr);
+                self.bind(name, ty.clone(), "assignment to", &expr.span);
+                ty
+            }
+
+            ExprKind::Destructure { pattern, expr } => {
+                let ty = self.infer(expr);
+                self.infer_pattern(pattern, &ty, &expr.span);
+                ty
+            }
+
+            ExprKind::Binary { left, op, right } => {
+                let lhs = self.infer(left);
+                let rhs = self.infer(right);
+                let symbol = operator_symbol(op);
+                match op {
+                    Token::Plus => {
+                        self.unify(&lhs, &rhs, "operator `+`", &right.span);
+                        self.ordered.push((lhs.clone(), symbol, expr.span.clone()));
+                        lhs
+                    }
+                    // Values of any kinds may be compared for equality.
+                    Token::EqualEqual | Token::BangEqual => {
+                        self.try_unify(&lhs, &rhs);
+                        Type::Bool
+                    }
+                    Token::Less | Token::LessEqual | Token::Greater | Token::GreaterEqual => {
+                        let context = format!("operator `{}`", symbol);
+                        self.unify(&lhs, &rhs, &context, &right.span);
+                        self.ordered.push((lhs, symbol, expr.span.clone()));
+                        Type::Bool
+                    }
+                    _ => {
+                        for (operand, ty) in [(left, &lhs), (right, &rhs)] {
+                            let context = match &operand.kind {
+                                ExprKind::Variable(name, _) => {
+                                    format!("operator `{}` on variable `{}`", symbol, name)
+                                }
+                                _ => format!("operator `{}`", symbol),
+                            };
+                            self.unify(&Type::Number, ty, &context, &operand.span);
+                        }
+                        Type::Number
+                    }
+                }
+            }
+
+            ExprKind::Block(statements) => {
+                for stmt in statements {
+                    self.infer(stmt);
+                }
+                Type::Nil
+            }
+
+            ExprKind::If { condition, then_branch, else_branch } => {
+                self.infer(condition);
+                let then_ty = self.infer(then_branch);
+                match else_branch {
+                    Some(else_expr) => {
+                        let else_ty = self.infer(else_expr);
+                        // Branches are often plain statements, so only treat
+                        // the `if` as a typed expression when they agree.
+                        if self.try_unify(&then_ty, &else_ty) {
+                            then_ty
+                        } else {
+                            self.fresh()
+                        }
+                    }
+                    None => Type::Nil,
+                }
+            }
+
+            ExprKind::While { condition, body } => {
+                self.infer(condition);
+                self.infer(body);
+                Type::Nil
+            }
+
+            ExprKind::For { var, iterable, body, .. } => {
+                let iterable_ty = self.infer(iterable);
+                let elem = match self.resolve(&iterable_ty) {
+                    Type::List(elem) | Type::Iterator(elem) => *elem,
+                    Type::String | Type::Map => Type::String,
+                    Type::Var(_) | Type::Tuple(_) => self.fresh(),
+                    other => {
+                        let found = self.display(&other);
+                        let message = format!("`for` expects a List, Tuple, Map, Iterator or String, found {}", found);
+                        let diagnostic = Diagnostic::error("E0403", message)
+                            .with_label(iterable.span.clone(), format!("this is {}", found));
+                        self.errors.push(diagnostic);
+                        self.fresh()
+                    }
+                };
+                self.bind(var, elem, "loop variable", &iterable.span);
+                self.infer(body);
+                Type::Nil
+            }
+
+            ExprKind::FunctionDef { name, params, body, .. } => {
+                let param_types: Vec<Type> = params.iter().map(|_| self.fresh()).collect();
+                let ret = self.fresh();
+                let fn_ty = Type::Function(param_types.clone(), Box::new(ret.clone()));
+
+                // Bind the name before the body so recursive calls resolve.
+                self.declare(name, Scheme::mono(fn_ty.clone()));
+                if self.scopes.len() == 1 {
+                    self.params.insert(name.clone(), params.clone());
+                }
+
+                let mut locals = HashMap::new();
+                for (param, ty) in params.iter().zip(&param_types) {
+                    locals.insert(param.clone(), Scheme::mono(ty.clone()));
+                }
+                self.scopes.push(locals);
+                if contains_yield(body) {
+                    // A generator's call evaluates to an iterator over the
+                    // yielded values; `return` only ends it.
+                    let elem = self.fresh();
+                    self.unify(&ret, &Type::Iterator(Box::new(elem.clone())), "generator", &expr.span);
+                    let ignored = self.fresh();
+                    self.returns.push(ignored);
+                    self.yields.push(elem);
+                    self.infer(body);
+                    self.yields.pop();
+                } else {
+                    self.returns.push(ret.clone());
+                    self.yields.push(Type::Nil);
+                    self.infer(body);
+                    self.yields.pop();
+                    if !contains_return(body) {
+                        self.unify(&ret, &Type::Nil, "function without return", &expr.span);
+                    }
+                }
+                self.returns.pop();
+                self.scopes.pop();
+
+                // The monomorphic binding used for recursion must not pin the
+                // function's own type variables during generalization.
+                self.scopes.last_mut().unwrap().remove(name);
+                let scheme = self.generalize(&fn_ty);
+                self.declare(name, scheme);
+                Type::Nil
+            }
+
+            ExprKind::FunctionCall { name, args, .. } => {
+                let arg_types: Vec<Type> = args.iter().map(|arg| self.infer(arg)).collect();
+                if name == "print" {
+                    return Type::Nil;
+                }
+
+                let user_defined = self.lookup(name);
+                let is_builtin = user_defined.is_none();
+                let Some(scheme) = user_defined.or_else(|| builtin_scheme(name)) else {
+                    return self.fresh();
+                };
+                let callee = self.instantiate(&scheme);
+                match self.resolve(&callee) {
+                    // Builtins may take optional trailing arguments.
+                    Type::Function(params, ret) if is_builtin && params.len() > arg_types.len() => {
+                        for ((param, arg), arg_expr) in params.iter().zip(&arg_types).zip(args) {
+                            let context = format!("call to `{}`", name);
+                            self.unify(param, arg, &context, &arg_expr.span);
+                        }
+                        *ret
+                    }
+                    Type::Function(params, _) if params.len() != arg_types.len() => {
+                        let message = format!(
+                            "function `{}` expects {} argument(s), found {}",
+                            name,
+                            params.len(),
+                            arg_types.len()
+                        );
+                        let diagnostic = Diagnostic::error("E0404", message)
+                            .with_label(expr.span.clone(), format!("called with {}", arg_types.len()));
+                        self.errors.push(diagnostic);
+                        self.fresh()
+                    }
+                    _ => {
+                        let ret = self.fresh();
+                        let expected = Type::Function(arg_types, Box::new(ret.clone()));
+                        let context = format!("call to `{}`", name);
+                        self.unify(&callee, &expected, &context, &expr.span);
+                        ret
+                    }
+                }
+            }
+
+            ExprKind::Return(expr) => {
+                let ty = self.infer(expr);
+                if let Some(ret) = self.returns.last().cloned() {
+                    self.unify(&ret, &ty, "return value", &expr.span);
+                }
+                Type::Nil
+            }
+
+            ExprKind::Yield(expr) => {
+                let ty = self.infer(expr);
+                if let Some(elem) = self.yields.last().cloned() {
+                    self.unify(&elem, &ty, "yielded value", &expr.span);
+                }
+                Type::Nil
+            }
+
+            ExprKind::Break | ExprKind::Continue => Type::Nil,
+
+            // Already reported as a syntax error; anything goes.
+            ExprKind::Error => self.fresh(),
+        }
+    }
+
+    /// Assigns `ty`, the type of what `span` covers, to `name`, checking it
+    /// against any existing binding.
+    fn bind(&mut self, name: &str, ty: Type, what: &str, span: &Span) {
+        match self.lookup(name) {
+            Some(scheme) => {
+                let existing = self.instantiate(&scheme);
+                let context = format!("{} `{}`", what, name);
+                self.unify(&existing, &ty, &context, span);
+            }
+            None => self.declare(name, Scheme::mono(ty)),
+        }
+    }
+
+    /// Binds the targets of `pattern` to the parts of `ty`, the type of the
+    /// value at `span`.
+    fn infer_pattern(&mut self, pattern: &Pattern, ty: &Type, span: &Span) {
+        match pattern {
+            Pattern::Variable(name, _) => self.bind(name, ty.clone(), "assignment to", span),
+            Pattern::Index { object, index } => {
+                let kind = ExprKind::Index {
+                    object: object.clone(),
+                    index: index.clone(),
+                };
+                let target = Expr::new(kind, object.span.to(&index.span));
+                let existing = self.infer(&target);
+                self.unify(&existing, ty, "indexed assignment", span);
+            }
+            Pattern::Tuple(targets) => match self.resolve(ty) {
+                Type::List(elem) => {
+                    for target in targets {
+                        self.infer_pattern(target, &elem, span);
+                    }
+                }
+                Type::Tuple(items) if items.len() != targets.len() => {
+                    let message = format!("cannot unpack {} value(s) into {} targets", items.len(), targets.len());
+                    let diagnostic = Diagnostic::error("E0405", message)
+                        .with_label(span.clone(), format!("a tuple of {}", items.len()));
+                    self.errors.push(diagnostic);
+                }
+                _ => {
+                    let items: Vec<Type> = targets.iter().map(|_| self.fresh()).collect();
+                    self.unify(&Type::Tuple(items.clone()), ty, "destructuring", span);
+                    for (target, item) in targets.iter().zip(&items) {
+                        self.infer_pattern(target, item, span);
+                    }
+                }
+            },
+            Pattern::List { items, rest } => {
+                let elem = match self.resolve(ty) {
+                    Type::Tuple(types) if types.len() >= items.len() => {
+                        for (target, item) in items.iter().zip(&types) {
+                            self.infer_pattern(target, item, span);
+                        }
+                        if let Some((name, _)) = rest {
+                            let list = Type::List(Box::new(self.fresh()));
+                            self.bind(name, list, "assignment to", span);
+                        }
+                        return;
+                    }
+                    _ => self.fresh(),
+                };
+                self.unify(&Type::List(Box::new(elem.clone())), ty, "list destructuring", span);
+                for target in items {
+                    self.infer_pattern(target, &elem, span);
+                }
+                if let Some((name, _)) = rest {
+                    self.bind(name, Type::List(Box::new(elem)), "assignment to", span);
+                }
+            }
+            Pattern::Map(entries) => {
+                self.unify(&Type::Map, ty, "map destructuring", span);
+                for (_, target) in entries {
+                    let value = self.fresh();
+                    self.infer_pattern(target, &value, span);
+                }
+            }
+        }
+    }
+
+    fn fresh(&mut self) -> Type {
+        self.substitution.push(None);
+        Type::Var(self.substitution.len() - 1)
+    }
+
+    fn lookup(&self, name: &str) -> Option<Scheme> {
+        // Functions see their own locals first, then globals, matching how the
+        // evaluator resolves names.
+        let local = self.scopes.last().and_then(|scope| scope.get(name));
+        local.or_else(|| self.scopes[0].get(name)).cloned()
+    }
+
+    fn declare(&mut self, name: &str, scheme: Scheme) {
+        if self.scopes.len() == 1 && !self.order.iter().any(|n| n == name) {
+            self.order.push(name.to_string());
+        }
+        let scope = self.scopes.last_mut().unwrap();
+        scope.insert(name.to_string(), scheme);
+    }
+
+    fn resolve(&self, ty: &Type) -> Type {
+        match ty {
+            Type::Var(id) => match &self.substitution[*id] {
+                Some(bound) => self.resolve(bound),
+                None => ty.clone(),
+            },
+            Type::List(elem) => Type::List(Box::new(self.resolve(elem))),
+            Type::Iterator(elem) => Type::Iterator(Box::new(self.resolve(elem))),
+            Type::Tuple(items) => Type::Tuple(items.iter().map(|t| self.resolve(t)).collect()),
+            Type::Function(params, ret) => Type::Function(
+                params.iter().map(|p| self.resolve(p)).collect(),
+                Box::new(self.resolve(ret)),
+            ),
+            _ => ty.clone(),
+        }
+    }
+
+    /// Unifies, reporting a mismatch at `span`, the code whose type is
+    /// `found`.
+    fn unify(&mut self, expected: &Type, found: &Type, context: &str, span: &Span) {
+        if let Err((expected, found)) = self.unify_inner(expected, found) {
+            let (expected, found) = (self.display(&expected), self.display(&found));
+            let message = format!("{} expects {}, found {}", context, expected, found);
+            let diagnostic = Diagnostic::error("E0401", message)
+                .with_label(span.clone(), format!("expected {}, found {}", expected, found));
+            self.errors.push(diagnostic);
+        }
+    }
+
+    /// Unifies without reporting, rolling back any bindings on failure.
+    fn try_unify(&mut self, a: &Type, b: &Type) -> bool {
+        let snapshot = self.substitution.clone();
+        if self.unify_inner(a, b).is_ok() {
+            true
+        } else {
+            self.substitution = snapshot;
+            false
+        }
+    }
+
+    fn unify_inner(&mut self, a: &Type, b: &Type) -> Result<(), (Type, Type)> {
+        let a = self.resolve(a);
+        let b = self.resolve(b);
+        match (&a, &b) {
+            (Type::Var(x), Type::Var(y)) if x == y => Ok(()),
+            (Type::Var(id), other) | (other, Type::Var(id)) => {
+                if self.occurs(*id, other) {
+                    return Err((a.clone(), b.clone()));
+                }
+                self.substitution[*id] = Some(other.clone());
+                Ok(())
+            }
+            (Type::List(ea), Type::List(eb)) | (Type::Iterator(ea), Type::Iterator(eb)) => {
+                self.unify_inner(ea, eb).map_err(|_| (a.clone(), b.clone()))
+            }
+            (Type::Tuple(ia), Type::Tuple(ib)) if ia.len() == ib.len() => {
+                for (x, y) in ia.iter().zip(ib) {
+                    self.unify_inner(x, y).map_err(|_| (a.clone(), b.clone()))?;
+                }
+                Ok(())
+            }
+            (Type::Function(pa, ra), Type::Function(pb, rb)) if pa.len() == pb.len() => {
+                for (x, y) in pa.iter().zip(pb) {
+                    self.unify_inner(x, y).map_err(|_| (a.clone(), b.clone()))?;
+                }
+                self.unify_inner(ra, rb).map_err(|_| (a.clone(), b.clone()))
+            }
+            _ if a == b => Ok(()),
+            _ => Err((a, b)),
+        }
+    }
+
+    fn occurs(&self, id: usize, ty: &Type) -> bool {
+        match self.resolve(ty) {
+            Type::Var(other) => other == id,
+            Type::List(elem) | Type::Iterator(elem) => self.occurs(id, &elem),
+            Type::Tuple(items) => items.iter().any(|t| self.occurs(id, t)),
+            Type::Function(params, ret) => {
+                params.iter().any(|p| self.occurs(id, p)) || self.occurs(id, &ret)
+            }
+            _ => false,
+        }
+    }
+
+    fn instantiate(&mut self, scheme: &Scheme) -> Type {
+        let mapping: HashMap<usize, Type> = scheme
+            .vars
+            .iter()
+            .map(|var| (*var, self.fresh()))
+            .collect();
+        // Quantified variables are replaced before resolving, since a
+        // builtin's `Var(0)` is only a placeholder, not the program's first
+        // type variable.
+        self.resolve(&replace_vars(&scheme.ty, &mapping))
+    }
+
+    fn generalize(&self, ty: &Type) -> Scheme {
+        let ty = self.resolve(ty);
+        let mut env_vars = Vec::new();
+        for scope in &self.scopes {
+            for scheme in scope.values() {
+                free_vars(&self.resolve(&scheme.ty), &mut env_vars);
+            }
+        }
+
+        let mut vars = Vec::new();
+        free_vars(&ty, &mut vars);
+        vars.retain(|var| !env_vars.contains(var));
+        Scheme { vars, ty }
+    }
+
+    fn display(&self, ty: &Type) -> String {
+        TypeDisplay(&self.resolve(ty)).to_string()
+    }
+}
+
+/// Types of the builtins whose signatures fit the type system. `'a` is
+/// written as `Var(0)`.
+fn builtin_scheme(name: &str) -> Option<Scheme> {
+    use Type::{Number as N, String as S};
+    let list = |t: Type| Type::List(Box::new(t));
+    let (params, ret) = match name {
+        "string.len" => (vec![S], N),
+        "string.upper" | "string.lower" | "string.trim" => (vec![S], S),
+        "string.split" => (vec![S, S], list(S)),
+        "string.join" => (vec![list(Type::Var(0)), S], S),
+        "string.replace" => (vec![S, S, S], S),
+        "string.find" => (vec![S, S], N),
+        "string.starts_with" | "string.ends_with" | "string.contains" => (vec![S, S], Type::Bool),
+        "string.repeat" => (vec![S, N], S),
+        "string.chars" => (vec![S], list(S)),
+        "string.substring" => (vec![S, N, N], S),
+        "string.pad_left" | "string.pad_right" => (vec![S, N, S], S),
+        "math.abs" | "math.floor" | "math.ceil" | "math.round" | "math.sqrt" | "math.exp"
+        | "math.sin" | "math.cos" | "math.tan" | "math.asin" | "math.acos" | "math.atan"
+        | "math.log10" | "math.log2" => (vec![N], N),
+        "math.is_nan" | "math.is_finite" => (vec![N], Type::Bool),
+        "math.atan2" | "math.pow" | "math.log" => (vec![N, N], N),
+        "range" => (vec![N, N, N], Type::Iterator(Box::new(N))),
+        "fs.exists" => (vec![S], Type::Bool),
+        "now" | "clock" | "random.random" => (vec![], N),
+        "args" => (vec![], list(S)),
+        "exit" => (vec![N], Type::Var(0)),
+        "sleep" => (vec![N], Type::Nil),
+        "random.randint" => (vec![N, N], N),
+        "random.choice" => (vec![list(Type::Var(0))], Type::Var(0)),
+        "random.shuffle" => (vec![list(Type::Var(0))], Type::Nil),
+        _ => return None,
+    };
+    let ty = Type::Function(params, Box::new(ret));
+    let mut vars = Vec::new();
+    free_vars(&ty, &mut vars);
+    Some(Scheme { vars, ty })
+}
+
+fn replace_vars(ty: &Type, mapping: &HashMap<usize, Type>) -> Type {
+    match ty {
+        Type::Var(id) => mapping.get(id).cloned().unwrap_or(Type::Var(*id)),
+        Type::List(elem) => Type::List(Box::new(replace_vars(elem, mapping))),
+        Type::Iterator(elem) => Type::Iterator(Box::new(replace_vars(elem, mapping))),
+        Type::Tuple(items) => Type::Tuple(items.iter().map(|t| replace_vars(t, mapping)).collect()),
+        Type::Function(params, ret) => Type::Function(
+            params.iter().map(|p| replace_vars(p, mapping)).collect(),
+            Box::new(replace_vars(ret, mapping)),
+        ),
+        _ => ty.clone(),
+    }
+}
+
+fn free_vars(ty: &Type, out: &mut Vec<usize>) {
+    match ty {
+        Type::Var(id) if !out.contains(id) => out.push(*id),
+        Type::List(elem) | Type::Iterator(elem) => free_vars(elem, out),
+        Type::Tuple(items) => {
+            for item in items {
+                free_vars(item, out);
+            }
+        }
+        Type::Function(params, ret) => {
+            for param in params {
+                free_vars(param, out);
+            }
+            free_vars(ret, out);
+        }
+        _ => {}
+    }
+}
+
+fn contains_return(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Return(_) => true,
+        ExprKind::Block(statements) => statements.iter().any(contains_return),
+        ExprKind::If { then_branch, else_branch, .. } => {
+            contains_return(then_branch) || else_branch.as_deref().is_some_and(contains_return)
+        }
+        ExprKind::While { body, .. } | ExprKind::For { body, .. } => contains_return(body),
+        _ => false,
+    }
+}
+
+fn operator_symbol(op: &Token) -> &'static str {
+    match op {
+        Token::Plus => "+",
+        Token::Minus => "-",
+        Token::Star => "*",
+        Token::Slash => "/",
+        Token::EqualEqual => "==",
+        Token::BangEqual => "!=",
+        Token::Less => "<",
+        Token::LessEqual => "<=",
+        Token::Greater => ">",
+        Token::GreaterEqual => ">=",
+        _ => "?",
+    }
+}
+
+/// Prints type variables as `'a`, `'b`, ... in order of first appearance.
+struct TypeDisplay<'a>(&'a Type);
+
+impl fmt::Display for TypeDisplay<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut vars = Vec::new();
+        free_vars(self.0, &mut vars);
+        write_type(f, self.0, &vars)
+    }
+}
+
+fn write_type(f: &mut fmt::Formatter<'_>, ty: &Type, vars: &[usize]) -> fmt::Result {
+    match ty {
+        Type::Number => write!(f, "Number"),
+        Type::String => write!(f, "String"),
+        Type::Bool => write!(f, "Bool"),
+        Type::Nil => write!(f, "Nil"),
+        Type::Var(id) => write!(f, "{}", var_name(vars, *id)),
+        Type::List(elem) => {
+            write!(f, "[")?;
+            write_type(f, elem, vars)?;
+            write!(f, "]")
+        }
+        Type::Tuple(items) => {
+            write!(f, "(")?;
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write_type(f, item, vars)?;
+            }
+            write!(f, ")")
+        }
+        Type::Map => write!(f, "Map"),
+        Type::Iterator(elem) => {
+            write!(f, "Iterator[")?;
+            write_type(f, elem, vars)?;
+            write!(f, "]")
+        }
+        Type::Function(params, ret) => {
+            write!(f, "fn(")?;
+            for (i, param) in params.iter().enumerate() {
+                if i > 0 {
+                    write!(f, ", ")?;
+                }
+                write_type(f, param, vars)?;
+            }
+            write!(f, ") -> ")?;
+            write_type(f, ret, vars)
+        }
+    }
+}
+
+fn var_name(vars: &[usize], id: usize) -> String {
+    let index = vars.iter().position(|v| *v == id).unwrap_or(0);
+    let letter = (b'a' + (index % 26) as u8) as char;
+    if index < 26 {
+        format!("'{}", letter)
+    } else {
+        format!("'{}{}", letter, index / 26)
+    }
+}
+
+impl fmt::Display for Signature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ty = &self.scheme.ty;
+        let mut vars = Vec::new();
+        free_vars(ty, &mut vars);
+
+        match (ty, &self.params) {
+            (Type::Function(param_types, ret), Some(names)) => {
+                write!(f, "fn {}(", self.name)?;
+                for (i, (name, param)) in names.iter().zip(param_types).enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: ", name)?;
+                    write_type(f, param, &vars)?;
+                }
+                write!(f, ") -> ")?;
+                write_type(f, ret, &vars)
+            }
+            _ => {
+                write!(f, "{}: ", self.name)?;
+                write_type(f, ty, &vars)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    fn infer(source: &str) -> Inference {
+        let tokens = Lexer::new(source, "test.alin").tokenize().unwrap();
+        Inferer::new().infer_program(&Parser::new(tokens).parse().unwrap())
+    }
+
+    /// The signatures `alin check --show-types` prints for `source`, which
+    /// must type-check.
+    fn signatures(source: &str) -> Vec<String> {
+        let inference = infer(source);
+        let messages: Vec<&str> = inference.errors.iter().map(|error| error.message.as_str()).collect();
+        assert!(messages.is_empty(), "unexpected type errors: {:?}", messages);
+        inference.signatures.iter().map(|signature| signature.to_string()).collect()
+    }
+
+    /// The one error `source` has: its code, message and the line and column
+    /// of its primary label.
+    fn error(source: &str) -> (&'static str, String, (usize, usize)) {
+        let inference = infer(source);
+        let [error] = &inference.errors[..] else {
+            panic!("expected one error, found {:?}", inference.errors);
+        };
+        let label = error.labels.iter().find(|label| label.primary).unwrap();
+        (error.code, error.message.clone(), (label.span.line, label.span.column))
+    }
+
+    #[test]
+    fn infers_signatures() {
+        let source = "\
+fn add(a, b) { return a + b * 2 }
+fn greet(name) { return \"hi \" + name }
+fn count(n) { i = 0; while i < n { yield i; i = i + 1 } }
+fn noop() { }
+fn swap(x, y) { return (y, x) }
+xs = [1, 2]
+big = add(1, 2) > 2
+";
+        assert_eq!(
+            signatures(source),
+            [
+                "fn add(a: Number, b: Number) -> Number",
+                "fn greet(name: String) -> String",
+                "fn count(n: Number) -> Iterator[Number]",
+                "fn noop() -> Nil",
+                "fn swap(x: 'a, y: 'b) -> ('b, 'a)",
+                "xs: [Number]",
+                "big: Bool",
+            ]
+        );
+    }
+
+    #[test]
+    fn generalizes_functions_for_each_use() {
+        let source = "fn id(x) { return x }\na = id(1)\nb = id(\"s\")\nc = id([id(true)])\n";
+        assert_eq!(signatures(source), ["fn id(x: 'a) -> 'a", "a: Number", "b: String", "c: [Bool]"]);
+        // Inside its own body a function is monomorphic.
+        let (code, message, _) = error("fn f(x) { f(1); f(\"s\"); return x }\n");
+        assert_eq!((code, message.as_str()), ("E0401", "call to `f` expects fn(Number) -> 'a, found fn(String) -> 'a"));
+    }
+
+    #[test]
+    fn types_builtin_calls() {
+        let source = "\
+n = string.len(\"abc\")
+ok = string.contains(\"a\", \"b\")
+parts = string.split(\"a,b\", \",\")
+first = random.choice(parts)
+digits = random.choice([1, 2])
+padded = string.pad_left(\"7\", 3)
+";
+        assert_eq!(
+            signatures(source),
+            ["n: Number", "ok: Bool", "parts: [String]", "first: String", "digits: Number", "padded: String"]
+        );
+        let (code, message, at) = error("u = string.upper(1)\n");
+        assert_eq!(code, "E0401");
+        assert_eq!(message, "call to `string.upper` expects fn(String) -> String, found fn(Number) -> 'a");
+        assert_eq!(at, (1, 5));
+    }
+
+    #[test]
+    fn reports_type_errors_where_they_happen() {
+        let cases = [
+            ("x = 1\nx = \"s\"\n", "E0401", "assignment to `x` expects Number, found String", (2, 5)),
+            ("q = 5[0]\n", "E0402", "cannot index into Number", (1, 5)),
+            ("for i in 5 {}\n", "E0403", "`for` expects a List, Tuple, Map, Iterator or String, found Number", (1, 10)),
+            ("fn f(a) { return a }\nf(1, 2)\n", "E0404", "function `f` expects 1 argument(s), found 2", (2, 1)),
+            ("(c, d) = (1, 2, 3)\n", "E0405", "cannot unpack 3 value(s) into 2 targets", (1, 10)),
+            ("e = [1] < [2]\n", "E0406", "operator `<` expects Number or String, found [Number]", (1, 5)),
+            ("n = 1 - \"s\"\n", "E0401", "operator `-` expects Number, found String", (1, 9)),
+        ];
+        for (source, code, message, at) in cases {
+            assert_eq!(error(source), (code, message.to_string(), at), "for:\n{}", source);
+        }
+    }
+
+    #[test]
+    fn functions_see_their_own_locals_and_globals_only() {
+        // Assigning to a name with a global sets the global.
+        let (code, message, at) = error("y = 1\nfn g() { y = \"s\" }\n");
+        assert_eq!((code, message.as_str(), at), ("E0401", "assignment to `y` expects Number, found String", (2, 14)));
+        // A local of the same name elsewhere is a different variable.
+        assert_eq!(signatures("fn f() { y = 1; return y }\nfn g() { y = \"s\"; return y }\n"), [
+            "fn f() -> Number",
+            "fn g() -> String",
+        ]);
+        // A nested function can't see the enclosing function's locals (the
+        // resolver reports reading one), so nothing is known about `z`.
+        let source = "fn outer() { z = \"s\"; fn inner() { return z } return inner() }\n";
+        assert_eq!(signatures(source), ["fn outer() -> 'a"]);
+    }
+}
@@ -0,0 +1,314 @@
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::rc::Rc;
+
+use crate::ast::{Expr, ExprKind};
+use crate::eval::{is_truthy, Body, EvalResult, Evaluator, Frame, Function, Value};
+use crate::stdlib::io_error;
+use crate::vm;
+
+/// A source of values that `for` loops, `list()` and the higher-order
+/// builtins all pull from, one item at a time.
+#[derive(Debug)]
+pub enum Iter {
+    List { items: Rc<RefCell<Vec<Value>>>, index: usize },
+    Chars { chars: Vec<char>, index: usize },
+    Range { next: f64, end: f64, step: f64 },
+    /// Lines of a file read on demand; the reader is dropped after the last
+    /// line or the first error.
+    Lines { path: String, reader: Option<BufReader<File>> },
+    /// Lines of standard input, shared with the REPL's own reads.
+    Stdin,
+    Generator(Box<Generator>),
+    /// A generator function compiled to bytecode.
+    Compiled(Box<vm::Generator>),
+    Map { func: Value, source: Box<Iter> },
+    Filter { func: Value, source: Box<Iter> },
+    /// An iterator value shared between variables, so consuming it from one
+    /// place advances it everywhere.
+    Shared(Rc<RefCell<Iter>>),
+}
+
+/// The suspended state of a generator function call: its locals and the
+/// statements it still has to run, kept as an explicit stack so execution
+/// can stop at a `yield` and pick up there later.
+#[derive(Debug)]
+pub struct Generator {
+    func: Rc<Function>,
+    locals: Vec<Option<Value>>,
+    stack: Vec<Step>,
+}
+
+/// Statements are addressed by their child-index path from the function body.
+#[derive(Debug)]
+enum Step {
+    Statement(Vec<usize>),
+    Block { path: Vec<usize>, next: usize },
+    While(Vec<usize>),
+    For { path: Vec<usize>, iter: Iter },
+}
+
+impl Generator {
+    pub fn new(func: Rc<Function>, locals: Vec<Option<Value>>) -> Self {
+        Self {
+            func,
+            locals,
+            stack: vec![Step::Statement(Vec::new())],
+        }
+    }
+}
+
+/// Whether a function body contains `yield`, which makes it a generator.
+pub fn contains_yield(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Yield(_) => true,
+        ExprKind::Block(statements) => statements.iter().any(contains_yield),
+        ExprKind::If { then_branch, else_branch, .. } => {
+            contains_yield(then_branch) || else_branch.as_deref().is_some_and(contains_yield)
+        }
+        ExprKind::While { body, .. } | ExprKind::For { body, .. } => contains_yield(body),
+        _ => false,
+    }
+}
+
+fn node<'a>(root: &'a Expr, path: &[usize]) -> &'a Expr {
+    path.iter().fold(root, |expr, &i| match &expr.kind {
+        ExprKind::Block(statements) => &statements[i],
+        ExprKind::If { then_branch, else_branch, .. } => match (i, else_branch) {
+            (1, Some(else_expr)) => else_expr,
+            _ => then_branch,
+        },
+        ExprKind::While { body, .. } | ExprKind::For { body, .. } => body,
+        _ => expr,
+    })
+}
+
+/// Reads one line from stdin without its line ending; `None` at end of input.
+pub fn read_stdin_line() -> io::Result<Option<String>> {
+    read_line(&mut io::stdin().lock())
+}
+
+/// Reads one line from `reader` without its line ending; `None` at end of
+/// input.
+pub fn read_line(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    let len = line.trim_end_matches(['\n', '\r']).len();
+    line.truncate(len);
+    Ok(Some(line))
+}
+
+fn child(path: &[usize], index: usize) -> Vec<usize> {
+    let mut path = path.to_vec();
+    path.push(index);
+    path
+}
+
+impl Evaluator {
+    /// Turns a value into an iterator, or `None` if it cannot be iterated.
+    pub(crate) fn iterate(&mut self, value: Value) -> Option<Iter> {
+        match value {
+            Value::List(items) => Some(Iter::List { items, index: 0 }),
+            Value::Tuple(items) => Some(Iter::List {
+                items: Rc::new(RefCell::new(items.as_ref().clone())),
+                index: 0,
+            }),
+            Value::Map(entries) => Some(Iter::List {
+                items: Rc::new(RefCell::new(
+                    entries.borrow().keys().cloned().map(Value::String).collect(),
+                )),
+                index: 0,
+            }),
+            Value::String(s) => Some(Iter::Chars {
+                chars: s.chars().collect(),
+                index: 0,
+            }),
+            Value::Iterator(iter) => Some(Iter::Shared(iter)),
+            other => {
+                self.error_here("E0213", format!("a {} is not iterable", other.type_name()));
+                None
+            }
+        }
+    }
+
+    pub(crate) fn next_value(&mut self, iter: &mut Iter) -> Option<Value> {
+        match iter {
+            Iter::List { items, index } => {
+                let item = items.borrow().get(*index).cloned();
+                *index += 1;
+                item
+            }
+            Iter::Chars { chars, index } => {
+                let item = chars.get(*index).map(|c| Value::String(c.to_string()));
+                *index += 1;
+                item
+            }
+            Iter::Range { next, end, step } => {
+                let remaining = (*step > 0.0 && *next < *end) || (*step < 0.0 && *next > *end);
+                if !remaining {
+                    return None;
+                }
+                let item = Value::Number(*next);
+                *next += *step;
+                Some(item)
+            }
+            Iter::Lines { path, reader } => {
+                let mut line = String::new();
+                match reader.as_mut()?.read_line(&mut line) {
+                    Ok(0) => {
+                        *reader = None;
+                        None
+                    }
+                    Ok(_) => {
+                        let len = line.trim_end_matches(['\n', '\r']).len();
+                        line.truncate(len);
+                        Some(Value::String(line))
+                    }
+                    Err(err) => {
+                        *reader = None;
+                        Some(io_error(err, path))
+                    }
+                }
+            }
+            Iter::Stdin => match read_stdin_line() {
+                Ok(Some(line)) => Some(Value::String(line)),
+                Ok(None) => None,
+                Err(err) => Some(io_error(err, "<stdin>")),
+            },
+            Iter::Generator(generator) => self.resume(generator),
+            Iter::Compiled(generator) => self.resume_compiled(generator),
+            Iter::Map { func, source } => {
+                let item = self.next_value(source)?;
+                self.call_value(func, vec![item])
+            }
+            Iter::Filter { func, source } => loop {
+                let item = self.next_value(source)?;
+                if is_truthy(&self.call_value(func, vec![item.clone()])?) {
+                    return Some(item);
+                }
+            },
+            Iter::Shared(shared) => match shared.try_borrow_mut() {
+                Ok(mut inner) => self.next_value(&mut inner),
+                Err(_) => {
+                    self.error_here("E0214", "iterator is already running".to_string());
+                    None
+                }
+            },
+        }
+    }
+
+    /// Runs a generator until its next `yield`, or returns `None` once it
+    /// has finished.
+    fn resume(&mut self, generator: &mut Generator) -> Option<Value> {
+        let func = generator.func.clone();
+        let Body::Tree(body) = &func.body else {
+            return None;
+        };
+        // Resuming nests like a call, natively as well.
+        let site = self.site.clone();
+        if !self.check_depth(&func, &site) {
+            return None;
+        }
+        self.frames.push(Frame {
+            function: func.clone(),
+            call_site: self.site.clone(),
+            locals: std::mem::take(&mut generator.locals),
+        });
+        let result = self.run_steps(body, &mut generator.stack);
+        generator.locals = self.frames.pop().map(|frame| frame.locals).unwrap_or_default();
+        result
+    }
+
+    fn run_steps(&mut self, body: &Expr, stack: &mut Vec<Step>) -> Option<Value> {
+        while let Some(step) = stack.pop() {
+            match step {
+                Step::Statement(path) => match &node(body, &path).kind {
+                    ExprKind::Yield(expr) => match self.eval(expr) {
+                        EvalResult::Value(Some(val)) => return Some(val),
+                        _ => break,
+                    },
+                    ExprKind::Block(_) => stack.push(Step::Block { path, next: 0 }),
+                    ExprKind::If { condition, else_branch, .. } => {
+                        let Some(cond) = self.eval_value(condition) else {
+                            break;
+                        };
+                        if is_truthy(&cond) {
+                            stack.push(Step::Statement(child(&path, 0)));
+                        } else if else_branch.is_some() {
+                            stack.push(Step::Statement(child(&path, 1)));
+                        }
+                    }
+                    ExprKind::While { .. } => stack.push(Step::While(path)),
+                    ExprKind::For { iterable, .. } => {
+                        let Some(value) = self.eval_value(iterable) else {
+                            break;
+                        };
+                        let Some(iter) = self.at(&iterable.span, |ev| ev.iterate(value)) else {
+                            break;
+                        };
+                        stack.push(Step::For { path, iter });
+                    }
+                    ExprKind::Break => {
+                        while let Some(step) = stack.pop() {
+                            if matches!(step, Step::While(_) | Step::For { .. }) {
+                                break;
+                            }
+                        }
+                    }
+                    ExprKind::Continue => {
+                        while !matches!(stack.last(), None | Some(Step::While(_) | Step::For { .. })) {
+                            stack.pop();
+                        }
+                    }
+                    ExprKind::Return(expr) => {
+                        self.eval(expr);
+                        break;
+                    }
+                    _ => {
+                        let statement = node(body, &path);
+                        if let EvalResult::Value(None) | EvalResult::Return(_) | EvalResult::TailCall(..) = self.eval(statement) {
+                            break;
+                        }
+                    }
+                },
+                Step::Block { path, next } => {
+                    if let ExprKind::Block(statements) = &node(body, &path).kind {
+                        if next < statements.len() {
+                            let statement = child(&path, next);
+                            stack.push(Step::Block { path, next: next + 1 });
+                            stack.push(Step::Statement(statement));
+                        }
+                    }
+                }
+                Step::While(path) => {
+                    let ExprKind::While { condition, .. } = &node(body, &path).kind else {
+                        break;
+                    };
+                    if self.eval_value(condition).as_ref().is_some_and(is_truthy) {
+                        let statement = child(&path, 0);
+                        stack.push(Step::While(path));
+                        stack.push(Step::Statement(statement));
+                    }
+                }
+                Step::For { path, mut iter } => {
+                    let ExprKind::For { var, slot, iterable, .. } = &node(body, &path).kind else {
+                        break;
+                    };
+                    if let Some(item) = self.at(&iterable.span, |ev| ev.next_value(&mut iter)) {
+                        self.assign(var, *slot, item);
+                        let statement = child(&path, 0);
+                        stack.push(Step::For { path, iter });
+                        stack.push(Step::Statement(statement));
+                    }
+                }
+            }
+        }
+
+        // Finished (or failed): make sure a later resume does nothing.
+        stack.clear();
+        None
+    }
+}
@@ -1,22 +1,70 @@
-use crate::token::Token;
+use std::rc::Rc;
+
+use crate::diagnostic::Diagnostic;
+use crate::error::AlinError;
+use crate::token::{Comment, Span, SpannedToken, Token};
 
 pub struct Lexer {
     input: Vec<char>,
     pos: usize,
+    file: Rc<str>,
+    /// Byte offset, line and column of `pos`, for spans.
+    offset: usize,
+    line: usize,
+    column: usize,
+    diagnostics: Vec<Diagnostic>,
+    comments: Vec<Comment>,
 }
 
 impl Lexer {
-    pub fn new(source: &str) -> Self {
+    /// `file` names the source in spans, such as a path or `<repl>`.
+    pub fn new(source: &str, file: &str) -> Self {
         Self {
             input: source.chars().collect(),
             pos: 0,
+            file: file.into(),
+            offset: 0,
+            line: 1,
+            column: 1,
+            diagnostics: Vec::new(),
+            comments: Vec::new(),
+        }
+    }
+
+    /// Warnings found by a successful `tokenize`.
+    pub fn warnings(&self) -> &[Diagnostic] {
+        &self.diagnostics
+    }
+
+    /// The comments found by `tokenize`, in order.
+    pub fn comments(&self) -> &[Comment] {
+        &self.comments
+    }
+
+    /// Splits the source into tokens ending with `Eof`, or fails with every
+    /// diagnostic found if any of them is an error.
+    pub fn tokenize(&mut self) -> Result<Vec<SpannedToken>, AlinError> {
+        let tokens = self.lex();
+        if self.diagnostics.iter().any(Diagnostic::is_error) {
+            return Err(AlinError::Lex(std::mem::take(&mut self.diagnostics)));
         }
+        Ok(tokens)
+    }
+
+    /// Splits the source into tokens even if some are invalid, returning
+    /// every diagnostic found. An invalid number still becomes a token, so
+    /// tools see all of the source.
+    pub fn tokenize_partial(&mut self) -> (Vec<SpannedToken>, Vec<Diagnostic>) {
+        let tokens = self.lex();
+        (tokens, std::mem::take(&mut self.diagnostics))
     }
 
-    pub fn tokenize(&mut self) -> Vec<Token> {
+    fn lex(&mut self) -> Vec<SpannedToken> {
         let mut tokens = Vec::new();
+        let mut spans = Vec::new();
 
         while let Some(ch) = self.peek_char() {
+            let start = self.span();
             match ch {
                 c if c.is_whitespace() => {
                     self.next_char();
@@ -31,8 +79,9 @@ impl Lexer {
                 }
 
                 '"' | '\'' => {
+                    let start = self.span();
                     let quote = self.next_char().unwrap();
-                    tokens.push(self.lex_string(quote));
+                    tokens.push(self.lex_string(quote, start));
                 }
 
                 '+' => {
@@ -53,7 +102,7 @@ impl Lexer {
                 '/' => {
                     self.next_char();
                     if self.peek_char() == Some('/') {
-                        self.skip_line_comment();
+                        self.lex_line_comment(start.clone());
                     } else {
                         tokens.push(Token::Slash);
                     }
@@ -75,7 +124,10 @@ impl Lexer {
                         self.next_char();
                         tokens.push(Token::BangEqual);
                     } else {
-                        println!("Lexer warning: unexpected '!' without '='");
+                        let diagnostic = Diagnostic::warning("W0001", "unexpected `!` without `=`")
+                            .with_label(start.to(&self.span()), "ignored")
+                            .with_note("there is no `!` operator; use `== 0` or `== false` to negate");
+                        self.diagnostics.push(diagnostic);
                     }
                 }
 
@@ -119,25 +171,74 @@ impl Lexer {
                     tokens.push(Token::RBrace);
                 }
 
+                '[' => {
+                    self.next_char();
+                    tokens.push(Token::LBracket);
+                }
+
+                ']' => {
+                    self.next_char();
+                    tokens.push(Token::RBracket);
+                }
+
                 ',' => {
                     self.next_char();
                     tokens.push(Token::Comma);
                 }
 
+                ':' => {
+                    self.next_char();
+                    tokens.push(Token::Colon);
+                }
+
+                '.' if self.input.get(self.pos..self.pos + 3) == Some(&['.', '.', '.'][..]) => {
+                    for _ in 0..3 {
+                        self.next_char();
+                    }
+                    tokens.push(Token::Ellipsis);
+                }
+
+                '.' => {
+                    self.next_char();
+                    tokens.push(Token::Dot);
+                }
+
                 ';' => {
                     self.next_char();
                     tokens.push(Token::Semicolon);
                 }
 
                 _ => {
-                    println!("Lexer warning: unknown character '{}'", ch);
                     self.next_char();
+                    let diagnostic = Diagnostic::warning("W0002", format!("unknown character `{}`", ch))
+                        .with_label(start.to(&self.span()), "ignored");
+                    self.diagnostics.push(diagnostic);
                 }
             }
+
+            if spans.len() < tokens.len() {
+                spans.push(start.to(&self.span()));
+            }
         }
 
         tokens.push(Token::Eof);
+        spans.push(self.span());
         tokens
+            .into_iter()
+            .zip(spans)
+            .map(|(token, span)| SpannedToken { token, span })
+            .collect()
+    }
+
+    /// An empty span at the current position.
+    fn span(&self) -> Span {
+        Span {
+            file: self.file.clone(),
+            offset: self.offset,
+            len: 0,
+            line: self.line,
+            column: self.column,
+        }
     }
 
     fn lex_identifier_or_keyword(&mut self) -> Token {
@@ -155,15 +256,22 @@ impl Lexer {
             "if" => Token::If,
             "else" => Token::Else,
             "while" => Token::While,
+            "for" => Token::For,
+            "in" => Token::In,
             "fn" => Token::Fn,
             "return" => Token::Return,
             "break" => Token::Break,
             "continue" => Token::Continue,
+            "yield" => Token::Yield,
+            "true" => Token::True,
+            "false" => Token::False,
+            "nil" => Token::Nil,
             _ => Token::Identifier(ident),
         }
     }
 
     fn lex_number(&mut self) -> Token {
+        let start = self.span();
         let mut num = String::new();
         while let Some(c) = self.peek_char() {
             if c.is_ascii_digit() || c == '.' {
@@ -177,16 +285,28 @@ impl Lexer {
         match num.parse::<f64>() {
             Ok(n) => Token::Number(n),
             Err(_) => {
-                println!("Lexer error: invalid number '{}'", num);
+                let diagnostic = Diagnostic::error("E0001", format!("invalid number `{}`", num))
+                    .with_label(start.to(&self.span()), "not a valid number")
+                    .with_note("a number has at most one decimal point");
+                self.diagnostics.push(diagnostic);
                 Token::Number(0.0)
             }
         }
     }
 
-    fn lex_string(&mut self, delimiter: char) -> Token {
+    /// Reads a string whose opening quote, `delimiter`, was at `start`.
+    fn lex_string(&mut self, delimiter: char, start: Span) -> Token {
+        let quote = start.to(&self.span());
         let mut result = String::new();
 
-        while let Some(c) = self.peek_char() {
+        loop {
+            let Some(c) = self.peek_char() else {
+                let diagnostic = Diagnostic::error("E0002", "unterminated string")
+                    .with_label(quote, "this string is never closed")
+                    .with_note(format!("add a closing `{}`", delimiter));
+                self.diagnostics.push(diagnostic);
+                break;
+            };
             match c {
                 ch if ch == delimiter => {
                     self.next_char(); // consume closing quote
@@ -215,13 +335,17 @@ impl Lexer {
         Token::String(result)
     }
 
-    fn skip_line_comment(&mut self) {
-        while let Some(c) = self.peek_char() {
+    /// Reads the rest of a comment whose first `/` was at `start`, leaving
+    /// the line ending.
+    fn lex_line_comment(&mut self, start: Span) {
+        let mut text = String::from("/");
+        while let Some(c) = self.peek_char().filter(|&c| c != '\n') {
+            text.push(c);
             self.next_char();
-            if c == '\n' {
-                break;
-            }
         }
+        let text = text.trim_end().to_string();
+        let span = Span { len: text.len(), ..start };
+        self.comments.push(Comment { text, span });
     }
 
     fn peek_char(&self) -> Option<char> {
@@ -231,6 +355,39 @@ impl Lexer {
     fn next_char(&mut self) -> Option<char> {
         let ch = self.peek_char();
         self.pos += 1;
+        if let Some(c) = ch {
+            self.offset += c.len_utf8();
+            if c == '\n' {
+                self.line += 1;
+                self.column = 1;
+            } else {
+                self.column += 1;
+            }
+        }
         ch
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unterminated_string_fails_at_its_opening_quote() {
+        let Err(AlinError::Lex(diagnostics)) = Lexer::new("x = 1\ny = 'abc\n", "test.alin").tokenize() else {
+            panic!("an unterminated string should fail lexing");
+        };
+        assert_eq!(diagnostics.len(), 1);
+        let diagnostic = &diagnostics[0];
+        assert_eq!(diagnostic.code, "E0002");
+        let span = &diagnostic.labels[0].span;
+        assert_eq!((span.line, span.column, span.offset, span.len), (2, 5, 10, 1));
+    }
+
+    #[test]
+    fn terminated_strings_lex() {
+        let tokens = Lexer::new(r#""a\"b" 'c'"#, "test.alin").tokenize().unwrap();
+        let strings: Vec<_> = tokens.into_iter().map(|token| token.token).collect();
+        assert!(matches!(&strings[..], [Token::String(a), Token::String(c), ..] if a == "a\"b" && c == "c"));
+    }
+}
@@ -0,0 +1,545 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::ast::{Expr, ExprKind, Pattern};
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::eval::Value;
+use crate::resolve::bindings;
+use crate::stdlib::{toml, Builtins};
+use crate::token::{Comment, Span, SpannedToken, Token};
+
+/// The file that configures a project, looked for beside a script and in
+/// the directories above it.
+pub const PROJECT_FILE: &str = "alin.toml";
+
+/// How seriously a rule's findings are taken.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Level {
+    Allow,
+    Warn,
+    Error,
+}
+
+impl Level {
+    fn parse(name: &str) -> Option<Level> {
+        match name {
+            "allow" => Some(Level::Allow),
+            "warn" => Some(Level::Warn),
+            "error" => Some(Level::Error),
+            _ => None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Level::Allow => "allow",
+            Level::Warn => "warn",
+            Level::Error => "error",
+        }
+    }
+}
+
+/// A check the linter makes. Its code is stable; its name is what project
+/// files and `// alin-ignore` comments use, though both are accepted.
+#[derive(Debug)]
+pub struct Rule {
+    pub code: &'static str,
+    pub name: &'static str,
+    pub level: Level,
+}
+
+static UNUSED_VARIABLE: Rule = Rule { code: "L0001", name: "unused-variable", level: Level::Warn };
+static UNREACHABLE_CODE: Rule = Rule { code: "L0002", name: "unreachable-code", level: Level::Warn };
+static SHADOWING: Rule = Rule { code: "L0003", name: "shadowing", level: Level::Warn };
+static ASSIGNMENT_IN_CONDITION: Rule = Rule { code: "L0004", name: "assignment-in-condition", level: Level::Warn };
+static NIL_COMPARISON: Rule = Rule { code: "L0005", name: "nil-comparison", level: Level::Warn };
+static EMPTY_BLOCK: Rule = Rule { code: "L0006", name: "empty-block", level: Level::Warn };
+
+pub static RULES: [&Rule; 6] =
+    [&UNUSED_VARIABLE, &UNREACHABLE_CODE, &SHADOWING, &ASSIGNMENT_IN_CONDITION, &NIL_COMPARISON, &EMPTY_BLOCK];
+
+/// The levels a project sets for rules in the `[lint]` table of its project
+/// file, such as `shadowing = "allow"`.
+#[derive(Debug, Default)]
+pub struct Config {
+    levels: HashMap<&'static str, Level>,
+    /// The project file the levels came from.
+    file: Option<PathBuf>,
+}
+
+impl Config {
+    /// The configuration in the nearest project file at or above the
+    /// directory of `script`, or the defaults if there is none.
+    pub fn find(script: &Path) -> Result<Config, String> {
+        let script = fs::canonicalize(script).unwrap_or_else(|_| script.to_path_buf());
+        for dir in script.ancestors().skip(1) {
+            let file = dir.join(PROJECT_FILE);
+            if let Ok(text) = fs::read_to_string(&file) {
+                let levels = Self::parse(&text).map_err(|message| format!("{}: {}", file.display(), message))?;
+                return Ok(Config { levels, file: Some(file) });
+            }
+        }
+        Ok(Config::default())
+    }
+
+    fn parse(text: &str) -> Result<HashMap<&'static str, Level>, String> {
+        let root = toml::read(text)?;
+        let mut levels = HashMap::new();
+        let Some(lint) = root.get("lint") else {
+            return Ok(levels);
+        };
+        let Value::Map(table) = lint else {
+            return Err("`lint` must be a table".to_string());
+        };
+        for (key, value) in table.borrow().iter() {
+            let Some(rule) = RULES.iter().find(|rule| rule.name == key || rule.code == key) else {
+                return Err(format!("unknown lint rule `{}`", key));
+            };
+            let level = match value {
+                Value::String(level) => Level::parse(level),
+                _ => None,
+            };
+            let Some(level) = level else {
+                return Err(format!("`{}` must be \"allow\", \"warn\" or \"error\"", key));
+            };
+            levels.insert(rule.code, level);
+        }
+        Ok(levels)
+    }
+
+    fn level(&self, rule: &Rule) -> Level {
+        self.levels.get(rule.code).copied().unwrap_or(rule.level)
+    }
+
+    /// Why `rule` is at its level, for the note on each finding.
+    fn origin(&self, rule: &Rule) -> String {
+        match (self.levels.get(rule.code), &self.file) {
+            (Some(level), Some(file)) => format!("`{}` is set to `{}` in {}", rule.name, level.name(), file.display()),
+            _ => format!("`{}` is on by default", rule.name),
+        }
+    }
+}
+
+/// Checks a parsed script, given with the tokens it was parsed from,
+/// against every rule `config` doesn't allow, leaving out findings that
+/// `// alin-ignore` comments suppress. Findings come in source order.
+pub fn lint(
+    exprs: &[Expr],
+    source: &str,
+    tokens: &[SpannedToken],
+    comments: &[Comment],
+    config: &Config,
+    builtins: &Builtins,
+) -> Vec<Diagnostic> {
+    let mut linter = Linter {
+        tokens,
+        config,
+        builtins,
+        globals: HashMap::new(),
+        global_reads: HashSet::new(),
+        found: Vec::new(),
+    };
+    linter.script(exprs);
+    exprs.iter().for_each(|expr| linter.walk(expr));
+
+    let ignored = ignored(comments, source);
+    let mut found: Vec<_> = linter
+        .found
+        .into_iter()
+        .filter(|(rule, diagnostic)| {
+            let line = diagnostic.labels.first().map_or(0, |label| label.span.line);
+            match ignored.get(&line) {
+                Some(None) => false,
+                Some(Some(names)) => !names.iter().any(|name| name == rule.name || name == rule.code),
+                None => true,
+            }
+        })
+        .map(|(_, diagnostic)| diagnostic)
+        .collect();
+    found.sort_by_key(|diagnostic| diagnostic.labels.first().map_or(0, |label| label.span.offset));
+    found
+}
+
+struct Linter<'a> {
+    tokens: &'a [SpannedToken],
+    config: &'a Config,
+    builtins: &'a Builtins,
+    /// Where top-level names are first bound.
+    globals: HashMap<String, Span>,
+    /// Names read anywhere they refer to a global.
+    global_reads: HashSet<String>,
+    found: Vec<(&'static Rule, Diagnostic)>,
+}
+
+impl Linter<'_> {
+    /// Checks the names the top level and each function bind.
+    fn script(&mut self, exprs: &[Expr]) {
+        let mut reads = HashSet::new();
+        let mut functions = Vec::new();
+        for expr in exprs {
+            scan(expr, &mut reads, &mut functions);
+            for (name, span) in bindings(expr) {
+                self.globals.entry(name).or_insert(span);
+            }
+        }
+        for function in &functions {
+            self.function(function);
+        }
+        self.global_reads.extend(reads);
+
+        let globals: Vec<_> = self.globals.iter().map(|(name, span)| (name.clone(), span.clone())).collect();
+        for (name, span) in globals {
+            self.shadowed_builtin(&name, &span);
+            if !self.global_reads.contains(&name) && !defines(&functions, &name) {
+                self.unused(&name, &span);
+            }
+        }
+    }
+
+    fn function(&mut self, function: &Expr) {
+        let ExprKind::FunctionDef { params, body, .. } = &function.kind else {
+            return;
+        };
+        let mut reads = HashSet::new();
+        let mut functions = Vec::new();
+        scan(body, &mut reads, &mut functions);
+        for nested in &functions {
+            self.function(nested);
+        }
+
+        // Assigning to a name that is already global assigns the global.
+        let locals: Vec<_> = bindings(body)
+            .into_iter()
+            .filter(|(name, _)| !self.globals.contains_key(name) && !params.contains(name))
+            .collect();
+        for name in &reads {
+            if !params.contains(name) && !locals.iter().any(|(local, _)| local == name) {
+                self.global_reads.insert(name.clone());
+            }
+        }
+
+        let params_start = self.tokens[self.token_index(function.span.offset)..]
+            .iter()
+            .find(|token| token.token == Token::LParen)
+            .map_or(function.span.offset, |token| token.span.offset);
+        for param in params {
+            let span = self.name_span(&function.span, params_start, param);
+            if let Some(global) = self.globals.get(param).cloned() {
+                let diagnostic = Diagnostic::warning(SHADOWING.code, format!("parameter `{}` shadows a global", param))
+                    .with_label(span.clone(), "hides the global inside this function")
+                    .with_secondary(global, "global defined here");
+                self.report(&SHADOWING, diagnostic);
+            }
+            self.shadowed_builtin(param, &span);
+        }
+        for (name, span) in locals {
+            self.shadowed_builtin(&name, &span);
+            if !reads.contains(&name) && !defines(&functions, &name) {
+                self.unused(&name, &span);
+            }
+        }
+    }
+
+    fn unused(&mut self, name: &str, binding: &Span) {
+        if name.starts_with('_') {
+            return;
+        }
+        let span = self.name_span(binding, binding.offset, name);
+        let diagnostic = Diagnostic::warning(UNUSED_VARIABLE.code, format!("unused variable `{}`", name))
+            .with_label(span, "assigned but never read")
+            .with_note(format!("if this is intentional, name it `_{}`", name));
+        self.report(&UNUSED_VARIABLE, diagnostic);
+    }
+
+    fn shadowed_builtin(&mut self, name: &str, binding: &Span) {
+        let kind = if self.builtins.get(name).is_some() {
+            "function"
+        } else if self.builtins.module(name).is_some() {
+            "module"
+        } else {
+            return;
+        };
+        let span = self.name_span(binding, binding.offset, name);
+        let diagnostic = Diagnostic::warning(SHADOWING.code, format!("`{}` shadows the builtin {}", name, kind))
+            .with_label(span, format!("the builtin `{}` can't be used while this is defined", name));
+        self.report(&SHADOWING, diagnostic);
+    }
+
+    /// Makes the checks that look at one expression at a time.
+    fn walk(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Block(statements) => {
+                if statements.is_empty() {
+                    let diagnostic = Diagnostic::warning(EMPTY_BLOCK.code, "empty block")
+                        .with_label(expr.span.clone(), "does nothing")
+                        .with_note("an empty block does nothing and evaluates to nil");
+                    self.report(&EMPTY_BLOCK, diagnostic);
+                }
+                let exit = statements.iter().position(|statement| {
+                    matches!(statement.kind, ExprKind::Break | ExprKind::Continue | ExprKind::Return(_))
+                });
+                if let Some(exit) = exit.filter(|&exit| exit + 1 < statements.len()) {
+                    let (first, last) = (&statements[exit + 1], &statements[statements.len() - 1]);
+                    let diagnostic = Diagnostic::warning(UNREACHABLE_CODE.code, "unreachable code")
+                        .with_label(first.span.to(&last.span), "never runs")
+                        .with_secondary(statements[exit].span.clone(), "everything after this is skipped");
+                    self.report(&UNREACHABLE_CODE, diagnostic);
+                }
+            }
+            ExprKind::If { condition, .. } | ExprKind::While { condition, .. } => {
+                if let ExprKind::Assignment { .. } | ExprKind::Destructure { .. } = condition.kind {
+                    let diagnostic = Diagnostic::warning(ASSIGNMENT_IN_CONDITION.code, "assignment used as a condition")
+                        .with_label(condition.span.clone(), "this assigns rather than compares")
+                        .with_note("use `==` to compare");
+                    self.report(&ASSIGNMENT_IN_CONDITION, diagnostic);
+                }
+            }
+            ExprKind::Binary { left, op: op @ (Token::EqualEqual | Token::BangEqual), right }
+                if matches!(left.kind, ExprKind::Nil) || matches!(right.kind, ExprKind::Nil) =>
+            {
+                let diagnostic = Diagnostic::warning(NIL_COMPARISON.code, format!("comparison with `nil` using {}", op))
+                    .with_label(expr.span.clone(), "")
+                    .with_note("failed operations return an error value rather than nil; check with `is_error(x)`");
+                self.report(&NIL_COMPARISON, diagnostic);
+            }
+            _ => {}
+        }
+        children(expr).into_iter().for_each(|child| self.walk(child));
+    }
+
+    /// Records a finding at the level `config` gives its rule.
+    fn report(&mut self, rule: &'static Rule, mut diagnostic: Diagnostic) {
+        diagnostic.severity = match self.config.level(rule) {
+            Level::Allow => return,
+            Level::Warn => Severity::Warning,
+            Level::Error => Severity::Error,
+        };
+        diagnostic = diagnostic.with_note(self.config.origin(rule));
+        self.found.push((rule, diagnostic));
+    }
+
+    /// The first identifier token naming `name` in `within`, at or after
+    /// byte `from`, falling back on all of `within`. Strings and comments
+    /// that mention the name are other tokens, or none at all.
+    fn name_span(&self, within: &Span, from: usize, name: &str) -> Span {
+        let end = within.offset + within.len;
+        self.tokens[self.token_index(from.max(within.offset))..]
+            .iter()
+            .take_while(|token| token.span.offset < end)
+            .find(|token| matches!(&token.token, Token::Identifier(identifier) if identifier == name))
+            .map_or_else(|| within.clone(), |token| token.span.clone())
+    }
+
+    /// The index of the first token at or after byte `offset`.
+    fn token_index(&self, offset: usize) -> usize {
+        self.tokens.partition_point(|token| token.span.offset < offset)
+    }
+}
+
+/// Collects the names `expr` reads and the functions it defines, without
+/// looking inside those functions.
+fn scan<'e>(expr: &'e Expr, reads: &mut HashSet<String>, functions: &mut Vec<&'e Expr>) {
+    match &expr.kind {
+        ExprKind::Variable(name, _) => {
+            reads.insert(name.clone());
+        }
+        ExprKind::FunctionCall { name, .. } => {
+            reads.insert(name.clone());
+        }
+        ExprKind::FunctionDef { .. } => {
+            functions.push(expr);
+            return;
+        }
+        _ => {}
+    }
+    children(expr).into_iter().for_each(|child| scan(child, reads, functions));
+}
+
+fn defines(functions: &[&Expr], name: &str) -> bool {
+    functions.iter().any(|function| matches!(&function.kind, ExprKind::FunctionDef { name: defined, .. } if defined == name))
+}
+
+/// The expressions directly inside `expr`, in source order.
+pub(crate) fn children(expr: &Expr) -> Vec<&Expr> {
+    match &expr.kind {
+        ExprKind::Number(_)
+        | ExprKind::StringLiteral(_)
+        | ExprKind::Bool(_)
+        | ExprKind::Nil
+        | ExprKind::Variable(_, _)
+        | ExprKind::Break
+        | ExprKind::Continue
+        | ExprKind::Error => Vec::new(),
+        ExprKind::List(items) | ExprKind::Tuple(items) | ExprKind::Block(items) => items.iter().collect(),
+        ExprKind::FunctionCall { args, .. } => args.iter().collect(),
+        ExprKind::Map(entries) => entries.iter().map(|(_, value)| value).collect(),
+        ExprKind::Spread(inner) | ExprKind::Return(inner) | ExprKind::Yield(inner) => vec![inner],
+        ExprKind::Binary { left, right, .. } => vec![left, right],
+        ExprKind::Index { object, index } => vec![object, index],
+        ExprKind::Assignment { expr, .. } => vec![expr],
+        ExprKind::Destructure { pattern, expr } => {
+            let mut items = Vec::new();
+            pattern_children(pattern, &mut items);
+            items.push(expr);
+            items
+        }
+        ExprKind::If { condition, then_branch, else_branch } => {
+            let mut items: Vec<&Expr> = vec![condition, then_branch];
+            items.extend(else_branch.as_deref());
+            items
+        }
+        ExprKind::While { condition, body } => vec![condition, body],
+        ExprKind::For { iterable, body, .. } => vec![iterable, body],
+        ExprKind::FunctionDef { body, .. } => vec![body],
+    }
+}
+
+fn pattern_children<'e>(pattern: &'e Pattern, items: &mut Vec<&'e Expr>) {
+    match pattern {
+        Pattern::Variable(_, _) => {}
+        Pattern::Index { object, index } => items.extend([&**object, &**index]),
+        Pattern::Tuple(targets) | Pattern::List { items: targets, .. } => {
+            targets.iter().for_each(|target| pattern_children(target, items));
+        }
+        Pattern::Map(entries) => entries.iter().for_each(|(_, target)| pattern_children(target, items)),
+    }
+}
+
+/// The lines whose findings `// alin-ignore` comments suppress, each with
+/// the rules named after it, with or without a colon, or `None` for every
+/// rule. A comment on a line of its own covers the next line.
+fn ignored(comments: &[Comment], source: &str) -> HashMap<usize, Option<Vec<String>>> {
+    let mut ignored = HashMap::new();
+    for comment in comments {
+        let Some(rest) = comment.text[2..].trim().strip_prefix("alin-ignore") else {
+            continue;
+        };
+        // `alin-ignored` and the like are other words, not this comment.
+        if !rest.is_empty() && !rest.starts_with([':', ' ', '\t']) {
+            continue;
+        }
+        let names = rest.strip_prefix(':').unwrap_or(rest).trim();
+        let rules = (!names.is_empty()).then(|| names.split(',').map(|name| name.trim().to_string()).collect());
+        let line_start = source[..comment.span.offset].rfind('\n').map_or(0, |i| i + 1);
+        let own_line = source[line_start..comment.span.offset].trim().is_empty();
+        ignored.insert(if own_line { comment.span.line + 1 } else { comment.span.line }, rules);
+    }
+    ignored
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    /// Each finding for `source` under `config`: its code, the line and
+    /// column of its primary label, and whether it is an error.
+    fn findings_with(source: &str, config: &Config) -> Vec<(&'static str, usize, usize, bool)> {
+        let mut lexer = Lexer::new(source, "test.alin");
+        let tokens = lexer.tokenize().unwrap();
+        let ast = Parser::new(tokens.clone()).parse().unwrap();
+        lint(&ast, source, &tokens, lexer.comments(), config, &Builtins::new())
+            .iter()
+            .map(|finding| {
+                let span = &finding.labels[0].span;
+                (finding.code, span.line, span.column, finding.severity == Severity::Error)
+            })
+            .collect()
+    }
+
+    fn findings(source: &str) -> Vec<(&'static str, usize, usize, bool)> {
+        findings_with(source, &Config::default())
+    }
+
+    fn config(text: &str) -> Config {
+        Config { levels: Config::parse(text).unwrap(), file: None }
+    }
+
+    #[test]
+    fn unused_variables() {
+        let source = "used = 1\nunused = 2\n\
+                      fn f(a) {\n    local = a\n    _skipped = 3\n    return 0\n}\n\
+                      print(used, f(1))\n";
+        assert_eq!(findings(source), [("L0001", 2, 1, false), ("L0001", 4, 5, false)]);
+        // A function that is only defined is not a variable left unused.
+        assert_eq!(findings("fn helper() { return 1 }\n"), []);
+    }
+
+    #[test]
+    fn unreachable_code() {
+        let source = "fn f() {\n    return 1\n    print(2)\n    print(3)\n}\nprint(f())\n";
+        assert_eq!(findings(source), [("L0002", 3, 5, false)]);
+        assert_eq!(findings("while true {\n    break\n    print(1)\n}\n"), [("L0002", 3, 5, false)]);
+    }
+
+    #[test]
+    fn shadowing() {
+        let source = "len = 3\nwidth = 2\nfn area(width) { return width * len }\nprint(area(width))\n";
+        assert_eq!(findings(source), [("L0003", 1, 1, false), ("L0003", 3, 9, false)]);
+        assert_eq!(findings("math = 1\nprint(math)\n"), [("L0003", 1, 1, false)]);
+    }
+
+    #[test]
+    fn assignments_in_conditions() {
+        let source = "x = 1\nif x = 2 { print(x) }\nwhile x = 0 { print(x) }\n";
+        assert_eq!(findings(source), [("L0004", 2, 4, false), ("L0004", 3, 7, false)]);
+        assert_eq!(findings("x = 1\nif x == 2 { print(x) }\n"), []);
+    }
+
+    #[test]
+    fn comparisons_with_nil() {
+        let source = "x = 1\nprint(x == nil)\nprint(nil != x)\n";
+        assert_eq!(findings(source), [("L0005", 2, 7, false), ("L0005", 3, 7, false)]);
+    }
+
+    #[test]
+    fn empty_blocks() {
+        let source = "x = 1\nif x == 1 {\n} else { print(x) }\n";
+        assert_eq!(findings(source), [("L0006", 2, 11, false)]);
+    }
+
+    #[test]
+    fn names_are_found_by_token_not_by_text() {
+        // The key string and the comment both mention `total` first.
+        let source = "m = {\"total\": 1}\n{\"total\": total} = m\n";
+        assert_eq!(findings(source), [("L0001", 2, 11, false)]);
+        let source = "[\n    first, // not total\n    total\n] = [1, 2]\nprint(first)\n";
+        assert_eq!(findings(source), [("L0001", 3, 5, false)]);
+        let source = "fn f(f_count, count) { return f_count }\nprint(f(1, 2))\n";
+        assert_eq!(findings(source), []);
+        let source = "count = 1\nfn count_all(xs, count) { return count }\nprint(count_all([], count))\n";
+        assert_eq!(findings(source), [("L0003", 2, 18, false)]);
+    }
+
+    #[test]
+    fn comments_suppress_findings() {
+        // A trailing comment without rules covers every rule on its line.
+        assert_eq!(findings("x = nil == nil // alin-ignore\n"), []);
+        // Naming a rule, by name or code, leaves the others on.
+        assert_eq!(findings("x = nil == nil // alin-ignore: unused-variable\n"), [("L0005", 1, 5, false)]);
+        assert_eq!(findings("x = nil == nil // alin-ignore L0005, L0001\n"), []);
+        // A comment on a line of its own covers the next line only.
+        let source = "// alin-ignore\nx = 1\ny = 2\n";
+        assert_eq!(findings(source), [("L0001", 3, 1, false)]);
+        assert_eq!(findings("x = 1 // alin-ignored\n"), [("L0001", 1, 1, false)]);
+    }
+
+    #[test]
+    fn project_files_set_levels() {
+        let source = "x = 1\nprint(x == nil)\nif x {\n}\n";
+        let config = config("[lint]\nnil-comparison = \"error\"\nL0006 = \"allow\"\n");
+        assert_eq!(findings_with(source, &config), [("L0005", 2, 7, true)]);
+        assert_eq!(findings_with(source, &self::config("title = \"no lint table\"\n")).len(), 2);
+    }
+
+    #[test]
+    fn bad_project_files_are_rejected() {
+        let error = |text: &str| Config::parse(text).unwrap_err();
+        assert_eq!(error("[lint]\nunused = \"warn\"\n"), "unknown lint rule `unused`");
+        assert_eq!(error("[lint]\nshadowing = \"loud\"\n"), "`shadowing` must be \"allow\", \"warn\" or \"error\"");
+        assert_eq!(error("[lint]\nL0002 = true\n"), "`L0002` must be \"allow\", \"warn\" or \"error\"");
+        assert_eq!(error("lint = 1\n"), "`lint` must be a table");
+    }
+}
@@ -0,0 +1,977 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+
+use crate::ast::{Expr, ExprKind};
+use crate::diagnostic::{Diagnostic, Severity};
+use crate::eval::{Evaluator, Value};
+use crate::format::is_identifier;
+use crate::infer::Inferer;
+use crate::lexer::Lexer;
+use crate::lint::{children, lint, Config};
+use crate::parser::Parser;
+use crate::playground::Options;
+use crate::resolve::{bindings, slots};
+use crate::stdlib::json::{write_value, Reader};
+use crate::stdlib::{list, map, Builtins};
+use crate::token::{Span, SpannedToken, Token, KEYWORDS};
+
+/// Serves the Language Server Protocol on stdin and stdout until the client
+/// says to exit, returning whether it asked to shut down first, as the
+/// protocol expects.
+///
+/// Documents are synced in full, and every request looks at the latest text
+/// afresh: diagnostics come from the lexer, parser, resolver and linter, and
+/// names are matched up with the same scoping rules the resolver uses.
+pub fn serve(options: &Options) -> bool {
+    session(options, &mut io::stdin().lock(), io::stdout().lock())
+}
+
+/// Serves one client, reading its messages from `input` and writing
+/// responses and notifications to `output`.
+fn session(options: &Options, input: &mut impl BufRead, output: impl Write) -> bool {
+    let mut server = Server {
+        evaluator: options.evaluator(),
+        documents: HashMap::new(),
+        shut_down: false,
+        output,
+    };
+    while let Some(message) = read_message(input) {
+        let method = text(&get(&message, "method"));
+        let params = get(&message, "params");
+        if method == "exit" {
+            return server.shut_down;
+        }
+        let id = get(&message, "id");
+        if matches!(id, Value::Nil) {
+            server.notification(&method, &params);
+            continue;
+        }
+        let response = match server.request(&method, &params) {
+            Ok(result) => object([("jsonrpc", string("2.0")), ("id", id), ("result", result)]),
+            Err((code, message)) => {
+                let error = object([("code", Value::Number(code as f64)), ("message", string(message))]);
+                object([("jsonrpc", string("2.0")), ("id", id), ("error", error)])
+            }
+        };
+        send(&mut server.output, &response);
+    }
+    false
+}
+
+struct Server<W> {
+    evaluator: Evaluator,
+    /// The text of each open document, by URI.
+    documents: HashMap<String, String>,
+    shut_down: bool,
+    output: W,
+}
+
+/// JSON-RPC error codes.
+const METHOD_NOT_FOUND: i32 = -32601;
+const INVALID_PARAMS: i32 = -32602;
+
+impl<W: Write> Server<W> {
+    fn request(&mut self, method: &str, params: &Value) -> Result<Value, (i32, String)> {
+        let position = get(params, "position");
+        let result = match method {
+            "initialize" => capabilities(),
+            "shutdown" => {
+                self.shut_down = true;
+                Value::Nil
+            }
+            "textDocument/hover" => {
+                self.document(params, |uri, analysis| analysis.hover(uri, &position)).unwrap_or(Value::Nil)
+            }
+            "textDocument/definition" => {
+                self.document(params, |uri, analysis| analysis.definition(uri, &position)).unwrap_or(Value::Nil)
+            }
+            "textDocument/references" => {
+                let declaration = matches!(get(&get(params, "context"), "includeDeclaration"), Value::Bool(true));
+                let references = self.document(params, |uri, analysis| analysis.references(uri, &position, declaration));
+                references.unwrap_or(Value::Nil)
+            }
+            "textDocument/documentSymbol" => {
+                self.document(params, |_, analysis| list(analysis.outline(None))).unwrap_or(Value::Nil)
+            }
+            "textDocument/completion" => {
+                self.document(params, |_, analysis| analysis.completion(&position)).unwrap_or(Value::Nil)
+            }
+            "textDocument/rename" => {
+                let name = text(&get(params, "newName"));
+                if !is_identifier(&name) {
+                    return Err((INVALID_PARAMS, format!("`{}` is not a valid name", name)));
+                }
+                let edit = self.document(params, |uri, analysis| analysis.rename(uri, &position, &name));
+                edit.unwrap_or(Ok(Value::Nil)).map_err(|message| (INVALID_PARAMS, message))?
+            }
+            _ => return Err((METHOD_NOT_FOUND, format!("unknown method `{}`", method))),
+        };
+        Ok(result)
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) {
+        let document = get(params, "textDocument");
+        let uri = text(&get(&document, "uri"));
+        match method {
+            "textDocument/didOpen" => {
+                self.documents.insert(uri.clone(), text(&get(&document, "text")));
+                self.publish(&uri);
+            }
+            "textDocument/didChange" => {
+                // Full sync: the last change holds the whole new text.
+                let Value::List(changes) = get(params, "contentChanges") else {
+                    return;
+                };
+                if let Some(change) = changes.borrow().last() {
+                    self.documents.insert(uri.clone(), text(&get(change, "text")));
+                }
+                self.publish(&uri);
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(&uri);
+                let params = object([("uri", string(uri)), ("diagnostics", list(Vec::new()))]);
+                notify(&mut self.output, "textDocument/publishDiagnostics", params);
+            }
+            _ => {}
+        }
+    }
+
+    /// Answers a request about the document `params` names, if it is open.
+    fn document<T>(&self, params: &Value, answer: impl FnOnce(&str, &Analysis) -> T) -> Option<T> {
+        let uri = text(&get(&get(params, "textDocument"), "uri"));
+        let source = self.documents.get(&uri)?;
+        Some(answer(&uri, &Analysis::new(source, &path(&uri), &self.evaluator.builtins)))
+    }
+
+    /// Sends the client everything `alin check` and `alin lint` would
+    /// report about a document.
+    fn publish(&mut self, uri: &str) {
+        let Some(source) = self.documents.get(uri) else {
+            return;
+        };
+        let file = path(uri);
+        let mut lexer = Lexer::new(source, &file);
+        let (tokens, mut diagnostics) = lexer.tokenize_partial();
+        let (mut ast, syntax_errors) = Parser::new(tokens.clone()).parse_partial();
+        diagnostics.extend(syntax_errors);
+        // Unused variables come from the linter, at the level the project sets.
+        if let Err(err) = self.evaluator.resolve(&mut ast) {
+            diagnostics.extend_from_slice(err.diagnostics());
+        }
+        let config = Config::find(Path::new(&file)).unwrap_or_else(|message| {
+            eprintln!("Invalid {}", message);
+            Config::default()
+        });
+        diagnostics.extend(lint(&ast, source, &tokens, lexer.comments(), &config, &self.evaluator.builtins));
+
+        let diagnostics = diagnostics.iter().map(|diagnostic| lsp_diagnostic(diagnostic, uri, source)).collect();
+        let params = object([("uri", string(uri)), ("diagnostics", list(diagnostics))]);
+        notify(&mut self.output, "textDocument/publishDiagnostics", params);
+    }
+}
+
+fn capabilities() -> Value {
+    let capabilities = object([
+        // Full document sync.
+        ("textDocumentSync", number(1)),
+        ("hoverProvider", Value::Bool(true)),
+        ("definitionProvider", Value::Bool(true)),
+        ("referencesProvider", Value::Bool(true)),
+        ("documentSymbolProvider", Value::Bool(true)),
+        ("completionProvider", object([("triggerCharacters", list(vec![string(".")]))])),
+        ("renameProvider", Value::Bool(true)),
+    ]);
+    object([("capabilities", capabilities), ("serverInfo", object([("name", string("alin"))]))])
+}
+
+fn lsp_diagnostic(diagnostic: &Diagnostic, uri: &str, source: &str) -> Value {
+    let primary = diagnostic.labels.iter().find(|label| label.primary).map(|label| label.span.clone());
+    let mut message = diagnostic.message.clone();
+    for note in &diagnostic.notes {
+        message = format!("{}\n{}", message, note);
+    }
+    let related = diagnostic
+        .labels
+        .iter()
+        .filter(|label| !label.primary)
+        .map(|label| object([("location", location(uri, source, &label.span)), ("message", string(&label.message))]))
+        .collect();
+    let severity = match diagnostic.severity {
+        Severity::Error => 1,
+        Severity::Warning => 2,
+    };
+    object([
+        ("range", range(source, &primary.unwrap_or_default())),
+        ("severity", number(severity)),
+        ("code", string(diagnostic.code)),
+        ("source", string("alin")),
+        ("message", string(message)),
+        ("relatedInformation", list(related)),
+    ])
+}
+
+/// What the editor features need to know about one version of a document:
+/// every name it mentions and what each refers to.
+struct Analysis<'a> {
+    source: &'a str,
+    builtins: &'a Builtins,
+    tokens: Vec<SpannedToken>,
+    exprs: Vec<Expr>,
+    functions: Vec<Function>,
+    symbols: Vec<Symbol>,
+    occurrences: Vec<Occurrence>,
+}
+
+/// A function definition, which has its own locals.
+struct Function {
+    name: String,
+    params: Vec<String>,
+    slots: Vec<String>,
+    /// Where each local is first bound.
+    bindings: HashMap<String, Span>,
+    span: Span,
+    /// The offset of the name after `fn`.
+    name_offset: usize,
+    /// The function this one is defined in.
+    parent: Option<usize>,
+}
+
+/// A variable or function, local to a function (`scope`) or global.
+struct Symbol {
+    name: String,
+    scope: Option<usize>,
+    kind: SymbolKind,
+    /// The name where the symbol is first bound.
+    definition: Option<Span>,
+    /// The whole expression that first binds it.
+    extent: Option<Span>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum SymbolKind {
+    /// A function, with its index in `functions`.
+    Function(usize),
+    Parameter,
+    Variable,
+    Builtin,
+}
+
+/// A place the source names a symbol.
+struct Occurrence {
+    span: Span,
+    symbol: usize,
+    /// Whether it is `x` in `{x}`, which also names a key.
+    shorthand: bool,
+}
+
+impl<'a> Analysis<'a> {
+    fn new(source: &'a str, file: &str, builtins: &'a Builtins) -> Self {
+        let (tokens, _) = Lexer::new(source, file).tokenize_partial();
+        let (exprs, _) = Parser::new(tokens.clone()).parse_partial();
+        let mut functions = Vec::new();
+        for expr in &exprs {
+            collect_functions(expr, None, &tokens, &mut functions);
+        }
+        let mut analysis = Analysis {
+            source,
+            builtins,
+            tokens,
+            exprs,
+            functions,
+            symbols: Vec::new(),
+            occurrences: Vec::new(),
+        };
+        analysis.find_symbols();
+        analysis
+    }
+
+    /// Matches every name in the source up with the symbol it refers to,
+    /// then finds where each symbol is defined.
+    fn find_symbols(&mut self) {
+        let mut globals: HashMap<String, Span> = HashMap::new();
+        for expr in &self.exprs {
+            for (name, span) in bindings(expr) {
+                globals.entry(name).or_insert(span);
+            }
+        }
+        let shorthands = shorthands(&self.exprs, &self.tokens);
+        let mut keys: HashMap<(Option<usize>, String), usize> = HashMap::new();
+        for i in 0..self.tokens.len() {
+            let Token::Identifier(name) = &self.tokens[i].token else {
+                continue;
+            };
+            if !is_reference(&self.tokens, i) {
+                continue;
+            }
+            let span = self.tokens[i].span.clone();
+            let (scope, kind) = self.lookup(name, self.scope_at(span.offset), &globals);
+            let symbol = *keys.entry((scope, name.clone())).or_insert_with(|| {
+                self.symbols.push(Symbol { name: name.clone(), scope, kind, definition: None, extent: None });
+                self.symbols.len() - 1
+            });
+            let shorthand = shorthands.contains(&span.offset);
+            self.occurrences.push(Occurrence { span, symbol, shorthand });
+        }
+
+        for (index, symbol) in self.symbols.iter_mut().enumerate() {
+            let mut occurrences = self.occurrences.iter().filter(|occurrence| occurrence.symbol == index);
+            // Parameters are the first names in their function.
+            if symbol.kind == SymbolKind::Parameter {
+                symbol.definition = occurrences.next().map(|occurrence| occurrence.span.clone());
+                symbol.extent = symbol.definition.clone();
+                continue;
+            }
+            let extent = match symbol.scope {
+                _ if symbol.kind == SymbolKind::Builtin => continue,
+                Some(function) => self.functions[function].bindings.get(&symbol.name),
+                None => globals.get(&symbol.name),
+            };
+            let Some(extent) = extent else {
+                continue;
+            };
+            let function = self.functions.iter().position(|function| {
+                function.span.offset == extent.offset && function.name == symbol.name && function.parent == symbol.scope
+            });
+            if let Some(function) = function {
+                symbol.kind = SymbolKind::Function(function);
+            }
+            let definition = occurrences.find(|occurrence| contains(extent, occurrence.span.offset));
+            symbol.definition = definition.map(|occurrence| occurrence.span.clone());
+            symbol.extent = Some(extent.clone());
+        }
+    }
+
+    /// What `name` refers to in `scope`: a function's own locals, else a
+    /// global, else a builtin. Names nothing binds are kept as globals.
+    fn lookup(&self, name: &str, scope: Option<usize>, globals: &HashMap<String, Span>) -> (Option<usize>, SymbolKind) {
+        if let Some(index) = scope {
+            let function = &self.functions[index];
+            if function.params.iter().any(|param| param == name) {
+                return (scope, SymbolKind::Parameter);
+            }
+            // Assigning to a name that has a global sets the global.
+            if function.slots.iter().any(|slot| slot == name) && !globals.contains_key(name) {
+                return (scope, SymbolKind::Variable);
+            }
+        }
+        if !globals.contains_key(name) && (self.builtins.get(name).is_some() || self.builtins.module(name).is_some()) {
+            return (None, SymbolKind::Builtin);
+        }
+        (None, SymbolKind::Variable)
+    }
+
+    /// The innermost function whose body `offset` is in; a function's name
+    /// belongs to the code around it.
+    fn scope_at(&self, offset: usize) -> Option<usize> {
+        self.functions
+            .iter()
+            .enumerate()
+            .filter(|(_, function)| contains(&function.span, offset) && function.name_offset != offset)
+            .max_by_key(|(_, function)| function.span.offset)
+            .map(|(index, _)| index)
+    }
+
+    /// The name at an LSP position, including just after its last letter.
+    fn occurrence_at(&self, position: &Value) -> Option<&Occurrence> {
+        let offset = offset(self.source, position);
+        self.occurrences
+            .iter()
+            .find(|occurrence| occurrence.span.offset <= offset && offset <= occurrence.span.offset + occurrence.span.len)
+    }
+
+    fn hover(&self, _uri: &str, position: &Value) -> Value {
+        let Some(occurrence) = self.occurrence_at(position) else {
+            return Value::Nil;
+        };
+        let symbol = &self.symbols[occurrence.symbol];
+        let name = &symbol.name;
+        let code = |text: String| format!("```alin\n{}\n```", text);
+        let contents = match (symbol.kind, symbol.scope) {
+            (SymbolKind::Builtin, _) if self.builtins.get(name).is_some() => format!("builtin function `{}`", name),
+            (SymbolKind::Builtin, _) => format!("builtin module `{}`", name),
+            (kind, None) => {
+                // Only globals have inferred types.
+                let inference = Inferer::new().infer_program(&self.exprs);
+                match inference.signatures.iter().find(|signature| &signature.name == name) {
+                    Some(signature) => code(signature.to_string()),
+                    None if symbol.definition.is_none() => format!("undefined `{}`", name),
+                    None => match kind {
+                        SymbolKind::Function(function) => code(self.header(function)),
+                        _ => format!("global variable `{}`", name),
+                    },
+                }
+            }
+            (SymbolKind::Function(function), _) => code(self.header(function)),
+            (SymbolKind::Parameter, Some(scope)) => {
+                format!("parameter `{}` of `{}`", name, self.functions[scope].name)
+            }
+            (_, Some(scope)) => format!("local variable `{}` of `{}`", name, self.functions[scope].name),
+        };
+        let contents = object([("kind", string("markdown")), ("value", string(contents))]);
+        object([("contents", contents), ("range", range(self.source, &occurrence.span))])
+    }
+
+    /// A function's first line, such as `fn add(a, b)`.
+    fn header(&self, function: usize) -> String {
+        let function = &self.functions[function];
+        format!("fn {}({})", function.name, function.params.join(", "))
+    }
+
+    fn definition(&self, uri: &str, position: &Value) -> Value {
+        let occurrence = self.occurrence_at(position);
+        let definition = occurrence.and_then(|occurrence| self.symbols[occurrence.symbol].definition.as_ref());
+        definition.map_or(Value::Nil, |span| location(uri, self.source, span))
+    }
+
+    fn references(&self, uri: &str, position: &Value, declaration: bool) -> Value {
+        let Some(occurrence) = self.occurrence_at(position) else {
+            return Value::Nil;
+        };
+        let definition = self.symbols[occurrence.symbol].definition.as_ref();
+        let locations = self
+            .occurrences
+            .iter()
+            .filter(|other| other.symbol == occurrence.symbol)
+            .filter(|other| declaration || Some(&other.span) != definition)
+            .map(|other| location(uri, self.source, &other.span))
+            .collect();
+        list(locations)
+    }
+
+    /// Renames the symbol at `position` everywhere, keeping the keys that
+    /// `{x}` shorthand names.
+    fn rename(&self, uri: &str, position: &Value, name: &str) -> Result<Value, String> {
+        let Some(occurrence) = self.occurrence_at(position) else {
+            return Ok(Value::Nil);
+        };
+        let symbol = &self.symbols[occurrence.symbol];
+        if symbol.kind == SymbolKind::Builtin {
+            return Err(format!("`{}` is a builtin and can't be renamed", symbol.name));
+        }
+        let edits = self
+            .occurrences
+            .iter()
+            .filter(|other| other.symbol == occurrence.symbol)
+            .map(|other| {
+                let text = if other.shorthand { format!("{}: {}", symbol.name, name) } else { name.to_string() };
+                object([("range", range(self.source, &other.span)), ("newText", string(text))])
+            })
+            .collect();
+        let changes = map(BTreeMap::from([(uri.to_string(), list(edits))]));
+        Ok(object([("changes", changes)]))
+    }
+
+    /// The document symbols defined in `scope`, each function with its own
+    /// locals inside it.
+    fn outline(&self, scope: Option<usize>) -> Vec<Value> {
+        let mut symbols: Vec<&Symbol> = self
+            .symbols
+            .iter()
+            .filter(|symbol| symbol.scope == scope && symbol.kind != SymbolKind::Builtin)
+            .filter(|symbol| symbol.definition.is_some())
+            .collect();
+        symbols.sort_by_key(|symbol| symbol.definition.as_ref().map(|span| span.offset));
+        symbols
+            .into_iter()
+            .map(|symbol| {
+                let (kind, children) = match symbol.kind {
+                    SymbolKind::Function(function) => (12, self.outline(Some(function))),
+                    _ => (13, Vec::new()),
+                };
+                let definition = symbol.definition.clone().unwrap_or_default();
+                let extent = symbol.extent.clone().unwrap_or_default();
+                object([
+                    ("name", string(&symbol.name)),
+                    ("kind", number(kind)),
+                    ("range", range(self.source, &extent)),
+                    ("selectionRange", range(self.source, &definition)),
+                    ("children", list(children)),
+                ])
+            })
+            .collect()
+    }
+
+    /// Keywords, builtins and the names in scope at `position`, or a
+    /// module's members after `module.`.
+    fn completion(&self, position: &Value) -> Value {
+        const FUNCTION: usize = 3;
+        const VARIABLE: usize = 6;
+        const MODULE: usize = 9;
+        const KEYWORD: usize = 14;
+        const CONSTANT: usize = 21;
+        let offset = offset(self.source, position);
+        let builtin_kind = |name: &str| if self.builtins.get(name).is_some() { FUNCTION } else { CONSTANT };
+
+        // Leave out the word being typed, which the client matches against.
+        let mut before: Vec<&Token> = self
+            .tokens
+            .iter()
+            .filter(|token| token.token != Token::Eof && token.span.offset + token.span.len <= offset)
+            .map(|token| &token.token)
+            .collect();
+        if let Some(Token::Identifier(_)) = before.last() {
+            before.pop();
+        }
+        let mut items: BTreeMap<String, usize> = BTreeMap::new();
+        if let [.., Token::Identifier(module), Token::Dot] = before[..] {
+            let prefix = format!("{}.", module);
+            for name in self.builtins.names() {
+                if let Some(member) = name.strip_prefix(&prefix) {
+                    items.insert(member.to_string(), builtin_kind(name));
+                }
+            }
+        } else {
+            for keyword in KEYWORDS {
+                items.insert(keyword.to_string(), KEYWORD);
+            }
+            for name in self.builtins.names() {
+                match name.split_once('.') {
+                    Some((module, _)) => items.insert(module.to_string(), MODULE),
+                    None => items.insert(name.to_string(), builtin_kind(name)),
+                };
+            }
+            let scope = self.scope_at(offset);
+            for symbol in &self.symbols {
+                if symbol.definition.is_some() && (symbol.scope.is_none() || symbol.scope == scope) {
+                    let kind = if matches!(symbol.kind, SymbolKind::Function(_)) { FUNCTION } else { VARIABLE };
+                    items.insert(symbol.name.clone(), kind);
+                }
+            }
+        }
+        let items = items
+            .into_iter()
+            .map(|(label, kind)| object([("label", string(label)), ("kind", number(kind))]))
+            .collect();
+        list(items)
+    }
+}
+
+/// Adds `expr`'s function definitions and those inside it to `functions`.
+fn collect_functions(expr: &Expr, parent: Option<usize>, tokens: &[SpannedToken], functions: &mut Vec<Function>) {
+    let mut parent = parent;
+    if let ExprKind::FunctionDef { name, params, body, .. } = &expr.kind {
+        let name_offset = token_at(tokens, expr.span.offset)
+            .and_then(|fn_token| tokens.get(fn_token + 1))
+            .map_or(expr.span.offset, |name| name.span.offset);
+        functions.push(Function {
+            name: name.clone(),
+            params: params.clone(),
+            slots: slots(params, body),
+            bindings: bindings(body).into_iter().collect(),
+            span: expr.span.clone(),
+            name_offset,
+            parent,
+        });
+        parent = Some(functions.len() - 1);
+    }
+    for child in children(expr) {
+        collect_functions(child, parent, tokens, functions);
+    }
+}
+
+/// Whether the identifier at `tokens[i]` names a variable or function,
+/// rather than a map key, a field after `.`, or part of a qualified builtin
+/// name such as `math.sqrt(`.
+fn is_reference(tokens: &[SpannedToken], i: usize) -> bool {
+    let at = |j: usize| tokens.get(j).map(|token| &token.token);
+    if (i > 0 && at(i - 1) == Some(&Token::Dot)) || at(i + 1) == Some(&Token::Colon) {
+        return false;
+    }
+    let mut end = i + 1;
+    while at(end) == Some(&Token::Dot) && matches!(at(end + 1), Some(Token::Identifier(_))) {
+        end += 2;
+    }
+    !(end > i + 1 && at(end) == Some(&Token::LParen))
+}
+
+/// The offsets of names written as `{x}` shorthand, in map literals and in
+/// map patterns.
+fn shorthands(exprs: &[Expr], tokens: &[SpannedToken]) -> HashSet<usize> {
+    let mut offsets = HashSet::new();
+    let mut pending: Vec<&Expr> = exprs.iter().collect();
+    while let Some(expr) = pending.pop() {
+        match &expr.kind {
+            ExprKind::Map(entries) => {
+                for (key, value) in entries {
+                    let written_out = token_at(tokens, value.span.offset)
+                        .is_some_and(|i| i > 0 && tokens[i - 1].token == Token::Colon);
+                    if matches!(&value.kind, ExprKind::Variable(name, _) if name == key) && !written_out {
+                        offsets.insert(value.span.offset);
+                    }
+                }
+            }
+            // Patterns have no spans, so their tokens are read directly, up
+            // to the `=`.
+            ExprKind::Destructure { .. } => {
+                let Some(first) = token_at(tokens, expr.span.offset) else {
+                    continue;
+                };
+                let mut open = Vec::new();
+                for i in first..tokens.len() {
+                    match &tokens[i].token {
+                        Token::LBrace | Token::LBracket | Token::LParen => open.push(&tokens[i].token),
+                        Token::RBrace | Token::RBracket | Token::RParen => {
+                            open.pop();
+                        }
+                        Token::Equal | Token::Eof if open.is_empty() => break,
+                        Token::Identifier(_) if open.last() == Some(&&Token::LBrace) => {
+                            let after_open = matches!(tokens[i - 1].token, Token::LBrace | Token::Comma);
+                            let before_close = matches!(tokens[i + 1].token, Token::RBrace | Token::Comma);
+                            if after_open && before_close {
+                                offsets.insert(tokens[i].span.offset);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            _ => {}
+        }
+        pending.extend(children(expr));
+    }
+    offsets
+}
+
+/// The index of the token starting at `offset`.
+fn token_at(tokens: &[SpannedToken], offset: usize) -> Option<usize> {
+    tokens.binary_search_by_key(&offset, |token| token.span.offset).ok()
+}
+
+fn contains(span: &Span, offset: usize) -> bool {
+    span.offset <= offset && offset < span.offset + span.len
+}
+
+/// Reads one message, or `None` once the input ends. A body that isn't JSON
+/// reads as `null`, which is ignored.
+fn read_message(input: &mut impl BufRead) -> Option<Value> {
+    let mut length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line).ok()? == 0 {
+            return None;
+        }
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(value) = line.strip_prefix("Content-Length:") {
+            length = value.trim().parse().ok();
+        }
+    }
+    let mut body = vec![0; length?];
+    input.read_exact(&mut body).ok()?;
+    Some(Reader::new(&String::from_utf8_lossy(&body)).document().unwrap_or(Value::Nil))
+}
+
+fn send(output: &mut impl Write, message: &Value) {
+    let mut body = String::new();
+    write_value(&mut body, message, 0, 0).expect("LSP messages only hold JSON values");
+    let _ = write!(output, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+    let _ = output.flush();
+}
+
+fn notify(output: &mut impl Write, method: &str, params: Value) {
+    send(output, &object([("jsonrpc", string("2.0")), ("method", string(method)), ("params", params)]));
+}
+
+/// The file a `file://` URI names, or the URI itself for other schemes.
+fn path(uri: &str) -> String {
+    let Some(path) = uri.strip_prefix("file://") else {
+        return uri.to_string();
+    };
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let hex = path.get(i + 1..i + 3).filter(|_| bytes[i] == b'%');
+        let escaped = hex.and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// The LSP position of byte `offset` in `source`: a 0-based line, and
+/// UTF-16 code units into it.
+fn position(source: &str, offset: usize) -> Value {
+    let before = &source[..offset.min(source.len())];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    let character: usize = before[line_start..].chars().map(char::len_utf16).sum();
+    object([("line", number(before.matches('\n').count())), ("character", number(character))])
+}
+
+/// The byte offset of an LSP position in `source`, kept within its line.
+fn offset(source: &str, position: &Value) -> usize {
+    let index = |key| match get(position, key) {
+        Value::Number(n) if n >= 0.0 => n as usize,
+        _ => 0,
+    };
+    let mut start = 0;
+    for _ in 0..index("line") {
+        match source[start..].find('\n') {
+            Some(i) => start += i + 1,
+            None => return source.len(),
+        }
+    }
+    let mut units = 0;
+    for (i, c) in source[start..].char_indices() {
+        if c == '\n' || units >= index("character") {
+            return start + i;
+        }
+        units += c.len_utf16();
+    }
+    source.len()
+}
+
+fn range(source: &str, span: &Span) -> Value {
+    object([("start", position(source, span.offset)), ("end", position(source, span.offset + span.len))])
+}
+
+fn location(uri: &str, source: &str, span: &Span) -> Value {
+    object([("uri", string(uri)), ("range", range(source, span))])
+}
+
+fn get(value: &Value, key: &str) -> Value {
+    match value {
+        Value::Map(entries) => entries.borrow().get(key).cloned().unwrap_or(Value::Nil),
+        _ => Value::Nil,
+    }
+}
+
+fn text(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        _ => String::new(),
+    }
+}
+
+fn object<const N: usize>(entries: [(&str, Value); N]) -> Value {
+    map(entries.into_iter().map(|(key, value)| (key.to_string(), value)).collect())
+}
+
+fn string(text: impl Into<String>) -> Value {
+    Value::String(text.into())
+}
+
+fn number(n: usize) -> Value {
+    Value::Number(n as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URI: &str = "file:///nonexistent/alin-lsp-test/test.alin";
+    const SOURCE: &str = "\
+fn area(width, height) {
+    size = width * height
+    return {size}
+}
+total = area(2, 3)
+print(total, missing)
+";
+
+    /// Frames `message` as the client would send it.
+    fn frame(message: Value) -> Vec<u8> {
+        let mut body = String::new();
+        write_value(&mut body, &message, 0, 0).unwrap();
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body).into_bytes()
+    }
+
+    fn request(id: usize, method: &str, params: Value) -> Vec<u8> {
+        let method = string(method);
+        frame(object([("jsonrpc", string("2.0")), ("id", number(id)), ("method", method), ("params", params)]))
+    }
+
+    fn notification(method: &str, params: Value) -> Vec<u8> {
+        frame(object([("jsonrpc", string("2.0")), ("method", string(method)), ("params", params)]))
+    }
+
+    fn at(line: usize, character: usize) -> Value {
+        let document = object([("uri", string(URI))]);
+        let position = object([("line", number(line)), ("character", number(character))]);
+        object([("textDocument", document), ("position", position)])
+    }
+
+    fn with(params: Value, key: &str, value: Value) -> Value {
+        if let Value::Map(entries) = &params {
+            entries.borrow_mut().insert(key.to_string(), value);
+        }
+        params
+    }
+
+    /// Opens `SOURCE`, sends `requests` numbered from 1, and gives whether
+    /// the session ended cleanly, the diagnostics first published, and the
+    /// result of each request.
+    fn run(requests: Vec<(&str, Value)>, shut_down: bool) -> (bool, Value, Vec<Value>) {
+        let document = object([("uri", string(URI)), ("languageId", string("alin")), ("text", string(SOURCE))]);
+        let mut input = request(0, "initialize", map(BTreeMap::new()));
+        input.extend(notification("initialized", map(BTreeMap::new())));
+        input.extend(notification("textDocument/didOpen", object([("textDocument", document)])));
+        let count = requests.len();
+        for (id, (method, params)) in requests.into_iter().enumerate() {
+            input.extend(request(id + 1, method, params));
+        }
+        if shut_down {
+            input.extend(request(count + 1, "shutdown", Value::Nil));
+        }
+        input.extend(notification("exit", Value::Nil));
+
+        let mut output = Vec::new();
+        let clean = session(&Options::default(), &mut &input[..], &mut output);
+        let mut output = &output[..];
+        let mut messages = Vec::new();
+        while let Some(message) = read_message(&mut output) {
+            messages.push(message);
+        }
+        let initialize = messages.remove(0);
+        assert!(matches!(get(&get(&get(&initialize, "result"), "capabilities"), "hoverProvider"), Value::Bool(true)));
+        let published = messages.remove(0);
+        assert_eq!(text(&get(&published, "method")), "textDocument/publishDiagnostics");
+        let results = messages.iter().map(|message| get(message, "result")).collect();
+        (clean, get(&get(&published, "params"), "diagnostics"), results)
+    }
+
+    fn items(value: &Value) -> Vec<Value> {
+        match value {
+            Value::List(items) => items.borrow().clone(),
+            other => panic!("not a list: {}", other),
+        }
+    }
+
+    /// `(line, character)` of the start of a range.
+    fn start(range: &Value) -> (f64, f64) {
+        let start = get(range, "start");
+        match (get(&start, "line"), get(&start, "character")) {
+            (Value::Number(line), Value::Number(character)) => (line, character),
+            other => panic!("not a position: {:?}", other),
+        }
+    }
+
+    fn hover(line: usize, character: usize) -> String {
+        let (_, _, results) = run(vec![("textDocument/hover", at(line, character))], true);
+        text(&get(&get(&results[0], "contents"), "value"))
+    }
+
+    #[test]
+    fn publishes_diagnostics_when_a_document_opens() {
+        let (_, diagnostics, _) = run(Vec::new(), true);
+        let diagnostics = items(&diagnostics);
+        assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+        assert_eq!(text(&get(&diagnostics[0], "code")), "E0301");
+        assert_eq!(text(&get(&diagnostics[0], "message")), "undefined variable `missing`");
+        assert_eq!(start(&get(&diagnostics[0], "range")), (5.0, 13.0));
+    }
+
+    #[test]
+    fn hovers_over_functions_parameters_and_builtins() {
+        assert_eq!(hover(4, 9), "```alin\nfn area(width: Number, height: Number) -> Map\n```");
+        assert_eq!(hover(0, 9), "parameter `width` of `area`");
+        assert_eq!(hover(1, 5), "local variable `size` of `area`");
+        assert_eq!(hover(5, 1), "builtin function `print`");
+        assert_eq!(hover(5, 15), "undefined `missing`");
+    }
+
+    #[test]
+    fn finds_definitions() {
+        let requests = vec![("textDocument/definition", at(2, 13)), ("textDocument/definition", at(5, 7))];
+        let (_, _, results) = run(requests, true);
+        assert_eq!(text(&get(&results[0], "uri")), URI);
+        assert_eq!(start(&get(&results[0], "range")), (1.0, 4.0));
+        assert_eq!(start(&get(&results[1], "range")), (4.0, 0.0));
+    }
+
+    #[test]
+    fn finds_references() {
+        let context = |declaration| object([("includeDeclaration", Value::Bool(declaration))]);
+        let (_, _, results) = run(
+            vec![
+                ("textDocument/references", with(at(1, 16), "context", context(true))),
+                ("textDocument/references", with(at(1, 16), "context", context(false))),
+            ],
+            true,
+        );
+        let starts = |result: &Value| items(result).iter().map(|at| start(&get(at, "range"))).collect::<Vec<_>>();
+        assert_eq!(starts(&results[0]), [(0.0, 8.0), (1.0, 11.0)]);
+        assert_eq!(starts(&results[1]), [(1.0, 11.0)]);
+    }
+
+    #[test]
+    fn renames_keeping_shorthand_keys() {
+        let (_, _, results) = run(vec![("textDocument/rename", with(at(1, 4), "newName", string("product")))], true);
+        let edits = items(&get(&get(&results[0], "changes"), URI));
+        let edit = |edit: &Value| (start(&get(edit, "range")), text(&get(edit, "newText")));
+        let edits: Vec<_> = edits.iter().map(edit).collect();
+        assert_eq!(edits, [((1.0, 4.0), "product".to_string()), ((2.0, 12.0), "size: product".to_string())]);
+    }
+
+    #[test]
+    fn rejects_renaming_to_a_non_name_or_a_builtin() {
+        let mut input = request(0, "initialize", map(BTreeMap::new()));
+        let document = object([("uri", string(URI)), ("text", string(SOURCE))]);
+        input.extend(notification("textDocument/didOpen", object([("textDocument", document)])));
+        input.extend(request(1, "textDocument/rename", with(at(1, 4), "newName", string("2x"))));
+        input.extend(request(2, "textDocument/rename", with(at(5, 1), "newName", string("show"))));
+        let mut output = Vec::new();
+        session(&Options::default(), &mut &input[..], &mut output);
+        let mut output = &output[..];
+        let messages: Vec<Value> = std::iter::from_fn(|| read_message(&mut output)).collect();
+        let error = |message: &Value| text(&get(&get(message, "error"), "message"));
+        let errors: Vec<String> = messages.iter().map(error).collect();
+        assert_eq!(errors[2], "`2x` is not a valid name");
+        assert_eq!(errors[3], "`print` is a builtin and can't be renamed");
+    }
+
+    #[test]
+    fn completes_names_in_scope_and_module_members() {
+        let requests = vec![("textDocument/completion", at(5, 0)), ("textDocument/completion", at(1, 4))];
+        let (_, _, results) = run(requests, true);
+        let labels = |result: &Value| items(result).iter().map(|item| text(&get(item, "label"))).collect::<Vec<_>>();
+        let top = labels(&results[0]);
+        for expected in ["area", "total", "print", "string", "while"] {
+            assert!(top.contains(&expected.to_string()), "no `{}` in {:?}", expected, top);
+        }
+        assert!(!top.contains(&"width".to_string()));
+        assert!(labels(&results[1]).contains(&"width".to_string()));
+
+        let source = "string.";
+        let document = object([("uri", string(URI)), ("text", string(source))]);
+        let mut input = notification("textDocument/didOpen", object([("textDocument", document)]));
+        input.extend(request(1, "textDocument/completion", at(0, 7)));
+        let mut output = Vec::new();
+        session(&Options::default(), &mut &input[..], &mut output);
+        let mut output = &output[..];
+        let messages: Vec<Value> = std::iter::from_fn(|| read_message(&mut output)).collect();
+        let members = labels(&get(&messages[1], "result"));
+        assert!(members.contains(&"upper".to_string()) && !members.contains(&"print".to_string()), "{:?}", members);
+    }
+
+    #[test]
+    fn outlines_functions_with_their_locals() {
+        let document = object([("textDocument", object([("uri", string(URI))]))]);
+        let (_, _, results) = run(vec![("textDocument/documentSymbol", document)], true);
+        let names = |symbols: &Value| items(symbols).iter().map(|s| text(&get(s, "name"))).collect::<Vec<_>>();
+        assert_eq!(names(&results[0]), ["area", "total"]);
+        let area = &items(&results[0])[0];
+        assert_eq!(get(area, "kind").to_string(), "12");
+        assert_eq!(names(&get(area, "children")), ["width", "height", "size"]);
+    }
+
+    #[test]
+    fn exit_reports_whether_shutdown_came_first() {
+        let (clean, _, results) = run(Vec::new(), true);
+        assert!(clean);
+        assert!(matches!(results[..], [Value::Nil]));
+        let (clean, _, _) = run(Vec::new(), false);
+        assert!(!clean);
+    }
+}
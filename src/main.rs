@@ -1,23 +1,175 @@
 mod token;
+mod diagnostic;
+mod error;
 mod lexer;
 mod parser;
+mod cst;
 mod ast;
 mod eval;
+mod bytecode;
+mod resolve;
+mod compiler;
+mod vm;
+mod alinc;
+mod optimize;
+mod infer;
+mod lint;
+mod lsp;
+mod format;
+mod iter;
 mod repl;
 mod playground;
+mod stdlib;
 
 
-use std::env;
+use std::{env, process, thread};
+use diagnostic::{Color, ErrorFormat};
+use eval::{Engine, MAX_CALL_DEPTH};
+use lsp::serve;
 use repl::start_repl;
-use playground::run_file;
+use playground::{check_file, compile_file, format_file, lint_file, run_file, Options};
+
+/// The tree-walking engine recurses natively for each script call, as the
+/// VM does for calls made back through builtins such as `map` and for
+/// generators, so the interpreter runs on a thread with room for
+/// `MAX_CALL_DEPTH` of them. The most one call was measured taking is about
+/// 93 KB, in a debug build, for a call nested a few expressions and loops
+/// deep in its caller; release builds take about a seventh of that.
+/// Expressions nested far deeper than that can still run out.
+const STACK_PER_CALL: usize = 128 * 1024;
+const STACK_SIZE: usize = MAX_CALL_DEPTH * STACK_PER_CALL;
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let interpreter = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(run)
+        .expect("failed to start the interpreter thread");
+    if !interpreter.join().unwrap_or(false) {
+        process::exit(1);
+    }
+}
+
+/// Parses the command line and does what it asks, returning whether it
+/// succeeded.
+fn run() -> bool {
+    let mut args: Vec<String> = env::args().skip(1).collect();
 
-    if args.len() > 1 {
-        let path = &args[1];
-        run_file(path);
-    } else {
-        start_repl();
+    // Interpreter options come before the script path; everything after the
+    // path belongs to the script.
+    let mut options = Options::default();
+    while args.first().is_some_and(|arg| arg.starts_with("--")) {
+        if let Some(seed) = take_option(&mut args, "--seed") {
+            match seed.parse() {
+                Ok(seed) => options.seed = Some(seed),
+                Err(_) => {
+                    eprintln!("Invalid --seed value: {}", seed);
+                    process::exit(2);
+                }
+            }
+        } else if let Some(format) = take_option(&mut args, "--error-format") {
+            options.error_format = match format.as_str() {
+                "human" => ErrorFormat::Human,
+                "json" => ErrorFormat::Json,
+                _ => {
+                    eprintln!("Invalid --error-format value: {} (expected human or json)", format);
+                    process::exit(2);
+                }
+            };
+        } else if let Some(color) = take_option(&mut args, "--color") {
+            options.color = match color.as_str() {
+                "auto" => Color::Auto,
+                "always" => Color::Always,
+                "never" => Color::Never,
+                _ => {
+                    eprintln!("Invalid --color value: {} (expected auto, always or never)", color);
+                    process::exit(2);
+                }
+            };
+        } else if let Some(engine) = take_option(&mut args, "--engine") {
+            options.engine = match engine.as_str() {
+                "vm" => Engine::Vm,
+                "tree" => Engine::Tree,
+                _ => {
+                    eprintln!("Invalid --engine value: {} (expected vm or tree)", engine);
+                    process::exit(2);
+                }
+            };
+        } else if args[0] == "--no-opt" {
+            args.remove(0);
+            options.no_opt = true;
+        } else {
+            eprintln!("Unknown option: {}", args[0]);
+            process::exit(2);
+        }
     }
+
+    match args.first().map(String::as_str) {
+        Some("check") => {
+            let show_types = args[1..].iter().any(|arg| arg == "--show-types");
+            match args[1..].iter().find(|arg| !arg.starts_with("--")) {
+                Some(path) => check_file(path, show_types, &options),
+                None => {
+                    eprintln!("Usage: alin check [--show-types] <file>");
+                    process::exit(2);
+                }
+            }
+        }
+        Some("lint") => match args.get(1) {
+            Some(path) => lint_file(path, &options),
+            None => {
+                eprintln!("Usage: alin lint <file>");
+                process::exit(2);
+            }
+        },
+        Some("fmt") => {
+            let check = args[1..].iter().any(|arg| arg == "--check");
+            match args[1..].iter().find(|arg| !arg.starts_with("--")) {
+                Some(path) => format_file(path, check, &options),
+                None => {
+                    eprintln!("Usage: alin fmt [--check] <file>");
+                    process::exit(2);
+                }
+            }
+        }
+        Some("lsp") => serve(&options),
+        Some("compile") => match args.get(1) {
+            Some(path) => compile_file(path, &options),
+            None => {
+                eprintln!("Usage: alin compile <file>");
+                process::exit(2);
+            }
+        },
+        Some("run") => match args.get(1) {
+            Some(path) => {
+                options.args = args[2..].to_vec();
+                run_file(path, &options)
+            }
+            None => {
+                eprintln!("Usage: alin run <file> [args...]");
+                process::exit(2);
+            }
+        },
+        Some(path) => {
+            options.args = args[1..].to_vec();
+            run_file(path, &options)
+        }
+        None => {
+            start_repl(&options);
+            true
+        }
+    }
+}
+
+/// Removes `--name value` or `--name=value` from the front of `args`,
+/// returning the value.
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let prefix = format!("{}=", name);
+    let arg = args.first().filter(|arg| *arg == name || arg.starts_with(&prefix))?.clone();
+    let value = match arg.strip_prefix(&prefix) {
+        Some(value) => value.to_string(),
+        None if args.len() > 1 => args.remove(1),
+        None => String::new(),
+    };
+    args.remove(0);
+    Some(value)
 }
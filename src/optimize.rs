@@ -0,0 +1,387 @@
+use std::collections::{HashMap, HashSet};
+use std::mem;
+
+use crate::ast::{Expr, ExprKind, Pattern};
+use crate::eval::{binary, is_truthy, BinOp, Value};
+use crate::iter::contains_yield;
+use crate::lint::children;
+use crate::resolve::bindings;
+
+/// Simplifies a parsed script without changing what it does: folds
+/// operators on literals, picks the branch of an `if` whose condition is a
+/// literal, drops statements a block can never reach or that have no
+/// effect, and inlines the constants of functions. Top-level statements are
+/// kept apart, since a `return` there only ends its own statement.
+///
+/// A constant is a local that a function binds only once, by assigning a
+/// literal in a statement of its body, and its reads after that statement
+/// become the literal. Names the top level binds are left alone, since
+/// assigning one inside a function assigns the global, and so is everything
+/// when more code may follow, as in the REPL, which may yet define globals.
+pub fn optimize(exprs: Vec<Expr>, open: bool) -> Vec<Expr> {
+    let globals = exprs.iter().flat_map(bindings).map(|(name, _)| name).collect();
+    let mut optimizer = Optimizer { open, globals, candidates: HashSet::new(), constants: HashMap::new() };
+    exprs.into_iter().map(|expr| optimizer.expr(expr)).collect()
+}
+
+struct Optimizer {
+    open: bool,
+    /// Names the top level binds.
+    globals: HashSet<String>,
+    /// Locals the current function binds only once.
+    candidates: HashSet<String>,
+    /// Constants of the current function seen so far, with their values.
+    constants: HashMap<String, ExprKind>,
+}
+
+impl Optimizer {
+    fn expr(&mut self, expr: Expr) -> Expr {
+        let span = expr.span;
+        let kind = match expr.kind {
+            ExprKind::Variable(name, slot) => match self.constants.get(&name) {
+                Some(value) => value.clone(),
+                None => ExprKind::Variable(name, slot),
+            },
+            ExprKind::List(items) => ExprKind::List(self.all(items)),
+            ExprKind::Tuple(items) => ExprKind::Tuple(self.all(items)),
+            ExprKind::Map(fields) => {
+                ExprKind::Map(fields.into_iter().map(|(key, value)| (key, self.expr(value))).collect())
+            }
+            ExprKind::Spread(inner) => ExprKind::Spread(self.boxed(*inner)),
+            ExprKind::Binary { left, op, right } => {
+                let (left, right) = (self.expr(*left), self.expr(*right));
+                // A mismatch such as `1 + "a"` is left for the engine to report.
+                let folded = BinOp::from_token(&op)
+                    .zip(literal(&left).zip(literal(&right)))
+                    .and_then(|(op, (lhs, rhs))| binary(op, lhs, rhs).ok())
+                    .and_then(|value| match value {
+                        Value::Number(n) => Some(ExprKind::Number(n)),
+                        Value::String(s) => Some(ExprKind::StringLiteral(s)),
+                        Value::Bool(b) => Some(ExprKind::Bool(b)),
+                        _ => None,
+                    });
+                folded.unwrap_or_else(|| ExprKind::Binary { left: Box::new(left), op, right: Box::new(right) })
+            }
+            ExprKind::Index { object, index } => {
+                ExprKind::Index { object: self.boxed(*object), index: self.boxed(*index) }
+            }
+            ExprKind::Assignment { name, slot, expr } => ExprKind::Assignment { name, slot, expr: self.boxed(*expr) },
+            ExprKind::Destructure { pattern, expr } => {
+                let expr = self.boxed(*expr);
+                ExprKind::Destructure { pattern: self.pattern(pattern), expr }
+            }
+            ExprKind::Block(statements) => ExprKind::Block(self.block(statements, false)),
+            ExprKind::If { condition, then_branch, else_branch } => {
+                let condition = self.expr(*condition);
+                match literal(&condition) {
+                    // The chosen branch takes the place, and the span, of the
+                    // whole `if`, which is what errors about it point at.
+                    Some(value) if is_truthy(&value) => self.expr(*then_branch).kind,
+                    Some(_) => else_branch.map_or(ExprKind::Nil, |else_expr| self.expr(*else_expr).kind),
+                    None => ExprKind::If {
+                        condition: Box::new(condition),
+                        then_branch: self.boxed(*then_branch),
+                        else_branch: else_branch.map(|else_expr| self.boxed(*else_expr)),
+                    },
+                }
+            }
+            ExprKind::While { condition, body } => {
+                ExprKind::While { condition: self.boxed(*condition), body: self.boxed(*body) }
+            }
+            ExprKind::For { var, slot, iterable, body } => {
+                ExprKind::For { var, slot, iterable: self.boxed(*iterable), body: self.boxed(*body) }
+            }
+            ExprKind::FunctionDef { name, slot, params, body } => {
+                // Dropping an unreachable `yield` would stop the function being
+                // a generator, so such a body is kept as written.
+                let optimized = self.function(&params, (*body).clone());
+                let body = if contains_yield(&optimized) == contains_yield(&body) { Box::new(optimized) } else { body };
+                ExprKind::FunctionDef { name, slot, params, body }
+            }
+            ExprKind::FunctionCall { name, slot, args } => ExprKind::FunctionCall { name, slot, args: self.all(args) },
+            ExprKind::Return(value) => ExprKind::Return(self.boxed(*value)),
+            ExprKind::Yield(value) => ExprKind::Yield(self.boxed(*value)),
+            kind => kind,
+        };
+        Expr::new(kind, span)
+    }
+
+    fn boxed(&mut self, expr: Expr) -> Box<Expr> {
+        Box::new(self.expr(expr))
+    }
+
+    fn all(&mut self, exprs: Vec<Expr>) -> Vec<Expr> {
+        exprs.into_iter().map(|expr| self.expr(expr)).collect()
+    }
+
+    /// Optimizes the body of a function, with constants of its own.
+    fn function(&mut self, params: &[String], body: Expr) -> Expr {
+        let mut counts = HashMap::new();
+        count_bindings(&body, &mut counts);
+        let candidates = counts
+            .into_iter()
+            .filter(|(name, count)| *count == 1 && !params.contains(name) && !self.globals.contains(name))
+            .map(|(name, _)| name);
+        let candidates = if self.open { HashSet::new() } else { candidates.collect() };
+        let outer_candidates = mem::replace(&mut self.candidates, candidates);
+        let outer_constants = mem::take(&mut self.constants);
+        let body = match body.kind {
+            ExprKind::Block(statements) => Expr::new(ExprKind::Block(self.block(statements, true)), body.span),
+            _ => self.expr(body),
+        };
+        self.candidates = outer_candidates;
+        self.constants = outer_constants;
+        body
+    }
+
+    /// A block's statements, with nested blocks spliced in, literals whose
+    /// value is thrown away removed, and nothing after a `break`, `continue`
+    /// or `return`. In the body of a function, assigning a literal to a
+    /// candidate makes it a constant from there on.
+    fn block(&mut self, statements: Vec<Expr>, function_body: bool) -> Vec<Expr> {
+        let mut result = Vec::new();
+        for statement in statements {
+            let statement = self.expr(statement);
+            if let ExprKind::Assignment { name, expr: value, .. } = &statement.kind {
+                if function_body && self.candidates.contains(name) && literal(value).is_some() {
+                    self.constants.insert(name.clone(), value.kind.clone());
+                }
+            }
+            let inlined = match statement.kind {
+                ExprKind::Block(inner) => inner,
+                _ => vec![statement],
+            };
+            for statement in inlined {
+                if literal(&statement).is_some() {
+                    continue;
+                }
+                let exits = matches!(statement.kind, ExprKind::Break | ExprKind::Continue | ExprKind::Return(_));
+                result.push(statement);
+                if exits {
+                    return result;
+                }
+            }
+        }
+        result
+    }
+
+    fn pattern(&mut self, pattern: Pattern) -> Pattern {
+        match pattern {
+            Pattern::Index { object, index } => {
+                Pattern::Index { object: self.boxed(*object), index: self.boxed(*index) }
+            }
+            Pattern::Tuple(items) => Pattern::Tuple(items.into_iter().map(|item| self.pattern(item)).collect()),
+            Pattern::List { items, rest } => {
+                Pattern::List { items: items.into_iter().map(|item| self.pattern(item)).collect(), rest }
+            }
+            Pattern::Map(fields) => {
+                Pattern::Map(fields.into_iter().map(|(key, pattern)| (key, self.pattern(pattern))).collect())
+            }
+            pattern @ Pattern::Variable(..) => pattern,
+        }
+    }
+}
+
+/// Counts how many times each name is bound in `expr`, outside the
+/// functions it defines.
+fn count_bindings(expr: &Expr, counts: &mut HashMap<String, usize>) {
+    let mut bind = |name: &str| *counts.entry(name.to_string()).or_insert(0) += 1;
+    match &expr.kind {
+        ExprKind::Assignment { name, .. } | ExprKind::For { var: name, .. } => bind(name),
+        ExprKind::Destructure { pattern, .. } => pattern_names(pattern).iter().for_each(|name| bind(name)),
+        ExprKind::FunctionDef { name, .. } => {
+            bind(name);
+            return;
+        }
+        _ => {}
+    }
+    children(expr).into_iter().for_each(|child| count_bindings(child, counts));
+}
+
+fn pattern_names(pattern: &Pattern) -> Vec<&str> {
+    match pattern {
+        Pattern::Variable(name, _) => vec![name],
+        Pattern::Index { .. } => Vec::new(),
+        Pattern::Tuple(targets) => targets.iter().flat_map(pattern_names).collect(),
+        Pattern::List { items, rest } => {
+            let mut names: Vec<&str> = items.iter().flat_map(pattern_names).collect();
+            names.extend(rest.as_ref().map(|(name, _)| name.as_str()));
+            names
+        }
+        Pattern::Map(entries) => entries.iter().flat_map(|(_, target)| pattern_names(target)).collect(),
+    }
+}
+
+/// The value of a literal, which evaluating can neither fail nor affect
+/// anything.
+fn literal(expr: &Expr) -> Option<Value> {
+    match &expr.kind {
+        ExprKind::Number(n) => Some(Value::Number(*n)),
+        ExprKind::StringLiteral(s) => Some(Value::String(s.clone())),
+        ExprKind::Bool(b) => Some(Value::Bool(*b)),
+        ExprKind::Nil => Some(Value::Nil),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eval::Engine;
+    use crate::format::format;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+    use crate::playground::run_captured;
+
+    /// Optimizes `source` and prints the result back as source.
+    fn optimized(source: &str) -> String {
+        let tokens = Lexer::new(source, "test.alin").tokenize().unwrap();
+        let ast = Parser::new(tokens).parse().unwrap();
+        format(&optimize(ast, false), &[], source)
+    }
+
+    /// Runs `source` on both engines with and without the optimizer, checks
+    /// that all four print and report exactly the same, and gives that output.
+    fn run_all(source: &str) -> String {
+        let expected = run_captured(source, Engine::Tree, false);
+        for (engine, optimize) in [(Engine::Tree, true), (Engine::Vm, false), (Engine::Vm, true)] {
+            let output = run_captured(source, engine, optimize);
+            assert_eq!(output, expected, "{:?} with optimize = {} disagrees on:\n{}", engine, optimize, source);
+        }
+        expected
+    }
+
+    #[test]
+    fn folds_operators_on_literals() {
+        let source = "x = 2 * 3 + 1\ny = \"a\" + \"b\" + \"c\"\nz = 1 < 2 == true\nw = 1 + \"a\"\nv = x * (2 + 2)\n";
+        assert_eq!(optimized(source), "x = 7\ny = \"abc\"\nz = true\nw = 1 + \"a\"\nv = x * 4\n");
+    }
+
+    #[test]
+    fn picks_the_branch_of_a_constant_if() {
+        let source = "if 1 { print(\"one\") } else { print(\"never\") }\nif nil { print(\"never\") }\n\
+                      y = if false { 1 }\n";
+        assert_eq!(optimized(source), "{\n    print(\"one\")\n}\nnil\ny = nil\n");
+        let kept = "if x {\n    print(1)\n} else {\n    print(2)\n}\n";
+        assert_eq!(optimized("if x { print(1) } else { print(2) }\n"), kept);
+    }
+
+    #[test]
+    fn drops_code_after_break_continue_and_return() {
+        let source = "\
+fn f(n) {
+    while true {
+        if n > 3 { break; print(\"dead\") }
+        n = n + 1
+        if n == 2 { continue; print(\"dead\") }
+    }
+    return n
+    print(\"dead too\")
+}
+";
+        let output = optimized(source);
+        assert!(!output.contains("dead"), "unreachable code survived:\n{}", output);
+        assert!(output.contains("break") && output.contains("continue") && output.contains("return n"));
+    }
+
+    #[test]
+    fn inlines_the_constants_of_functions() {
+        let source = "\
+fn area(r) {
+    pi = 3
+    if debug { print(pi) }
+    scale = 2
+    twice = scale * pi
+    return twice * r * r
+}
+";
+        let expected = "\
+fn area(r) {
+    pi = 3
+    if debug {
+        print(3)
+    }
+    scale = 2
+    twice = 6
+    return 6 * r * r
+}
+";
+        assert_eq!(optimized(source), expected);
+    }
+
+    #[test]
+    fn leaves_names_that_can_change_alone() {
+        let source = "\
+count = 0
+fn f(param) {
+    param = 1
+    count = 1
+    again = 1
+    again = 2
+    if param { branch = 1 }
+    print(early)
+    early = 1
+    for item in [1] { print(item) }
+    fn inner() { return early }
+    return param + count + again + branch + early + inner()
+}
+";
+        let output = optimized(source);
+        // Only reads after the assignment, in this function, see the constant.
+        let end = "return early\n    }\n    return param + count + again + branch + 1 + inner()\n}\n";
+        assert!(output.ends_with(end), "{}", output);
+        assert!(output.contains("print(early)"), "{}", output);
+        // More code may yet define any name as a global.
+        let tokens = Lexer::new("fn f() { x = 1\nreturn x }\n", "test.alin").tokenize().unwrap();
+        let ast = optimize(Parser::new(tokens).parse().unwrap(), true);
+        assert!(format(&ast, &[], "").contains("return x"));
+    }
+
+    #[test]
+    fn keeps_generators_that_never_yield_at_run_time() {
+        let output = optimized("fn g() { return 1\nyield 2 }\nfn h() { if false { yield 1 } return 3 }\n");
+        assert_eq!(output.matches("yield").count(), 2, "{}", output);
+    }
+
+    #[test]
+    fn optimized_scripts_run_the_same() {
+        let output = run_all(
+            "x = 2 * 3 + 1
+print(x, \"a\" + \"b\" + \"c\", 1 < 2, \"x\" == \"x\", nil == nil, 10 / 4 - 1)
+if 1 { print(\"one\") } else { print(\"never\") }
+if \"s\" { print(\"string is truthy\") } else { print(\"string is falsy\") }
+y = if false { 1 }
+print(y)
+fn f(n) {
+    while true {
+        if n > 3 { break; print(\"dead\") }
+        n = n + 1
+        { print(n); 5; { \"nested\" } }
+    }
+    return n
+    print(\"dead too\")
+}
+print(f(1))
+fn g() { return 1\nyield 2 }
+fn h() { if false { yield 1 } return 3 }
+print(list(g()), list(h()))
+fn constants(n) {
+    step = 2
+    limit = step * 3
+    total = 0
+    while n < limit { n = n + step\ntotal = total + step }
+    early = n
+    return total + early
+}
+print(constants(1))
+for i in [1, 2, 3] { if i == 2 { continue; print(\"no\") } print(i) }
+if true { return 5 }
+print(\"after top-level return\")
+z = 1 + \"a\"
+",
+        );
+        let expected = "7 abc true true true 1.5\none\nstring is falsy\nnil\n2\n3\n4\n4\n[] []\n13\n1\n3\n";
+        assert!(output.starts_with(expected), "{}", output);
+        assert!(output.contains("after top-level return\nerror[E0203]"));
+    }
+}
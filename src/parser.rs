@@ -1,18 +1,97 @@
-use crate::ast::Expr;
-use crate::token::Token;
+use crate::ast::{Expr, ExprKind, Pattern};
+use crate::diagnostic::Diagnostic;
+use crate::error::AlinError;
+use crate::token::{Span, SpannedToken, Token};
 
 pub struct Parser {
-    tokens: Vec<Token>,
+    tokens: Vec<SpannedToken>,
     pos: usize,
+    diagnostics: Vec<Diagnostic>,
+    /// Set after an error until the next statement, so one mistake isn't
+    /// reported again by everything that follows it.
+    recovering: bool,
 }
 
 impl Parser {
-    pub fn new(tokens: Vec<Token>) -> Self {
-        Self { tokens, pos: 0 }
+    pub fn new(tokens: Vec<SpannedToken>) -> Self {
+        Self { tokens, pos: 0, diagnostics: Vec::new(), recovering: false }
+    }
+
+    /// Records a syntax error, unless the statement already has one.
+    fn report(&mut self, diagnostic: Diagnostic) {
+        if !self.recovering {
+            self.diagnostics.push(diagnostic);
+        }
+        self.recovering = true;
+    }
+
+    /// Reports the next token as a syntax error.
+    fn expected<T>(&mut self, what: &str) -> Option<T> {
+        let diagnostic = Diagnostic::error("E0101", format!("expected {}, found {}", what, self.peek()))
+            .with_label(self.span(), format!("expected {}", what));
+        self.report(diagnostic);
+        None
+    }
+
+    /// Consumes the delimiter closing the one at `open`. A missing one is
+    /// reported; if it turns up later on the same line, everything before it
+    /// is skipped, and otherwise it's taken as if it had been there.
+    fn close(&mut self, delimiter: Token, open: &Span) {
+        if self.eat(&delimiter) {
+            return;
+        }
+        let diagnostic = Diagnostic::error("E0103", format!("expected {}, found {}", delimiter, self.peek()))
+            .with_label(self.span(), format!("expected {}", delimiter))
+            .with_secondary(open.clone(), "unclosed delimiter");
+        self.report(diagnostic);
+        if let Some(end) = self.closing_on_line(&delimiter) {
+            self.pos = end + 1;
+        }
+    }
+
+    /// Where `delimiter` next appears on the line of the last consumed token,
+    /// outside any brackets opened before it.
+    fn closing_on_line(&self, delimiter: &Token) -> Option<usize> {
+        let line = self.tokens.get(self.pos.checked_sub(1)?)?.span.line;
+        let mut depth = 0usize;
+        for (i, t) in self.tokens.iter().enumerate().skip(self.pos) {
+            if t.span.line != line {
+                return None;
+            }
+            match &t.token {
+                token if depth == 0 && token == delimiter => return Some(i),
+                Token::LParen | Token::LBracket | Token::LBrace => depth += 1,
+                Token::RParen | Token::RBracket | Token::RBrace => depth = depth.checked_sub(1)?,
+                Token::Semicolon | Token::Eof if depth == 0 => return None,
+                _ => {}
+            }
+        }
+        None
     }
 
     fn peek(&self) -> Token {
-        self.tokens.get(self.pos).cloned().unwrap_or(Token::Eof)
+        self.peek_at(0)
+    }
+
+    fn peek_at(&self, offset: usize) -> Token {
+        self.tokens.get(self.pos + offset).map_or(Token::Eof, |t| t.token.clone())
+    }
+
+    /// The span of the next token, where a node starting here begins.
+    fn span(&self) -> Span {
+        self.tokens
+            .get(self.pos)
+            .or(self.tokens.last())
+            .map_or_else(Span::default, |t| t.span.clone())
+    }
+
+    /// A node running from `start` to the end of the last consumed token.
+    fn finish(&self, kind: ExprKind, start: &Span) -> Expr {
+        let end = match self.pos.checked_sub(1).and_then(|i| self.tokens.get(i)) {
+            Some(last) if last.span.offset >= start.offset => start.to(&last.span),
+            _ => start.clone(),
+        };
+        Expr::new(kind, end)
     }
 
     fn next(&mut self) -> Token {
@@ -30,76 +109,238 @@ impl Parser {
         }
     }
 
-    pub fn parse(&mut self) -> Vec<Expr> {
+    pub fn parse(&mut self) -> Result<Vec<Expr>, AlinError> {
+        let (exprs, diagnostics) = self.parse_partial();
+        if diagnostics.is_empty() {
+            Ok(exprs)
+        } else {
+            Err(AlinError::Parse(diagnostics))
+        }
+    }
+
+    /// Parses the whole input even if it has syntax errors, returning every
+    /// error found. Broken statements become `ExprKind::Error` nodes, so
+    /// tools can still look at the rest of the program.
+    pub fn parse_partial(&mut self) -> (Vec<Expr>, Vec<Diagnostic>) {
         let mut exprs = Vec::new();
         while self.peek() != Token::Eof {
-            if let Some(expr) = self.parse_expr() {
-                exprs.push(expr);
-                self.eat(&Token::Semicolon); // optional
-            } else {
-                panic!("Syntax error near {:?}", self.peek());
+            exprs.push(self.parse_statement_or_recover());
+            self.eat(&Token::Semicolon); // optional
+        }
+        (exprs, std::mem::take(&mut self.diagnostics))
+    }
+
+    /// Parses a statement, or skips past a broken one and returns an error
+    /// node covering what was skipped.
+    fn parse_statement_or_recover(&mut self) -> Expr {
+        let start = self.span();
+        let first = self.pos;
+        self.recovering = false;
+        if let Some(expr) = self.parse_statement() {
+            return expr;
+        }
+        self.expected::<()>("a statement");
+        self.synchronize(first);
+        self.finish(ExprKind::Error, &start)
+    }
+
+    /// Skips to where the next statement probably starts: after a `;`,
+    /// before the `}` closing the enclosing block, before a statement
+    /// keyword, or at the start of a new line. Bracketed groups are skipped
+    /// whole, as are the closers of brackets the broken statement opened,
+    /// and at least one token always is.
+    fn synchronize(&mut self, first: usize) {
+        let line = self.tokens[self.pos.saturating_sub(1).max(first)].span.line;
+        let mut unclosed = self.tokens[first..self.pos].iter().fold(0usize, |open, t| match t.token {
+            Token::LParen | Token::LBracket | Token::LBrace => open + 1,
+            Token::RParen | Token::RBracket | Token::RBrace => open.saturating_sub(1),
+            _ => open,
+        });
+        let mut depth = 0usize;
+        loop {
+            let at_boundary = depth == 0 && self.pos > first;
+            match self.peek() {
+                Token::Eof => break,
+                Token::RParen | Token::RBracket | Token::RBrace if depth == 0 && unclosed > 0 => unclosed -= 1,
+                Token::RBrace if depth == 0 => break,
+                Token::Semicolon if depth == 0 => break,
+                Token::If | Token::While | Token::For | Token::Fn | Token::Return | Token::Yield
+                | Token::Break | Token::Continue if at_boundary => break,
+                _ if at_boundary && self.span().line > line => break,
+                Token::LParen | Token::LBracket | Token::LBrace => depth += 1,
+                Token::RParen | Token::RBracket | Token::RBrace => depth = depth.saturating_sub(1),
+                _ => {}
             }
+            self.pos += 1;
+        }
+        if self.pos == first && self.peek() != Token::Eof {
+            self.pos += 1;
+        }
+    }
+
+    /// Like `parse_expr`, but a statement may also be a bare tuple such as
+    /// `x, y = y, x`.
+    fn parse_statement(&mut self) -> Option<Expr> {
+        match self.peek() {
+            Token::LBrace if self.at_map_pattern() => self.parse_assignment(true),
+            Token::If | Token::While | Token::For | Token::LBrace | Token::Return
+            | Token::Yield | Token::Fn => self.parse_expr(),
+            _ => self.parse_assignment(true),
         }
-        exprs
     }
 
     fn parse_expr(&mut self) -> Option<Expr> {
         match self.peek() {
             Token::If => self.parse_if(),
             Token::While => self.parse_while(),
-            Token::LBrace => self.parse_block(),
+            Token::For => self.parse_for(),
+            Token::LBrace if !self.at_map_literal() => self.parse_block(),
             Token::Return => self.parse_return(),
-            _ => self.parse_assignment(),
+            Token::Yield => self.parse_yield(),
+            Token::Fn => self.parse_function(),
+            _ => self.parse_assignment(false),
         }
     }
 
-    fn parse_assignment(&mut self) -> Option<Expr> {
-        let expr = self.parse_comparison()?;
+    /// An expression in value position, where `{}` is an empty map rather
+    /// than an empty block, and `{x}` or `{x, ...` a map with shorthand
+    /// entries rather than a block reading `x`.
+    fn parse_value(&mut self) -> Option<Expr> {
+        if self.peek() == Token::LBrace && self.peek_at(1) == Token::RBrace {
+            let start = self.span();
+            self.pos += 2;
+            return Some(self.finish(ExprKind::Map(Vec::new()), &start));
+        }
+        if self.peek() == Token::LBrace
+            && matches!(self.peek_at(1), Token::Identifier(_))
+            && matches!(self.peek_at(2), Token::RBrace | Token::Comma)
+        {
+            return self.parse_assignment(false);
+        }
+        self.parse_expr()
+    }
+
+    /// Parses `value, value, ...` when `allow_tuple` is set, otherwise a
+    /// single value.
+    fn parse_values(&mut self, allow_tuple: bool) -> Option<Expr> {
+        let start = self.span();
+        let first = self.parse_value()?;
+        if !allow_tuple || self.peek() != Token::Comma {
+            return Some(first);
+        }
+
+        let mut items = vec![first];
+        while self.eat(&Token::Comma) {
+            items.push(self.parse_value()?);
+        }
+        Some(self.finish(ExprKind::Tuple(items), &start))
+    }
+
+    fn parse_assignment(&mut self, allow_tuple: bool) -> Option<Expr> {
+        let start = self.span();
+        let mut expr = self.parse_comparison()?;
+
+        if allow_tuple && self.peek() == Token::Comma {
+            let mut items = vec![expr];
+            while self.eat(&Token::Comma) {
+                items.push(self.parse_comparison()?);
+            }
+            expr = self.finish(ExprKind::Tuple(items), &start);
+        }
 
         if self.peek() == Token::Equal {
             self.next(); // consume '='
-            if let Expr::Variable(name) = expr {
-                let value = self.parse_expr()?;
-                return Some(Expr::Assignment {
-                    name,
-                    expr: Box::new(value),
-                });
-            } else {
-                panic!("Invalid assignment target");
-            }
+            let value = Box::new(self.parse_values(allow_tuple)?);
+            let target = expr.span.clone();
+            return match expr.kind {
+                ExprKind::Variable(name, slot) => {
+                    Some(self.finish(ExprKind::Assignment { name, slot, expr: value }, &start))
+                }
+                _ => match to_pattern(expr) {
+                    Some(pattern) => Some(self.finish(ExprKind::Destructure { pattern, expr: value }, &start)),
+                    None => {
+                        let diagnostic = Diagnostic::error("E0102", "invalid assignment target")
+                            .with_label(target, "cannot assign to this")
+                            .with_note("only variables, indexes and tuple, list or map patterns can be assigned to");
+                        self.report(diagnostic);
+                        None
+                    }
+                },
+            };
         }
 
         Some(expr)
     }
 
+    /// `.a.b(` after an identifier: a dotted function name.
+    fn at_qualified_call(&self) -> bool {
+        let mut offset = 0;
+        while self.peek_at(offset) == Token::Dot {
+            if !matches!(self.peek_at(offset + 1), Token::Identifier(_)) {
+                return false;
+            }
+            offset += 2;
+        }
+        offset > 0 && self.peek_at(offset) == Token::LParen
+    }
+
+    /// `{ key: ...` starts a map literal rather than a block.
+    fn at_map_literal(&self) -> bool {
+        matches!(self.peek_at(1), Token::Identifier(_) | Token::String(_))
+            && self.peek_at(2) == Token::Colon
+    }
+
+    /// `{name, age} = person`: braces directly followed by `=`.
+    fn at_map_pattern(&self) -> bool {
+        let mut depth = 0;
+        for (offset, token) in self.tokens[self.pos..].iter().enumerate() {
+            match token.token {
+                Token::LBrace => depth += 1,
+                Token::RBrace => {
+                    depth -= 1;
+                    if depth == 0 {
+                        return self.peek_at(offset + 1) == Token::Equal;
+                    }
+                }
+                Token::Eof => break,
+                _ => {}
+            }
+        }
+        false
+    }
+
     fn parse_comparison(&mut self) -> Option<Expr> {
+        let start = self.span();
         let mut expr = self.parse_binary(5)?;
 
         while matches!(
             self.peek(),
-            Token::EqualEqual | Token::BangEqual | Token::Less | Token::LessEqual
+            Token::EqualEqual
+                | Token::BangEqual
+                | Token::Less
+                | Token::LessEqual
+                | Token::Greater
+                | Token::GreaterEqual
         ) {
             let op = self.next();
             let right = self.parse_binary(5)?;
-            expr = Expr::Binary {
+            let kind = ExprKind::Binary {
                 left: Box::new(expr),
                 op,
                 right: Box::new(right),
             };
+            expr = self.finish(kind, &start);
         }
 
         Some(expr)
     }
 
     fn parse_binary(&mut self, min_prec: u8) -> Option<Expr> {
+        let start = self.span();
         let mut lhs = self.parse_primary()?;
 
-        loop {
-            let op = match self.peek() {
-                Token::Plus | Token::Minus | Token::Star | Token::Slash => self.peek(),
-                _ => break,
-            };
-
+        while let Token::Plus | Token::Minus | Token::Star | Token::Slash = self.peek() {
+            let op = self.peek();
             let prec = get_precedence(&op);
             if prec < min_prec {
                 break;
@@ -107,55 +348,169 @@ impl Parser {
 
             let op_token = self.next();
             let rhs = self.parse_binary(prec + 1)?;
-            lhs = Expr::Binary {
+            let kind = ExprKind::Binary {
                 left: Box::new(lhs),
                 op: op_token,
                 right: Box::new(rhs),
             };
+            lhs = self.finish(kind, &start);
         }
 
         Some(lhs)
     }
 
     fn parse_primary(&mut self) -> Option<Expr> {
-        match self.next() {
-            Token::Number(n) => Some(Expr::Number(n)),
-            Token::String(s) => Some(Expr::StringLiteral(s)),
-            Token::Identifier(name) => {
+        let start = self.span();
+        let mut expr = self.parse_atom()?;
+
+        loop {
+            if self.peek() == Token::Dot {
+                // `person.name` is shorthand for `person["name"]`.
+                let Token::Identifier(field) = self.peek_at(1) else {
+                    break;
+                };
+                let field_span = self.tokens[self.pos + 1].span.clone();
+                self.pos += 2;
+                let kind = ExprKind::Index {
+                    object: Box::new(expr),
+                    index: Box::new(Expr::new(ExprKind::StringLiteral(field), field_span)),
+                };
+                expr = self.finish(kind, &start);
+                continue;
+            }
+            if self.peek() != Token::LBracket {
+                break;
+            }
+
+            // Statements need no separator, so `[` may instead start a list
+            // pattern on the next line (`[first, ...rest] = xs`); only treat
+            // it as an index when it holds exactly one expression.
+            let bracket = self.pos;
+            let (reported, recovering) = (self.diagnostics.len(), self.recovering);
+            self.next(); // consume '['
+            let index = match self.parse_expr() {
+                Some(index) if self.eat(&Token::RBracket) => index,
+                _ => {
+                    self.pos = bracket;
+                    self.diagnostics.truncate(reported);
+                    self.recovering = recovering;
+                    break;
+                }
+            };
+            let kind = ExprKind::Index {
+                object: Box::new(expr),
+                index: Box::new(index),
+            };
+            expr = self.finish(kind, &start);
+        }
+
+        Some(expr)
+    }
+
+    fn parse_atom(&mut self) -> Option<Expr> {
+        let start = self.span();
+        let kind = match self.next() {
+            Token::Number(n) => ExprKind::Number(n),
+            Token::String(s) => ExprKind::StringLiteral(s),
+            Token::Identifier(mut name) => {
+                // `string.upper(s)` calls a function from a builtin module.
+                if self.at_qualified_call() {
+                    while self.eat(&Token::Dot) {
+                        if let Token::Identifier(part) = self.next() {
+                            name = format!("{}.{}", name, part);
+                        }
+                    }
+                }
                 if self.peek() == Token::LParen {
+                    let open = self.span();
                     self.next(); // consume '('
                     let mut args = Vec::new();
                     while self.peek() != Token::RParen {
-                        if let Some(arg) = self.parse_expr() {
-                            args.push(arg);
-                        }
+                        args.push(self.parse_value()?);
                         if self.peek() == Token::Comma {
                             self.next(); // consume ','
                         } else {
                             break;
                         }
                     }
-                    self.eat(&Token::RParen);
-                    Some(Expr::FunctionCall { name, args })
+                    self.close(Token::RParen, &open);
+                    ExprKind::FunctionCall { name, slot: None, args }
                 } else {
-                    Some(Expr::Variable(name))
+                    ExprKind::Variable(name, None)
                 }
             }
             Token::LParen => {
-                let expr = self.parse_expr()?;
-                self.eat(&Token::RParen);
-                Some(expr)
+                if self.eat(&Token::RParen) {
+                    return Some(self.finish(ExprKind::Tuple(Vec::new()), &start));
+                }
+                let expr = self.parse_value()?;
+                if self.peek() != Token::Comma {
+                    self.close(Token::RParen, &start);
+                    return Some(expr);
+                }
+
+                let mut items = vec![expr];
+                while self.eat(&Token::Comma) && self.peek() != Token::RParen {
+                    items.push(self.parse_value()?);
+                }
+                self.close(Token::RParen, &start);
+                ExprKind::Tuple(items)
             }
-            Token::Break => Some(Expr::Break),
+            Token::LBracket => {
+                let mut items = Vec::new();
+                while self.peek() != Token::RBracket {
+                    let item_start = self.span();
+                    if self.eat(&Token::Ellipsis) {
+                        let inner = Box::new(self.parse_value()?);
+                        items.push(self.finish(ExprKind::Spread(inner), &item_start));
+                    } else {
+                        items.push(self.parse_value()?);
+                    }
+                    if !self.eat(&Token::Comma) {
+                        break;
+                    }
+                }
+                self.close(Token::RBracket, &start);
+                ExprKind::List(items)
+            }
+            Token::LBrace => {
+                let mut entries = Vec::new();
+                while self.peek() != Token::RBrace {
+                    let key_span = self.span();
+                    let (Token::Identifier(key) | Token::String(key)) = self.peek() else {
+                        return self.expected("a map key");
+                    };
+                    self.next();
+                    let value = if self.eat(&Token::Colon) {
+                        self.parse_value()?
+                    } else {
+                        Expr::new(ExprKind::Variable(key.clone(), None), key_span)
+                    };
+                    entries.push((key, value));
+                    if !self.eat(&Token::Comma) {
+                        break;
+                    }
+                }
+                self.close(Token::RBrace, &start);
+                ExprKind::Map(entries)
+            }
+            Token::True => ExprKind::Bool(true),
+            Token::False => ExprKind::Bool(false),
+            Token::Nil => ExprKind::Nil,
+            Token::Break => ExprKind::Break,
 
-            Token::Continue => Some(Expr::Continue),
+            Token::Continue => ExprKind::Continue,
 
-            
-            _ => None,
-        }
+            _ => {
+                self.pos -= 1;
+                return self.expected("an expression");
+            }
+        };
+        Some(self.finish(kind, &start))
     }
 
     fn parse_if(&mut self) -> Option<Expr> {
+        let start = self.span();
         self.next(); // consume `if`
         let condition = self.parse_expr()?;
         let then_branch = Box::new(self.parse_expr()?);
@@ -166,40 +521,100 @@ impl Parser {
             None
         };
 
-        Some(Expr::If {
+        let kind = ExprKind::If {
             condition: Box::new(condition),
             then_branch,
             else_branch,
-        })
+        };
+        Some(self.finish(kind, &start))
     }
 
     fn parse_while(&mut self) -> Option<Expr> {
+        let start = self.span();
         self.next(); // consume `while`
         let condition = self.parse_expr()?;
         let body = Box::new(self.parse_expr()?);
-        Some(Expr::While {
+        let kind = ExprKind::While {
             condition: Box::new(condition),
             body,
-        })
+        };
+        Some(self.finish(kind, &start))
+    }
+
+    fn parse_for(&mut self) -> Option<Expr> {
+        let start = self.span();
+        self.next(); // consume `for`
+        let Token::Identifier(var) = self.peek() else {
+            return self.expected("a loop variable");
+        };
+        self.next();
+        if !self.eat(&Token::In) {
+            return self.expected("`in`");
+        }
+        let iterable = self.parse_expr()?;
+        let body = Box::new(self.parse_expr()?);
+        let kind = ExprKind::For {
+            var,
+            slot: None,
+            iterable: Box::new(iterable),
+            body,
+        };
+        Some(self.finish(kind, &start))
+    }
+
+    fn parse_function(&mut self) -> Option<Expr> {
+        let start = self.span();
+        self.next(); // consume `fn`
+        let Token::Identifier(name) = self.peek() else {
+            return self.expected("a function name");
+        };
+        self.next();
+
+        if !self.eat(&Token::LParen) {
+            return self.expected("`(`");
+        }
+        let mut params = Vec::new();
+        while let Token::Identifier(param) = self.peek() {
+            self.next();
+            params.push(param);
+            if !self.eat(&Token::Comma) {
+                break;
+            }
+        }
+        if !self.eat(&Token::RParen) {
+            return self.expected("`)`");
+        }
+
+        let body = Box::new(self.parse_block()?);
+        Some(self.finish(ExprKind::FunctionDef { name, slot: None, params, body }, &start))
     }
 
     fn parse_return(&mut self) -> Option<Expr> {
+        let start = self.span();
         self.next(); // consume `return`
-        let value = self.parse_expr()?;
-        Some(Expr::Return(Box::new(value)))
+        let value = self.parse_values(true)?;
+        Some(self.finish(ExprKind::Return(Box::new(value)), &start))
+    }
+
+    fn parse_yield(&mut self) -> Option<Expr> {
+        let start = self.span();
+        self.next(); // consume `yield`
+        let value = self.parse_value()?;
+        Some(self.finish(ExprKind::Yield(Box::new(value)), &start))
     }
 
     fn parse_block(&mut self) -> Option<Expr> {
-        self.eat(&Token::LBrace); // consume '{'
+        let start = self.span();
+        if !self.eat(&Token::LBrace) {
+            return self.expected("`{`");
+        }
         let mut exprs = Vec::new();
         while self.peek() != Token::RBrace && self.peek() != Token::Eof {
-            if let Some(expr) = self.parse_expr() {
-                exprs.push(expr);
-                self.eat(&Token::Semicolon); // optional
-            }
+            exprs.push(self.parse_statement_or_recover());
+            self.eat(&Token::Semicolon); // optional
         }
-        self.eat(&Token::RBrace); // consume '}'
-        Some(Expr::Block(exprs))
+        self.close(Token::RBrace, &start);
+        Some(self.finish(ExprKind::Block(exprs), &start))
     }
 }
 
@@ -210,3 +625,125 @@ fn get_precedence(tok: &Token) -> u8 {
         _ => 0,
     }
 }
+
+/// Reinterprets an expression parsed on the left of `=` as a pattern.
+fn to_pattern(expr: Expr) -> Option<Pattern> {
+    match expr.kind {
+        ExprKind::Variable(name, slot) => Some(Pattern::Variable(name, slot)),
+        ExprKind::Index { object, index } => Some(Pattern::Index { object, index }),
+        ExprKind::Tuple(items) => items.into_iter().map(to_pattern).collect::<Option<_>>().map(Pattern::Tuple),
+        ExprKind::List(mut items) => {
+            let rest = match items.last().map(|item| &item.kind) {
+                Some(ExprKind::Spread(inner)) => match &inner.kind {
+                    ExprKind::Variable(name, slot) => Some((name.clone(), *slot)),
+                    _ => return None,
+                },
+                _ => None,
+            };
+            if rest.is_some() {
+                items.pop();
+            }
+            let items = items.into_iter().map(to_pattern).collect::<Option<_>>()?;
+            Some(Pattern::List { items, rest })
+        }
+        ExprKind::Map(entries) => entries
+            .into_iter()
+            .map(|(key, value)| Some((key, to_pattern(value)?)))
+            .collect::<Option<_>>()
+            .map(Pattern::Map),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+
+    fn parse(source: &str) -> Result<Vec<Expr>, AlinError> {
+        Parser::new(Lexer::new(source, "test.alin").tokenize()?).parse()
+    }
+
+    /// The code and line of each syntax error in `source`.
+    fn errors(source: &str) -> Vec<(&'static str, usize)> {
+        match parse(source) {
+            Err(AlinError::Parse(diagnostics)) => {
+                diagnostics.iter().map(|diagnostic| (diagnostic.code, diagnostic.labels[0].span.line)).collect()
+            }
+            result => panic!("no syntax errors: {:?}", result),
+        }
+    }
+
+    /// The keys of the map assigned by `source`, a single `y = {...}`, and
+    /// whether each is a shorthand for the variable of the same name.
+    fn assigned_map(source: &str) -> Vec<(String, bool)> {
+        let ast = parse(source).unwrap();
+        let [Expr { kind: ExprKind::Assignment { expr, .. }, .. }] = &ast[..] else {
+            panic!("not one assignment: {:?}", ast);
+        };
+        let ExprKind::Map(entries) = &expr.kind else {
+            panic!("not a map: {:?}", expr);
+        };
+        let short = |key: &String, value: &Expr| matches!(&value.kind, ExprKind::Variable(name, _) if name == key);
+        entries.iter().map(|(key, value)| (key.clone(), short(key, value))).collect()
+    }
+
+    #[test]
+    fn shorthand_maps_in_value_position() {
+        assert_eq!(assigned_map("y = {x}"), [("x".to_string(), true)]);
+        assert_eq!(assigned_map("y = {x, w: 2}"), [("x".to_string(), true), ("w".to_string(), false)]);
+        assert_eq!(assigned_map("y = {a: 1, x}"), [("a".to_string(), false), ("x".to_string(), true)]);
+        assert_eq!(assigned_map("y = {x, w,}"), [("x".to_string(), true), ("w".to_string(), true)]);
+        assert!(assigned_map("y = {}").is_empty());
+    }
+
+    #[test]
+    fn shorthand_maps_as_arguments_items_and_results() {
+        let ast = parse("print({x}, [{x}])\nfn f(x) { return {x} }").unwrap();
+        let ExprKind::FunctionCall { args, .. } = &ast[0].kind else { panic!("{:?}", ast[0]) };
+        assert!(matches!(&args[0].kind, ExprKind::Map(entries) if entries.len() == 1));
+        assert!(matches!(&args[1].kind, ExprKind::List(items) if matches!(items[0].kind, ExprKind::Map(_))));
+        let ExprKind::FunctionDef { body, .. } = &ast[1].kind else { panic!("{:?}", ast[1]) };
+        let ExprKind::Block(statements) = &body.kind else { panic!("{:?}", body) };
+        assert!(matches!(&statements[0].kind, ExprKind::Return(value) if matches!(value.kind, ExprKind::Map(_))));
+    }
+
+    #[test]
+    fn braces_starting_a_statement_are_blocks() {
+        let ast = parse("{x}\nif c {x}\n{x, y} = p").unwrap();
+        assert!(matches!(&ast[0].kind, ExprKind::Block(_)));
+        let ExprKind::If { then_branch, .. } = &ast[1].kind else { panic!("{:?}", ast[1]) };
+        assert!(matches!(then_branch.kind, ExprKind::Block(_)));
+        assert!(matches!(&ast[2].kind, ExprKind::Destructure { pattern: Pattern::Map(_), .. }));
+    }
+
+    #[test]
+    fn reports_each_syntax_error_once() {
+        let source = "\
+1 = y
+fn f(a) {
+    b = (a * 2
+    return b
+}
+print(x y)
+z = [1, 2
+w = {a: }
+print(w)
+if true { print(1)) }
+";
+        let expected = [("E0102", 1), ("E0103", 4), ("E0103", 6), ("E0103", 8), ("E0101", 8), ("E0101", 10)];
+        assert_eq!(errors(source), expected);
+    }
+
+    #[test]
+    fn keeps_the_statements_around_syntax_errors() {
+        let source = "x = {a: }\ny = [1, (2 3), 4]\nprint(x y)\nprint(y)\n";
+        let (ast, diagnostics) = Parser::new(Lexer::new(source, "test.alin").tokenize().unwrap()).parse_partial();
+        assert_eq!(diagnostics.len(), 3);
+        assert!(matches!(ast[0].kind, ExprKind::Error));
+        assert!(matches!(&ast[1].kind, ExprKind::Assignment { expr, .. } if matches!(expr.kind, ExprKind::List(_))));
+        assert!(matches!(&ast[2].kind, ExprKind::FunctionCall { args, .. } if args.len() == 1));
+        assert!(matches!(&ast[3].kind, ExprKind::FunctionCall { name, .. } if name == "print"));
+        assert_eq!(ast.len(), 4);
+    }
+}
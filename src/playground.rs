@@ -1,19 +1,346 @@
 use std::fs;
-use crate::{lexer::Lexer, parser::Parser, eval::Evaluator};
+use std::path::Path;
+use std::rc::Rc;
+use crate::{lexer::Lexer, parser::Parser, eval::{Engine, Evaluator}, infer::Inferer};
+use crate::alinc::{cache_path, compiled_path, source_hash};
+use crate::ast::Expr;
+use crate::bytecode::Proto;
+use crate::cst::SyntaxTree;
+use crate::diagnostic::{Color, Diagnostic, Emitter, ErrorFormat};
+use crate::error::AlinError;
+use crate::format::format;
+use crate::lint::{lint, Config};
+use crate::optimize::optimize;
 
-pub fn run_file(path: &str) {
+/// Settings from the command line that affect how programs run.
+#[derive(Debug, Default)]
+pub struct Options {
+    /// Seed for the `random` module, for reproducible runs.
+    pub seed: Option<u64>,
+    /// Arguments after the script path, returned by `args()`.
+    pub args: Vec<String>,
+    pub error_format: ErrorFormat,
+    pub color: Color,
+    /// The bytecode VM by default; the tree-walker is kept as the reference
+    /// to compare it against.
+    pub engine: Engine,
+    /// Runs scripts exactly as parsed, skipping `optimize`.
+    pub no_opt: bool,
+}
+
+impl Options {
+    pub fn emitter(&self) -> Emitter {
+        Emitter::new(self.error_format, self.color)
+    }
+
+    pub fn evaluator(&self) -> Evaluator {
+        let mut evaluator = Evaluator::new();
+        if let Some(seed) = self.seed {
+            evaluator.seed(seed);
+        }
+        evaluator.set_args(self.args.clone());
+        evaluator.set_engine(self.engine);
+        evaluator.set_optimize(!self.no_opt);
+        evaluator
+    }
+}
+
+/// Lexes, parses and runs `source` in `evaluator`, printing any lexer
+/// warnings along the way.
+pub fn run_source(evaluator: &mut Evaluator, emitter: &Emitter, source: &str, file: &str) -> Result<(), AlinError> {
+    let (ast, _) = parse_source(evaluator, emitter, source, file)?;
+    evaluator.run(&ast)
+}
+
+/// Runs `source` with a fixed seed on a thread with the interpreter's
+/// stack, giving what it printed followed by the diagnostics it failed
+/// with, rendered without color.
+#[cfg(test)]
+pub(crate) fn run_captured(source: &str, engine: Engine, optimize: bool) -> String {
+    let source = source.to_string();
+    let run = move || {
+        let mut evaluator = Evaluator::new();
+        evaluator.seed(1);
+        evaluator.set_engine(engine);
+        evaluator.set_optimize(optimize);
+        evaluator.output = Some(String::new());
+        let mut emitter = Emitter::new(ErrorFormat::Human, Color::Never);
+        emitter.add_source("test.alin", &source);
+
+        let result = run_source(&mut evaluator, &emitter, &source, "test.alin");
+        let mut output = evaluator.output.take().unwrap_or_default();
+        if let Err(error) = result {
+            for diagnostic in error.diagnostics() {
+                output.push_str(&emitter.render(diagnostic));
+            }
+        }
+        output
+    };
+    std::thread::Builder::new().stack_size(crate::STACK_SIZE).spawn(run).unwrap().join().unwrap()
+}
+
+/// Lexes, parses and compiles `source` to bytecode, printing any warnings
+/// along the way and handing them back with the code.
+fn compile_source(
+    evaluator: &mut Evaluator,
+    emitter: &Emitter,
+    source: &str,
+    file: &str,
+) -> Result<(Rc<Proto>, Vec<Diagnostic>), AlinError> {
+    let (ast, warnings) = parse_source(evaluator, emitter, source, file)?;
+    Ok((evaluator.compile(&ast), warnings))
+}
+
+/// Lexes, parses and resolves `source`, optimizing the result unless
+/// `evaluator` was told not to. Warnings from lexing and resolving are
+/// printed as they come, and handed back with the result.
+fn parse_source(
+    evaluator: &Evaluator,
+    emitter: &Emitter,
+    source: &str,
+    file: &str,
+) -> Result<(Vec<Expr>, Vec<Diagnostic>), AlinError> {
+    let mut lexer = Lexer::new(source, file);
+    let tokens = lexer.tokenize()?;
+    emitter.emit_all(lexer.warnings());
+    let mut warnings = lexer.warnings().to_vec();
+
+    let mut ast = Parser::new(tokens).parse()?;
+    let unused = evaluator.resolve(&mut ast)?;
+    emitter.emit_all(&unused);
+    warnings.extend(unused);
+    let ast = if evaluator.optimizes() { optimize(ast, evaluator.is_interactive()) } else { ast };
+    Ok((ast, warnings))
+}
+
+/// Runs `source` on the VM, reusing its cached `.alinc` file when it was
+/// compiled from the same source by the same build of alin, and caching one
+/// otherwise. A cached script repeats the warnings compiling it gave.
+fn run_cached(evaluator: &mut Evaluator, emitter: &Emitter, source: &str, path: &str) -> Result<(), AlinError> {
+    let hash = source_hash(source);
+    let cache = cache_path(path);
+    let cached = fs::read(&cache)
+        .ok()
+        .and_then(|bytes| evaluator.decode(&bytes).ok())
+        .filter(|module| module.file == path && module.source_hash == hash);
+    let script = match cached {
+        Some(module) => {
+            emitter.emit_all(&module.warnings);
+            module.script
+        }
+        None => {
+            let (script, warnings) = compile_source(evaluator, emitter, source, path)?;
+            // The cache is only an optimization, e.g. without a writable
+            // cache directory.
+            if let Some(dir) = cache.parent() {
+                let _ = fs::create_dir_all(dir);
+            }
+            let _ = fs::write(&cache, evaluator.encode(&script, &warnings, path, hash));
+            script
+        }
+    };
+    evaluator.run_compiled(script)
+}
+
+/// Runs a script, or a compiled `.alinc` file, returning whether it
+/// finished without errors.
+pub fn run_file(path: &str, options: &Options) -> bool {
+    if path.ends_with(".alinc") {
+        return run_compiled_file(path, options);
+    }
     let Ok(source) = fs::read_to_string(path) else {
         eprintln!("File not found: {}", path);
-        return;
+        return false;
     };
 
     println!("Running file: {}", path);
-    let mut lexer = Lexer::new(&source);
-    let tokens = lexer.tokenize();
+    let mut emitter = options.emitter();
+    emitter.add_source(path, &source);
+    let mut evaluator = options.evaluator();
+    let result = match options.engine {
+        // Cached code may have been optimized, so `--no-opt` bypasses it.
+        Engine::Vm if !options.no_opt => run_cached(&mut evaluator, &emitter, &source, path),
+        _ => run_source(&mut evaluator, &emitter, &source, path),
+    };
+    match result {
+        Ok(()) => true,
+        Err(err) => {
+            emitter.emit_all(err.diagnostics());
+            false
+        }
+    }
+}
+
+fn run_compiled_file(path: &str, options: &Options) -> bool {
+    let Ok(bytes) = fs::read(path) else {
+        eprintln!("File not found: {}", path);
+        return false;
+    };
+    let mut evaluator = options.evaluator();
+    let module = match evaluator.decode(&bytes) {
+        Ok(module) => module,
+        Err(reason) => {
+            eprintln!("Cannot load {}: {}", path, reason);
+            return false;
+        }
+    };
+
+    println!("Running file: {}", path);
+    let mut emitter = options.emitter();
+    // Errors can only quote the source if it hasn't changed since.
+    if let Ok(source) = fs::read_to_string(&module.file) {
+        if source_hash(&source) == module.source_hash {
+            emitter.add_source(&module.file, &source);
+        }
+    }
+    match evaluator.run_compiled(module.script) {
+        Ok(()) => true,
+        Err(err) => {
+            emitter.emit_all(err.diagnostics());
+            false
+        }
+    }
+}
+
+/// Compiles a script to an `.alinc` file beside it, returning whether it
+/// compiled.
+pub fn compile_file(path: &str, options: &Options) -> bool {
+    let Ok(source) = fs::read_to_string(path) else {
+        eprintln!("File not found: {}", path);
+        return false;
+    };
+    let mut emitter = options.emitter();
+    emitter.add_source(path, &source);
+    let mut evaluator = options.evaluator();
+    let (script, warnings) = match compile_source(&mut evaluator, &emitter, &source, path) {
+        Ok(compiled) => compiled,
+        Err(err) => {
+            emitter.emit_all(err.diagnostics());
+            return false;
+        }
+    };
+
+    let output = compiled_path(path);
+    match fs::write(&output, evaluator.encode(&script, &warnings, path, source_hash(&source))) {
+        Ok(()) => {
+            println!("Compiled {} to {}", path, output.display());
+            true
+        }
+        Err(err) => {
+            eprintln!("Cannot write {}: {}", output.display(), err);
+            false
+        }
+    }
+}
+
+/// Rewrites the script at `path` in the canonical style, or with `check`
+/// only reports whether it already is, returning whether it was.
+pub fn format_file(path: &str, check: bool, options: &Options) -> bool {
+    let Ok(source) = fs::read_to_string(path) else {
+        eprintln!("File not found: {}", path);
+        return false;
+    };
+    let mut emitter = options.emitter();
+    emitter.add_source(path, &source);
+
+    // Only a script that parses completely can be printed back in full.
+    let tree = SyntaxTree::parse(&source, path);
+    let ast = match tree.lower() {
+        Ok(ast) => ast,
+        Err(err) => {
+            emitter.emit_all(err.diagnostics());
+            return false;
+        }
+    };
+    let formatted = format(&ast, &tree.comments(), &source);
+    if formatted == source {
+        return true;
+    }
+    if check {
+        eprintln!("{} is not formatted", path);
+        return false;
+    }
+    match fs::write(path, formatted) {
+        Ok(()) => true,
+        Err(err) => {
+            eprintln!("Cannot write {}: {}", path, err);
+            false
+        }
+    }
+}
+
+/// Lints a script with the rules its project configures, returning whether
+/// nothing was found at the `error` level.
+pub fn lint_file(path: &str, options: &Options) -> bool {
+    let Ok(source) = fs::read_to_string(path) else {
+        eprintln!("File not found: {}", path);
+        return false;
+    };
+    let config = match Config::find(Path::new(path)) {
+        Ok(config) => config,
+        Err(message) => {
+            eprintln!("Invalid {}", message);
+            return false;
+        }
+    };
+    let mut emitter = options.emitter();
+    emitter.add_source(path, &source);
+
+    let mut lexer = Lexer::new(&source, path);
+    let tokens = match lexer.tokenize() {
+        Ok(tokens) => tokens,
+        Err(err) => {
+            emitter.emit_all(err.diagnostics());
+            return false;
+        }
+    };
+    emitter.emit_all(lexer.warnings());
+
+    // Lint whatever parsed, as `check_file` does.
+    let (ast, syntax_errors) = Parser::new(tokens.clone()).parse_partial();
+    emitter.emit_all(&syntax_errors);
+
+    let findings = lint(&ast, &source, &tokens, lexer.comments(), &config, &options.evaluator().builtins);
+    emitter.emit_all(&findings);
+    syntax_errors.is_empty() && !findings.iter().any(Diagnostic::is_error)
+}
+
+/// Type-checks a script, returning whether it has no errors.
+pub fn check_file(path: &str, show_types: bool, options: &Options) -> bool {
+    let Ok(source) = fs::read_to_string(path) else {
+        eprintln!("File not found: {}", path);
+        return false;
+    };
+    let mut emitter = options.emitter();
+    emitter.add_source(path, &source);
+
+    let mut lexer = Lexer::new(&source, path);
+    let tokens = match lexer.tokenize() {
+        Ok(tokens) => tokens,
+        Err(err) => {
+            emitter.emit_all(err.diagnostics());
+            return false;
+        }
+    };
+    emitter.emit_all(lexer.warnings());
+
+    // Type-check whatever parsed, so one syntax error doesn't hide the rest.
+    let (mut ast, syntax_errors) = Parser::new(tokens).parse_partial();
+    emitter.emit_all(&syntax_errors);
+
+    let resolved = options.evaluator().resolve(&mut ast);
+    match &resolved {
+        Ok(warnings) => emitter.emit_all(warnings),
+        Err(err) => emitter.emit_all(err.diagnostics()),
+    }
 
-    let mut parser = Parser::new(tokens);
-    let ast = parser.parse();
+    let inference = Inferer::new().infer_program(&ast);
+    emitter.emit_all(&inference.errors);
 
-    let mut evaluator = Evaluator::new();
-    evaluator.run(&ast);
-}
\ No newline at end of file
+    if show_types {
+        for signature in &inference.signatures {
+            println!("{}", signature);
+        }
+    }
+    syntax_errors.is_empty() && resolved.is_ok() && inference.errors.is_empty()
+}
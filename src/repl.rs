@@ -1,21 +1,30 @@
 use std::io::{self, Write};
 
-use crate::lexer::Lexer;
-use crate::parser::Parser;
-use crate::eval::Evaluator;
+use crate::playground::{run_source, Options};
 
-pub fn start_repl() {
-    let mut evaluator = Evaluator::new();
+pub fn start_repl(options: &Options) {
+    let mut evaluator = options.evaluator();
+    evaluator.set_interactive(true);
+    let mut emitter = options.emitter();
 
     println!("Alin REPL v0.1 — type 'exit()' to quit");
+    let mut count = 0;
     loop {
         print!(">>> ");
         io::stdout().flush().unwrap();
 
         let mut input = String::new();
-        if io::stdin().read_line(&mut input).is_err() {
-            println!("Error membaca input");
-            continue;
+        match io::stdin().read_line(&mut input) {
+            Ok(0) => {
+                // End of input, possibly after a script consumed it with `read_all()`.
+                println!();
+                break;
+            }
+            Ok(_) => {}
+            Err(_) => {
+                println!("Error membaca input");
+                continue;
+            }
         }
 
         let trimmed = input.trim();
@@ -23,12 +32,15 @@ pub fn start_repl() {
             break;
         }
 
-        let mut lexer = Lexer::new(trimmed);
-        let tokens = lexer.tokenize();
+        // Each input is its own source, so errors in functions defined
+        // earlier still quote the right line.
+        count += 1;
+        let file = format!("<repl:{}>", count);
+        emitter.add_source(&file, trimmed);
 
-        let mut parser = Parser::new(tokens);
-        let exprs = parser.parse();
-
-        evaluator.run(&exprs);
+        // A failed input is reported; the session and its variables carry on.
+        if let Err(err) = run_source(&mut evaluator, &emitter, trimmed, &file) {
+            emitter.emit_all(err.diagnostics());
+        }
     }
 }
@@ -0,0 +1,532 @@
+use std::collections::{HashMap, HashSet};
+use std::mem;
+
+use crate::ast::{Expr, ExprKind, Pattern, Slot};
+use crate::diagnostic::Diagnostic;
+use crate::error::AlinError;
+use crate::stdlib::Builtins;
+use crate::token::Span;
+
+/// Checks, before anything runs, that every name a script reads can exist
+/// by then, and warns about variables that are never read.
+///
+/// A name is local to a function if the function binds it (its parameters,
+/// assignments, loop variables and nested definitions), and global if the
+/// top level binds it anywhere; functions read globals when called, so only
+/// top-level code and locals are held to source order. Names bound in a
+/// branch count as bound after it, and names bound anywhere in a loop count
+/// as bound throughout it, so only reads that must fail are reported.
+/// `break` and `continue` must be inside a loop of their own function.
+///
+/// Resolving also numbers the names each function binds, as `slots` does,
+/// and records the slot on every use of the name, so the evaluator can keep
+/// locals in a vector rather than look them up by name.
+pub struct Resolver<'a> {
+    builtins: &'a Builtins,
+    /// Names the top level binds, or that an earlier run already defined.
+    globals: HashSet<String>,
+    /// Whether more code may run later and define globals that functions
+    /// use, as in the REPL.
+    open: bool,
+    /// The locals of the function being resolved, with where each is first
+    /// bound, or `None` at the top level.
+    locals: Option<HashMap<String, Span>>,
+    /// The slots of the function being resolved, or `None` at the top level.
+    slots: Option<HashMap<String, u32>>,
+    /// Where top-level names are first bound.
+    definitions: HashMap<String, Span>,
+    /// Names that may have been bound by this point in the current scope.
+    defined: HashSet<String>,
+    /// Locals of the current function that it reads.
+    local_reads: HashSet<String>,
+    /// Names read anywhere they refer to a global.
+    global_reads: HashSet<String>,
+    /// Functions the current scope defines, which needn't be called.
+    functions: HashSet<String>,
+    /// How many loops of the current function enclose the expression.
+    loops: usize,
+    diagnostics: Vec<Diagnostic>,
+    warnings: Vec<Diagnostic>,
+}
+
+impl<'a> Resolver<'a> {
+    /// A resolver for code run after `defined`, the names earlier runs left
+    /// behind, with `open` if yet more code may follow.
+    pub fn new(builtins: &'a Builtins, defined: impl IntoIterator<Item = String>, open: bool) -> Self {
+        let defined: HashSet<String> = defined.into_iter().collect();
+        Self {
+            builtins,
+            globals: defined.clone(),
+            open,
+            locals: None,
+            slots: None,
+            definitions: HashMap::new(),
+            defined,
+            local_reads: HashSet::new(),
+            global_reads: HashSet::new(),
+            functions: HashSet::new(),
+            loops: 0,
+            diagnostics: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Resolves top-level statements, filling in the slot of every local,
+    /// and returns the warnings about unused variables, or fails with every
+    /// name that can't be found.
+    pub fn resolve(mut self, exprs: &mut [Expr]) -> Result<Vec<Diagnostic>, AlinError> {
+        for expr in exprs.iter() {
+            for (name, span) in bindings(expr) {
+                self.globals.insert(name.clone());
+                self.definitions.entry(name).or_insert(span);
+            }
+        }
+        for expr in exprs.iter_mut() {
+            self.expr(expr);
+        }
+        if !self.diagnostics.is_empty() {
+            return Err(AlinError::Resolve(self.diagnostics));
+        }
+        // Later input may still read the globals.
+        if !self.open {
+            let mut unused: Vec<_> = mem::take(&mut self.definitions)
+                .into_iter()
+                .filter(|(name, _)| !self.global_reads.contains(name) && !self.functions.contains(name))
+                .collect();
+            unused.sort_by_key(|(_, span)| span.offset);
+            unused.iter().for_each(|(name, span)| self.unused(name, span));
+        }
+        self.warnings.sort_by_key(|warning| warning.labels.first().map_or(0, |label| label.span.offset));
+        Ok(self.warnings)
+    }
+
+    fn expr(&mut self, expr: &mut Expr) {
+        let span = &expr.span;
+        match &mut expr.kind {
+            ExprKind::Number(_)
+            | ExprKind::StringLiteral(_)
+            | ExprKind::Bool(_)
+            | ExprKind::Nil
+            | ExprKind::Error => {}
+            ExprKind::Break | ExprKind::Continue => {
+                if self.loops == 0 {
+                    let keyword = if matches!(expr.kind, ExprKind::Break) { "break" } else { "continue" };
+                    let note = if self.locals.is_some() {
+                        "a loop around the call doesn't count; `return` leaves a function"
+                    } else {
+                        "`break` and `continue` only work inside `while` and `for`"
+                    };
+                    let diagnostic = Diagnostic::error("E0304", format!("`{}` outside of a loop", keyword))
+                        .with_label(expr.span.clone(), "not inside `while` or `for`")
+                        .with_note(note);
+                    self.diagnostics.push(diagnostic);
+                }
+            }
+            ExprKind::Variable(name, slot) => {
+                *slot = self.slot(name);
+                self.read(name);
+                if self.builtins.module(name).is_none() && !self.is_bound(name, span) {
+                    self.undefined(span, "E0301", format!("undefined variable `{}`", name));
+                }
+            }
+            ExprKind::FunctionCall { name, slot, args } => {
+                args.iter_mut().for_each(|arg| self.expr(arg));
+                // Qualified names like `string.upper` only ever name builtins.
+                let found = if name.contains('.') {
+                    self.builtins.get(name).is_some()
+                } else {
+                    *slot = self.slot(name);
+                    self.read(name);
+                    self.builtins.get(name).is_some() || self.is_bound(name, span)
+                };
+                if !found {
+                    self.undefined(span, "E0302", format!("unknown function `{}`", name));
+                }
+            }
+            ExprKind::List(items) | ExprKind::Tuple(items) | ExprKind::Block(items) => {
+                items.iter_mut().for_each(|item| self.expr(item));
+            }
+            ExprKind::Map(entries) => entries.iter_mut().for_each(|(_, value)| self.expr(value)),
+            ExprKind::Spread(inner) | ExprKind::Return(inner) | ExprKind::Yield(inner) => self.expr(inner),
+            ExprKind::Binary { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            ExprKind::Index { object, index } => {
+                self.expr(object);
+                self.expr(index);
+            }
+            ExprKind::Assignment { name, slot, expr } => {
+                self.expr(expr);
+                *slot = self.slot(name);
+                self.defined.insert(name.clone());
+            }
+            ExprKind::Destructure { pattern, expr } => {
+                self.expr(expr);
+                self.pattern(pattern);
+            }
+            ExprKind::If { condition, then_branch, else_branch } => {
+                self.expr(condition);
+                self.expr(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.expr(else_branch);
+                }
+            }
+            ExprKind::While { condition, body } => {
+                self.define_all(condition);
+                self.define_all(body);
+                self.expr(condition);
+                self.loop_body(body);
+            }
+            ExprKind::For { var, slot, iterable, body } => {
+                self.expr(iterable);
+                *slot = self.slot(var);
+                self.defined.insert(var.clone());
+                self.define_all(body);
+                self.loop_body(body);
+            }
+            ExprKind::FunctionDef { name, slot, params, body } => {
+                *slot = self.slot(name);
+                self.defined.insert(name.clone());
+                self.functions.insert(name.clone());
+                self.function(params, body, span);
+            }
+        }
+    }
+
+    /// Resolves the body of a function, in a scope of its own.
+    fn function(&mut self, params: &[String], body: &mut Expr, span: &Span) {
+        let mut locals: HashMap<String, Span> = params.iter().map(|param| (param.clone(), span.clone())).collect();
+        for (name, span) in bindings(body) {
+            locals.entry(name).or_insert(span);
+        }
+        let index = slots(params, body).into_iter().enumerate().map(|(i, name)| (name, i as u32)).collect();
+        let outer_locals = self.locals.replace(locals);
+        let outer_slots = self.slots.replace(index);
+        let outer_defined = mem::replace(&mut self.defined, params.iter().cloned().collect());
+        let outer_reads = mem::take(&mut self.local_reads);
+        let outer_functions = mem::take(&mut self.functions);
+        let outer_loops = mem::replace(&mut self.loops, 0);
+        self.expr(body);
+
+        // Assigning to a name that is already global assigns the global.
+        for (name, span) in bindings(body) {
+            let local = !params.contains(&name) && !self.globals.contains(&name);
+            if local && !self.local_reads.contains(&name) && !self.functions.contains(&name) {
+                self.unused(&name, &span);
+            }
+        }
+        self.locals = outer_locals;
+        self.slots = outer_slots;
+        self.defined = outer_defined;
+        self.local_reads = outer_reads;
+        self.functions = outer_functions;
+        self.loops = outer_loops;
+    }
+
+    fn pattern(&mut self, pattern: &mut Pattern) {
+        match pattern {
+            Pattern::Variable(name, slot) => {
+                *slot = self.slot(name);
+                self.defined.insert(name.clone());
+            }
+            Pattern::Index { object, index } => {
+                self.expr(object);
+                self.expr(index);
+            }
+            Pattern::Tuple(targets) => targets.iter_mut().for_each(|target| self.pattern(target)),
+            Pattern::List { items, rest } => {
+                items.iter_mut().for_each(|target| self.pattern(target));
+                if let Some((name, slot)) = rest {
+                    *slot = self.slot(name);
+                    self.defined.insert(name.clone());
+                }
+            }
+            Pattern::Map(entries) => entries.iter_mut().for_each(|(_, target)| self.pattern(target)),
+        }
+    }
+
+    fn loop_body(&mut self, body: &mut Expr) {
+        self.loops += 1;
+        self.expr(body);
+        self.loops -= 1;
+    }
+
+    /// Marks everything `expr` binds as bound, for a loop that may run it
+    /// before reaching a read.
+    fn define_all(&mut self, expr: &Expr) {
+        self.defined.extend(bindings(expr).into_iter().map(|(name, _)| name));
+    }
+
+    /// The slot of `name` in the current function, if it binds the name.
+    fn slot(&self, name: &str) -> Slot {
+        self.slots.as_ref()?.get(name).copied()
+    }
+
+    /// Notes that `name` is read, as a local of the current function or as
+    /// a global.
+    fn read(&mut self, name: &str) {
+        let local = self.locals.as_ref().is_some_and(|locals| locals.contains_key(name));
+        if local && !self.globals.contains(name) {
+            self.local_reads.insert(name.to_string());
+        } else {
+            self.global_reads.insert(name.to_string());
+        }
+    }
+    /// Whether `name` can have a value where it is read at `span`,
+    /// reporting a read that comes before its only definitions.
+    fn is_bound(&mut self, name: &str, span: &Span) -> bool {
+        // Any name might be a global by the time a function runs.
+        if self.open && self.locals.is_some() {
+            return true;
+        }
+        let definition = match &self.locals {
+            // A local not yet assigned falls back on the global.
+            Some(locals) if !self.globals.contains(name) => locals.get(name),
+            Some(_) => return true,
+            None => self.definitions.get(name),
+        };
+        let Some(definition) = definition.cloned() else {
+            return self.globals.contains(name);
+        };
+        if !self.defined.contains(name) {
+            let diagnostic = Diagnostic::error("E0303", format!("`{}` is used before it is defined", name))
+                .with_label(span.clone(), "used here")
+                .with_secondary(definition, "defined here");
+            self.diagnostics.push(diagnostic);
+        }
+        true
+    }
+
+    fn undefined(&mut self, span: &Span, code: &'static str, message: String) {
+        self.diagnostics.push(Diagnostic::error(code, message).with_label(span.clone(), "not defined"));
+    }
+
+    fn unused(&mut self, name: &str, binding: &Span) {
+        if name.starts_with('_') {
+            return;
+        }
+        let warning = Diagnostic::warning("W0301", format!("unused variable `{}`", name))
+            .with_label(binding.clone(), format!("`{}` is bound here but never read", name))
+            .with_note(format!("if this is intentional, name it `_{}`", name));
+        self.warnings.push(warning);
+    }
+}
+
+/// The local slots of a function: its parameters, then the names its body
+/// binds, in order.
+pub fn slots(params: &[String], body: &Expr) -> Vec<String> {
+    let mut slots = params.to_vec();
+    for (name, _) in bindings(body) {
+        if !slots.contains(&name) {
+            slots.push(name);
+        }
+    }
+    slots
+}
+
+/// The names `expr` binds, each with the expression that first binds it,
+/// without looking inside nested function definitions, which have their own
+/// locals.
+pub(crate) fn bindings(expr: &Expr) -> Vec<(String, Span)> {
+    let mut names = Vec::new();
+    collect(expr, &mut names);
+    names
+}
+
+fn collect(expr: &Expr, names: &mut Vec<(String, Span)>) {
+    match &expr.kind {
+        ExprKind::Number(_)
+        | ExprKind::StringLiteral(_)
+        | ExprKind::Bool(_)
+        | ExprKind::Nil
+        | ExprKind::Variable(..)
+        | ExprKind::Break
+        | ExprKind::Continue
+        | ExprKind::Error => {}
+        ExprKind::List(items) | ExprKind::Tuple(items) | ExprKind::Block(items) => {
+            items.iter().for_each(|item| collect(item, names));
+        }
+        ExprKind::FunctionCall { args, .. } => args.iter().for_each(|arg| collect(arg, names)),
+        ExprKind::Map(entries) => entries.iter().for_each(|(_, value)| collect(value, names)),
+        ExprKind::Spread(inner) | ExprKind::Return(inner) | ExprKind::Yield(inner) => collect(inner, names),
+        ExprKind::Binary { left, right, .. } => {
+            collect(left, names);
+            collect(right, names);
+        }
+        ExprKind::Index { object, index } => {
+            collect(object, names);
+            collect(index, names);
+        }
+        ExprKind::Assignment { name, expr: value, .. } => {
+            collect(value, names);
+            add(name, &expr.span, names);
+        }
+        ExprKind::Destructure { pattern, expr: value } => {
+            collect(value, names);
+            collect_pattern(pattern, &expr.span, names);
+        }
+        ExprKind::If { condition, then_branch, else_branch } => {
+            collect(condition, names);
+            collect(then_branch, names);
+            if let Some(else_branch) = else_branch {
+                collect(else_branch, names);
+            }
+        }
+        ExprKind::While { condition, body } => {
+            collect(condition, names);
+            collect(body, names);
+        }
+        ExprKind::For { var, iterable, body, .. } => {
+            collect(iterable, names);
+            add(var, &expr.span, names);
+            collect(body, names);
+        }
+        ExprKind::FunctionDef { name, .. } => add(name, &expr.span, names),
+    }
+}
+
+fn collect_pattern(pattern: &Pattern, span: &Span, names: &mut Vec<(String, Span)>) {
+    match pattern {
+        Pattern::Variable(name, _) => add(name, span, names),
+        Pattern::Index { object, index } => {
+            collect(object, names);
+            collect(index, names);
+        }
+        Pattern::Tuple(targets) | Pattern::List { items: targets, .. } => {
+            targets.iter().for_each(|target| collect_pattern(target, span, names));
+            if let Pattern::List { rest: Some((name, _)), .. } = pattern {
+                add(name, span, names);
+            }
+        }
+        Pattern::Map(entries) => entries.iter().for_each(|(_, target)| collect_pattern(target, span, names)),
+    }
+}
+
+fn add(name: &str, span: &Span, names: &mut Vec<(String, Span)>) {
+    if !names.iter().any(|(known, _)| known == name) {
+        names.push((name.to_string(), span.clone()));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lexer::Lexer;
+    use crate::parser::Parser;
+
+    /// The codes and messages of what resolving `source` reports: its
+    /// errors, or else its warnings.
+    fn errors(source: &str) -> Vec<(&'static str, String)> {
+        let tokens = Lexer::new(source, "test.alin").tokenize().unwrap();
+        let mut ast = Parser::new(tokens).parse().unwrap();
+        let diagnostics = match Resolver::new(&Builtins::new(), Vec::new(), false).resolve(&mut ast) {
+            Ok(warnings) => warnings,
+            Err(err) => err.diagnostics().to_vec(),
+        };
+        diagnostics.iter().map(|d| (d.code, d.message.clone())).collect()
+    }
+
+    /// The names `source` reads and assigns in its first function, each
+    /// with the slot resolving gave it.
+    fn function_slots(source: &str) -> Vec<(String, Slot)> {
+        let tokens = Lexer::new(source, "test.alin").tokenize().unwrap();
+        let mut ast = Parser::new(tokens).parse().unwrap();
+        Resolver::new(&Builtins::new(), Vec::new(), false).resolve(&mut ast).unwrap();
+        let body = ast.iter().find_map(|expr| match &expr.kind {
+            ExprKind::FunctionDef { body, .. } => Some(body),
+            _ => None,
+        });
+        let mut found = Vec::new();
+        visit(body.unwrap(), &mut found);
+        found
+    }
+
+    fn visit(expr: &Expr, found: &mut Vec<(String, Slot)>) {
+        match &expr.kind {
+            ExprKind::Variable(name, slot) => found.push((name.clone(), *slot)),
+            ExprKind::Assignment { name, slot, expr } => {
+                visit(expr, found);
+                found.push((name.clone(), *slot));
+            }
+            ExprKind::FunctionCall { name, slot, args } => {
+                args.iter().for_each(|arg| visit(arg, found));
+                found.push((name.clone(), *slot));
+            }
+            ExprKind::For { var, slot, iterable, body } => {
+                visit(iterable, found);
+                found.push((var.clone(), *slot));
+                visit(body, found);
+            }
+            ExprKind::Block(items) => items.iter().for_each(|item| visit(item, found)),
+            ExprKind::Binary { left, right, .. } => {
+                visit(left, found);
+                visit(right, found);
+            }
+            ExprKind::Return(inner) => visit(inner, found),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn reports_undefined_names() {
+        assert_eq!(errors("print(missing)\n"), [("E0301", "undefined variable `missing`".to_string())]);
+        assert_eq!(errors("nothing(1)\n"), [("E0302", "unknown function `nothing`".to_string())]);
+        let qualified = errors("x = string.nope(\"a\")\nprint(x)\n");
+        assert_eq!(qualified, [("E0302", "unknown function `string.nope`".to_string())]);
+        // Functions can't see the locals of the function around them.
+        let source = "fn outer(a) {\n    fn inner() { return a }\n    return inner()\n}\nprint(outer(1))\n";
+        assert_eq!(errors(source), [("E0301", "undefined variable `a`".to_string())]);
+        // Builtins, builtin modules and globals defined later are all found.
+        assert!(errors("fn f() { return g() + math.pi }\nfn g() { return len([1]) }\nprint(f())\n").is_empty());
+    }
+
+    #[test]
+    fn reports_reads_before_the_only_definition() {
+        let used_early = [("E0303", "`x` is used before it is defined".to_string())];
+        assert_eq!(errors("print(x)\nx = 1\n"), used_early);
+        assert_eq!(errors("fn f() {\n    print(x)\n    x = 1\n}\nf()\n"), used_early);
+        // A branch or a loop may bind the name before the read runs.
+        assert!(errors("if true { x = 1 }\nprint(x)\n").is_empty());
+        assert!(errors("while false { print(x)\n x = 1 }\n").is_empty());
+        // Functions read globals when called, not where they are defined.
+        assert!(errors("fn f() { return x }\nx = 1\nprint(f())\n").is_empty());
+    }
+
+    #[test]
+    fn warns_about_unused_variables() {
+        let source = "kept = 1\nlost = 2\n\
+                      fn f(param) {\n    local = 1\n    _ignored = 2\n    \
+                      fn helper() { return 0 }\n    return kept\n}\n\
+                      fn never_called() { return 0 }\nprint(f(0))\n";
+        let unused = |name: &str| ("W0301", format!("unused variable `{}`", name));
+        assert_eq!(errors(source), [unused("lost"), unused("local")]);
+        // Assigning a global inside a function doesn't make it a local.
+        assert!(errors("total = 0\nfn add(n) { total = total + n\n return n }\nprint(add(1), total)\n").is_empty());
+        let source = "fn pairs(xs) {\n    for x in xs { print(1) }\n    [first, ...rest] = xs\n    return first\n}\n\
+                      print(pairs([]))\n";
+        assert_eq!(errors(source), [unused("x"), unused("rest")]);
+    }
+
+    #[test]
+    fn gives_each_local_a_slot() {
+        let source = "g = 1\nfn f(a, b) {\n    c = a + b\n    for i in b { c = c + i + g }\n    \
+                      return f(c, len(c))\n}\n";
+        let slots: Vec<_> =
+            function_slots(source).into_iter().map(|(name, slot)| format!("{} {:?}", name, slot)).collect();
+        let expected = [
+            "a Some(0)", "b Some(1)", "c Some(2)", "b Some(1)", "i Some(3)", "c Some(2)", "i Some(3)", "g None",
+            "c Some(2)", "c Some(2)", "c Some(2)", "len None", "f None",
+        ];
+        assert_eq!(slots, expected);
+    }
+
+    #[test]
+    fn break_and_continue_must_be_inside_a_loop_of_their_function() {
+        assert_eq!(errors("fn f() { break }\nf()\n"), [("E0304", "`break` outside of a loop".to_string())]);
+        let nested = errors("while true { fn g() { continue } }\n");
+        assert_eq!(nested, [("E0304", "`continue` outside of a loop".to_string())]);
+        assert_eq!(errors("continue\n").len(), 1);
+        assert!(errors("fn f(xs) { for x in xs { if x { break } while x { continue } } }\n").is_empty());
+    }
+}
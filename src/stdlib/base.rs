@@ -0,0 +1,111 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use crate::eval::{Evaluator, Value};
+use crate::iter::Iter;
+use crate::stdlib::{boolean, invalid_args, list, Builtins};
+
+pub fn register(builtins: &mut Builtins) {
+    builtins.function("print", print);
+    builtins.function("len", len);
+    builtins.function("list", to_list);
+    builtins.function("map", map);
+    builtins.function("filter", filter);
+    builtins.function("reduce", reduce);
+    builtins.function("next", next);
+    builtins.function("range", range);
+    builtins.function("is_error", is_error);
+}
+
+fn print(ev: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let output: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+    match &mut ev.output {
+        Some(captured) => {
+            captured.push_str(&output.join(" "));
+            captured.push('\n');
+        }
+        None => println!("{}", output.join(" ")),
+    }
+    Some(Value::Nil)
+}
+
+fn len(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let len = match args {
+        [Value::String(s)] => s.chars().count(),
+        [Value::List(items)] => items.borrow().len(),
+        [Value::Tuple(items)] => items.len(),
+        [Value::Map(entries)] => entries.borrow().len(),
+        _ => return invalid_args(),
+    };
+    Some(Value::Number(len as f64))
+}
+
+fn to_list(ev: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [iterable] = args else {
+        return invalid_args();
+    };
+    // An error inside the iterator fails the call instead of ending the list.
+    Some(list(ev.spread(iterable.clone())?))
+}
+
+fn map(ev: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [func, iterable] = args else {
+        return invalid_args();
+    };
+    let source = Box::new(ev.iterate(iterable.clone())?);
+    Some(iterator(Iter::Map { func: func.clone(), source }))
+}
+
+fn filter(ev: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [func, iterable] = args else {
+        return invalid_args();
+    };
+    let source = Box::new(ev.iterate(iterable.clone())?);
+    Some(iterator(Iter::Filter { func: func.clone(), source }))
+}
+
+fn reduce(ev: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [func, iterable, initial] = args else {
+        return invalid_args();
+    };
+    let mut iter = ev.iterate(iterable.clone())?;
+    let reported = ev.reported();
+    let mut acc = initial.clone();
+    while let Some(item) = ev.next_value(&mut iter) {
+        acc = ev.call_value(func, vec![acc, item])?;
+    }
+    (ev.reported() == reported).then_some(acc)
+}
+
+fn next(ev: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [Value::Iterator(iter)] = args else {
+        return invalid_args();
+    };
+    let mut iter = Iter::Shared(iter.clone());
+    let reported = ev.reported();
+    let item = ev.next_value(&mut iter);
+    (ev.reported() == reported).then(|| item.unwrap_or(Value::Nil))
+}
+
+fn range(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let (start, end, step) = match args {
+        [Value::Number(end)] => (0.0, *end, 1.0),
+        [Value::Number(start), Value::Number(end)] => (*start, *end, 1.0),
+        [Value::Number(start), Value::Number(end), Value::Number(step)] if *step != 0.0 => {
+            (*start, *end, *step)
+        }
+        _ => return invalid_args(),
+    };
+    Some(iterator(Iter::Range { next: start, end, step }))
+}
+
+fn is_error(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [value] = args else {
+        return invalid_args();
+    };
+    Some(boolean(matches!(value, Value::Error(_))))
+}
+
+pub fn iterator(iter: Iter) -> Value {
+    Value::Iterator(Rc::new(RefCell::new(iter)))
+}
@@ -0,0 +1,261 @@
+use std::collections::BTreeSet;
+use std::fs;
+
+use crate::eval::{is_truthy, Evaluator, Value};
+use crate::stdlib::{invalid_args, io_error, list, map, parse_error, Builtins};
+
+/// Every function takes an optional options map: `delimiter` (one
+/// character, `","` by default), `header` (whether the first row names the
+/// columns, true by default) and, for `csv.write` and `csv.stringify`,
+/// `columns` to fix the column order.
+pub fn register(builtins: &mut Builtins) {
+    builtins.function("csv.parse", parse);
+    builtins.function("csv.stringify", stringify);
+    builtins.function("csv.read", read);
+    builtins.function("csv.write", write);
+}
+
+struct Options {
+    delimiter: char,
+    header: bool,
+    columns: Option<Vec<String>>,
+}
+
+/// Parses the trailing options argument, or `None` if it is malformed.
+fn options(args: &[Value]) -> Option<Options> {
+    let mut options = Options {
+        delimiter: ',',
+        header: true,
+        columns: None,
+    };
+    let entries = match args {
+        [] => return Some(options),
+        [Value::Map(entries)] => entries.borrow(),
+        _ => return None,
+    };
+    for (key, value) in entries.iter() {
+        match (key.as_str(), value) {
+            ("delimiter", Value::String(s)) if s.chars().count() == 1 => options.delimiter = s.chars().next()?,
+            ("header", value) => options.header = is_truthy(value),
+            ("columns", Value::List(items)) => {
+                let items = items.borrow();
+                let columns = items.iter().map(|item| match item {
+                    Value::String(s) => Some(s.clone()),
+                    _ => None,
+                });
+                options.columns = Some(columns.collect::<Option<_>>()?);
+            }
+            _ => return None,
+        }
+    }
+    Some(options)
+}
+
+/// `csv.parse(text[, options])` returns a list of maps keyed by the header
+/// row, or a list of lists when `header` is false. Fields stay strings.
+fn parse(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let (Some(Value::String(text)), Some(options)) = (args.first(), options(args.get(1..)?)) else {
+        return invalid_args();
+    };
+    Some(records(text, &options))
+}
+
+/// `csv.read(path[, options])` parses a file as `csv.parse` does its text.
+fn read(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let (Some(Value::String(path)), Some(options)) = (args.first(), options(args.get(1..)?)) else {
+        return invalid_args();
+    };
+    Some(match fs::read_to_string(path) {
+        Ok(text) => records(&text, &options),
+        Err(err) => io_error(err, path),
+    })
+}
+
+/// The rows of `text`, or the error value for malformed CSV.
+fn records(text: &str, options: &Options) -> Value {
+    let rows = match parse_rows(text, options.delimiter) {
+        Ok(rows) => rows,
+        Err(err) => return err,
+    };
+    let strings = |fields: Vec<String>| fields.into_iter().map(Value::String);
+    if !options.header {
+        let rows = rows.into_iter().map(|(_, fields)| list(strings(fields).collect()));
+        return list(rows.collect());
+    }
+    let mut rows = rows.into_iter();
+    let Some((_, header)) = rows.next() else {
+        return list(Vec::new());
+    };
+    let mut records = Vec::new();
+    for (line, fields) in rows {
+        if fields.len() != header.len() {
+            let message = format!("row has {} fields but the header has {}", fields.len(), header.len());
+            return parse_error("csv", &message, line, 1);
+        }
+        records.push(map(header.iter().cloned().zip(strings(fields)).collect()));
+    }
+    list(records)
+}
+
+/// `csv.stringify(rows[, options])` returns a list of maps as CSV text under
+/// a header row, or a list of lists as they are.
+fn stringify(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let (Some(Value::List(rows)), Some(options)) = (args.first(), options(args.get(1..)?)) else {
+        return invalid_args();
+    };
+    Some(Value::String(text(&rows.borrow(), &options)?))
+}
+
+/// `csv.write(path, rows[, options])` writes the text `csv.stringify` gives
+/// for `rows` to a file.
+fn write(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let (Some(Value::String(path)), Some(Value::List(rows)), Some(options)) =
+        (args.first(), args.get(1), options(args.get(2..)?))
+    else {
+        return invalid_args();
+    };
+    let text = text(&rows.borrow(), &options)?;
+    Some(match fs::write(path, text) {
+        Ok(()) => Value::Nil,
+        Err(err) => io_error(err, path),
+    })
+}
+
+/// `rows` as CSV text, or `None` if a row is neither a map nor a sequence.
+fn text(rows: &[Value], options: &Options) -> Option<String> {
+    let mut lines = Vec::new();
+    if rows.iter().all(|row| matches!(row, Value::Map(_))) {
+        let columns = options.columns.clone().unwrap_or_else(|| {
+            let mut keys = BTreeSet::new();
+            for row in rows.iter() {
+                if let Value::Map(entries) = row {
+                    keys.extend(entries.borrow().keys().cloned());
+                }
+            }
+            keys.into_iter().collect()
+        });
+        if options.header {
+            lines.push(columns.clone());
+        }
+        for row in rows.iter() {
+            if let Value::Map(entries) = row {
+                let entries = entries.borrow();
+                lines.push(columns.iter().map(|column| field(entries.get(column))).collect());
+            }
+        }
+    } else {
+        if let (Some(columns), true) = (&options.columns, options.header) {
+            lines.push(columns.clone());
+        }
+        for row in rows.iter() {
+            let fields: Vec<String> = match row {
+                Value::List(items) => items.borrow().iter().map(|item| field(Some(item))).collect(),
+                Value::Tuple(items) => items.iter().map(|item| field(Some(item))).collect(),
+                _ => return None,
+            };
+            lines.push(fields);
+        }
+    }
+    let mut text = String::new();
+    for fields in &lines {
+        let quoted: Vec<String> = fields.iter().map(|f| quote(f, options.delimiter)).collect();
+        text.push_str(&quoted.join(&options.delimiter.to_string()));
+        text.push('\n');
+    }
+    Some(text)
+}
+
+/// Splits CSV text into rows, each with the line it starts on. Quoted fields
+/// may contain the delimiter, newlines and doubled `""` quotes. Blank lines
+/// are skipped.
+fn parse_rows(text: &str, delimiter: char) -> Result<Vec<(usize, Vec<String>)>, Value> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut rows = Vec::new();
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let (mut line, mut column) = (1, 1);
+    let mut row_line = 1;
+    // Whether the current row has any content, so blank lines can be skipped.
+    let mut in_row = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c != '\n' && c != '\r' {
+            in_row = true;
+        }
+        if c == '"' && field.is_empty() {
+            let (start_line, start_column) = (line, column);
+            i += 1;
+            column += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err(parse_error("csv", "unterminated quoted field", start_line, start_column)),
+                    Some('"') if chars.get(i + 1) == Some(&'"') => {
+                        field.push('"');
+                        i += 2;
+                        column += 2;
+                    }
+                    Some('"') => {
+                        i += 1;
+                        column += 1;
+                        break;
+                    }
+                    Some(&c) => {
+                        field.push(c);
+                        i += 1;
+                        if c == '\n' {
+                            line += 1;
+                            column = 1;
+                        } else {
+                            column += 1;
+                        }
+                    }
+                }
+            }
+            if !matches!(chars.get(i), None | Some('\n' | '\r')) && chars.get(i) != Some(&delimiter) {
+                return Err(parse_error("csv", "unexpected text after a quoted field", line, column));
+            }
+            continue;
+        }
+        i += 1;
+        column += 1;
+        if c == delimiter {
+            fields.push(std::mem::take(&mut field));
+        } else if c == '\n' || c == '\r' {
+            if c == '\r' && chars.get(i) == Some(&'\n') {
+                i += 1;
+            }
+            if in_row {
+                fields.push(std::mem::take(&mut field));
+                rows.push((row_line, std::mem::take(&mut fields)));
+                in_row = false;
+            }
+            line += 1;
+            column = 1;
+            row_line = line;
+        } else {
+            field.push(c);
+        }
+    }
+    if in_row {
+        fields.push(field);
+        rows.push((row_line, fields));
+    }
+    Ok(rows)
+}
+
+fn field(value: Option<&Value>) -> String {
+    match value {
+        None | Some(Value::Nil) => String::new(),
+        Some(Value::String(s)) => s.clone(),
+        Some(other) => other.to_string(),
+    }
+}
+
+fn quote(field: &str, delimiter: char) -> String {
+    if field.contains([delimiter, '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
@@ -0,0 +1,255 @@
+use std::fs;
+use std::io::{BufReader, Write};
+use std::path::Path;
+
+use crate::eval::{Evaluator, Value};
+use crate::iter::Iter;
+use crate::stdlib::base::iterator;
+use crate::stdlib::{boolean, invalid_args, io_error, list, Builtins};
+
+/// Every `fs` function returns an error value instead of failing the script,
+/// so callers can check the result with `is_error`.
+pub fn register(builtins: &mut Builtins) {
+    builtins.function("fs.read_file", read_file);
+    builtins.function("fs.write_file", write_file);
+    builtins.function("fs.append_file", append_file);
+    builtins.function("fs.read_lines", read_lines);
+    builtins.function("fs.exists", exists);
+    builtins.function("fs.list_dir", list_dir);
+    builtins.function("fs.mkdir", mkdir);
+    builtins.function("fs.remove", remove);
+
+    builtins.function("path.join", join);
+    builtins.function("path.basename", basename);
+    builtins.function("path.dirname", dirname);
+    builtins.function("path.extension", extension);
+}
+
+fn read_file(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [Value::String(path)] = args else {
+        return invalid_args();
+    };
+    Some(match fs::read_to_string(path) {
+        Ok(content) => Value::String(content),
+        Err(err) => io_error(err, path),
+    })
+}
+
+fn write_file(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [Value::String(path), Value::String(content)] = args else {
+        return invalid_args();
+    };
+    Some(unit(fs::write(path, content), path))
+}
+
+fn append_file(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [Value::String(path), Value::String(content)] = args else {
+        return invalid_args();
+    };
+    let result = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(content.as_bytes()));
+    Some(unit(result, path))
+}
+
+/// Lines are read lazily, so large files can be streamed with `for`.
+fn read_lines(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [Value::String(path)] = args else {
+        return invalid_args();
+    };
+    Some(match fs::File::open(path) {
+        Ok(file) => iterator(Iter::Lines {
+            path: path.clone(),
+            reader: Some(BufReader::new(file)),
+        }),
+        Err(err) => io_error(err, path),
+    })
+}
+
+fn exists(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [Value::String(path)] = args else {
+        return invalid_args();
+    };
+    Some(boolean(Path::new(path).exists()))
+}
+
+/// Entry names in a directory, sorted.
+fn list_dir(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [Value::String(path)] = args else {
+        return invalid_args();
+    };
+    let entries = fs::read_dir(path).and_then(|entries| {
+        entries
+            .map(|entry| Ok(entry?.file_name().to_string_lossy().into_owned()))
+            .collect::<std::io::Result<Vec<String>>>()
+    });
+    Some(match entries {
+        Ok(mut names) => {
+            names.sort();
+            list(names.into_iter().map(Value::String).collect())
+        }
+        Err(err) => io_error(err, path),
+    })
+}
+
+/// Creates a directory along with any missing parents.
+fn mkdir(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [Value::String(path)] = args else {
+        return invalid_args();
+    };
+    Some(unit(fs::create_dir_all(path), path))
+}
+
+/// Removes a file or an empty directory; `fs.remove(path, 1)` also removes
+/// a directory with everything inside it.
+fn remove(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let (path, recursive) = match args {
+        [Value::String(path)] => (path, false),
+        [Value::String(path), Value::Number(flag)] => (path, *flag != 0.0),
+        _ => return invalid_args(),
+    };
+    let result = match fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() && recursive => fs::remove_dir_all(path),
+        Ok(meta) if meta.is_dir() => fs::remove_dir(path),
+        Ok(_) => fs::remove_file(path),
+        Err(err) => Err(err),
+    };
+    Some(unit(result, path))
+}
+
+fn join(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let mut joined = std::path::PathBuf::new();
+    for part in args {
+        let Value::String(part) = part else {
+            return invalid_args();
+        };
+        joined.push(part);
+    }
+    Some(Value::String(joined.to_string_lossy().into_owned()))
+}
+
+fn basename(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [Value::String(path)] = args else {
+        return invalid_args();
+    };
+    Some(optional(Path::new(path).file_name()))
+}
+
+fn dirname(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [Value::String(path)] = args else {
+        return invalid_args();
+    };
+    Some(optional(Path::new(path).parent().map(Path::as_os_str)))
+}
+
+/// The extension without the dot, or nil when there is none.
+fn extension(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [Value::String(path)] = args else {
+        return invalid_args();
+    };
+    Some(optional(Path::new(path).extension()))
+}
+
+fn unit(result: std::io::Result<()>, path: &str) -> Value {
+    match result {
+        Ok(()) => Value::Nil,
+        Err(err) => io_error(err, path),
+    }
+}
+
+fn optional(part: Option<&std::ffi::OsStr>) -> Value {
+    match part {
+        Some(part) => Value::String(part.to_string_lossy().into_owned()),
+        None => Value::Nil,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    /// A fresh directory under the system's temporary one, removed again
+    /// when dropped.
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(name: &str) -> Self {
+            let dir = std::env::temp_dir().join(format!("alin-fs-{}-{}", std::process::id(), name));
+            let _ = fs::remove_dir_all(&dir);
+            fs::create_dir_all(&dir).unwrap();
+            Self(dir)
+        }
+
+        fn path(&self, name: &str) -> String {
+            self.0.join(name).to_string_lossy().into_owned()
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.0);
+        }
+    }
+
+    fn call(func: fn(&mut Evaluator, &[Value]) -> Option<Value>, args: &[&str]) -> Value {
+        let args: Vec<Value> = args.iter().map(|arg| Value::String(arg.to_string())).collect();
+        func(&mut Evaluator::new(), &args).unwrap()
+    }
+
+    /// The kind of the error `result` is, checking it names `path`.
+    fn failure(result: Value, path: &str) -> String {
+        match result {
+            Value::Error(err) => {
+                assert_eq!(err.fields["path"].to_string(), path);
+                assert!(err.message.starts_with(&format!("{}: ", path)), "{}", err.message);
+                err.kind.clone()
+            }
+            other => panic!("not an error: {}", other),
+        }
+    }
+
+    #[test]
+    fn missing_files_give_not_found_errors() {
+        let dir = TempDir::new("missing");
+        let missing = dir.path("missing.txt");
+        assert_eq!(failure(call(read_file, &[&missing]), &missing), "not_found");
+        assert_eq!(failure(call(read_lines, &[&missing]), &missing), "not_found");
+        assert_eq!(failure(call(list_dir, &[&missing]), &missing), "not_found");
+        assert_eq!(failure(call(remove, &[&missing]), &missing), "not_found");
+        let nested = dir.path("missing/file.txt");
+        assert_eq!(failure(call(write_file, &[&nested, "x"]), &nested), "not_found");
+        assert_eq!(failure(call(append_file, &[&nested, "x"]), &nested), "not_found");
+        assert_eq!(call(exists, &[&missing]).to_string(), "false");
+    }
+
+    #[test]
+    fn misused_paths_give_errors_of_their_kind() {
+        let dir = TempDir::new("misused");
+        let (file, sub) = (dir.path("file.txt"), dir.path("sub"));
+        assert_eq!(call(write_file, &[&file, "x"]).to_string(), "nil");
+        assert_eq!(call(mkdir, &[&dir.path("sub/inner")]).to_string(), "nil");
+        assert_eq!(failure(call(read_file, &[&sub]), &sub), "is_a_directory");
+        assert_eq!(failure(call(list_dir, &[&file]), &file), "not_a_directory");
+        assert_eq!(failure(call(remove, &[&sub]), &sub), "directory_not_empty");
+        assert_eq!(failure(call(mkdir, &[&file]), &file), "already_exists");
+    }
+
+    #[test]
+    fn writes_reads_lists_and_removes() {
+        let dir = TempDir::new("round-trip");
+        let (file, sub) = (dir.path("notes.txt"), dir.path("sub"));
+        assert_eq!(call(write_file, &[&file, "one\n"]).to_string(), "nil");
+        assert_eq!(call(append_file, &[&file, "two\n"]).to_string(), "nil");
+        assert_eq!(call(read_file, &[&file]).to_string(), "one\ntwo\n");
+        assert_eq!(call(mkdir, &[&dir.path("sub/inner")]).to_string(), "nil");
+        let root = dir.0.to_string_lossy();
+        assert_eq!(call(list_dir, &[&root]).to_string(), "[\"notes.txt\", \"sub\"]");
+        let removed = remove(&mut Evaluator::new(), &[Value::String(sub.clone()), Value::Number(1.0)]).unwrap();
+        assert_eq!(removed.to_string(), "nil");
+        assert_eq!(call(exists, &[&sub]).to_string(), "false");
+        assert_eq!(call(exists, &[&file]).to_string(), "true");
+    }
+}
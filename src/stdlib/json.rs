@@ -0,0 +1,470 @@
+use std::collections::BTreeMap;
+
+use crate::eval::{Evaluator, Value};
+use crate::stdlib::{error, invalid_args, list, map, parse_error, Builtins};
+
+pub fn register(builtins: &mut Builtins) {
+    builtins.function("json.parse", parse);
+    builtins.function("json.stringify", stringify);
+}
+
+/// Parses JSON text. Invalid input gives a `json` error value carrying the
+/// `line` and `column` (both 1-based) where parsing stopped.
+fn parse(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [Value::String(text)] = args else {
+        return invalid_args();
+    };
+    let mut reader = Reader::new(text);
+    Some(reader.document().unwrap_or_else(|message| reader.error(&message)))
+}
+
+/// `json.stringify(value)` is compact; `json.stringify(value, indent)`
+/// pretty-prints with `indent` spaces per level. Maps are written with
+/// their keys sorted.
+fn stringify(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let (value, indent) = match args {
+        [value] => (value, 0),
+        [value, Value::Number(indent)] if *indent >= 0.0 => (value, *indent as usize),
+        _ => return invalid_args(),
+    };
+    let mut out = String::new();
+    Some(match write_value(&mut out, value, indent, 0) {
+        Ok(()) => Value::String(out),
+        Err(message) => error("json", message, BTreeMap::new()),
+    })
+}
+
+/// Nesting deeper than this is rejected instead of overflowing the stack.
+const MAX_DEPTH: usize = 512;
+
+/// A recursive-descent JSON reader that tracks line and column.
+pub struct Reader {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    column: usize,
+    depth: usize,
+}
+
+impl Reader {
+    pub fn new(text: &str) -> Self {
+        Self {
+            chars: text.chars().collect(),
+            pos: 0,
+            line: 1,
+            column: 1,
+            depth: 0,
+        }
+    }
+
+    /// A complete document: one value surrounded only by whitespace.
+    pub fn document(&mut self) -> Result<Value, String> {
+        let value = self.value()?;
+        self.skip_whitespace();
+        match self.peek() {
+            None => Ok(value),
+            Some(c) => Err(format!("unexpected {:?} after the JSON value", c)),
+        }
+    }
+
+    /// An error value pointing at the current position.
+    pub fn error(&self, message: &str) -> Value {
+        parse_error("json", message, self.line, self.column)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.next();
+                Ok(())
+            }
+            Some(c) => Err(format!("expected {:?} but found {:?}", expected, c)),
+            None => Err(format!("expected {:?} but reached the end of input", expected)),
+        }
+    }
+
+    fn skip_whitespace(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+            self.next();
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('{') => self.nested(Self::object),
+            Some('[') => self.nested(Self::array),
+            Some('"') => Ok(Value::String(self.string()?)),
+            Some('-' | '0'..='9') => self.number(),
+            Some('t') => self.literal("true", Value::Bool(true)),
+            Some('f') => self.literal("false", Value::Bool(false)),
+            Some('n') => self.literal("null", Value::Nil),
+            Some(c) => Err(format!("unexpected {:?}", c)),
+            None => Err("unexpected end of input".to_string()),
+        }
+    }
+
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<Value, String>) -> Result<Value, String> {
+        if self.depth == MAX_DEPTH {
+            return Err("nesting is too deep".to_string());
+        }
+        self.depth += 1;
+        let value = parse(self);
+        self.depth -= 1;
+        value
+    }
+
+    fn object(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+        let mut entries = BTreeMap::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.next();
+            return Ok(map(entries));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('"') {
+                return Err("expected a string key".to_string());
+            }
+            let key = self.string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            let value = self.value()?;
+            entries.insert(key, value);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => {
+                    self.next();
+                }
+                Some('}') => {
+                    self.next();
+                    return Ok(map(entries));
+                }
+                _ => return Err("expected ',' or '}'".to_string()),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.next();
+            return Ok(list(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.peek() {
+                Some(',') => {
+                    self.next();
+                }
+                Some(']') => {
+                    self.next();
+                    return Ok(list(items));
+                }
+                _ => return Err("expected ',' or ']'".to_string()),
+            }
+        }
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut result = String::new();
+        loop {
+            match self.peek() {
+                None => return Err("unterminated string".to_string()),
+                Some('"') => {
+                    self.next();
+                    return Ok(result);
+                }
+                Some('\\') => {
+                    self.next();
+                    let escaped = match self.peek() {
+                        Some('"') => '"',
+                        Some('\\') => '\\',
+                        Some('/') => '/',
+                        Some('b') => '\u{8}',
+                        Some('f') => '\u{c}',
+                        Some('n') => '\n',
+                        Some('r') => '\r',
+                        Some('t') => '\t',
+                        Some('u') => {
+                            self.next();
+                            result.push(self.unicode_escape()?);
+                            continue;
+                        }
+                        _ => return Err("invalid escape sequence".to_string()),
+                    };
+                    self.next();
+                    result.push(escaped);
+                }
+                Some(c) if (c as u32) < 0x20 => {
+                    return Err("control character in string".to_string());
+                }
+                Some(c) => {
+                    self.next();
+                    result.push(c);
+                }
+            }
+        }
+    }
+
+    /// The part of `\uXXXX` after the `u`, joining UTF-16 surrogate pairs.
+    fn unicode_escape(&mut self) -> Result<char, String> {
+        let high = self.hex4()?;
+        if !(0xD800..0xDC00).contains(&high) {
+            return char::from_u32(high).ok_or_else(|| "invalid unicode escape".to_string());
+        }
+        if self.next() != Some('\\') || self.next() != Some('u') {
+            return Err("unpaired surrogate in unicode escape".to_string());
+        }
+        let low = self.hex4()?;
+        if !(0xDC00..0xE000).contains(&low) {
+            return Err("unpaired surrogate in unicode escape".to_string());
+        }
+        let code = 0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00);
+        char::from_u32(code).ok_or_else(|| "invalid unicode escape".to_string())
+    }
+
+    fn hex4(&mut self) -> Result<u32, String> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .peek()
+                .and_then(|c| c.to_digit(16))
+                .ok_or_else(|| "expected four hex digits".to_string())?;
+            self.next();
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.pos;
+        if self.peek() == Some('-') {
+            self.next();
+        }
+        match self.peek() {
+            Some('0') => {
+                self.next();
+            }
+            Some('1'..='9') => self.digits(),
+            _ => return Err("expected a digit".to_string()),
+        }
+        if self.peek() == Some('.') {
+            self.next();
+            if !matches!(self.peek(), Some('0'..='9')) {
+                return Err("expected a digit after '.'".to_string());
+            }
+            self.digits();
+        }
+        if matches!(self.peek(), Some('e' | 'E')) {
+            self.next();
+            if matches!(self.peek(), Some('+' | '-')) {
+                self.next();
+            }
+            if !matches!(self.peek(), Some('0'..='9')) {
+                return Err("expected a digit in the exponent".to_string());
+            }
+            self.digits();
+        }
+        let text: String = self.chars[start..self.pos].iter().collect();
+        text.parse()
+            .map(Value::Number)
+            .map_err(|_| format!("invalid number {:?}", text))
+    }
+
+    fn digits(&mut self) {
+        while matches!(self.peek(), Some('0'..='9')) {
+            self.next();
+        }
+    }
+
+    fn literal(&mut self, word: &str, value: Value) -> Result<Value, String> {
+        for expected in word.chars() {
+            if self.peek() != Some(expected) {
+                return Err(format!("invalid literal, expected {:?}", word));
+            }
+            self.next();
+        }
+        Ok(value)
+    }
+}
+
+/// Writes `value` as JSON. With a non-zero `indent`, nested values go on
+/// their own lines indented by `indent * depth` spaces.
+pub fn write_value(out: &mut String, value: &Value, indent: usize, depth: usize) -> Result<(), String> {
+    match value {
+        Value::Nil => out.push_str("null"),
+        Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Value::Number(n) if n.is_finite() => out.push_str(&n.to_string()),
+        Value::Number(n) => return Err(format!("{} cannot be represented in JSON", n)),
+        Value::String(s) => write_string(out, s),
+        Value::List(items) => write_array(out, &items.borrow(), indent, depth)?,
+        Value::Tuple(items) => write_array(out, items, indent, depth)?,
+        Value::Map(entries) => {
+            let entries = entries.borrow();
+            if entries.is_empty() {
+                out.push_str("{}");
+                return Ok(());
+            }
+            out.push('{');
+            for (i, (key, item)) in entries.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                newline(out, indent, depth + 1);
+                write_string(out, key);
+                out.push(':');
+                if indent > 0 {
+                    out.push(' ');
+                }
+                write_value(out, item, indent, depth + 1)?;
+            }
+            newline(out, indent, depth);
+            out.push('}');
+        }
+        other => return Err(format!("{} cannot be represented in JSON", other)),
+    }
+    Ok(())
+}
+
+fn write_array(out: &mut String, items: &[Value], indent: usize, depth: usize) -> Result<(), String> {
+    if items.is_empty() {
+        out.push_str("[]");
+        return Ok(());
+    }
+    out.push('[');
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            out.push(',');
+        }
+        newline(out, indent, depth + 1);
+        write_value(out, item, indent, depth + 1)?;
+    }
+    newline(out, indent, depth);
+    out.push(']');
+    Ok(())
+}
+
+fn newline(out: &mut String, indent: usize, depth: usize) {
+    if indent > 0 {
+        out.push('\n');
+        out.push_str(&" ".repeat(indent * depth));
+    }
+}
+
+pub fn write_string(out: &mut String, s: &str) {
+    out.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(text: &str) -> Value {
+        parse(&mut Evaluator::new(), &[Value::String(text.to_string())]).unwrap()
+    }
+
+    fn stringified(args: &[Value]) -> Value {
+        stringify(&mut Evaluator::new(), args).unwrap()
+    }
+
+    /// The message of the error `result` is.
+    fn failure(result: Value) -> String {
+        match result {
+            Value::Error(err) => {
+                assert_eq!(err.kind, "json");
+                err.message.clone()
+            }
+            other => panic!("not an error: {}", other),
+        }
+    }
+
+    #[test]
+    fn round_trips_documents() {
+        let texts = [
+            "null",
+            "[true,false,null]",
+            r#"{"a":[1,2.5,-3,1000000000000000000000],"b":{"c":"d"},"e":[],"f":{}}"#,
+            r#""quote \" backslash \\ newline \n tab \t bell \u0007 é""#,
+        ];
+        for text in texts {
+            assert_eq!(stringified(&[parsed(text)]).to_string(), text, "for {:?}", text);
+        }
+        let escaped = parsed(r#"["\u00e9\ud83d\ude00", "\/", 1.5E2, -0.25e-1]"#);
+        assert_eq!(stringified(&[escaped]).to_string(), r#"["é😀","/",150,-0.025]"#);
+    }
+
+    #[test]
+    fn pretty_prints_with_sorted_keys() {
+        let value = parsed(r#"{"b": [1, {}], "a": {"x": []}}"#);
+        let expected = "{\n  \"a\": {\n    \"x\": []\n  },\n  \"b\": [\n    1,\n    {}\n  ]\n}";
+        assert_eq!(stringified(&[value, Value::Number(2.0)]).to_string(), expected);
+    }
+
+    #[test]
+    fn reports_errors_where_they_are() {
+        let cases = [
+            ("", "unexpected end of input at line 1, column 1"),
+            ("[1, 2", "expected ',' or ']' at line 1, column 6"),
+            ("{\"a\": 1,}", "expected a string key at line 1, column 9"),
+            ("{\"a\" 1}", "expected ':' but found '1' at line 1, column 6"),
+            ("\"open", "unterminated string at line 1, column 6"),
+            ("\"\\x\"", "invalid escape sequence at line 1, column 3"),
+            ("\"\\ud83d\"", "unpaired surrogate in unicode escape at line 1, column 9"),
+            ("01", "unexpected '1' after the JSON value at line 1, column 2"),
+            ("1.", "expected a digit after '.' at line 1, column 3"),
+            ("tru", "invalid literal, expected \"true\" at line 1, column 4"),
+            ("[1]\n  x", "unexpected 'x' after the JSON value at line 2, column 3"),
+        ];
+        for (text, message) in cases {
+            assert_eq!(failure(parsed(text)), message, "for {:?}", text);
+        }
+        let deep = "[".repeat(MAX_DEPTH + 1);
+        assert!(failure(parsed(&deep)).starts_with("nesting is too deep"));
+        let Value::Error(err) = parsed("{\n  \"a\": ?}") else { panic!() };
+        assert_eq!(err.fields["line"].to_string(), "2");
+        assert_eq!(err.fields["column"].to_string(), "8");
+    }
+
+    #[test]
+    fn rejects_what_json_cannot_hold() {
+        assert_eq!(failure(stringified(&[Value::Number(f64::NAN)])), "NaN cannot be represented in JSON");
+        let nested = list(vec![Value::Number(1.0), Value::Number(f64::INFINITY)]);
+        assert_eq!(failure(stringified(&[nested])), "inf cannot be represented in JSON");
+        assert!(stringify(&mut Evaluator::new(), &[Value::Nil, Value::Number(-1.0)]).is_none());
+        assert!(parse(&mut Evaluator::new(), &[Value::Nil]).is_none());
+    }
+}
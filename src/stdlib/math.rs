@@ -0,0 +1,80 @@
+use crate::eval::{Evaluator, Value};
+use crate::stdlib::{boolean, invalid_args, Builtins};
+
+pub fn register(builtins: &mut Builtins) {
+    builtins.constant("math.pi", Value::Number(std::f64::consts::PI));
+    builtins.constant("math.e", Value::Number(std::f64::consts::E));
+    builtins.constant("math.inf", Value::Number(f64::INFINITY));
+    builtins.constant("math.nan", Value::Number(f64::NAN));
+
+    builtins.function("math.abs", |_, args| unary(args, f64::abs));
+    builtins.function("math.floor", |_, args| unary(args, f64::floor));
+    builtins.function("math.ceil", |_, args| unary(args, f64::ceil));
+    builtins.function("math.round", |_, args| unary(args, f64::round));
+    builtins.function("math.sqrt", |_, args| unary(args, f64::sqrt));
+    builtins.function("math.exp", |_, args| unary(args, f64::exp));
+    builtins.function("math.sin", |_, args| unary(args, f64::sin));
+    builtins.function("math.cos", |_, args| unary(args, f64::cos));
+    builtins.function("math.tan", |_, args| unary(args, f64::tan));
+    builtins.function("math.asin", |_, args| unary(args, f64::asin));
+    builtins.function("math.acos", |_, args| unary(args, f64::acos));
+    builtins.function("math.atan", |_, args| unary(args, f64::atan));
+    builtins.function("math.log10", |_, args| unary(args, f64::log10));
+    builtins.function("math.log2", |_, args| unary(args, f64::log2));
+    builtins.function("math.atan2", |_, args| binary(args, f64::atan2));
+    builtins.function("math.pow", |_, args| binary(args, f64::powf));
+    builtins.function("math.log", log);
+    builtins.function("math.min", |_, args| fold(args, f64::min));
+    builtins.function("math.max", |_, args| fold(args, f64::max));
+    builtins.function("math.is_nan", |_, args| test(args, f64::is_nan));
+    builtins.function("math.is_finite", |_, args| test(args, f64::is_finite));
+}
+
+fn unary(args: &[Value], op: fn(f64) -> f64) -> Option<Value> {
+    match args {
+        [Value::Number(x)] => Some(Value::Number(op(*x))),
+        _ => invalid_args(),
+    }
+}
+
+fn binary(args: &[Value], op: fn(f64, f64) -> f64) -> Option<Value> {
+    match args {
+        [Value::Number(x), Value::Number(y)] => Some(Value::Number(op(*x, *y))),
+        _ => invalid_args(),
+    }
+}
+
+fn test(args: &[Value], op: fn(f64) -> bool) -> Option<Value> {
+    match args {
+        [Value::Number(x)] => Some(boolean(op(*x))),
+        _ => invalid_args(),
+    }
+}
+
+/// `math.log(x)` is the natural logarithm, `math.log(x, base)` any other.
+fn log(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    match args {
+        [Value::Number(x)] => Some(Value::Number(x.ln())),
+        [Value::Number(x), Value::Number(base)] => Some(Value::Number(x.log(*base))),
+        _ => invalid_args(),
+    }
+}
+
+/// `math.min`/`math.max` take either several numbers or a single list.
+fn fold(args: &[Value], op: fn(f64, f64) -> f64) -> Option<Value> {
+    let numbers: Vec<Value> = match args {
+        [Value::List(items)] => items.borrow().clone(),
+        _ => args.to_vec(),
+    };
+    let mut result: Option<f64> = None;
+    for number in &numbers {
+        let Value::Number(n) = number else {
+            return invalid_args();
+        };
+        result = Some(result.map_or(*n, |acc| op(acc, *n)));
+    }
+    match result {
+        Some(n) => Some(Value::Number(n)),
+        None => invalid_args(),
+    }
+}
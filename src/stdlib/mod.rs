@@ -0,0 +1,140 @@
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::io;
+use std::rc::Rc;
+
+use crate::eval::{ErrorValue, Evaluator, Value};
+
+pub mod base;
+pub mod csv;
+pub mod fs;
+pub mod json;
+pub mod math;
+pub mod process;
+pub mod random;
+pub mod stdin;
+pub mod string;
+pub mod time;
+pub mod toml;
+pub mod yaml;
+
+/// A native function. It receives already evaluated arguments and returns
+/// `None` on error, either after reporting it with `error_here` or to have
+/// the evaluator report invalid arguments.
+pub type BuiltinFn = fn(&mut Evaluator, &[Value]) -> Option<Value>;
+
+/// Every builtin function and constant, keyed by its full name such as
+/// `print` or `math.sqrt`.
+pub struct Builtins {
+    functions: HashMap<&'static str, BuiltinFn>,
+    constants: HashMap<&'static str, Value>,
+}
+
+impl Builtins {
+    pub fn new() -> Self {
+        let mut builtins = Self {
+            functions: HashMap::new(),
+            constants: HashMap::new(),
+        };
+        base::register(&mut builtins);
+        string::register(&mut builtins);
+        math::register(&mut builtins);
+        fs::register(&mut builtins);
+        stdin::register(&mut builtins);
+        time::register(&mut builtins);
+        random::register(&mut builtins);
+        process::register(&mut builtins);
+        json::register(&mut builtins);
+        toml::register(&mut builtins);
+        yaml::register(&mut builtins);
+        csv::register(&mut builtins);
+        builtins
+    }
+
+    pub fn function(&mut self, name: &'static str, func: BuiltinFn) {
+        self.functions.insert(name, func);
+    }
+
+    pub fn constant(&mut self, name: &'static str, value: Value) {
+        self.constants.insert(name, value);
+    }
+
+    pub fn get(&self, name: &str) -> Option<BuiltinFn> {
+        self.functions.get(name).copied()
+    }
+
+    /// Every builtin function and constant name, sorted.
+    pub fn names(&self) -> Vec<&'static str> {
+        let mut names: Vec<&'static str> = self.functions.keys().chain(self.constants.keys()).copied().collect();
+        names.sort();
+        names
+    }
+
+    /// A module's constants as a map, so `math.pi` reads like a field.
+    pub fn module(&self, name: &str) -> Option<Value> {
+        let prefix = format!("{}.", name);
+        let entries: BTreeMap<String, Value> = self
+            .constants
+            .iter()
+            .filter_map(|(key, value)| Some((key.strip_prefix(&prefix)?.to_string(), value.clone())))
+            .collect();
+        if entries.is_empty() {
+            return None;
+        }
+        Some(map(entries))
+    }
+}
+
+/// Fails a call whose arguments don't match any accepted form; the
+/// evaluator reports it, listing the argument types.
+pub fn invalid_args() -> Option<Value> {
+    None
+}
+
+pub fn boolean(b: bool) -> Value {
+    Value::Bool(b)
+}
+
+pub fn list(items: Vec<Value>) -> Value {
+    Value::List(Rc::new(RefCell::new(items)))
+}
+
+pub fn map(entries: BTreeMap<String, Value>) -> Value {
+    Value::Map(Rc::new(RefCell::new(entries)))
+}
+
+/// Builds an error value for the script to inspect.
+pub fn error(kind: &str, message: String, fields: BTreeMap<String, Value>) -> Value {
+    Value::Error(Rc::new(ErrorValue {
+        kind: kind.to_string(),
+        message,
+        fields,
+    }))
+}
+
+/// An error for malformed input to one of the data format parsers, with the
+/// 1-based `line` and `column` where it was detected.
+pub fn parse_error(kind: &str, message: &str, line: usize, column: usize) -> Value {
+    let mut fields = BTreeMap::new();
+    fields.insert("line".to_string(), Value::Number(line as f64));
+    fields.insert("column".to_string(), Value::Number(column as f64));
+    let message = format!("{} at line {}, column {}", message, line, column);
+    error(kind, message, fields)
+}
+
+/// Converts an I/O failure on `path` into an error value.
+pub fn io_error(err: io::Error, path: &str) -> Value {
+    let kind = match err.kind() {
+        io::ErrorKind::NotFound => "not_found",
+        io::ErrorKind::PermissionDenied => "permission_denied",
+        io::ErrorKind::AlreadyExists => "already_exists",
+        io::ErrorKind::InvalidData => "invalid_data",
+        io::ErrorKind::IsADirectory => "is_a_directory",
+        io::ErrorKind::NotADirectory => "not_a_directory",
+        io::ErrorKind::DirectoryNotEmpty => "directory_not_empty",
+        _ => "io",
+    };
+    let mut fields = BTreeMap::new();
+    fields.insert("path".to_string(), Value::String(path.to_string()));
+    error(kind, format!("{}: {}", path, err), fields)
+}
@@ -0,0 +1,104 @@
+use std::env;
+use std::io::{self, Write};
+
+use crate::eval::{Evaluator, Value};
+use crate::stdlib::{invalid_args, list, Builtins};
+
+pub fn register(builtins: &mut Builtins) {
+    builtins.function("args", args);
+    builtins.function("env", get_env);
+    builtins.function("set_env", set_env);
+    builtins.function("exit", exit);
+}
+
+/// The command-line arguments that follow the script path.
+fn args(evaluator: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    if !args.is_empty() {
+        return invalid_args();
+    }
+    Some(list(evaluator.args.iter().cloned().map(Value::String).collect()))
+}
+
+/// `env(name)` is the variable's value, or nil if it is unset.
+fn get_env(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [Value::String(name)] = args else {
+        return invalid_args();
+    };
+    Some(env::var(name).map_or(Value::Nil, Value::String))
+}
+
+/// `set_env(name, value)` sets a variable for this process and any it
+/// starts; a nil value removes it.
+fn set_env(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    match args {
+        [Value::String(name), Value::String(value)] if valid_name(name) => env::set_var(name, value),
+        [Value::String(name), Value::Nil] if valid_name(name) => env::remove_var(name),
+        _ => return invalid_args(),
+    }
+    Some(Value::Nil)
+}
+
+/// `env::set_var` panics on names it cannot store.
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && !name.contains(['=', '\0'])
+}
+
+/// `exit([code])` ends the process straight away, with status 0 by default.
+fn exit(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let Some(code) = status(args) else {
+        return invalid_args();
+    };
+    let _ = io::stdout().flush();
+    std::process::exit(code)
+}
+
+/// The status `exit` was asked for, or `None` when the arguments are invalid.
+fn status(args: &[Value]) -> Option<i32> {
+    match args {
+        [] => Some(0),
+        [Value::Number(code)] if code.fract() == 0.0 => Some(*code as i32),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::Command;
+
+    #[test]
+    fn lists_the_script_arguments() {
+        let mut evaluator = Evaluator::new();
+        assert_eq!(args(&mut evaluator, &[]).unwrap().to_string(), "[]");
+        evaluator.set_args(vec!["one".to_string(), "two words".to_string()]);
+        assert_eq!(args(&mut evaluator, &[]).unwrap().to_string(), "[\"one\", \"two words\"]");
+        assert!(args(&mut evaluator, &[Value::Nil]).is_none());
+    }
+
+    #[test]
+    fn reads_the_exit_status() {
+        assert_eq!(status(&[]), Some(0));
+        assert_eq!(status(&[Value::Number(3.0)]), Some(3));
+        assert_eq!(status(&[Value::Number(-1.0)]), Some(-1));
+        assert_eq!(status(&[Value::Number(1.5)]), None);
+        assert_eq!(status(&[Value::String("1".to_string())]), None);
+        assert_eq!(status(&[Value::Number(1.0), Value::Number(2.0)]), None);
+    }
+
+    /// Runs itself again in a child process, which exits through `exit`.
+    #[test]
+    fn exits_with_the_status_after_flushing() {
+        if env::var_os("ALIN_TEST_EXIT").is_some() {
+            print!("before exit");
+            exit(&mut Evaluator::new(), &[Value::Number(7.0)]);
+            unreachable!();
+        }
+        let output = Command::new(env::current_exe().unwrap())
+            .args(["--exact", "stdlib::process::tests::exits_with_the_status_after_flushing", "--nocapture"])
+            .env("ALIN_TEST_EXIT", "1")
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(7));
+        assert!(String::from_utf8_lossy(&output.stdout).contains("before exit"));
+    }
+}
@@ -0,0 +1,105 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::eval::{Evaluator, Value};
+use crate::stdlib::{invalid_args, Builtins};
+
+/// A small SplitMix64 generator. The same seed always produces the same
+/// sequence, which `--seed` relies on for reproducible scripts.
+#[derive(Debug)]
+pub struct Rng {
+    state: u64,
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    /// Seeds from the current time when no seed was requested.
+    pub fn from_time() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or(0);
+        Self::new(nanos)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// A float in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// An index in `0..len`; `len` must be non-zero.
+    fn below(&mut self, len: usize) -> usize {
+        (self.next_f64() * len as f64) as usize
+    }
+}
+
+pub fn register(builtins: &mut Builtins) {
+    builtins.function("random.seed", seed);
+    builtins.function("random.random", random);
+    builtins.function("random.randint", randint);
+    builtins.function("random.choice", choice);
+    builtins.function("random.shuffle", shuffle);
+}
+
+fn seed(ev: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [Value::Number(n)] = args else {
+        return invalid_args();
+    };
+    ev.rng = Rng::new(*n as u64);
+    Some(Value::Nil)
+}
+
+fn random(ev: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    if !args.is_empty() {
+        return invalid_args();
+    }
+    Some(Value::Number(ev.rng.next_f64()))
+}
+
+/// A whole number between `a` and `b`, both included.
+fn randint(ev: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [Value::Number(a), Value::Number(b)] = args else {
+        return invalid_args();
+    };
+    let (low, high) = (a.ceil(), b.floor());
+    if low > high {
+        return invalid_args();
+    }
+    let span = (high - low + 1.0) as usize;
+    Some(Value::Number(low + ev.rng.below(span) as f64))
+}
+
+fn choice(ev: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [Value::List(items)] = args else {
+        return invalid_args();
+    };
+    let items = items.borrow();
+    if items.is_empty() {
+        ev.error_here("E0215", "`random.choice()` called on an empty list".to_string());
+        return None;
+    }
+    Some(items[ev.rng.below(items.len())].clone())
+}
+
+/// Shuffles a list in place (Fisher-Yates).
+fn shuffle(ev: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [Value::List(items)] = args else {
+        return invalid_args();
+    };
+    let mut items = items.borrow_mut();
+    for i in (1..items.len()).rev() {
+        let j = ev.rng.below(i + 1);
+        items.swap(i, j);
+    }
+    Some(Value::Nil)
+}
@@ -0,0 +1,112 @@
+use std::io::{self, BufRead, Read, Write};
+
+use crate::eval::{Evaluator, Value};
+use crate::iter::{self, Iter};
+use crate::stdlib::base::iterator;
+use crate::stdlib::{invalid_args, io_error, Builtins};
+
+/// Standard input builtins. They read through the same buffered handle as
+/// the REPL, so in an interactive session `input()` takes the next line typed
+/// after the command. All of them return nil at end of input.
+pub fn register(builtins: &mut Builtins) {
+    builtins.function("input", input);
+    builtins.function("read_line", read_line);
+    builtins.function("read_all", read_all);
+    builtins.function("read_lines", read_lines);
+}
+
+/// `input(prompt)` shows the prompt on the same line, then reads a line.
+fn input(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    match args {
+        [] => {}
+        [prompt] => {
+            print!("{}", prompt);
+            io::stdout().flush().ok();
+        }
+        _ => return invalid_args(),
+    }
+    Some(line())
+}
+
+fn read_line(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    if !args.is_empty() {
+        return invalid_args();
+    }
+    Some(line())
+}
+
+fn read_all(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    if !args.is_empty() {
+        return invalid_args();
+    }
+    Some(all(&mut io::stdin().lock()))
+}
+
+/// An iterator over the remaining lines of stdin, read on demand.
+fn read_lines(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    if !args.is_empty() {
+        return invalid_args();
+    }
+    Some(iterator(Iter::Stdin))
+}
+
+fn line() -> Value {
+    line_from(&mut io::stdin().lock())
+}
+
+fn line_from(reader: &mut impl BufRead) -> Value {
+    match iter::read_line(reader) {
+        Ok(Some(line)) => Value::String(line),
+        Ok(None) => Value::Nil,
+        Err(err) => io_error(err, "<stdin>"),
+    }
+}
+
+fn all(reader: &mut impl Read) -> Value {
+    let mut content = String::new();
+    match reader.read_to_string(&mut content) {
+        Ok(0) => Value::Nil,
+        Ok(_) => Value::String(content),
+        Err(err) => io_error(err, "<stdin>"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn reads_lines_without_their_endings() {
+        let mut input = Cursor::new("one\r\ntwo\n\nlast");
+        let lines: Vec<String> = (0..5).map(|_| line_from(&mut input).to_string()).collect();
+        assert_eq!(lines, ["one", "two", "", "last", "nil"]);
+    }
+
+    #[test]
+    fn reads_everything_left() {
+        let mut input = Cursor::new("first\nrest\nof it\n");
+        assert_eq!(line_from(&mut input).to_string(), "first");
+        assert_eq!(all(&mut input).to_string(), "rest\nof it\n");
+        assert_eq!(all(&mut input).to_string(), "nil");
+    }
+
+    #[test]
+    fn invalid_text_gives_error_values() {
+        for result in [line_from(&mut Cursor::new(b"ok\xff\n")), all(&mut Cursor::new(b"\xfe"))] {
+            let Value::Error(err) = result else { panic!("not an error: {}", result) };
+            assert_eq!(err.kind, "invalid_data");
+            assert_eq!(err.fields["path"].to_string(), "<stdin>");
+        }
+    }
+
+    #[test]
+    fn rejects_extra_arguments() {
+        let mut evaluator = Evaluator::new();
+        let two = [Value::Nil, Value::Nil];
+        assert!(input(&mut evaluator, &two).is_none());
+        assert!(read_line(&mut evaluator, &two[..1]).is_none());
+        assert!(read_all(&mut evaluator, &two[..1]).is_none());
+        assert!(read_lines(&mut evaluator, &two[..1]).is_none());
+    }
+}
@@ -0,0 +1,190 @@
+use crate::eval::{Evaluator, Value};
+use crate::stdlib::{boolean, invalid_args, list, Builtins};
+
+/// Indices and lengths count characters, not bytes.
+pub fn register(builtins: &mut Builtins) {
+    builtins.function("string.len", len);
+    builtins.function("string.upper", upper);
+    builtins.function("string.lower", lower);
+    builtins.function("string.trim", trim);
+    builtins.function("string.split", split);
+    builtins.function("string.join", join);
+    builtins.function("string.replace", replace);
+    builtins.function("string.find", find);
+    builtins.function("string.starts_with", starts_with);
+    builtins.function("string.ends_with", ends_with);
+    builtins.function("string.contains", contains);
+    builtins.function("string.repeat", repeat);
+    builtins.function("string.chars", chars);
+    builtins.function("string.substring", substring);
+    builtins.function("string.pad_left", pad_left);
+    builtins.function("string.pad_right", pad_right);
+}
+
+fn len(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [Value::String(s)] = args else {
+        return invalid_args();
+    };
+    Some(Value::Number(s.chars().count() as f64))
+}
+
+fn upper(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [Value::String(s)] = args else {
+        return invalid_args();
+    };
+    Some(Value::String(s.to_uppercase()))
+}
+
+fn lower(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [Value::String(s)] = args else {
+        return invalid_args();
+    };
+    Some(Value::String(s.to_lowercase()))
+}
+
+fn trim(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [Value::String(s)] = args else {
+        return invalid_args();
+    };
+    Some(Value::String(s.trim().to_string()))
+}
+
+fn split(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let parts: Vec<String> = match args {
+        [Value::String(s)] => s.split_whitespace().map(str::to_string).collect(),
+        [Value::String(s), Value::String(sep)] if sep.is_empty() => {
+            s.chars().map(String::from).collect()
+        }
+        [Value::String(s), Value::String(sep)] => s.split(sep.as_str()).map(str::to_string).collect(),
+        _ => return invalid_args(),
+    };
+    Some(strings(parts))
+}
+
+fn join(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [Value::List(items), Value::String(sep)] = args else {
+        return invalid_args();
+    };
+    let parts: Vec<String> = items.borrow().iter().map(|item| item.to_string()).collect();
+    Some(Value::String(parts.join(sep)))
+}
+
+fn replace(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [Value::String(s), Value::String(from), Value::String(to)] = args else {
+        return invalid_args();
+    };
+    Some(Value::String(s.replace(from.as_str(), to)))
+}
+
+fn find(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [Value::String(s), Value::String(sub)] = args else {
+        return invalid_args();
+    };
+    let index = match s.find(sub.as_str()) {
+        Some(byte) => s[..byte].chars().count() as f64,
+        None => -1.0,
+    };
+    Some(Value::Number(index))
+}
+
+fn starts_with(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [Value::String(s), Value::String(prefix)] = args else {
+        return invalid_args();
+    };
+    Some(boolean(s.starts_with(prefix.as_str())))
+}
+
+fn ends_with(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [Value::String(s), Value::String(suffix)] = args else {
+        return invalid_args();
+    };
+    Some(boolean(s.ends_with(suffix.as_str())))
+}
+
+fn contains(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [Value::String(s), Value::String(sub)] = args else {
+        return invalid_args();
+    };
+    Some(boolean(s.contains(sub.as_str())))
+}
+
+fn repeat(ev: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [Value::String(s), Value::Number(n)] = args else {
+        return invalid_args();
+    };
+    if !n.is_finite() || *n < 0.0 {
+        return invalid_args();
+    }
+    let count = *n as usize;
+    check_len(ev, "string.repeat", s.len().checked_mul(count))?;
+    Some(Value::String(s.repeat(count)))
+}
+
+fn chars(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [Value::String(s)] = args else {
+        return invalid_args();
+    };
+    Some(strings(s.chars().map(String::from).collect()))
+}
+
+fn substring(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let result = match args {
+        [Value::String(s), Value::Number(start)] => s.chars().skip(index(*start)).collect(),
+        [Value::String(s), Value::Number(start), Value::Number(end)] => {
+            let (start, end) = (index(*start), index(*end));
+            s.chars().skip(start).take(end.saturating_sub(start)).collect()
+        }
+        _ => return invalid_args(),
+    };
+    Some(Value::String(result))
+}
+
+fn pad_left(ev: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let (s, padding) = padding(ev, "string.pad_left", args)?;
+    Some(Value::String(padding + s))
+}
+
+fn pad_right(ev: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let (s, padding) = padding(ev, "string.pad_right", args)?;
+    Some(Value::String(s.to_string() + &padding))
+}
+
+fn strings(items: Vec<String>) -> Value {
+    list(items.into_iter().map(Value::String).collect())
+}
+
+fn index(n: f64) -> usize {
+    n.max(0.0) as usize
+}
+
+/// Parses `(s, width[, fill])` into the string and the fill it needs to
+/// reach `width` characters.
+fn padding<'a>(ev: &mut Evaluator, name: &str, args: &'a [Value]) -> Option<(&'a str, String)> {
+    let (s, width, fill) = match args {
+        [Value::String(s), Value::Number(width)] if width.is_finite() => (s, width, ' '),
+        [Value::String(s), Value::Number(width), Value::String(fill)]
+            if width.is_finite() && fill.chars().count() == 1 =>
+        {
+            (s, width, fill.chars().next()?)
+        }
+        // Leaves the evaluator to report the arguments as invalid.
+        _ => return None,
+    };
+    let missing = index(*width).saturating_sub(s.chars().count());
+    check_len(ev, name, fill.len_utf8().checked_mul(missing).and_then(|len| len.checked_add(s.len())))?;
+    Some((s, std::iter::repeat_n(fill, missing).collect()))
+}
+
+/// The longest string, in bytes, that `repeat` and the padding functions
+/// build, so a huge count is an error rather than aborting the process.
+const MAX_LEN: usize = 1 << 30;
+
+/// Reports a result of `len` bytes (`None` if even that overflowed) that is
+/// over `MAX_LEN`.
+fn check_len(ev: &mut Evaluator, name: &str, len: Option<usize>) -> Option<()> {
+    if len.is_some_and(|len| len <= MAX_LEN) {
+        return Some(());
+    }
+    ev.error_here("E0215", format!("`{}()` would build a string longer than {} bytes", name, MAX_LEN));
+    None
+}
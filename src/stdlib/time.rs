@@ -0,0 +1,46 @@
+use std::sync::OnceLock;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::eval::{Evaluator, Value};
+use crate::stdlib::{invalid_args, Builtins};
+
+pub fn register(builtins: &mut Builtins) {
+    builtins.function("now", now);
+    builtins.function("clock", clock);
+    builtins.function("sleep", sleep);
+}
+
+/// Wall-clock time in seconds since the Unix epoch.
+fn now(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    if !args.is_empty() {
+        return invalid_args();
+    }
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs_f64())
+        .unwrap_or(0.0);
+    Some(Value::Number(secs))
+}
+
+/// Monotonic seconds since the first call, for measuring durations.
+fn clock(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    static START: OnceLock<Instant> = OnceLock::new();
+    if !args.is_empty() {
+        return invalid_args();
+    }
+    let start = START.get_or_init(Instant::now);
+    Some(Value::Number(start.elapsed().as_secs_f64()))
+}
+
+fn sleep(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    // Negative, NaN and infinite or overflowing durations are invalid.
+    let [Value::Number(ms)] = args else {
+        return invalid_args();
+    };
+    let Ok(duration) = Duration::try_from_secs_f64(ms / 1000.0) else {
+        return invalid_args();
+    };
+    thread::sleep(duration);
+    Some(Value::Nil)
+}
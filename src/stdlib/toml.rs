@@ -0,0 +1,733 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use crate::eval::{Evaluator, Value};
+use crate::stdlib::json::write_string;
+use crate::stdlib::{error, invalid_args, list, parse_error, Builtins};
+
+pub fn register(builtins: &mut Builtins) {
+    builtins.function("toml.parse", parse);
+    builtins.function("toml.stringify", stringify);
+}
+
+/// Parses a TOML document into a map. Integers and floats both become
+/// numbers, and dates and times are kept as strings.
+fn parse(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [Value::String(text)] = args else {
+        return invalid_args();
+    };
+    let mut reader = Reader::new(text);
+    Some(match reader.document() {
+        Ok(()) => Value::Map(reader.root.clone()),
+        Err(message) => parse_error("toml", &message, reader.line, reader.column),
+    })
+}
+
+/// Parses a TOML document read by alin itself, such as a project file.
+pub fn read(text: &str) -> Result<BTreeMap<String, Value>, String> {
+    let mut reader = Reader::new(text);
+    match reader.document() {
+        Ok(()) => Ok(reader.root.borrow().clone()),
+        Err(message) => Err(format!("{} at line {}, column {}", message, reader.line, reader.column)),
+    }
+}
+
+/// Writes a map as a TOML document, with nested maps as `[tables]` and
+/// lists of maps as `[[arrays of tables]]`.
+fn stringify(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [Value::Map(root)] = args else {
+        return invalid_args();
+    };
+    let mut out = String::new();
+    Some(match write_table(&mut out, &root.borrow(), &mut Vec::new()) {
+        Ok(()) => Value::String(out),
+        Err(message) => error("toml", message, BTreeMap::new()),
+    })
+}
+
+type Table = Rc<RefCell<BTreeMap<String, Value>>>;
+
+struct Reader {
+    chars: Vec<char>,
+    pos: usize,
+    line: usize,
+    column: usize,
+    root: Table,
+    /// The table that `key = value` lines currently go into.
+    current: Table,
+    /// Headers declared so far, since a `[table]` may only appear once.
+    defined: Vec<Vec<String>>,
+}
+
+impl Reader {
+    fn new(text: &str) -> Self {
+        let root = Table::default();
+        Self {
+            chars: text.chars().collect(),
+            pos: 0,
+            line: 1,
+            column: 1,
+            current: root.clone(),
+            root,
+            defined: Vec::new(),
+        }
+    }
+
+    fn document(&mut self) -> Result<(), String> {
+        loop {
+            self.skip_spaces();
+            match self.peek() {
+                None => return Ok(()),
+                Some('\n' | '\r' | '#') => {}
+                Some('[') => self.header()?,
+                Some(_) => {
+                    let table = self.current.clone();
+                    self.key_value(&table)?;
+                }
+            }
+            self.end_of_line()?;
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn peek_at(&self, offset: usize) -> Option<char> {
+        self.chars.get(self.pos + offset).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += 1;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
+        Some(c)
+    }
+
+    fn eat(&mut self, expected: &str) -> bool {
+        let matches = expected.chars().enumerate().all(|(i, c)| self.peek_at(i) == Some(c));
+        if matches {
+            for _ in expected.chars() {
+                self.next();
+            }
+        }
+        matches
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.peek() {
+            Some(c) if c == expected => {
+                self.next();
+                Ok(())
+            }
+            Some(c) => Err(format!("expected {:?} but found {:?}", expected, c)),
+            None => Err(format!("expected {:?} but reached the end of input", expected)),
+        }
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.next();
+        }
+    }
+
+    fn skip_comment(&mut self) {
+        if self.peek() == Some('#') {
+            while !matches!(self.peek(), None | Some('\n')) {
+                self.next();
+            }
+        }
+    }
+
+    /// Skips whitespace, newlines and comments inside an array.
+    fn skip_blank(&mut self) {
+        loop {
+            self.skip_spaces();
+            self.skip_comment();
+            if !self.eat("\n") && !self.eat("\r\n") {
+                return;
+            }
+        }
+    }
+
+    fn end_of_line(&mut self) -> Result<(), String> {
+        self.skip_spaces();
+        self.skip_comment();
+        if self.peek().is_none() || self.eat("\n") || self.eat("\r\n") {
+            Ok(())
+        } else {
+            Err("expected a newline".to_string())
+        }
+    }
+
+    /// `[a.b]` or `[[a.b]]`, which switches the current table.
+    fn header(&mut self) -> Result<(), String> {
+        let start = (self.line, self.column);
+        self.expect('[')?;
+        let is_array = self.eat("[");
+        self.skip_spaces();
+        let path = self.key()?;
+        self.skip_spaces();
+        self.expect(']')?;
+        if is_array {
+            self.expect(']')?;
+        }
+        let (last, parents) = path.split_last().expect("keys are never empty");
+        let mut table = self.root.clone();
+        for key in parents {
+            table = descend(&table, key)?;
+        }
+        if is_array {
+            let entry = table.borrow().get(last).cloned();
+            let element = Table::default();
+            match entry {
+                None => {
+                    let items = list(vec![Value::Map(element.clone())]);
+                    table.borrow_mut().insert(last.clone(), items);
+                }
+                Some(Value::List(items)) => items.borrow_mut().push(Value::Map(element.clone())),
+                Some(_) => return Err(format!("key {:?} is already defined", last)),
+            }
+            // Sub-tables may be declared again for each new element.
+            self.defined.retain(|defined| !defined.starts_with(&path));
+            self.current = element;
+        } else {
+            if self.defined.contains(&path) {
+                (self.line, self.column) = start;
+                return Err(format!("table [{}] is already defined", path.join(".")));
+            }
+            self.current = descend(&table, last)?;
+            self.defined.push(path);
+        }
+        Ok(())
+    }
+
+    fn key_value(&mut self, table: &Table) -> Result<(), String> {
+        let start = (self.line, self.column);
+        let path = self.key()?;
+        self.skip_spaces();
+        self.expect('=')?;
+        self.skip_spaces();
+        let value = self.value()?;
+        let (last, parents) = path.split_last().expect("keys are never empty");
+        let mut table = table.clone();
+        for key in parents {
+            table = descend(&table, key)?;
+        }
+        if table.borrow().contains_key(last) {
+            // Point at the key rather than past its value.
+            (self.line, self.column) = start;
+            return Err(format!("duplicate key {:?}", last));
+        }
+        table.borrow_mut().insert(last.clone(), value);
+        Ok(())
+    }
+
+    /// A possibly dotted key such as `a."b c".d`.
+    fn key(&mut self) -> Result<Vec<String>, String> {
+        let mut path = vec![self.simple_key()?];
+        loop {
+            self.skip_spaces();
+            if !self.eat(".") {
+                return Ok(path);
+            }
+            self.skip_spaces();
+            path.push(self.simple_key()?);
+        }
+    }
+
+    fn simple_key(&mut self) -> Result<String, String> {
+        match self.peek() {
+            Some('"') => self.basic_string(),
+            Some('\'') => self.literal_string(),
+            _ => {
+                let mut key = String::new();
+                while let Some(c) = self.peek().filter(|c| is_bare(*c)) {
+                    self.next();
+                    key.push(c);
+                }
+                if key.is_empty() {
+                    return Err("expected a key".to_string());
+                }
+                Ok(key)
+            }
+        }
+    }
+
+    fn value(&mut self) -> Result<Value, String> {
+        match self.peek() {
+            Some('"') if self.eat("\"\"\"") => Ok(Value::String(self.multiline_string('"')?)),
+            Some('\'') if self.eat("'''") => Ok(Value::String(self.multiline_string('\'')?)),
+            Some('"') => Ok(Value::String(self.basic_string()?)),
+            Some('\'') => Ok(Value::String(self.literal_string()?)),
+            Some('[') => self.array(),
+            Some('{') => self.inline_table(),
+            Some(_) => self.scalar(),
+            None => Err("expected a value".to_string()),
+        }
+    }
+
+    fn array(&mut self) -> Result<Value, String> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        loop {
+            self.skip_blank();
+            if self.eat("]") {
+                return Ok(list(items));
+            }
+            items.push(self.value()?);
+            self.skip_blank();
+            if self.eat("]") {
+                return Ok(list(items));
+            }
+            self.expect(',')?;
+        }
+    }
+
+    fn inline_table(&mut self) -> Result<Value, String> {
+        self.expect('{')?;
+        let table = Table::default();
+        self.skip_spaces();
+        if self.eat("}") {
+            return Ok(Value::Map(table));
+        }
+        loop {
+            self.skip_spaces();
+            self.key_value(&table)?;
+            self.skip_spaces();
+            if self.eat("}") {
+                return Ok(Value::Map(table));
+            }
+            self.expect(',')?;
+        }
+    }
+
+    fn basic_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut result = String::new();
+        loop {
+            match self.peek() {
+                None | Some('\n') => return Err("unterminated string".to_string()),
+                Some('"') => {
+                    self.next();
+                    return Ok(result);
+                }
+                Some('\\') => result.push(self.escape()?),
+                Some(c) => {
+                    self.next();
+                    result.push(c);
+                }
+            }
+        }
+    }
+
+    fn literal_string(&mut self) -> Result<String, String> {
+        self.expect('\'')?;
+        let mut result = String::new();
+        loop {
+            match self.next() {
+                None | Some('\n') => return Err("unterminated string".to_string()),
+                Some('\'') => return Ok(result),
+                Some(c) => result.push(c),
+            }
+        }
+    }
+
+    /// The body of a `"""` or `'''` string, after its opening quotes. A
+    /// newline right after the opening quotes is not part of the string.
+    fn multiline_string(&mut self, quote: char) -> Result<String, String> {
+        let delimiter: String = std::iter::repeat_n(quote, 3).collect();
+        if !self.eat("\n") {
+            self.eat("\r\n");
+        }
+        let mut result = String::new();
+        loop {
+            if self.eat(&delimiter) {
+                // Up to two more quotes still belong to the string.
+                for _ in 0..2 {
+                    if self.peek() == Some(quote) {
+                        self.next();
+                        result.push(quote);
+                    }
+                }
+                return Ok(result);
+            }
+            match self.peek() {
+                None => return Err("unterminated string".to_string()),
+                Some('\\') if quote == '"' => {
+                    if self.line_ending_backslash() {
+                        self.next();
+                        while matches!(self.peek(), Some(' ' | '\t' | '\n' | '\r')) {
+                            self.next();
+                        }
+                    } else {
+                        result.push(self.escape()?);
+                    }
+                }
+                Some(c) => {
+                    self.next();
+                    result.push(c);
+                }
+            }
+        }
+    }
+
+    /// Whether the `\` at the current position is the last thing on its line.
+    fn line_ending_backslash(&self) -> bool {
+        let rest = self.chars[self.pos + 1..].iter();
+        let mut rest = rest.skip_while(|c| matches!(c, ' ' | '\t'));
+        matches!(rest.next(), Some('\n' | '\r'))
+    }
+
+    fn escape(&mut self) -> Result<char, String> {
+        self.expect('\\')?;
+        let c = match self.next() {
+            Some('b') => '\u{8}',
+            Some('t') => '\t',
+            Some('n') => '\n',
+            Some('f') => '\u{c}',
+            Some('r') => '\r',
+            Some('"') => '"',
+            Some('\\') => '\\',
+            Some('u') => self.unicode(4)?,
+            Some('U') => self.unicode(8)?,
+            _ => return Err("invalid escape sequence".to_string()),
+        };
+        Ok(c)
+    }
+
+    fn unicode(&mut self, digits: usize) -> Result<char, String> {
+        let mut code = 0;
+        for _ in 0..digits {
+            let digit = self
+                .peek()
+                .and_then(|c| c.to_digit(16))
+                .ok_or_else(|| format!("expected {} hex digits", digits))?;
+            self.next();
+            code = code * 16 + digit;
+        }
+        char::from_u32(code).ok_or_else(|| "invalid unicode escape".to_string())
+    }
+
+    /// Booleans, numbers, and dates and times, which are all unquoted.
+    fn scalar(&mut self) -> Result<Value, String> {
+        let mut token = self.token();
+        // A date and a time may be separated by a space.
+        let is_date = token.len() == 10 && token.as_bytes()[4] == b'-';
+        if is_date
+            && self.peek() == Some(' ')
+            && self.peek_at(1).is_some_and(|c| c.is_ascii_digit())
+            && self.peek_at(3) == Some(':')
+        {
+            self.next();
+            token.push(' ');
+            token.push_str(&self.token());
+        }
+        match token.as_str() {
+            "true" => return Ok(Value::Bool(true)),
+            "false" => return Ok(Value::Bool(false)),
+            "inf" | "+inf" => return Ok(Value::Number(f64::INFINITY)),
+            "-inf" => return Ok(Value::Number(f64::NEG_INFINITY)),
+            "nan" | "+nan" | "-nan" => return Ok(Value::Number(f64::NAN)),
+            _ => {}
+        }
+        let bytes = token.as_bytes();
+        if bytes.len() > 4 && bytes[4] == b'-' || bytes.len() > 2 && bytes[2] == b':' {
+            return Ok(Value::String(token));
+        }
+        parse_number(&token).ok_or_else(|| format!("invalid value {:?}", token))
+    }
+
+    fn token(&mut self) -> String {
+        let mut token = String::new();
+        while let Some(c) = self.peek() {
+            if matches!(c, ' ' | '\t' | '\n' | '\r' | ',' | ']' | '}' | '#') {
+                break;
+            }
+            self.next();
+            token.push(c);
+        }
+        token
+    }
+}
+
+/// The table stored under `key`, creating it if needed. For an array of
+/// tables this is its last element.
+fn descend(table: &Table, key: &str) -> Result<Table, String> {
+    let entry = table.borrow().get(key).cloned();
+    match entry {
+        Some(Value::Map(inner)) => Ok(inner),
+        Some(Value::List(items)) => match items.borrow().last() {
+            Some(Value::Map(inner)) => Ok(inner.clone()),
+            _ => Err(format!("key {:?} is already defined", key)),
+        },
+        Some(_) => Err(format!("key {:?} is already defined", key)),
+        None => {
+            let inner = Table::default();
+            table.borrow_mut().insert(key.to_string(), Value::Map(inner.clone()));
+            Ok(inner)
+        }
+    }
+}
+
+fn is_bare(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+/// Decimal, hex (`0x`), octal (`0o`) and binary (`0b`) numbers, with `_`
+/// allowed between digits.
+fn parse_number(token: &str) -> Option<Value> {
+    if token.starts_with('_') || token.ends_with('_') || token.contains("__") {
+        return None;
+    }
+    let digits = token.replace('_', "");
+    let radix = match digits.get(..2) {
+        Some("0x") => 16,
+        Some("0o") => 8,
+        Some("0b") => 2,
+        _ => {
+            let unsigned = digits.strip_prefix(['+', '-']).unwrap_or(&digits);
+            let integer = unsigned.split(['.', 'e', 'E']).next().unwrap_or_default();
+            // Leading zeros aren't allowed, so `07` isn't mistaken for octal.
+            if !unsigned.starts_with(|c: char| c.is_ascii_digit()) || (integer.len() > 1 && integer.starts_with('0')) {
+                return None;
+            }
+            return digits.parse().ok().map(Value::Number);
+        }
+    };
+    i64::from_str_radix(&digits[2..], radix).ok().map(|n| Value::Number(n as f64))
+}
+
+/// Plain values come first, since everything after a header belongs to it.
+fn write_table(out: &mut String, table: &BTreeMap<String, Value>, path: &mut Vec<String>) -> Result<(), String> {
+    for (key, value) in table {
+        if !matches!(value, Value::Map(_)) && !is_table_array(value) {
+            write_key(out, key);
+            out.push_str(" = ");
+            write_inline(out, value)?;
+            out.push('\n');
+        }
+    }
+    for (key, value) in table {
+        path.push(key.clone());
+        match value {
+            Value::Map(inner) => {
+                // A table holding only other tables is implied by their headers.
+                let inner = inner.borrow();
+                let implied = !inner.is_empty()
+                    && inner.values().all(|v| matches!(v, Value::Map(_)) || is_table_array(v));
+                if !implied {
+                    write_header(out, path, false);
+                }
+                write_table(out, &inner, path)?;
+            }
+            Value::List(items) if is_table_array(value) => {
+                for item in items.borrow().iter() {
+                    if let Value::Map(inner) = item {
+                        write_header(out, path, true);
+                        write_table(out, &inner.borrow(), path)?;
+                    }
+                }
+            }
+            _ => {}
+        }
+        path.pop();
+    }
+    Ok(())
+}
+
+fn is_table_array(value: &Value) -> bool {
+    match value {
+        Value::List(items) => {
+            let items = items.borrow();
+            !items.is_empty() && items.iter().all(|item| matches!(item, Value::Map(_)))
+        }
+        _ => false,
+    }
+}
+
+fn write_header(out: &mut String, path: &[String], is_array: bool) {
+    if !out.is_empty() {
+        out.push('\n');
+    }
+    out.push_str(if is_array { "[[" } else { "[" });
+    for (i, key) in path.iter().enumerate() {
+        if i > 0 {
+            out.push('.');
+        }
+        write_key(out, key);
+    }
+    out.push_str(if is_array { "]]\n" } else { "]\n" });
+}
+
+fn write_key(out: &mut String, key: &str) {
+    if !key.is_empty() && key.chars().all(is_bare) {
+        out.push_str(key);
+    } else {
+        write_string(out, key);
+    }
+}
+
+fn write_inline(out: &mut String, value: &Value) -> Result<(), String> {
+    match value {
+        Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Value::Number(n) if n.is_nan() => out.push_str("nan"),
+        Value::Number(n) if n.is_infinite() => out.push_str(if *n > 0.0 { "inf" } else { "-inf" }),
+        Value::Number(n) => out.push_str(&n.to_string()),
+        Value::String(s) => write_string(out, s),
+        Value::List(items) => write_array(out, &items.borrow())?,
+        Value::Tuple(items) => write_array(out, items)?,
+        Value::Map(entries) => {
+            out.push('{');
+            for (i, (key, item)) in entries.borrow().iter().enumerate() {
+                out.push_str(if i > 0 { ", " } else { " " });
+                write_key(out, key);
+                out.push_str(" = ");
+                write_inline(out, item)?;
+            }
+            out.push_str(if entries.borrow().is_empty() { "}" } else { " }" });
+        }
+        other => return Err(format!("{} cannot be represented in TOML", other)),
+    }
+    Ok(())
+}
+
+fn write_array(out: &mut String, items: &[Value]) -> Result<(), String> {
+    out.push('[');
+    for (i, item) in items.iter().enumerate() {
+        if i > 0 {
+            out.push_str(", ");
+        }
+        write_inline(out, item)?;
+    }
+    out.push(']');
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stdlib::json::Reader as Json;
+
+    fn parsed(text: &str) -> Value {
+        parse(&mut Evaluator::new(), &[Value::String(text.to_string())]).unwrap()
+    }
+
+    fn stringified(json: &str) -> String {
+        let value = Json::new(json).document().unwrap();
+        match stringify(&mut Evaluator::new(), &[value]).unwrap() {
+            Value::String(text) => text,
+            other => panic!("not a string: {}", other),
+        }
+    }
+
+    /// The message of the error parsing `text` gives.
+    fn parse_failure(text: &str) -> String {
+        match parsed(text) {
+            Value::Error(err) => err.message.clone(),
+            other => panic!("parsed {:?} as {}", text, other),
+        }
+    }
+
+    #[test]
+    fn parses_values() {
+        let text = "\
+title = \"T\\\"q\\\" \\u00e9\\n\"
+path = 'C:\\dir'
+count = 1_000
+ratio = -2.5e3
+mask = 0xff
+zero = 0
+on = true
+when = 1979-05-27T07:32:00Z
+mixed = [1, [2, 3], \"x\",]
+";
+        let expected = "{count: 1000, mask: 255, mixed: [1, [2, 3], \"x\"], on: true, path: \"C:\\\\dir\", \
+                        ratio: -2500, title: \"T\\\"q\\\" \u{e9}\\n\", when: \"1979-05-27T07:32:00Z\", zero: 0}";
+        assert_eq!(parsed(text).to_string(), expected);
+    }
+
+    #[test]
+    fn parses_nested_tables_and_arrays_of_tables() {
+        let text = "\
+inline = { a = 1, b.c = 2 }
+[server.http]
+port = 80 # the default
+\"quoted key\" = 1
+
+[[items]]
+name = \"a\"
+[[items]]
+name = \"b\"
+[items.sub]
+x = 1
+";
+        let expected = "{inline: {a: 1, b: {c: 2}}, items: [{name: \"a\"}, {name: \"b\", sub: {x: 1}}], \
+                        server: {http: {port: 80, quoted key: 1}}}";
+        assert_eq!(parsed(text).to_string(), expected);
+        let project = read("[lint]\nunused-variable = \"deny\"\n").unwrap();
+        assert_eq!(project["lint"].to_string(), "{unused-variable: \"deny\"}");
+    }
+
+    #[test]
+    fn stringifies_tables_and_quotes_what_needs_it() {
+        let json = r#"{"name": "tab\there", "odd key": 1, "list": [], "rows": [{"b": 1}, {"b": 2}],
+                       "mixed": [{"b": 1}, 2], "nested": {"deep": {"x": "y"}}}"#;
+        let expected = "\
+list = []
+mixed = [{ b = 1 }, 2]
+name = \"tab\\there\"
+\"odd key\" = 1
+
+[nested.deep]
+x = \"y\"
+
+[[rows]]
+b = 1
+
+[[rows]]
+b = 2
+";
+        assert_eq!(stringified(json), expected);
+        let err = stringify(&mut Evaluator::new(), &[Json::new(r#"{"a": null}"#).document().unwrap()]).unwrap();
+        assert_eq!(err.to_string(), "error(toml): nil cannot be represented in TOML");
+    }
+
+    #[test]
+    fn round_trips() {
+        let text = "a = [1, \"two\", [3.5]]\nb = \"quote \\\" and \\\\ and \\n\"\n\n[t.u]\nv = true\n\n[[w]]\nx = 1\n";
+        let value = parsed(text);
+        let Value::String(written) = stringify(&mut Evaluator::new(), std::slice::from_ref(&value)).unwrap() else {
+            panic!("stringify failed");
+        };
+        assert_eq!(written, text);
+        assert_eq!(parsed(&written).to_string(), value.to_string());
+    }
+
+    #[test]
+    fn reports_errors_where_they_are() {
+        let cases = [
+            ("a = ", "expected a value at line 1, column 5"),
+            ("a = 1\na = 2", "duplicate key \"a\" at line 2, column 1"),
+            ("[t]\nx = 1\n[t]", "table [t] is already defined at line 3, column 1"),
+            ("a = \"open", "unterminated string at line 1, column 10"),
+            ("a = [1, 2", "expected ',' but reached the end of input at line 1, column 10"),
+            ("= 1", "expected a key at line 1, column 1"),
+            ("a = 1 b", "expected a newline at line 1, column 7"),
+            ("a = 07", "invalid value \"07\" at line 1, column 7"),
+        ];
+        for (text, message) in cases {
+            assert_eq!(parse_failure(text), message, "for {:?}", text);
+        }
+        let Value::Error(err) = parsed("x = 1\ny = ") else { panic!() };
+        assert_eq!(err.fields["line"].to_string(), "2");
+        assert_eq!(err.fields["column"].to_string(), "5");
+        assert_eq!(read("a = 1\na = 2").unwrap_err(), "duplicate key \"a\" at line 2, column 1");
+    }
+}
@@ -0,0 +1,710 @@
+use std::collections::BTreeMap;
+
+use crate::eval::{Evaluator, Value};
+use crate::stdlib::json::write_string;
+use crate::stdlib::{error, invalid_args, list, map, parse_error, Builtins};
+
+pub fn register(builtins: &mut Builtins) {
+    builtins.function("yaml.parse", parse);
+    builtins.function("yaml.stringify", stringify);
+}
+
+/// Parses the first document of a YAML stream. Block and flow collections,
+/// quoted and block scalars are supported; anchors, aliases and tags are not.
+fn parse(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [Value::String(text)] = args else {
+        return invalid_args();
+    };
+    Some(Parser::new(text).document().unwrap_or_else(|err| err))
+}
+
+/// Writes a value in block style, quoting strings only where needed.
+fn stringify(_: &mut Evaluator, args: &[Value]) -> Option<Value> {
+    let [value] = args else {
+        return invalid_args();
+    };
+    let mut out = String::new();
+    Some(match write_node(&mut out, value, 0) {
+        Ok(()) => Value::String(out),
+        Err(message) => error("yaml", message, BTreeMap::new()),
+    })
+}
+
+/// Parse failures are already error values.
+type Parsed<T> = Result<T, Value>;
+
+#[derive(Clone)]
+struct Line {
+    number: usize,
+    indent: usize,
+    /// The content after the indentation, without any comment.
+    text: String,
+    raw: String,
+}
+
+struct Parser {
+    lines: Vec<Line>,
+    index: usize,
+}
+
+impl Parser {
+    fn new(text: &str) -> Self {
+        let lines = text
+            .lines()
+            .enumerate()
+            .map(|(i, raw)| {
+                let content = raw.trim_start_matches(' ');
+                Line {
+                    number: i + 1,
+                    indent: raw.len() - content.len(),
+                    text: strip_comment(content).trim_end().to_string(),
+                    raw: raw.to_string(),
+                }
+            })
+            .collect();
+        Self { lines, index: 0 }
+    }
+
+    fn document(&mut self) -> Parsed<Value> {
+        // Directives and the `---` marker that opens the first document.
+        while let Some(line) = self.lines.get(self.index) {
+            if line.text.is_empty() || line.text.starts_with('%') {
+                self.index += 1;
+            } else {
+                if line.indent == 0 && line.text == "---" {
+                    self.index += 1;
+                }
+                break;
+            }
+        }
+        let value = self.node(0)?;
+        match self.peek() {
+            None => Ok(value),
+            Some(line) => Err(fail(&line, line.indent, "unexpected content")),
+        }
+    }
+
+    /// The next line with content, or `None` at the end of the document.
+    fn peek(&mut self) -> Option<Line> {
+        while self.lines.get(self.index)?.text.is_empty() {
+            self.index += 1;
+        }
+        let line = &self.lines[self.index];
+        let marker = line.indent == 0 && (line.text == "---" || line.text == "...");
+        (!marker).then(|| line.clone())
+    }
+
+    /// A node indented by at least `min_indent`, or nil if there is none.
+    fn node(&mut self, min_indent: usize) -> Parsed<Value> {
+        let Some(line) = self.peek().filter(|line| line.indent >= min_indent) else {
+            return Ok(Value::Nil);
+        };
+        if is_sequence_item(&line.text) {
+            return self.sequence(line.indent);
+        }
+        if !matches!(mapping_key(&line), Ok(None)) {
+            return self.mapping(line.indent);
+        }
+        self.index += 1;
+        self.value(&line, 0, min_indent.saturating_sub(1))
+    }
+
+    fn sequence(&mut self, indent: usize) -> Parsed<Value> {
+        let mut items = Vec::new();
+        while let Some(line) = self.peek().filter(|line| line.indent == indent && is_sequence_item(&line.text)) {
+            let rest = &line.text[1..];
+            let content = rest.trim_start();
+            if content.is_empty() {
+                self.index += 1;
+            } else {
+                // Reread the rest of the line as a node indented past the `-`.
+                let line = &mut self.lines[self.index];
+                line.indent += 1 + rest.len() - content.len();
+                line.text = content.to_string();
+            }
+            items.push(self.node(indent + 1)?);
+            self.check_dedent(indent)?;
+        }
+        Ok(list(items))
+    }
+
+    fn mapping(&mut self, indent: usize) -> Parsed<Value> {
+        let mut entries = BTreeMap::new();
+        while let Some(line) = self.peek().filter(|line| line.indent == indent) {
+            let Some((key, offset)) = mapping_key(&line)? else {
+                return Err(fail(&line, line.indent, "expected a mapping key"));
+            };
+            self.index += 1;
+            let rest = &line.text[offset..];
+            let start = offset + rest.len() - rest.trim_start().len();
+            let value = if start == line.text.len() {
+                match self.peek() {
+                    Some(next) if next.indent > indent => self.node(indent + 1)?,
+                    // A sequence may sit at the same indentation as its key.
+                    Some(next) if next.indent == indent && is_sequence_item(&next.text) => {
+                        self.sequence(indent)?
+                    }
+                    _ => Value::Nil,
+                }
+            } else {
+                self.value(&line, start, indent)?
+            };
+            if entries.insert(key.clone(), value).is_some() {
+                return Err(fail(&line, line.indent, &format!("duplicate key {:?}", key)));
+            }
+            self.check_dedent(indent)?;
+        }
+        Ok(map(entries))
+    }
+
+    /// After an entry of a collection at `indent`, the next line must not be
+    /// indented further without belonging to that entry.
+    fn check_dedent(&mut self, indent: usize) -> Parsed<()> {
+        match self.peek() {
+            Some(line) if line.indent > indent => Err(fail(&line, line.indent, "unexpected indentation")),
+            _ => Ok(()),
+        }
+    }
+
+    /// The value starting at byte `start` of `line`'s text, inside a node
+    /// indented by `parent`. Flow collections and plain scalars may continue
+    /// on more indented lines.
+    fn value(&mut self, line: &Line, start: usize, parent: usize) -> Parsed<Value> {
+        let text = &line.text[start..];
+        let column = line.indent + start;
+        match text.chars().next() {
+            Some('|' | '>') => return self.block_scalar(line, text, parent),
+            Some('&' | '*' | '!') => {
+                return Err(fail(line, column, "anchors, aliases and tags are not supported"))
+            }
+            _ => {}
+        }
+        let mut text = text.to_string();
+        let is_flow = text.starts_with(['[', '{']);
+        let is_quoted = text.starts_with(['"', '\'']);
+        if !is_quoted {
+            while let Some(next) = self.peek().filter(|next| next.indent > parent) {
+                if is_flow && is_balanced(&text) || !is_flow && matches!(mapping_key(&next), Ok(Some(_))) {
+                    break;
+                }
+                text.push(' ');
+                text.push_str(&next.text);
+                self.index += 1;
+            }
+        }
+        if !is_flow && !is_quoted {
+            return Ok(resolve(&text));
+        }
+        let mut scanner = Scanner::new(&text);
+        let value = scanner.flow_value().and_then(|value| {
+            scanner.skip_spaces();
+            match scanner.peek() {
+                None => Ok(value),
+                Some(_) => Err("unexpected text after the value".to_string()),
+            }
+        });
+        value.map_err(|message| fail(line, column + scanner.pos, &message))
+    }
+
+    /// A `|` (literal) or `>` (folded) scalar whose content is the following
+    /// lines indented past `parent`.
+    fn block_scalar(&mut self, line: &Line, header: &str, parent: usize) -> Parsed<Value> {
+        let folded = header.starts_with('>');
+        let mut chomp = ' ';
+        let mut explicit = None;
+        for c in header[1..].chars() {
+            match c {
+                '-' | '+' => chomp = c,
+                '1'..='9' => explicit = c.to_digit(10).map(|d| parent + d as usize),
+                _ => return Err(fail(line, line.indent, "invalid block scalar header")),
+            }
+        }
+        let mut body: Vec<String> = Vec::new();
+        let mut content_indent = explicit;
+        while let Some(next) = self.lines.get(self.index) {
+            if next.raw.trim().is_empty() {
+                body.push(String::new());
+            } else {
+                let indent = *content_indent.get_or_insert(next.indent);
+                if next.indent < indent || next.indent <= parent {
+                    break;
+                }
+                body.push(next.raw[indent..].to_string());
+            }
+            self.index += 1;
+        }
+        let trailing = body.iter().rev().take_while(|l| l.is_empty()).count();
+        body.truncate(body.len() - trailing);
+        let mut text = String::new();
+        for (i, l) in body.iter().enumerate() {
+            if i > 0 {
+                let prev = &body[i - 1];
+                let joins = folded && !prev.is_empty() && !prev.starts_with(' ') && !l.starts_with(' ');
+                if joins && !l.is_empty() {
+                    text.push(' ');
+                } else if !(joins && l.is_empty()) {
+                    text.push('\n');
+                }
+            }
+            text.push_str(l);
+        }
+        match chomp {
+            '-' => {}
+            '+' => text.push_str(&"\n".repeat(trailing + usize::from(!body.is_empty()))),
+            _ if !body.is_empty() => text.push('\n'),
+            _ => {}
+        }
+        Ok(Value::String(text))
+    }
+}
+
+fn fail(line: &Line, column: usize, message: &str) -> Value {
+    parse_error("yaml", message, line.number, column + 1)
+}
+
+fn is_sequence_item(text: &str) -> bool {
+    text == "-" || text.starts_with("- ")
+}
+
+/// The key of a `key: value` line and the byte offset just past its colon,
+/// or `None` if the line is not a mapping entry.
+fn mapping_key(line: &Line) -> Parsed<Option<(String, usize)>> {
+    let text = &line.text;
+    if text.starts_with(['[', '{']) {
+        return Ok(None);
+    }
+    let (key, end) = if text.starts_with(['"', '\'']) {
+        let mut scanner = Scanner::new(text);
+        let key = scanner.quoted().map_err(|message| fail(line, line.indent + scanner.pos, &message))?;
+        (key, scanner.chars[..scanner.pos].iter().map(|c| c.len_utf8()).sum())
+    } else {
+        let Some(colon) = find_colon(text) else {
+            return Ok(None);
+        };
+        (text[..colon].trim_end().to_string(), colon)
+    };
+    let rest = text[end..].trim_start();
+    if rest == ":" || rest.starts_with(": ") {
+        Ok(Some((key, text.len() - rest.len() + 1)))
+    } else {
+        Ok(None)
+    }
+}
+
+/// The first `:` followed by a space or the end of the line.
+fn find_colon(text: &str) -> Option<usize> {
+    let bytes = text.as_bytes();
+    (0..bytes.len()).find(|&i| bytes[i] == b':' && matches!(bytes.get(i + 1), None | Some(b' ')))
+}
+
+/// Removes a `#` comment, which must start the text or follow whitespace and
+/// must be outside quotes.
+fn strip_comment(text: &str) -> &str {
+    let mut quote = None;
+    let mut escaped = false;
+    let mut prev = ' ';
+    for (i, c) in text.char_indices() {
+        match quote {
+            Some('"') if escaped => escaped = false,
+            Some('"') if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == '#' && matches!(prev, ' ' | '\t') => return &text[..i],
+            // `''` inside a single-quoted string closes and reopens it.
+            None if matches!(c, '"' | '\'') && (matches!(prev, ' ' | '\t' | '[' | '{' | ',') || c == '\'' && prev == c) => {
+                quote = Some(c)
+            }
+            None => {}
+        }
+        prev = c;
+    }
+    text
+}
+
+/// Whether every `[` and `{` outside quotes has been closed.
+fn is_balanced(text: &str) -> bool {
+    let mut scanner = Scanner::new(text);
+    scanner.flow_value().is_ok()
+}
+
+/// Reads flow collections and quoted scalars within a single logical line.
+struct Scanner {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Scanner {
+    fn new(text: &str) -> Self {
+        Self {
+            chars: text.chars().collect(),
+            pos: 0,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn skip_spaces(&mut self) {
+        while matches!(self.peek(), Some(' ' | '\t')) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, expected: char) -> bool {
+        let matches = self.peek() == Some(expected);
+        if matches {
+            self.pos += 1;
+        }
+        matches
+    }
+
+    fn flow_value(&mut self) -> Result<Value, String> {
+        self.skip_spaces();
+        match self.peek() {
+            Some('[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                loop {
+                    self.skip_spaces();
+                    if self.eat(']') {
+                        return Ok(list(items));
+                    }
+                    items.push(self.flow_value()?);
+                    self.skip_spaces();
+                    if self.eat(']') {
+                        return Ok(list(items));
+                    }
+                    if !self.eat(',') {
+                        return Err("expected ',' or ']'".to_string());
+                    }
+                }
+            }
+            Some('{') => {
+                self.pos += 1;
+                let mut entries = BTreeMap::new();
+                loop {
+                    self.skip_spaces();
+                    if self.eat('}') {
+                        return Ok(map(entries));
+                    }
+                    let key = match self.flow_value()? {
+                        Value::String(key) => key,
+                        other => other.to_string(),
+                    };
+                    self.skip_spaces();
+                    let value = if self.eat(':') { self.flow_value()? } else { Value::Nil };
+                    entries.insert(key, value);
+                    self.skip_spaces();
+                    if self.eat('}') {
+                        return Ok(map(entries));
+                    }
+                    if !self.eat(',') {
+                        return Err("expected ',' or '}'".to_string());
+                    }
+                }
+            }
+            Some('"' | '\'') => self.quoted().map(Value::String),
+            Some(_) => {
+                let start = self.pos;
+                while let Some(c) = self.peek() {
+                    let ends_key = c == ':' && matches!(self.chars.get(self.pos + 1), None | Some(' ' | ',' | ']' | '}'));
+                    if matches!(c, ',' | ']' | '}') || ends_key {
+                        break;
+                    }
+                    self.pos += 1;
+                }
+                let text: String = self.chars[start..self.pos].iter().collect();
+                Ok(resolve(text.trim()))
+            }
+            None => Err("unexpected end of line".to_string()),
+        }
+    }
+
+    fn quoted(&mut self) -> Result<String, String> {
+        let quote = self.peek().ok_or("expected a string")?;
+        self.pos += 1;
+        let mut result = String::new();
+        loop {
+            let c = self.peek().ok_or("unterminated string")?;
+            self.pos += 1;
+            match c {
+                '\'' if quote == '\'' => {
+                    if !self.eat('\'') {
+                        return Ok(result);
+                    }
+                    result.push('\'');
+                }
+                '"' if quote == '"' => return Ok(result),
+                '\\' if quote == '"' => result.push(self.escape()?),
+                c => result.push(c),
+            }
+        }
+    }
+
+    fn escape(&mut self) -> Result<char, String> {
+        let c = self.peek().ok_or("unterminated string")?;
+        self.pos += 1;
+        let digits = match c {
+            '0' => return Ok('\0'),
+            'a' => return Ok('\u{7}'),
+            'b' => return Ok('\u{8}'),
+            't' => return Ok('\t'),
+            'n' => return Ok('\n'),
+            'v' => return Ok('\u{b}'),
+            'f' => return Ok('\u{c}'),
+            'r' => return Ok('\r'),
+            'e' => return Ok('\u{1b}'),
+            ' ' | '"' | '/' | '\\' => return Ok(c),
+            'x' => 2,
+            'u' => 4,
+            'U' => 8,
+            _ => return Err("invalid escape sequence".to_string()),
+        };
+        let mut code = 0;
+        for _ in 0..digits {
+            let digit = self.peek().and_then(|c| c.to_digit(16)).ok_or("invalid escape sequence")?;
+            self.pos += 1;
+            code = code * 16 + digit;
+        }
+        char::from_u32(code).ok_or_else(|| "invalid escape sequence".to_string())
+    }
+}
+
+/// The value of a plain (unquoted) scalar under the YAML core schema.
+fn resolve(text: &str) -> Value {
+    match text {
+        "" | "~" | "null" | "Null" | "NULL" => return Value::Nil,
+        "true" | "True" | "TRUE" => return Value::Bool(true),
+        "false" | "False" | "FALSE" => return Value::Bool(false),
+        ".inf" | ".Inf" | ".INF" | "+.inf" | "+.Inf" | "+.INF" => return Value::Number(f64::INFINITY),
+        "-.inf" | "-.Inf" | "-.INF" => return Value::Number(f64::NEG_INFINITY),
+        ".nan" | ".NaN" | ".NAN" => return Value::Number(f64::NAN),
+        _ => {}
+    }
+    let number = if let Some(hex) = text.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok().map(|n| n as f64)
+    } else if let Some(octal) = text.strip_prefix("0o") {
+        i64::from_str_radix(octal, 8).ok().map(|n| n as f64)
+    } else {
+        let unsigned = text.strip_prefix(['+', '-']).unwrap_or(text);
+        let numeric = unsigned.strip_prefix('.').unwrap_or(unsigned);
+        if numeric.starts_with(|c: char| c.is_ascii_digit()) {
+            text.parse().ok()
+        } else {
+            None
+        }
+    };
+    match number {
+        Some(n) => Value::Number(n),
+        None => Value::String(text.to_string()),
+    }
+}
+
+/// Writes `value` starting on a fresh line indented by `indent` spaces.
+fn write_node(out: &mut String, value: &Value, indent: usize) -> Result<(), String> {
+    let pad = " ".repeat(indent);
+    match value {
+        Value::Map(entries) if !entries.borrow().is_empty() => {
+            for (key, item) in entries.borrow().iter() {
+                out.push_str(&pad);
+                write_scalar(out, &Value::String(key.clone()))?;
+                out.push(':');
+                if is_block(item) {
+                    out.push('\n');
+                    write_node(out, item, indent + 2)?;
+                } else {
+                    out.push(' ');
+                    write_scalar(out, item)?;
+                    out.push('\n');
+                }
+            }
+        }
+        Value::List(_) | Value::Tuple(_) if is_block(value) => {
+            for item in items(value) {
+                out.push_str(&pad);
+                out.push_str("- ");
+                if is_block(&item) {
+                    // The item's first line goes right after the dash.
+                    let mut nested = String::new();
+                    write_node(&mut nested, &item, indent + 2)?;
+                    out.push_str(&nested[indent + 2..]);
+                } else {
+                    write_scalar(out, &item)?;
+                    out.push('\n');
+                }
+            }
+        }
+        _ => {
+            out.push_str(&pad);
+            write_scalar(out, value)?;
+            out.push('\n');
+        }
+    }
+    Ok(())
+}
+
+/// Non-empty collections are written in block style.
+fn is_block(value: &Value) -> bool {
+    match value {
+        Value::Map(entries) => !entries.borrow().is_empty(),
+        Value::List(items) => !items.borrow().is_empty(),
+        Value::Tuple(items) => !items.is_empty(),
+        _ => false,
+    }
+}
+
+fn items(value: &Value) -> Vec<Value> {
+    match value {
+        Value::List(items) => items.borrow().clone(),
+        Value::Tuple(items) => items.as_ref().clone(),
+        _ => Vec::new(),
+    }
+}
+
+fn write_scalar(out: &mut String, value: &Value) -> Result<(), String> {
+    match value {
+        Value::Nil => out.push_str("null"),
+        Value::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+        Value::Number(n) if n.is_nan() => out.push_str(".nan"),
+        Value::Number(n) if n.is_infinite() => out.push_str(if *n > 0.0 { ".inf" } else { "-.inf" }),
+        Value::Number(n) => out.push_str(&n.to_string()),
+        Value::String(s) if is_plain(s) => out.push_str(s),
+        Value::String(s) => write_string(out, s),
+        Value::List(_) | Value::Tuple(_) => out.push_str("[]"),
+        Value::Map(_) => out.push_str("{}"),
+        other => return Err(format!("{} cannot be represented in YAML", other)),
+    }
+    Ok(())
+}
+
+/// Whether a string reads back as itself without quotes.
+fn is_plain(s: &str) -> bool {
+    !s.is_empty()
+        && matches!(resolve(s), Value::String(_))
+        && s.trim() == s
+        && !s.starts_with(|c| "-?:,[]{}#&*!|>'\"%@`".contains(c))
+        && !s.ends_with(':')
+        && !s.contains(": ")
+        && !s.contains(" #")
+        && !s.chars().any(char::is_control)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::stdlib::json::Reader as Json;
+
+    fn parsed(text: &str) -> Value {
+        parse(&mut Evaluator::new(), &[Value::String(text.to_string())]).unwrap()
+    }
+
+    fn stringified(value: Value) -> String {
+        match stringify(&mut Evaluator::new(), &[value]).unwrap() {
+            Value::String(text) => text,
+            other => panic!("not a string: {}", other),
+        }
+    }
+
+    fn json(text: &str) -> Value {
+        Json::new(text).document().unwrap()
+    }
+
+    #[test]
+    fn parses_scalars() {
+        let text = "\
+# a comment
+name: alin
+version: 1.5
+enabled: true
+answer: yes
+nothing: ~
+quoted: \"a\\tb \\\"c\\\" \\u00e9\"
+single: 'it''s # not a comment'
+block: |
+  line one
+  line two
+folded: >
+  folded
+  text
+";
+        let expected = "{answer: \"yes\", block: \"line one\\nline two\\n\", enabled: true, \
+                        folded: \"folded text\\n\", name: \"alin\", nothing: nil, quoted: \"a\\tb \\\"c\\\" \u{e9}\", \
+                        single: \"it's # not a comment\", version: 1.5}";
+        assert_eq!(parsed(text).to_string(), expected);
+        assert_eq!(parsed("plain").to_string(), "plain");
+        assert_eq!(parsed("").to_string(), "nil");
+        assert_eq!(parsed("---\na: 1\n...\n").to_string(), "{a: 1}");
+    }
+
+    #[test]
+    fn parses_nested_collections() {
+        let text = "\
+list:
+  - 1
+  - two
+  - [3, {four: 4}]
+nested:
+  deep:
+    key: value # trailing
+  items:
+  - a: 1
+    b: 2
+  - c
+empty: []
+";
+        let expected = "{empty: [], list: [1, \"two\", [3, {four: 4}]], \
+                        nested: {deep: {key: \"value\"}, items: [{a: 1, b: 2}, \"c\"]}}";
+        assert_eq!(parsed(text).to_string(), expected);
+        assert_eq!(parsed("- 1\n- - 2\n  - 3\n").to_string(), "[1, [2, 3]]");
+    }
+
+    #[test]
+    fn stringifies_and_quotes_only_where_needed() {
+        let value = json(r##"["a", "", "1", "true", "with: colon", "#hash", "multi\nline", null, true, 1.5, [], {}]"##);
+        let expected = "\
+- a
+- \"\"
+- \"1\"
+- \"true\"
+- \"with: colon\"
+- \"#hash\"
+- \"multi\\nline\"
+- null
+- true
+- 1.5
+- []
+- {}
+";
+        assert_eq!(stringified(value), expected);
+        assert_eq!(stringified(json(r#"{"a": {"b": [1, {"c": 2}]}}"#)), "a:\n  b:\n    - 1\n    - c: 2\n");
+    }
+
+    #[test]
+    fn round_trips() {
+        let value = json(r#"{"a": [1, "two", [3.5, null]], "b": "quote \" and \\ and \n", "c": {"d": {"e": true}},
+                             "f": [{"g": 1, "h": [2]}], "i": "", "j": "42"}"#);
+        let written = stringified(value.clone());
+        assert_eq!(parsed(&written).to_string(), value.to_string(), "written as:\n{}", written);
+        assert_eq!(stringified(parsed(&written)), written);
+    }
+
+    #[test]
+    fn reports_errors_where_they_are() {
+        let cases = [
+            ("a: [1, 2", "expected ',' or ']' at line 1, column 9"),
+            ("a: 1\n b: 2", "unexpected indentation at line 2, column 2"),
+            ("a: \"open", "unterminated string at line 1, column 9"),
+            ("- 1\nb: 2", "unexpected content at line 2, column 1"),
+            ("a: 1\na: 2", "duplicate key \"a\" at line 2, column 1"),
+            ("key: {x: 1", "expected ',' or '}' at line 1, column 11"),
+            ("&anchor a", "anchors, aliases and tags are not supported at line 1, column 1"),
+        ];
+        for (text, message) in cases {
+            match parsed(text) {
+                Value::Error(err) => assert_eq!(err.message, message, "for {:?}", text),
+                other => panic!("parsed {:?} as {}", text, other),
+            }
+        }
+    }
+}
@@ -1,3 +1,6 @@
+use std::fmt;
+use std::rc::Rc;
+
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Token {
@@ -22,16 +25,123 @@ pub enum Token {
     RParen,
     LBrace,
     RBrace,
+    LBracket,
+    RBracket,
     Comma,
+    Dot,
+    Colon,
     Semicolon,
+    Ellipsis,
 
     If,
     Else,
     While,
+    For,
+    In,
     Fn,
     Return,
     Break,
     Continue,
+    Yield,
+    True,
+    False,
+    Nil,
 
     Eof,
 }
+
+/// The words the lexer reads as keywords rather than identifiers.
+pub const KEYWORDS: [&str; 13] =
+    ["if", "else", "while", "for", "in", "fn", "return", "break", "continue", "yield", "true", "false", "nil"];
+
+/// How a token reads in error messages, such as `` `)` `` or `end of input`.
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let text = match self {
+            Token::Identifier(name) => return write!(f, "identifier `{}`", name),
+            Token::Number(n) => return write!(f, "number `{}`", n),
+            Token::String(s) => return write!(f, "string {:?}", s),
+            Token::Eof => return write!(f, "end of input"),
+            Token::Plus => "+",
+            Token::Minus => "-",
+            Token::Star => "*",
+            Token::Slash => "/",
+            Token::Equal => "=",
+            Token::EqualEqual => "==",
+            Token::BangEqual => "!=",
+            Token::Less => "<",
+            Token::LessEqual => "<=",
+            Token::Greater => ">",
+            Token::GreaterEqual => ">=",
+            Token::LParen => "(",
+            Token::RParen => ")",
+            Token::LBrace => "{",
+            Token::RBrace => "}",
+            Token::LBracket => "[",
+            Token::RBracket => "]",
+            Token::Comma => ",",
+            Token::Dot => ".",
+            Token::Colon => ":",
+            Token::Semicolon => ";",
+            Token::Ellipsis => "...",
+            Token::If => "if",
+            Token::Else => "else",
+            Token::While => "while",
+            Token::For => "for",
+            Token::In => "in",
+            Token::Fn => "fn",
+            Token::Return => "return",
+            Token::Break => "break",
+            Token::Continue => "continue",
+            Token::Yield => "yield",
+            Token::True => "true",
+            Token::False => "false",
+            Token::Nil => "nil",
+        };
+        write!(f, "`{}`", text)
+    }
+}
+
+/// A stretch of source code. `offset` and `len` count bytes; `line` and
+/// `column` are 1-based, with columns counting characters.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Span {
+    pub file: Rc<str>,
+    pub offset: usize,
+    pub len: usize,
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Span {
+    /// The span from the start of `self` to the end of `end`.
+    pub fn to(&self, end: &Span) -> Span {
+        Span {
+            file: self.file.clone(),
+            offset: self.offset,
+            len: (end.offset + end.len).saturating_sub(self.offset),
+            line: self.line,
+            column: self.column,
+        }
+    }
+}
+
+impl fmt::Display for Span {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}:{}", self.file, self.line, self.column)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SpannedToken {
+    pub token: Token,
+    pub span: Span,
+}
+
+/// A `//` comment, which the parser never sees but tools such as the
+/// formatter keep. `text` starts with the `//`.
+#[derive(Debug, Clone)]
+pub struct Comment {
+    pub text: String,
+    pub span: Span,
+}
@@ -0,0 +1,644 @@
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use crate::bytecode::{Constant, Op, Proto};
+use crate::error::AlinError;
+use crate::eval::{is_truthy, Body, Evaluator, Function, Value};
+use crate::iter::Iter;
+use crate::token::Span;
+
+/// The state of the bytecode interpreter: one operand stack and one array
+/// of local slots, shared by all active frames, each owning the part above
+/// its bases.
+#[derive(Default)]
+pub struct Vm {
+    stack: Vec<Value>,
+    locals: Vec<Option<Value>>,
+    /// Active compiled code, innermost last.
+    pub(crate) frames: Vec<Frame>,
+}
+
+pub(crate) struct Frame {
+    proto: Rc<Proto>,
+    /// The next instruction, kept up to date whenever the frame is left.
+    ip: usize,
+    stack_base: usize,
+    locals_base: usize,
+    /// `None` for top-level code.
+    pub(crate) function: Option<Rc<Function>>,
+    pub(crate) call_site: Span,
+}
+
+/// How a run of `execute` ended.
+enum Exit {
+    /// The first frame returned this value.
+    Return(Value),
+    /// The first frame, a generator's, suspended with this value.
+    Yield(Value),
+    /// Something reported an error; the frames have been dropped.
+    Failed,
+}
+
+/// A call of a compiled generator function, suspended between items: the
+/// instruction to resume at and its frame's part of the stack.
+#[derive(Debug)]
+pub struct Generator {
+    function: Rc<Function>,
+    proto: Rc<Proto>,
+    ip: usize,
+    locals: Vec<Option<Value>>,
+    stack: Vec<Value>,
+    done: bool,
+}
+
+impl Evaluator {
+    /// Runs a compiled script, stopping at the first statement that fails.
+    pub fn run_compiled(&mut self, script: Rc<Proto>) -> Result<(), AlinError> {
+        let boundary = self.vm.frames.len();
+        self.vm.frames.push(Frame {
+            proto: script,
+            ip: 0,
+            stack_base: self.vm.stack.len(),
+            locals_base: self.vm.locals.len(),
+            function: None,
+            call_site: Span::default(),
+        });
+        self.execute(boundary);
+        if self.reported() > 0 {
+            return Err(self.take_errors());
+        }
+        Ok(())
+    }
+
+    /// Calls a compiled function from native code, such as a builtin
+    /// applying it to each item of a list.
+    pub(crate) fn call_compiled(&mut self, function: Rc<Function>, proto: Rc<Proto>, args: Vec<Value>, call_site: Span) -> Option<Value> {
+        let boundary = self.vm.frames.len();
+        let base = self.vm.stack.len();
+        match self.enter(function, proto, args, base, call_site)? {
+            Some(generator) => Some(generator),
+            None => match self.execute(boundary) {
+                Exit::Return(value) => Some(value),
+                Exit::Yield(_) | Exit::Failed => None,
+            },
+        }
+    }
+
+    /// Runs a compiled generator until its next `yield`, or returns `None`
+    /// once it has finished or failed.
+    pub(crate) fn resume_compiled(&mut self, generator: &mut Generator) -> Option<Value> {
+        if generator.done {
+            return None;
+        }
+        // Resuming nests like a call, natively as well.
+        let site = self.site.clone();
+        if !self.check_depth(&generator.function, &site) {
+            return None;
+        }
+        let boundary = self.vm.frames.len();
+        self.vm.frames.push(Frame {
+            proto: generator.proto.clone(),
+            ip: generator.ip,
+            stack_base: self.vm.stack.len(),
+            locals_base: self.vm.locals.len(),
+            function: Some(generator.function.clone()),
+            call_site: self.site.clone(),
+        });
+        self.vm.stack.append(&mut generator.stack);
+        self.vm.locals.append(&mut generator.locals);
+
+        match self.execute(boundary) {
+            Exit::Yield(value) => {
+                let frame = self.vm.frames.pop()?;
+                generator.ip = frame.ip;
+                generator.stack = self.vm.stack.split_off(frame.stack_base);
+                generator.locals = self.vm.locals.split_off(frame.locals_base);
+                Some(value)
+            }
+            Exit::Return(_) | Exit::Failed => {
+                generator.done = true;
+                None
+            }
+        }
+    }
+
+    /// Checks a call and pushes its frame with the arguments in its first
+    /// slots. Calling a generator function instead gives `Some` of its
+    /// generator; `None` means the call failed.
+    fn enter(
+        &mut self,
+        function: Rc<Function>,
+        proto: Rc<Proto>,
+        args: Vec<Value>,
+        stack_base: usize,
+        call_site: Span,
+    ) -> Option<Option<Value>> {
+        if !self.check_arity(&function, args.len(), &call_site) {
+            return None;
+        }
+        let mut locals: Vec<Option<Value>> = args.into_iter().map(Some).collect();
+        locals.resize(proto.slots.len(), None);
+        if proto.is_generator {
+            let generator = Generator { function, proto, ip: 0, locals, stack: Vec::new(), done: false };
+            let iter = Iter::Compiled(Box::new(generator));
+            return Some(Some(Value::Iterator(Rc::new(RefCell::new(iter)))));
+        }
+        if !self.check_depth(&function, &call_site) {
+            return None;
+        }
+        let locals_base = self.vm.locals.len();
+        self.vm.locals.extend(locals);
+        self.vm.frames.push(Frame { proto, ip: 0, stack_base, locals_base, function: Some(function), call_site });
+        Some(None)
+    }
+
+    /// The code, next instruction and locals of the innermost frame.
+    fn load_frame(&self) -> (Rc<Proto>, usize, usize) {
+        let frame = &self.vm.frames[self.vm.frames.len() - 1];
+        (frame.proto.clone(), frame.ip, frame.locals_base)
+    }
+
+    fn save_ip(&mut self, ip: usize) {
+        if let Some(frame) = self.vm.frames.last_mut() {
+            frame.ip = ip;
+        }
+    }
+
+    fn pop(&mut self) -> Value {
+        self.vm.stack.pop().unwrap_or(Value::Nil)
+    }
+
+    /// A global by number, falling back on the builtin module of that name.
+    fn load_global(&self, global: u32) -> Option<Value> {
+        self.globals.values[global as usize]
+            .clone()
+            .or_else(|| self.builtins.module(&self.globals.names[global as usize]))
+    }
+
+    /// Runs the innermost frame, and the frames it calls, until it returns
+    /// or yields. Calls between compiled functions stay in this loop; only
+    /// native code calling back in starts another.
+    fn execute(&mut self, boundary: usize) -> Exit {
+        let (stack_floor, locals_floor) = {
+            let first = &self.vm.frames[boundary];
+            (first.stack_base, first.locals_base)
+        };
+        let (mut proto, mut ip, mut locals_base) = self.load_frame();
+
+        // Errors have been reported by the time anything fails, so failing
+        // just drops every frame this run started.
+        macro_rules! fail {
+            () => {{
+                self.vm.frames.truncate(boundary);
+                self.vm.stack.truncate(stack_floor);
+                self.vm.locals.truncate(locals_floor);
+                return Exit::Failed;
+            }};
+        }
+        macro_rules! attempt {
+            ($value:expr) => {
+                match $value {
+                    Some(value) => value,
+                    None => fail!(),
+                }
+            };
+        }
+        // Hands a result to the frame below the one just left.
+        macro_rules! deliver {
+            ($value:expr) => {{
+                let value = $value;
+                if self.vm.frames.len() <= boundary {
+                    return Exit::Return(value);
+                }
+                self.vm.stack.push(value);
+                (proto, ip, locals_base) = self.load_frame();
+            }};
+        }
+        macro_rules! leave {
+            () => {
+                if let Some(frame) = self.vm.frames.pop() {
+                    self.vm.stack.truncate(frame.stack_base);
+                    self.vm.locals.truncate(frame.locals_base);
+                }
+            };
+        }
+
+        loop {
+            let op = proto.code[ip];
+            ip += 1;
+            match op {
+                Op::Const(index) => {
+                    let value = match &proto.constants[index as usize] {
+                        Constant::Number(n) => Value::Number(*n),
+                        Constant::String(s) => Value::String(s.clone()),
+                        Constant::Function(_) => Value::Nil,
+                    };
+                    self.vm.stack.push(value);
+                }
+                Op::Nil => self.vm.stack.push(Value::Nil),
+                Op::True => self.vm.stack.push(Value::Bool(true)),
+                Op::False => self.vm.stack.push(Value::Bool(false)),
+                Op::Pop => {
+                    self.vm.stack.pop();
+                }
+                Op::PopN(n) => {
+                    let len = self.vm.stack.len().saturating_sub(n as usize);
+                    self.vm.stack.truncate(len);
+                }
+                Op::Dup => {
+                    let top = self.vm.stack.last().cloned().unwrap_or(Value::Nil);
+                    self.vm.stack.push(top);
+                }
+
+                Op::LoadName { slot, global, span } => {
+                    let value = match &self.vm.locals[locals_base + slot as usize] {
+                        Some(value) => Some(value.clone()),
+                        None => self.load_global(global),
+                    };
+                    let Some(value) = value else {
+                        let name = self.globals.names[global as usize].clone();
+                        self.undefined_variable(&proto.spans[span as usize], &name);
+                        fail!();
+                    };
+                    self.vm.stack.push(value);
+                }
+                Op::LoadGlobal { global, span } => {
+                    let Some(value) = self.load_global(global) else {
+                        let name = self.globals.names[global as usize].clone();
+                        self.undefined_variable(&proto.spans[span as usize], &name);
+                        fail!();
+                    };
+                    self.vm.stack.push(value);
+                }
+                Op::StoreName { slot, global } => {
+                    let value = self.vm.stack.last().cloned().unwrap_or(Value::Nil);
+                    let local = locals_base + slot as usize;
+                    if self.vm.locals[local].is_some() || self.globals.values[global as usize].is_none() {
+                        self.vm.locals[local] = Some(value);
+                    } else {
+                        self.globals.values[global as usize] = Some(value);
+                    }
+                }
+                Op::StoreGlobal(global) => {
+                    let value = self.vm.stack.last().cloned().unwrap_or(Value::Nil);
+                    self.globals.values[global as usize] = Some(value);
+                }
+
+                Op::List(n) => {
+                    let items = self.vm.stack.split_off(self.vm.stack.len() - n as usize);
+                    self.vm.stack.push(Value::List(Rc::new(RefCell::new(items))));
+                }
+                Op::Append => {
+                    let value = self.pop();
+                    if let Some(Value::List(items)) = self.vm.stack.last() {
+                        items.borrow_mut().push(value);
+                    }
+                }
+                Op::Extend { span } => {
+                    let value = self.pop();
+                    let spread = attempt!(self.at(&proto.spans[span as usize], |ev| ev.spread(value)));
+                    if let Some(Value::List(items)) = self.vm.stack.last() {
+                        items.borrow_mut().extend(spread);
+                    }
+                }
+                Op::Tuple(n) => {
+                    let items = self.vm.stack.split_off(self.vm.stack.len() - n as usize);
+                    self.vm.stack.push(Value::Tuple(Rc::new(items)));
+                }
+                Op::Map(n) => {
+                    let pairs = self.vm.stack.split_off(self.vm.stack.len() - 2 * n as usize);
+                    let mut map = BTreeMap::new();
+                    let mut pairs = pairs.into_iter();
+                    while let (Some(key), Some(value)) = (pairs.next(), pairs.next()) {
+                        if let Value::String(key) = key {
+                            map.insert(key, value);
+                        }
+                    }
+                    self.vm.stack.push(Value::Map(Rc::new(RefCell::new(map))));
+                }
+
+                Op::Index { spans } => {
+                    let index = self.pop();
+                    let object = self.pop();
+                    let spans = &proto.spans[spans as usize..spans as usize + 3];
+                    let item = attempt!(self.index(object, index, [&spans[0], &spans[1], &spans[2]]));
+                    self.vm.stack.push(item);
+                }
+                Op::Binary { op, spans } => {
+                    let rhs = self.pop();
+                    let lhs = self.pop();
+                    let spans = &proto.spans[spans as usize..spans as usize + 3];
+                    let result = attempt!(self.apply(op, lhs, rhs, [&spans[0], &spans[1], &spans[2]]));
+                    self.vm.stack.push(result);
+                }
+
+                Op::MakeFunction(index) => {
+                    let value = match &proto.constants[index as usize] {
+                        Constant::Function(code) => Value::Function(Rc::new(Function {
+                            name: code.name.clone(),
+                            params: code.params.clone(),
+                            body: Body::Code(code.clone()),
+                            is_generator: code.is_generator,
+                            span: code.span.clone(),
+                        })),
+                        _ => Value::Nil,
+                    };
+                    self.vm.stack.push(value);
+                }
+                Op::Callee { call } => {
+                    let info = &proto.calls[call as usize];
+                    let local = info.slot.and_then(|slot| self.vm.locals[locals_base + slot as usize].clone());
+                    match local.or_else(|| self.globals.values[info.global as usize].clone()) {
+                        Some(Value::Function(func)) => self.vm.stack.push(Value::Function(func)),
+                        _ if info.builtin.is_some() => self.vm.stack.push(Value::Nil),
+                        _ => {
+                            self.unknown_function(&proto.spans[info.span as usize], &info.name);
+                            fail!();
+                        }
+                    }
+                }
+                Op::Call { argc, call } => {
+                    let callee = self.vm.stack.len() - argc as usize - 1;
+                    let args = self.vm.stack.split_off(callee + 1);
+                    let info = &proto.calls[call as usize];
+                    let span = proto.spans[info.span as usize].clone();
+                    let Value::Function(func) = self.pop() else {
+                        let Some(builtin) = info.builtin else {
+                            fail!();
+                        };
+                        let result = attempt!(self.call_builtin(&info.name, builtin, &args, &span));
+                        self.vm.stack.push(result);
+                        continue;
+                    };
+                    let Body::Code(code) = &func.body else {
+                        let result = attempt!(self.at(&span, |ev| ev.call_function(func, args)));
+                        self.vm.stack.push(result);
+                        continue;
+                    };
+                    let code = code.clone();
+                    self.save_ip(ip);
+                    match attempt!(self.enter(func, code, args, callee, span)) {
+                        Some(generator) => self.vm.stack.push(generator),
+                        None => (proto, ip, locals_base) = self.load_frame(),
+                    }
+                }
+                Op::TailCall { argc, call } => {
+                    let callee = self.vm.stack.len() - argc as usize - 1;
+                    let args = self.vm.stack.split_off(callee + 1);
+                    let info = &proto.calls[call as usize];
+                    let span = proto.spans[info.span as usize].clone();
+                    let Value::Function(func) = self.pop() else {
+                        let Some(builtin) = info.builtin else {
+                            fail!();
+                        };
+                        let result = attempt!(self.call_builtin(&info.name, builtin, &args, &span));
+                        leave!();
+                        deliver!(result);
+                        continue;
+                    };
+                    // The caller's frame goes first, so the callee takes its
+                    // place instead of stacking up on top of it.
+                    let stack_base = self.vm.frames.last().map_or(callee, |frame| frame.stack_base);
+                    leave!();
+                    let Body::Code(code) = &func.body else {
+                        let result = attempt!(self.at(&span, |ev| ev.call_function(func, args)));
+                        deliver!(result);
+                        continue;
+                    };
+                    let code = code.clone();
+                    match attempt!(self.enter(func, code, args, stack_base, span)) {
+                        Some(generator) => deliver!(generator),
+                        None => (proto, ip, locals_base) = self.load_frame(),
+                    }
+                }
+                Op::Return => {
+                    let value = self.pop();
+                    leave!();
+                    deliver!(value);
+                }
+
+                Op::Jump(target) => ip = target as usize,
+                Op::JumpIfFalse(target) => {
+                    if !is_truthy(&self.pop()) {
+                        ip = target as usize;
+                    }
+                }
+
+                Op::Iterate { span } => {
+                    let value = self.pop();
+                    let iter = attempt!(self.at(&proto.spans[span as usize], |ev| ev.iterate(value)));
+                    self.vm.stack.push(Value::Number(self.reported() as f64));
+                    self.vm.stack.push(Value::Iterator(Rc::new(RefCell::new(iter))));
+                }
+                Op::Next { exit, span } => {
+                    let Some(Value::Iterator(iter)) = self.vm.stack.last().cloned() else {
+                        fail!();
+                    };
+                    match self.at(&proto.spans[span as usize], |ev| ev.next_value(&mut iter.borrow_mut())) {
+                        Some(item) => self.vm.stack.push(item),
+                        None => ip = exit as usize,
+                    }
+                }
+                Op::EndLoop => {
+                    self.pop();
+                    // An iterator also stops when producing an item fails.
+                    if let Value::Number(reported) = self.pop() {
+                        if self.reported() > reported as usize {
+                            fail!();
+                        }
+                    }
+                    self.vm.stack.push(Value::Nil);
+                }
+                Op::Yield => {
+                    let value = self.pop();
+                    self.save_ip(ip);
+                    return Exit::Yield(value);
+                }
+
+                Op::Unpack { count, span } => {
+                    let value = self.pop();
+                    let items = attempt!(self.at(&proto.spans[span as usize], |ev| ev.unpack_tuple(&value, count as usize)));
+                    self.vm.stack.extend(items.into_iter().rev());
+                }
+                Op::UnpackList { count, rest, span } => {
+                    let value = self.pop();
+                    let (items, remaining) = attempt!(self.at(&proto.spans[span as usize], |ev| {
+                        ev.unpack_list(&value, count as usize, rest)
+                    }));
+                    if rest {
+                        self.vm.stack.push(Value::List(Rc::new(RefCell::new(remaining))));
+                    }
+                    self.vm.stack.extend(items.into_iter().rev());
+                }
+                Op::UnpackMap { span } => {
+                    let value = self.vm.stack.last().cloned().unwrap_or(Value::Nil);
+                    attempt!(self.at(&proto.spans[span as usize], |ev| ev.unpack_map(&value)));
+                }
+                Op::Field { key } => {
+                    let map = self.pop();
+                    let value = match (&map, &proto.constants[key as usize]) {
+                        (Value::Map(entries), Constant::String(key)) => entries.borrow().get(key).cloned(),
+                        _ => None,
+                    };
+                    self.vm.stack.push(value.unwrap_or(Value::Nil));
+                }
+                Op::StoreIndex { span } => {
+                    let index = self.pop();
+                    let object = self.pop();
+                    let value = self.pop();
+                    if !self.at(&proto.spans[span as usize], |ev| ev.store_index(object, index, value)) {
+                        fail!();
+                    }
+                }
+
+                Op::EndStatement => {
+                    if self.reported() > 0 {
+                        fail!();
+                    }
+                }
+                Op::SpreadError { span } => {
+                    self.spread_error(&proto.spans[span as usize]);
+                    fail!();
+                }
+                Op::YieldError { span } => {
+                    self.yield_error(&proto.spans[span as usize]);
+                    fail!();
+                }
+                Op::LoopControlError { span } => {
+                    self.loop_control_error(&proto.spans[span as usize]);
+                    fail!();
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::eval::Engine;
+    use crate::playground::run_captured;
+
+    /// Runs `source` on both engines, checks that they print and report
+    /// exactly the same, and gives that output.
+    fn run_both(source: &str) -> String {
+        let tree = run_captured(source, Engine::Tree, true);
+        let vm = run_captured(source, Engine::Vm, true);
+        assert_eq!(tree, vm, "the engines disagree on:\n{}", source);
+        vm
+    }
+
+    #[test]
+    fn functions_as_values() {
+        let output = run_both(
+            "count = 0
+fn bump(by) { count = count + by; return count }
+fn twice(f, x) { return f(f(x)) }
+fn make() { fn inner(x) { return x * 3 } return inner }
+triple = make()
+print(twice(triple, 2), twice(bump, 1), count)
+fn even(x) { return x / 2 == math.floor(x / 2) }
+fn add(a, b) { return a + b }
+print(list(map(triple, filter(even, range(10)))), reduce(add, [1, 2, 3], 0))
+",
+        );
+        assert_eq!(output, "18 2 2\n[0, 6, 12, 18, 24] 6\n");
+
+        let captured = run_both("fn counter() { n = 0; fn step() { return n + 1 } return step }\n");
+        assert!(captured.starts_with("error[E0301]: undefined variable `n`"));
+    }
+
+    #[test]
+    fn generators() {
+        let output = run_both(
+            "fn count_to(n) { i = 0; while i < n { yield i; i = i + 1 } return \"unused\" }
+fn evens(xs) { for x in xs { if x / 2 != math.floor(x / 2) { continue } yield x } }
+fn pairs() { yield (1, \"a\"); yield (2, \"b\") }
+for x in count_to(5) { if x == 3 { break } print(x) }
+g = count_to(2)
+print(next(g), next(g), next(g), next(g))
+print(list(evens(count_to(7))), list(pairs()))
+fn bad() { yield 1; yield 1 + \"a\" }
+print(\"got\", list(bad()))
+",
+        );
+        assert!(output.starts_with("0\n1\n2\n0 1 nil nil\n[0, 2, 4, 6] [(1, \"a\"), (2, \"b\")]\nerror[E0203]"));
+        assert!(output.contains(", in bad\n"));
+    }
+
+    #[test]
+    fn spread_and_destructuring() {
+        let output = run_both(
+            "fn gen(n) { i = 0; while i < n { yield i; i = i + 1 } }
+xs = [1, 2]
+print([...xs, ...gen(3), 9], [...[], ...xs])
+[first, ...rest] = [1, 2, 3]
+{name, age: years} = {name: \"alin\", age: 3}
+a, b = 1, 2
+a, b = b, a
+print(first, rest, name, years, a, b)
+[x, y] = [1]
+",
+        );
+        assert!(output.starts_with("[1, 2, 0, 1, 2, 9] [1, 2]\n1 [2, 3] alin 3 2 1\nerror[E0208]"));
+    }
+
+    #[test]
+    fn tail_calls_and_deep_recursion() {
+        let output = run_both(
+            "fn loop(n) { if n == 0 { return \"done\" } return loop(n - 1) }
+fn fact(n, acc) { if n <= 1 { return acc } return fact(n - 1, acc * n) }
+fn ping(n) { if n == 0 { return \"ping\" } return pong(n - 1) }
+fn pong(n) { if n == 0 { return \"pong\" } return ping(n - 1) }
+print(loop(100000), fact(20, 1), ping(100001))
+fn deep(n) { if n == 0 { return 0 } return 1 + deep(n - 1) }
+print(deep(100000))
+",
+        );
+        assert!(output.starts_with("done 2432902008176640000 pong\nerror[E0216]"));
+        assert!(output.contains("[previous entry repeated 999 more time(s)]"));
+    }
+
+    #[test]
+    fn error_tracebacks() {
+        let nested = run_both(
+            "fn inner(x) { return x + \"a\" }
+fn middle(x) { y = inner(x); return y }
+fn outer() { return middle(1) * 2 }
+print(\"before\")
+print(outer())
+",
+        );
+        assert!(nested.starts_with("before\nerror[E0203]"));
+        for frame in ["<script>", "outer", "middle", "inner"] {
+            assert!(nested.contains(&format!(", in {}\n", frame)), "no `{}` in the traceback:\n{}", frame, nested);
+        }
+
+        let callback = run_both(
+            "fn check(x) { if x > 1 { return x.missing } return x }
+print(list(map(check, [1, 2])))
+",
+        );
+        assert!(callback.contains(", in check\n"));
+    }
+
+    #[test]
+    fn stdlib_calls() {
+        let output = run_both(
+            "print(string.upper(\"abc\"), string.split(\"a,b,c\", \",\"), string.pad_left(\"7\", 3, \"0\"))
+print(string.find(\"hello\", \"l\"), string.substring(\"hello\", 1, 3), string.chars(\"hi\"), len(\"h\u{e9}llo\"))
+print(math.sqrt(16), math.pow(2, 10), math.max(3, 9), math.round(2.5), 10 / 4, 1 / 0)
+data = json.parse(\"{\\\"a\\\": [1, 2, {\\\"b\\\": null}], \\\"c\\\": true}\")
+print(data, json.stringify(data))
+print(csv.parse(\"x,y\\n1,\\\"two, three\\\"\\n\"), toml.parse(\"a = 1\\n[t]\\nb = \\\"c\\\"\\n\"))
+xs = [1, 2, 3, 4]
+random.shuffle(xs)
+print(random.randint(1, 100), random.random(), random.choice([1, 2, 3]), xs)
+print(is_error(fs.read_file(\"/nonexistent/file\")), path.join(\"a\", \"b\"), path.extension(\"x.alin\"))
+print(string.repeat(\"x\", 0 - 1))
+",
+        );
+        assert!(output.starts_with("ABC [\"a\", \"b\", \"c\"] 007\n2 el [\"h\", \"i\"] 5\n4 1024 9 3 2.5 inf\n"));
+        assert!(output.contains("true a/b alin\nerror[E0215]"));
+    }
+}